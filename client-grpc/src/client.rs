@@ -29,6 +29,20 @@ impl crate::service::Client<RpcServiceClient<Channel>> for ContactClient {
     type ReadyResponse = ReadyResponse;
     type CargoConfirmationRequest = CargoConfirmationRequest;
     type CargoConfirmationResponse = CargoConfirmationResponse;
+    type CargoCancellationRequest = CargoCancellationRequest;
+    type CargoCancellationResponse = CargoCancellationResponse;
+    type DeliveryQueryRequest = DeliveryQueryRequest;
+    type DeliveryQueryResponse = DeliveryQueryResponse;
+    type DeliveryWatchRequest = DeliveryWatchRequest;
+    type DeliveryStatusUpdate = DeliveryStatusUpdate;
+    type FlightDelayRequest = FlightDelayRequest;
+    type FlightDelayResponse = FlightDelayResponse;
+    type ParcelArrivalRequest = ParcelArrivalRequest;
+    type ParcelArrivalResponse = ParcelArrivalResponse;
+    type NotificationRequest = NotificationRequest;
+    type NotificationResponse = NotificationResponse;
+    type NotificationBatchRequest = NotificationBatchRequest;
+    type NotificationBatchResponse = NotificationBatchResponse;
 
     async fn is_ready(
         &self,
@@ -47,6 +61,76 @@ impl crate::service::Client<RpcServiceClient<Channel>> for ContactClient {
         grpc_debug!("request: {:?}", request);
         self.get_client().await?.cargo_confirmation(request).await
     }
+
+    async fn cargo_cancellation(
+        &self,
+        request: Self::CargoCancellationRequest,
+    ) -> Result<tonic::Response<Self::CargoCancellationResponse>, tonic::Status> {
+        grpc_info!("{} client.", self.get_name());
+        grpc_debug!("request: {:?}", request);
+        self.get_client().await?.cargo_cancellation(request).await
+    }
+
+    async fn query_deliveries(
+        &self,
+        request: Self::DeliveryQueryRequest,
+    ) -> Result<tonic::Response<Self::DeliveryQueryResponse>, tonic::Status> {
+        grpc_info!("{} client.", self.get_name());
+        grpc_debug!("request: {:?}", request);
+        self.get_client().await?.query_deliveries(request).await
+    }
+
+    async fn watch_delivery(
+        &self,
+        request: Self::DeliveryWatchRequest,
+    ) -> Result<tonic::Response<tonic::codec::Streaming<Self::DeliveryStatusUpdate>>, tonic::Status>
+    {
+        grpc_info!("{} client.", self.get_name());
+        grpc_debug!("request: {:?}", request);
+        self.get_client().await?.watch_delivery(request).await
+    }
+
+    async fn flight_delay_notification(
+        &self,
+        request: Self::FlightDelayRequest,
+    ) -> Result<tonic::Response<Self::FlightDelayResponse>, tonic::Status> {
+        grpc_info!("{} client.", self.get_name());
+        grpc_debug!("request: {:?}", request);
+        self.get_client()
+            .await?
+            .flight_delay_notification(request)
+            .await
+    }
+
+    async fn parcel_arrival(
+        &self,
+        request: Self::ParcelArrivalRequest,
+    ) -> Result<tonic::Response<Self::ParcelArrivalResponse>, tonic::Status> {
+        grpc_info!("{} client.", self.get_name());
+        grpc_debug!("request: {:?}", request);
+        self.get_client().await?.parcel_arrival(request).await
+    }
+
+    async fn send_notification(
+        &self,
+        request: Self::NotificationRequest,
+    ) -> Result<tonic::Response<Self::NotificationResponse>, tonic::Status> {
+        grpc_info!("{} client.", self.get_name());
+        grpc_debug!("request: {:?}", request);
+        self.get_client().await?.send_notification(request).await
+    }
+
+    async fn send_notification_batch(
+        &self,
+        request: Self::NotificationBatchRequest,
+    ) -> Result<tonic::Response<Self::NotificationBatchResponse>, tonic::Status> {
+        grpc_info!("{} client.", self.get_name());
+        grpc_debug!("request: {:?}", request);
+        self.get_client()
+            .await?
+            .send_notification_batch(request)
+            .await
+    }
 }
 
 #[cfg(feature = "stub_client")]
@@ -56,6 +140,20 @@ impl crate::service::Client<RpcServiceClient<Channel>> for ContactClient {
     type ReadyResponse = ReadyResponse;
     type CargoConfirmationRequest = CargoConfirmationRequest;
     type CargoConfirmationResponse = CargoConfirmationResponse;
+    type CargoCancellationRequest = CargoCancellationRequest;
+    type CargoCancellationResponse = CargoCancellationResponse;
+    type DeliveryQueryRequest = DeliveryQueryRequest;
+    type DeliveryQueryResponse = DeliveryQueryResponse;
+    type DeliveryWatchRequest = DeliveryWatchRequest;
+    type DeliveryStatusUpdate = DeliveryStatusUpdate;
+    type FlightDelayRequest = FlightDelayRequest;
+    type FlightDelayResponse = FlightDelayResponse;
+    type ParcelArrivalRequest = ParcelArrivalRequest;
+    type ParcelArrivalResponse = ParcelArrivalResponse;
+    type NotificationRequest = NotificationRequest;
+    type NotificationResponse = NotificationResponse;
+    type NotificationBatchRequest = NotificationBatchRequest;
+    type NotificationBatchResponse = NotificationBatchResponse;
 
     async fn is_ready(
         &self,
@@ -74,6 +172,122 @@ impl crate::service::Client<RpcServiceClient<Channel>> for ContactClient {
         grpc_debug!("(MOCK) request: {:?}", request);
         Ok(tonic::Response::new(CargoConfirmationResponse {
             success: true,
+            attempts: 1,
+            message_id: Some(lib_common::uuid::Uuid::new_v4().to_string()),
+            channels: vec![DeliveryChannel::Email as i32],
+            recipient: String::from("i***o@aetheric.nl"),
+            invoice_id: String::from("1"),
+            failure_reason: FailureReason::None as i32,
+            email_verified: true,
+        }))
+    }
+
+    async fn cargo_cancellation(
+        &self,
+        request: Self::CargoCancellationRequest,
+    ) -> Result<tonic::Response<Self::CargoCancellationResponse>, tonic::Status> {
+        grpc_warn!("(MOCK) {} client.", self.get_name());
+        grpc_debug!("(MOCK) request: {:?}", request);
+        Ok(tonic::Response::new(CargoCancellationResponse {
+            success: true,
+            attempts: 1,
+            message_id: Some(lib_common::uuid::Uuid::new_v4().to_string()),
+            recipient: String::from("i***o@aetheric.nl"),
+        }))
+    }
+
+    async fn query_deliveries(
+        &self,
+        request: Self::DeliveryQueryRequest,
+    ) -> Result<tonic::Response<Self::DeliveryQueryResponse>, tonic::Status> {
+        grpc_warn!("(MOCK) {} client.", self.get_name());
+        grpc_debug!("(MOCK) request: {:?}", request);
+        Ok(tonic::Response::new(DeliveryQueryResponse {
+            deliveries: vec![],
+        }))
+    }
+
+    async fn watch_delivery(
+        &self,
+        request: Self::DeliveryWatchRequest,
+    ) -> Result<tonic::Response<tonic::codec::Streaming<Self::DeliveryStatusUpdate>>, tonic::Status>
+    {
+        grpc_warn!("(MOCK) {} client.", self.get_name());
+        grpc_debug!("(MOCK) request: {:?}", request);
+        // a streaming response can only be decoded from a server connection
+        Err(tonic::Status::unimplemented(
+            "(MOCK) delivery status streams need a server",
+        ))
+    }
+
+    async fn flight_delay_notification(
+        &self,
+        request: Self::FlightDelayRequest,
+    ) -> Result<tonic::Response<Self::FlightDelayResponse>, tonic::Status> {
+        grpc_warn!("(MOCK) {} client.", self.get_name());
+        grpc_debug!("(MOCK) request: {:?}", request);
+        Ok(tonic::Response::new(FlightDelayResponse {
+            notified: 0,
+            results: vec![],
+        }))
+    }
+
+    async fn parcel_arrival(
+        &self,
+        request: Self::ParcelArrivalRequest,
+    ) -> Result<tonic::Response<Self::ParcelArrivalResponse>, tonic::Status> {
+        grpc_warn!("(MOCK) {} client.", self.get_name());
+        grpc_debug!("(MOCK) request: {:?}", request);
+        Ok(tonic::Response::new(ParcelArrivalResponse {
+            success: true,
+            attempts: 1,
+            message_id: Some(lib_common::uuid::Uuid::new_v4().to_string()),
+            recipient: String::from("i***o@aetheric.nl"),
+            pickup_window_end: None,
+        }))
+    }
+
+    async fn send_notification(
+        &self,
+        request: Self::NotificationRequest,
+    ) -> Result<tonic::Response<Self::NotificationResponse>, tonic::Status> {
+        grpc_warn!("(MOCK) {} client.", self.get_name());
+        grpc_debug!("(MOCK) request: {:?}", request);
+        Ok(tonic::Response::new(NotificationResponse {
+            success: true,
+            attempts: 1,
+            message_id: Some(lib_common::uuid::Uuid::new_v4().to_string()),
+            channels: vec![DeliveryChannel::Email as i32],
+            recipient: String::from("i***o@aetheric.nl"),
+            failure_reason: FailureReason::None as i32,
+        }))
+    }
+
+    async fn send_notification_batch(
+        &self,
+        request: Self::NotificationBatchRequest,
+    ) -> Result<tonic::Response<Self::NotificationBatchResponse>, tonic::Status> {
+        grpc_warn!("(MOCK) {} client.", self.get_name());
+        grpc_debug!("(MOCK) request: {:?}", request);
+        let results: Vec<NotificationBatchResult> = request
+            .notifications
+            .into_iter()
+            .enumerate()
+            .map(|(index, notification)| NotificationBatchResult {
+                index: index as u32,
+                user_id: notification.user_id,
+                success: true,
+                channels: vec![DeliveryChannel::Email as i32],
+                message_id: Some(lib_common::uuid::Uuid::new_v4().to_string()),
+                failure_reason: FailureReason::None as i32,
+                error: String::new(),
+            })
+            .collect();
+
+        Ok(tonic::Response::new(NotificationBatchResponse {
+            sent: results.len() as u32,
+            failed: 0,
+            results,
         }))
     }
 }
@@ -25,15 +25,617 @@ pub struct CargoConfirmationRequest {
     /// Itinerary ID
     #[prost(string, tag = "2")]
     pub itinerary_id: ::prost::alloc::string::String,
+    /// Phone number (E.164) to send a text message confirmation to
+    #[prost(string, optional, tag = "3")]
+    pub phone_number: ::core::option::Option<::prost::alloc::string::String>,
+    /// Key identifying retries of the same request,
+    /// defaults to the parcel ID and itinerary ID
+    #[prost(string, optional, tag = "4")]
+    pub idempotency_key: ::core::option::Option<::prost::alloc::string::String>,
+    /// Language tag (e.g. nl-BE) of the confirmation, defaults to the user's preferred language
+    #[prost(string, optional, tag = "5")]
+    pub locale: ::core::option::Option<::prost::alloc::string::String>,
+    /// Price lines of the receipt, excluding tax, at least one.
+    /// Deprecated: requests without price lines get an empty receipt with a zero total,
+    /// they will be refused with INVALID_ARGUMENT in a future release
+    #[prost(message, repeated, tag = "6")]
+    pub price_lines: ::prost::alloc::vec::Vec<PriceLine>,
+    /// Tax rate over the price lines, in percent (e.g. 21), defaults to no tax
+    #[prost(string, tag = "7")]
+    pub tax_rate: ::prost::alloc::string::String,
+    /// ISO 4217 currency code of the price lines, defaults to EUR
+    #[prost(string, tag = "8")]
+    pub currency: ::prost::alloc::string::String,
+}
+/// A line on a receipt, e.g. the flight or a fee
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PriceLine {
+    /// Description shown on the receipt
+    #[prost(string, tag = "1")]
+    pub description: ::prost::alloc::string::String,
+    /// Amount excluding tax, as a decimal number (e.g. 12.50)
+    /// with at most the decimals of the currency, negative for discounts
+    #[prost(string, tag = "2")]
+    pub amount: ::prost::alloc::string::String,
 }
 /// Cargo confirmation response
-#[derive(Eq, Copy)]
+#[derive(Eq)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct CargoConfirmationResponse {
     /// True if confirmed
     #[prost(bool, tag = "1")]
     pub success: bool,
+    /// Number of email delivery attempts, more than 1 if a retry was needed
+    #[prost(uint32, tag = "2")]
+    pub attempts: u32,
+    /// Message ID assigned to the email by the provider
+    #[prost(string, optional, tag = "3")]
+    pub message_id: ::core::option::Option<::prost::alloc::string::String>,
+    /// Channels the confirmation was sent through
+    #[prost(enumeration = "DeliveryChannel", repeated, tag = "4")]
+    pub channels: ::prost::alloc::vec::Vec<i32>,
+    /// Email address the confirmation was sent to, masked
+    #[prost(string, tag = "5")]
+    pub recipient: ::prost::alloc::string::String,
+    /// Invoice number included in the confirmation, e.g. `AET-2024-000042`,
+    /// the same for every confirmation of a booking
+    #[prost(string, tag = "6")]
+    pub invoice_id: ::prost::alloc::string::String,
+    /// Why (part of) the confirmation failed,
+    /// FAILURE_REASON_NONE if every requested channel succeeded
+    #[prost(enumeration = "FailureReason", tag = "7")]
+    pub failure_reason: i32,
+    /// True if the email address the confirmation was sent to is verified
+    #[prost(bool, tag = "8")]
+    pub email_verified: bool,
+}
+/// Cargo cancellation request
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CargoCancellationRequest {
+    /// Itinerary ID
+    #[prost(string, tag = "1")]
+    pub itinerary_id: ::prost::alloc::string::String,
+    /// Parcel ID
+    #[prost(string, tag = "2")]
+    pub parcel_id: ::prost::alloc::string::String,
+    /// Why the itinerary was cancelled
+    #[prost(enumeration = "CancellationReason", tag = "3")]
+    pub reason: i32,
+    /// Amount refunded to the customer, in the minor units of the currency (e.g. cents)
+    #[prost(uint64, tag = "4")]
+    pub refund_amount_cents: u64,
+    /// ISO 4217 currency code of the refund, defaults to EUR
+    #[prost(string, tag = "5")]
+    pub currency: ::prost::alloc::string::String,
+    /// Language tag (e.g. nl-BE) of the cancellation, defaults to the user's preferred language
+    #[prost(string, optional, tag = "6")]
+    pub locale: ::core::option::Option<::prost::alloc::string::String>,
+}
+/// Cargo cancellation response
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CargoCancellationResponse {
+    /// True if the customer was notified
+    #[prost(bool, tag = "1")]
+    pub success: bool,
+    /// Number of email delivery attempts, more than 1 if a retry was needed
+    #[prost(uint32, tag = "2")]
+    pub attempts: u32,
+    /// Message ID assigned to the email by the provider
+    #[prost(string, optional, tag = "3")]
+    pub message_id: ::core::option::Option<::prost::alloc::string::String>,
+    /// Email address the notification was sent to, masked
+    #[prost(string, tag = "4")]
+    pub recipient: ::prost::alloc::string::String,
+}
+/// Delivery query request, all provided criteria must match
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeliveryQueryRequest {
+    /// Only deliveries to this user
+    #[prost(string, optional, tag = "1")]
+    pub user_id: ::core::option::Option<::prost::alloc::string::String>,
+    /// Only deliveries related to this itinerary
+    #[prost(string, optional, tag = "2")]
+    pub itinerary_id: ::core::option::Option<::prost::alloc::string::String>,
+    /// Only deliveries at or after this time
+    #[prost(message, optional, tag = "3")]
+    pub from: ::core::option::Option<::prost_types::Timestamp>,
+    /// Only deliveries at or before this time
+    #[prost(message, optional, tag = "4")]
+    pub to: ::core::option::Option<::prost_types::Timestamp>,
+    /// Maximum number of deliveries, defaults to 100
+    #[prost(uint32, optional, tag = "5")]
+    pub limit: ::core::option::Option<u32>,
+}
+/// A notification sent, or attempted to be sent, to a user
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Delivery {
+    /// Delivery ID
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+    /// ID of the user the notification was sent to
+    #[prost(string, tag = "2")]
+    pub user_id: ::prost::alloc::string::String,
+    /// Channel the notification was sent through
+    #[prost(enumeration = "DeliveryChannel", tag = "3")]
+    pub channel: i32,
+    /// Email address or phone number of the recipient
+    #[prost(string, tag = "4")]
+    pub recipient: ::prost::alloc::string::String,
+    /// Template name
+    #[prost(string, tag = "5")]
+    pub template: ::prost::alloc::string::String,
+    /// Message ID assigned by the provider, if accepted
+    #[prost(string, optional, tag = "6")]
+    pub message_id: ::core::option::Option<::prost::alloc::string::String>,
+    /// Current delivery status
+    #[prost(enumeration = "DeliveryStatus", tag = "7")]
+    pub status: i32,
+    /// Error reported by the provider, if any
+    #[prost(string, optional, tag = "8")]
+    pub error: ::core::option::Option<::prost::alloc::string::String>,
+    /// Related parcel, if any
+    #[prost(string, optional, tag = "9")]
+    pub parcel_id: ::core::option::Option<::prost::alloc::string::String>,
+    /// Related itinerary, if any
+    #[prost(string, optional, tag = "10")]
+    pub itinerary_id: ::core::option::Option<::prost::alloc::string::String>,
+    /// When the notification was handed to the provider
+    #[prost(message, optional, tag = "11")]
+    pub created_at: ::core::option::Option<::prost_types::Timestamp>,
+    /// When the status last changed
+    #[prost(message, optional, tag = "12")]
+    pub updated_at: ::core::option::Option<::prost_types::Timestamp>,
+}
+/// Delivery query response
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeliveryQueryResponse {
+    /// Matching deliveries, newest first
+    #[prost(message, repeated, tag = "1")]
+    pub deliveries: ::prost::alloc::vec::Vec<Delivery>,
+}
+/// Delivery status stream request
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeliveryWatchRequest {
+    /// Provider message ID returned when the notification was sent, or delivery ID
+    #[prost(string, tag = "1")]
+    pub notification_id: ::prost::alloc::string::String,
+}
+/// Delivery status change, the first update holds the current status
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeliveryStatusUpdate {
+    /// Delivery with its new status
+    #[prost(message, optional, tag = "1")]
+    pub delivery: ::core::option::Option<Delivery>,
+    /// True if the status is final, the stream ends after this update
+    #[prost(bool, tag = "2")]
+    pub done: bool,
+}
+/// Flight delay notification request
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FlightDelayRequest {
+    /// ID of the rescheduled flight plan
+    #[prost(string, tag = "1")]
+    pub flight_plan_id: ::prost::alloc::string::String,
+    /// Departure time before the delay
+    #[prost(message, optional, tag = "2")]
+    pub old_origin_timeslot_start: ::core::option::Option<::prost_types::Timestamp>,
+    /// Departure time after the delay
+    #[prost(message, optional, tag = "3")]
+    pub new_origin_timeslot_start: ::core::option::Option<::prost_types::Timestamp>,
+    /// Arrival time before the delay
+    #[prost(message, optional, tag = "4")]
+    pub old_target_timeslot_end: ::core::option::Option<::prost_types::Timestamp>,
+    /// Arrival time after the delay
+    #[prost(message, optional, tag = "5")]
+    pub new_target_timeslot_end: ::core::option::Option<::prost_types::Timestamp>,
+}
+/// Outcome of the notification about a single parcel
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NotificationResult {
+    /// Parcel ID
+    #[prost(string, tag = "1")]
+    pub parcel_id: ::prost::alloc::string::String,
+    /// True if the notification was sent
+    #[prost(bool, tag = "2")]
+    pub success: bool,
+    /// Message ID assigned to the email by the provider
+    #[prost(string, optional, tag = "3")]
+    pub message_id: ::core::option::Option<::prost::alloc::string::String>,
+    /// Why the notification failed, FAILURE_REASON_NONE if it was sent
+    #[prost(enumeration = "FailureReason", tag = "4")]
+    pub failure_reason: i32,
+}
+/// Flight delay notification response
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FlightDelayResponse {
+    /// Number of customers notified
+    #[prost(uint32, tag = "1")]
+    pub notified: u32,
+    /// Outcome per parcel on the flight
+    #[prost(message, repeated, tag = "2")]
+    pub results: ::prost::alloc::vec::Vec<NotificationResult>,
+}
+/// Parcel arrival request
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ParcelArrivalRequest {
+    /// ID of the parcel scanned at its destination
+    #[prost(string, tag = "1")]
+    pub parcel_id: ::prost::alloc::string::String,
+    /// Language tag (e.g. nl-BE) of the notification, defaults to the user's preferred language
+    #[prost(string, optional, tag = "2")]
+    pub locale: ::core::option::Option<::prost::alloc::string::String>,
+}
+/// Parcel arrival response
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ParcelArrivalResponse {
+    /// True if the customer was notified
+    #[prost(bool, tag = "1")]
+    pub success: bool,
+    /// Number of email delivery attempts, more than 1 if a retry was needed
+    #[prost(uint32, tag = "2")]
+    pub attempts: u32,
+    /// Message ID assigned to the email by the provider
+    #[prost(string, optional, tag = "3")]
+    pub message_id: ::core::option::Option<::prost::alloc::string::String>,
+    /// Email address the notification was sent to, masked
+    #[prost(string, tag = "4")]
+    pub recipient: ::prost::alloc::string::String,
+    /// End of the pickup window communicated to the customer
+    #[prost(message, optional, tag = "5")]
+    pub pickup_window_end: ::core::option::Option<::prost_types::Timestamp>,
+}
+/// Notification request, sends any template to a user
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NotificationRequest {
+    /// ID of the user to notify
+    #[prost(string, tag = "1")]
+    pub user_id: ::prost::alloc::string::String,
+    /// Template name, e.g. parcel-arrival
+    #[prost(string, tag = "2")]
+    pub template: ::prost::alloc::string::String,
+    /// Channels to send the notification through
+    #[prost(enumeration = "ChannelPreference", tag = "3")]
+    pub channel: i32,
+    /// Template model, must provide every field declared by the template
+    #[prost(map = "string, string", tag = "4")]
+    pub model: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ::prost::alloc::string::String,
+    >,
+    /// Phone number (E.164), required for text messages
+    #[prost(string, optional, tag = "5")]
+    pub phone_number: ::core::option::Option<::prost::alloc::string::String>,
+    /// Related parcel, recorded in the delivery log
+    #[prost(string, optional, tag = "6")]
+    pub parcel_id: ::core::option::Option<::prost::alloc::string::String>,
+    /// Related itinerary, recorded in the delivery log
+    #[prost(string, optional, tag = "7")]
+    pub itinerary_id: ::core::option::Option<::prost::alloc::string::String>,
+    /// Language tag (e.g. nl-BE) of the notification, defaults to the user's preferred language
+    #[prost(string, optional, tag = "8")]
+    pub locale: ::core::option::Option<::prost::alloc::string::String>,
+}
+/// Notification response
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NotificationResponse {
+    /// True if the notification was sent through at least one channel
+    #[prost(bool, tag = "1")]
+    pub success: bool,
+    /// Number of email delivery attempts, more than 1 if a retry was needed
+    #[prost(uint32, tag = "2")]
+    pub attempts: u32,
+    /// Message ID assigned to the email by the provider
+    #[prost(string, optional, tag = "3")]
+    pub message_id: ::core::option::Option<::prost::alloc::string::String>,
+    /// Channels the notification was sent through
+    #[prost(enumeration = "DeliveryChannel", repeated, tag = "4")]
+    pub channels: ::prost::alloc::vec::Vec<i32>,
+    /// Email address the notification was sent to, masked,
+    /// empty if no email was requested
+    #[prost(string, tag = "5")]
+    pub recipient: ::prost::alloc::string::String,
+    /// Why (part of) the notification failed,
+    /// FAILURE_REASON_NONE if every requested channel succeeded
+    #[prost(enumeration = "FailureReason", tag = "6")]
+    pub failure_reason: i32,
+}
+/// Notification batch request
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NotificationBatchRequest {
+    /// Notifications to send, each is sent independently
+    #[prost(message, repeated, tag = "1")]
+    pub notifications: ::prost::alloc::vec::Vec<NotificationRequest>,
+}
+/// Outcome of a single notification of a batch
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NotificationBatchResult {
+    /// Position of the notification in the request
+    #[prost(uint32, tag = "1")]
+    pub index: u32,
+    /// ID of the notified user
+    #[prost(string, tag = "2")]
+    pub user_id: ::prost::alloc::string::String,
+    /// True if the notification was sent through at least one channel
+    #[prost(bool, tag = "3")]
+    pub success: bool,
+    /// Channels the notification was sent through
+    #[prost(enumeration = "DeliveryChannel", repeated, tag = "4")]
+    pub channels: ::prost::alloc::vec::Vec<i32>,
+    /// Message ID assigned to the email by the provider
+    #[prost(string, optional, tag = "5")]
+    pub message_id: ::core::option::Option<::prost::alloc::string::String>,
+    /// Why (part of) the notification failed,
+    /// FAILURE_REASON_NONE if every requested channel succeeded
+    #[prost(enumeration = "FailureReason", tag = "6")]
+    pub failure_reason: i32,
+    /// Error message, empty if the notification was sent
+    #[prost(string, tag = "7")]
+    pub error: ::prost::alloc::string::String,
+}
+/// Notification batch response
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NotificationBatchResponse {
+    /// Number of notifications sent
+    #[prost(uint32, tag = "1")]
+    pub sent: u32,
+    /// Number of notifications that failed
+    #[prost(uint32, tag = "2")]
+    pub failed: u32,
+    /// Outcome per notification, in the order of the request
+    #[prost(message, repeated, tag = "3")]
+    pub results: ::prost::alloc::vec::Vec<NotificationBatchResult>,
+}
+/// Channel a notification was sent through
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum DeliveryChannel {
+    /// No channel given
+    Unspecified = 0,
+    /// Email
+    Email = 1,
+    /// Text message
+    Sms = 2,
+}
+impl DeliveryChannel {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            DeliveryChannel::Unspecified => "DELIVERY_CHANNEL_UNSPECIFIED",
+            DeliveryChannel::Email => "DELIVERY_CHANNEL_EMAIL",
+            DeliveryChannel::Sms => "DELIVERY_CHANNEL_SMS",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "DELIVERY_CHANNEL_UNSPECIFIED" => Some(Self::Unspecified),
+            "DELIVERY_CHANNEL_EMAIL" => Some(Self::Email),
+            "DELIVERY_CHANNEL_SMS" => Some(Self::Sms),
+            _ => None,
+        }
+    }
+}
+/// Delivery status of a notification
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum DeliveryStatus {
+    /// No status given
+    Unspecified = 0,
+    /// Accepted by the provider
+    Sent = 1,
+    /// Not accepted by the provider
+    Failed = 2,
+    /// Delivered to the recipient's mail server
+    Delivered = 3,
+    /// Returned by the recipient's mail server
+    Bounced = 4,
+    /// Marked as spam by the recipient
+    Complained = 5,
+    /// Accepted from the notification queue, not handed to the provider yet
+    Queued = 6,
+}
+impl DeliveryStatus {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Unspecified => "DELIVERY_STATUS_UNSPECIFIED",
+            DeliveryStatus::Sent => "DELIVERY_STATUS_SENT",
+            DeliveryStatus::Failed => "DELIVERY_STATUS_FAILED",
+            DeliveryStatus::Delivered => "DELIVERY_STATUS_DELIVERED",
+            DeliveryStatus::Bounced => "DELIVERY_STATUS_BOUNCED",
+            DeliveryStatus::Complained => "DELIVERY_STATUS_COMPLAINED",
+            DeliveryStatus::Queued => "DELIVERY_STATUS_QUEUED",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "DELIVERY_STATUS_UNSPECIFIED" => Some(Self::Unspecified),
+            "DELIVERY_STATUS_SENT" => Some(Self::Sent),
+            "DELIVERY_STATUS_FAILED" => Some(Self::Failed),
+            "DELIVERY_STATUS_DELIVERED" => Some(Self::Delivered),
+            "DELIVERY_STATUS_BOUNCED" => Some(Self::Bounced),
+            "DELIVERY_STATUS_COMPLAINED" => Some(Self::Complained),
+            "DELIVERY_STATUS_QUEUED" => Some(Self::Queued),
+            _ => None,
+        }
+    }
+}
+/// Why a notification, or part of it, failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum FailureReason {
+    /// No failure
+    None = 0,
+    /// The data needed to compose the notification could not be retrieved
+    DataUnavailable = 1,
+    /// The email address is marked undeliverable after a hard bounce
+    Undeliverable = 2,
+    /// The provider refused the message
+    Rejected = 3,
+    /// The provider could not be reached, also after retries
+    Unavailable = 4,
+    /// The delivery backend is misconfigured
+    Configuration = 5,
+    /// The email was sent but the text message failed
+    SmsFailed = 6,
+    /// Any other error
+    Internal = 7,
+    /// The notification request is invalid
+    InvalidRequest = 8,
+    /// The user's preferences don't allow the notification
+    OptedOut = 9,
+    /// The email address is not verified and verified addresses are required
+    Unverified = 10,
+}
+impl FailureReason {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            FailureReason::None => "FAILURE_REASON_NONE",
+            FailureReason::DataUnavailable => "FAILURE_REASON_DATA_UNAVAILABLE",
+            FailureReason::Undeliverable => "FAILURE_REASON_UNDELIVERABLE",
+            FailureReason::Rejected => "FAILURE_REASON_REJECTED",
+            FailureReason::Unavailable => "FAILURE_REASON_UNAVAILABLE",
+            FailureReason::Configuration => "FAILURE_REASON_CONFIGURATION",
+            FailureReason::SmsFailed => "FAILURE_REASON_SMS_FAILED",
+            FailureReason::Internal => "FAILURE_REASON_INTERNAL",
+            FailureReason::InvalidRequest => "FAILURE_REASON_INVALID_REQUEST",
+            FailureReason::OptedOut => "FAILURE_REASON_OPTED_OUT",
+            FailureReason::Unverified => "FAILURE_REASON_UNVERIFIED",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "FAILURE_REASON_NONE" => Some(Self::None),
+            "FAILURE_REASON_DATA_UNAVAILABLE" => Some(Self::DataUnavailable),
+            "FAILURE_REASON_UNDELIVERABLE" => Some(Self::Undeliverable),
+            "FAILURE_REASON_REJECTED" => Some(Self::Rejected),
+            "FAILURE_REASON_UNAVAILABLE" => Some(Self::Unavailable),
+            "FAILURE_REASON_CONFIGURATION" => Some(Self::Configuration),
+            "FAILURE_REASON_SMS_FAILED" => Some(Self::SmsFailed),
+            "FAILURE_REASON_INTERNAL" => Some(Self::Internal),
+            "FAILURE_REASON_INVALID_REQUEST" => Some(Self::InvalidRequest),
+            "FAILURE_REASON_OPTED_OUT" => Some(Self::OptedOut),
+            "FAILURE_REASON_UNVERIFIED" => Some(Self::Unverified),
+            _ => None,
+        }
+    }
+}
+/// Why an itinerary was cancelled
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum CancellationReason {
+    /// No reason given
+    Unspecified = 0,
+    /// Cancelled at the customer's request
+    CustomerRequest = 1,
+    /// Weather conditions don't allow the flight
+    Weather = 2,
+    /// No aircraft available for the flight
+    AircraftUnavailable = 3,
+    /// The airspace is restricted
+    AirspaceRestricted = 4,
+    /// The origin or target vertiport is closed
+    VertiportClosed = 5,
+    /// The payment could not be completed
+    PaymentFailed = 6,
+}
+impl CancellationReason {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            CancellationReason::Unspecified => "CANCELLATION_REASON_UNSPECIFIED",
+            CancellationReason::CustomerRequest => "CANCELLATION_REASON_CUSTOMER_REQUEST",
+            CancellationReason::Weather => "CANCELLATION_REASON_WEATHER",
+            CancellationReason::AircraftUnavailable => {
+                "CANCELLATION_REASON_AIRCRAFT_UNAVAILABLE"
+            }
+            CancellationReason::AirspaceRestricted => {
+                "CANCELLATION_REASON_AIRSPACE_RESTRICTED"
+            }
+            CancellationReason::VertiportClosed => "CANCELLATION_REASON_VERTIPORT_CLOSED",
+            CancellationReason::PaymentFailed => "CANCELLATION_REASON_PAYMENT_FAILED",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "CANCELLATION_REASON_UNSPECIFIED" => Some(Self::Unspecified),
+            "CANCELLATION_REASON_CUSTOMER_REQUEST" => Some(Self::CustomerRequest),
+            "CANCELLATION_REASON_WEATHER" => Some(Self::Weather),
+            "CANCELLATION_REASON_AIRCRAFT_UNAVAILABLE" => Some(Self::AircraftUnavailable),
+            "CANCELLATION_REASON_AIRSPACE_RESTRICTED" => Some(Self::AirspaceRestricted),
+            "CANCELLATION_REASON_VERTIPORT_CLOSED" => Some(Self::VertiportClosed),
+            "CANCELLATION_REASON_PAYMENT_FAILED" => Some(Self::PaymentFailed),
+            _ => None,
+        }
+    }
+}
+/// Channels a notification is sent through
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ChannelPreference {
+    /// Email only
+    Email = 0,
+    /// Text message only
+    Sms = 1,
+    /// Email and text message
+    EmailAndSms = 2,
+}
+impl ChannelPreference {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            ChannelPreference::Email => "CHANNEL_PREFERENCE_EMAIL",
+            ChannelPreference::Sms => "CHANNEL_PREFERENCE_SMS",
+            ChannelPreference::EmailAndSms => "CHANNEL_PREFERENCE_EMAIL_AND_SMS",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "CHANNEL_PREFERENCE_EMAIL" => Some(Self::Email),
+            "CHANNEL_PREFERENCE_SMS" => Some(Self::Sms),
+            "CHANNEL_PREFERENCE_EMAIL_AND_SMS" => Some(Self::EmailAndSms),
+            _ => None,
+        }
+    }
 }
 /// Generated client implementations.
 #[cfg(not(tarpaulin_include))]
@@ -168,5 +770,188 @@ pub mod rpc_service_client {
                 .insert(GrpcMethod::new("grpc.RpcService", "cargoConfirmation"));
             self.inner.unary(req, path, codec).await
         }
+        pub async fn cargo_cancellation(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CargoCancellationRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::CargoCancellationResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/grpc.RpcService/cargoCancellation",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("grpc.RpcService", "cargoCancellation"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// delivery interfaces
+        pub async fn query_deliveries(
+            &mut self,
+            request: impl tonic::IntoRequest<super::DeliveryQueryRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::DeliveryQueryResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/grpc.RpcService/queryDeliveries",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("grpc.RpcService", "queryDeliveries"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn watch_delivery(
+            &mut self,
+            request: impl tonic::IntoRequest<super::DeliveryWatchRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::DeliveryStatusUpdate>>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/grpc.RpcService/watchDelivery",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("grpc.RpcService", "watchDelivery"));
+            self.inner.server_streaming(req, path, codec).await
+        }
+        /// flight interfaces
+        pub async fn flight_delay_notification(
+            &mut self,
+            request: impl tonic::IntoRequest<super::FlightDelayRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::FlightDelayResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/grpc.RpcService/flightDelayNotification",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("grpc.RpcService", "flightDelayNotification"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        /// parcel interfaces
+        pub async fn parcel_arrival(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ParcelArrivalRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ParcelArrivalResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/grpc.RpcService/parcelArrival",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("grpc.RpcService", "parcelArrival"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// notification interfaces
+        pub async fn send_notification(
+            &mut self,
+            request: impl tonic::IntoRequest<super::NotificationRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::NotificationResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/grpc.RpcService/sendNotification",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("grpc.RpcService", "sendNotification"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn send_notification_batch(
+            &mut self,
+            request: impl tonic::IntoRequest<super::NotificationBatchRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::NotificationBatchResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/grpc.RpcService/sendNotificationBatch",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("grpc.RpcService", "sendNotificationBatch"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
     }
 }
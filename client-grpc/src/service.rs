@@ -15,6 +15,34 @@ where
     type CargoConfirmationRequest;
     /// The type expected for CargoConfirmationResponse structs.
     type CargoConfirmationResponse;
+    /// The type expected for CargoCancellationRequest structs.
+    type CargoCancellationRequest;
+    /// The type expected for CargoCancellationResponse structs.
+    type CargoCancellationResponse;
+    /// The type expected for DeliveryQueryRequest structs.
+    type DeliveryQueryRequest;
+    /// The type expected for DeliveryQueryResponse structs.
+    type DeliveryQueryResponse;
+    /// The type expected for DeliveryWatchRequest structs.
+    type DeliveryWatchRequest;
+    /// The type expected for DeliveryStatusUpdate structs.
+    type DeliveryStatusUpdate;
+    /// The type expected for FlightDelayRequest structs.
+    type FlightDelayRequest;
+    /// The type expected for FlightDelayResponse structs.
+    type FlightDelayResponse;
+    /// The type expected for ParcelArrivalRequest structs.
+    type ParcelArrivalRequest;
+    /// The type expected for ParcelArrivalResponse structs.
+    type ParcelArrivalResponse;
+    /// The type expected for NotificationRequest structs.
+    type NotificationRequest;
+    /// The type expected for NotificationResponse structs.
+    type NotificationResponse;
+    /// The type expected for NotificationBatchRequest structs.
+    type NotificationBatchRequest;
+    /// The type expected for NotificationBatchResponse structs.
+    type NotificationBatchResponse;
 
     /// Returns a [`tonic::Response`] containing a [`ReadyResponse`](Self::ReadyResponse)
     /// Takes an [`ReadyRequest`](Self::ReadyRequest).
@@ -63,6 +91,15 @@ where
     ///         .cargo_confirmation(contact::CargoConfirmationRequest {
     ///             parcel_id: Uuid::new_v4().to_string(),
     ///             itinerary_id: Uuid::new_v4().to_string(),
+    ///             phone_number: None,
+    ///             idempotency_key: None,
+    ///             locale: None,
+    ///             price_lines: vec![contact::PriceLine {
+    ///                 description: "Flight".to_string(),
+    ///                 amount: "40.00".to_string(),
+    ///             }],
+    ///             tax_rate: "21".to_string(),
+    ///             currency: "EUR".to_string(),
     ///         })
     ///         .await?;
     ///     println!("RESPONSE={:?}", response.into_inner());
@@ -73,4 +110,283 @@ where
         &self,
         request: Self::CargoConfirmationRequest,
     ) -> Result<tonic::Response<Self::CargoConfirmationResponse>, tonic::Status>;
+
+    /// Returns a [`tonic::Response`] containing a [`CargoCancellationResponse`](Self::CargoCancellationResponse)
+    /// Takes an [`CargoCancellationRequest`](Self::CargoCancellationRequest).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`tonic::Status`] with [`tonic::Code::Unknown`] if the server is not ready.
+    ///
+    /// # Examples
+    /// ```
+    /// use lib_common::grpc::get_endpoint_from_env;
+    /// use lib_common::uuid::Uuid;
+    /// use svc_contact_client_grpc::prelude::*;
+    ///
+    /// async fn example () -> Result<(), Box<dyn std::error::Error>> {
+    ///     let (host, port) = get_endpoint_from_env("SERVER_HOSTNAME", "SERVER_PORT_GRPC");
+    ///     let client = ContactClient::new_client(&host, port, "contact");
+    ///     let response = client
+    ///         .cargo_cancellation(contact::CargoCancellationRequest {
+    ///             itinerary_id: Uuid::new_v4().to_string(),
+    ///             parcel_id: Uuid::new_v4().to_string(),
+    ///             reason: contact::CancellationReason::Weather as i32,
+    ///             refund_amount_cents: 1250,
+    ///             currency: String::from("EUR"),
+    ///             locale: None,
+    ///         })
+    ///         .await?;
+    ///     println!("RESPONSE={:?}", response.into_inner());
+    ///     Ok(())
+    /// }
+    /// ```
+    async fn cargo_cancellation(
+        &self,
+        request: Self::CargoCancellationRequest,
+    ) -> Result<tonic::Response<Self::CargoCancellationResponse>, tonic::Status>;
+
+    /// Returns a [`tonic::Response`] containing a [`DeliveryQueryResponse`](Self::DeliveryQueryResponse)
+    /// Takes an [`DeliveryQueryRequest`](Self::DeliveryQueryRequest).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`tonic::Status`] with [`tonic::Code::Unknown`] if the server is not ready.
+    ///
+    /// # Examples
+    /// ```
+    /// use lib_common::grpc::get_endpoint_from_env;
+    /// use lib_common::uuid::Uuid;
+    /// use svc_contact_client_grpc::prelude::*;
+    ///
+    /// async fn example () -> Result<(), Box<dyn std::error::Error>> {
+    ///     let (host, port) = get_endpoint_from_env("SERVER_HOSTNAME", "SERVER_PORT_GRPC");
+    ///     let client = ContactClient::new_client(&host, port, "contact");
+    ///     let response = client
+    ///         .query_deliveries(contact::DeliveryQueryRequest {
+    ///             user_id: Some(Uuid::new_v4().to_string()),
+    ///             itinerary_id: None,
+    ///             from: None,
+    ///             to: None,
+    ///             limit: Some(10),
+    ///         })
+    ///         .await?;
+    ///     println!("RESPONSE={:?}", response.into_inner());
+    ///     Ok(())
+    /// }
+    /// ```
+    async fn query_deliveries(
+        &self,
+        request: Self::DeliveryQueryRequest,
+    ) -> Result<tonic::Response<Self::DeliveryQueryResponse>, tonic::Status>;
+
+    /// Returns a [`tonic::Response`] containing a stream of [`DeliveryStatusUpdate`](Self::DeliveryStatusUpdate)
+    /// Takes an [`DeliveryWatchRequest`](Self::DeliveryWatchRequest).
+    ///
+    /// The first update holds the current status, the stream ends after the
+    /// update with a final status.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`tonic::Status`] with [`tonic::Code::Unknown`] if the server is not ready.
+    /// Returns [`tonic::Status`] with [`tonic::Code::NotFound`] if the notification is unknown.
+    /// The stream ends with [`tonic::Code::DeadlineExceeded`] if no final status was reached in time.
+    ///
+    /// # Examples
+    /// ```
+    /// use lib_common::grpc::get_endpoint_from_env;
+    /// use svc_contact_client_grpc::prelude::*;
+    ///
+    /// async fn example () -> Result<(), Box<dyn std::error::Error>> {
+    ///     let (host, port) = get_endpoint_from_env("SERVER_HOSTNAME", "SERVER_PORT_GRPC");
+    ///     let client = ContactClient::new_client(&host, port, "contact");
+    ///     let mut updates = client
+    ///         .watch_delivery(contact::DeliveryWatchRequest {
+    ///             notification_id: String::from("b7c5a1e2-4f3d-4c8e-9a6b-0d1e2f3a4b5c"),
+    ///         })
+    ///         .await?
+    ///         .into_inner();
+    ///     while let Some(update) = updates.message().await? {
+    ///         println!("UPDATE={:?}", update);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    async fn watch_delivery(
+        &self,
+        request: Self::DeliveryWatchRequest,
+    ) -> Result<tonic::Response<tonic::codec::Streaming<Self::DeliveryStatusUpdate>>, tonic::Status>;
+
+    /// Returns a [`tonic::Response`] containing a [`FlightDelayResponse`](Self::FlightDelayResponse)
+    /// Takes an [`FlightDelayRequest`](Self::FlightDelayRequest).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`tonic::Status`] with [`tonic::Code::Unknown`] if the server is not ready.
+    ///
+    /// # Examples
+    /// ```
+    /// use lib_common::grpc::get_endpoint_from_env;
+    /// use lib_common::uuid::Uuid;
+    /// use svc_contact_client_grpc::prelude::*;
+    ///
+    /// async fn example () -> Result<(), Box<dyn std::error::Error>> {
+    ///     let (host, port) = get_endpoint_from_env("SERVER_HOSTNAME", "SERVER_PORT_GRPC");
+    ///     let client = ContactClient::new_client(&host, port, "contact");
+    ///     let departure = prost_types::Timestamp { seconds: 1704103200, nanos: 0 };
+    ///     let arrival = prost_types::Timestamp { seconds: 1704106800, nanos: 0 };
+    ///     let delay = 30 * 60;
+    ///     let response = client
+    ///         .flight_delay_notification(contact::FlightDelayRequest {
+    ///             flight_plan_id: Uuid::new_v4().to_string(),
+    ///             old_origin_timeslot_start: Some(departure.clone()),
+    ///             new_origin_timeslot_start: Some(prost_types::Timestamp {
+    ///                 seconds: departure.seconds + delay,
+    ///                 nanos: 0,
+    ///             }),
+    ///             old_target_timeslot_end: Some(arrival.clone()),
+    ///             new_target_timeslot_end: Some(prost_types::Timestamp {
+    ///                 seconds: arrival.seconds + delay,
+    ///                 nanos: 0,
+    ///             }),
+    ///         })
+    ///         .await?;
+    ///     println!("RESPONSE={:?}", response.into_inner());
+    ///     Ok(())
+    /// }
+    /// ```
+    async fn flight_delay_notification(
+        &self,
+        request: Self::FlightDelayRequest,
+    ) -> Result<tonic::Response<Self::FlightDelayResponse>, tonic::Status>;
+
+    /// Returns a [`tonic::Response`] containing a [`ParcelArrivalResponse`](Self::ParcelArrivalResponse)
+    /// Takes an [`ParcelArrivalRequest`](Self::ParcelArrivalRequest).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`tonic::Status`] with [`tonic::Code::Unknown`] if the server is not ready.
+    ///
+    /// # Examples
+    /// ```
+    /// use lib_common::grpc::get_endpoint_from_env;
+    /// use lib_common::uuid::Uuid;
+    /// use svc_contact_client_grpc::prelude::*;
+    ///
+    /// async fn example () -> Result<(), Box<dyn std::error::Error>> {
+    ///     let (host, port) = get_endpoint_from_env("SERVER_HOSTNAME", "SERVER_PORT_GRPC");
+    ///     let client = ContactClient::new_client(&host, port, "contact");
+    ///     let response = client
+    ///         .parcel_arrival(contact::ParcelArrivalRequest {
+    ///             parcel_id: Uuid::new_v4().to_string(),
+    ///             locale: Some("nl-BE".to_string()),
+    ///         })
+    ///         .await?;
+    ///     println!("RESPONSE={:?}", response.into_inner());
+    ///     Ok(())
+    /// }
+    /// ```
+    async fn parcel_arrival(
+        &self,
+        request: Self::ParcelArrivalRequest,
+    ) -> Result<tonic::Response<Self::ParcelArrivalResponse>, tonic::Status>;
+
+    /// Returns a [`tonic::Response`] containing a [`NotificationResponse`](Self::NotificationResponse)
+    /// Takes an [`NotificationRequest`](Self::NotificationRequest).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`tonic::Status`] with [`tonic::Code::Unknown`] if the server is not ready.
+    ///
+    /// # Examples
+    /// ```
+    /// use lib_common::grpc::get_endpoint_from_env;
+    /// use lib_common::uuid::Uuid;
+    /// use svc_contact_client_grpc::prelude::*;
+    ///
+    /// async fn example () -> Result<(), Box<dyn std::error::Error>> {
+    ///     let (host, port) = get_endpoint_from_env("SERVER_HOSTNAME", "SERVER_PORT_GRPC");
+    ///     let client = ContactClient::new_client(&host, port, "contact");
+    ///     let model = [
+    ///         ("customer_name", "Alice"),
+    ///         ("parcel_weight_kg", "1.50"),
+    ///         ("target_vertiport_name", "Utrecht"),
+    ///         ("target_vertiport_address", "Domplein 1"),
+    ///         ("pickup_window_start", "2024-01-01 11:00 CET"),
+    ///         ("pickup_window_end", "2024-01-03 11:00 CET"),
+    ///     ];
+    ///     let response = client
+    ///         .send_notification(contact::NotificationRequest {
+    ///             user_id: Uuid::new_v4().to_string(),
+    ///             template: "parcel-arrival".to_string(),
+    ///             channel: contact::ChannelPreference::EmailAndSms as i32,
+    ///             model: model
+    ///                 .into_iter()
+    ///                 .map(|(key, value)| (key.to_string(), value.to_string()))
+    ///                 .collect(),
+    ///             phone_number: Some("+31611111111".to_string()),
+    ///             parcel_id: None,
+    ///             itinerary_id: None,
+    ///             locale: None,
+    ///         })
+    ///         .await?;
+    ///     println!("RESPONSE={:?}", response.into_inner());
+    ///     Ok(())
+    /// }
+    /// ```
+    async fn send_notification(
+        &self,
+        request: Self::NotificationRequest,
+    ) -> Result<tonic::Response<Self::NotificationResponse>, tonic::Status>;
+
+    /// Returns a [`tonic::Response`] containing a [`NotificationBatchResponse`](Self::NotificationBatchResponse)
+    /// Takes an [`NotificationBatchRequest`](Self::NotificationBatchRequest).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`tonic::Status`] with [`tonic::Code::Unknown`] if the server is not ready.
+    /// Returns [`tonic::Status`] with [`tonic::Code::InvalidArgument`] if the batch is empty or too large.
+    ///
+    /// # Examples
+    /// ```
+    /// use lib_common::grpc::get_endpoint_from_env;
+    /// use lib_common::uuid::Uuid;
+    /// use svc_contact_client_grpc::prelude::*;
+    ///
+    /// async fn example () -> Result<(), Box<dyn std::error::Error>> {
+    ///     let (host, port) = get_endpoint_from_env("SERVER_HOSTNAME", "SERVER_PORT_GRPC");
+    ///     let client = ContactClient::new_client(&host, port, "contact");
+    ///     let notification = |name: &str| contact::NotificationRequest {
+    ///         user_id: Uuid::new_v4().to_string(),
+    ///         template: "parcel-arrival".to_string(),
+    ///         channel: contact::ChannelPreference::Email as i32,
+    ///         model: [
+    ///             ("customer_name", name),
+    ///             ("parcel_weight_kg", "1.50"),
+    ///             ("target_vertiport_name", "Utrecht"),
+    ///             ("target_vertiport_address", "Domplein 1"),
+    ///             ("pickup_window_start", "2024-01-01 11:00 CET"),
+    ///             ("pickup_window_end", "2024-01-03 11:00 CET"),
+    ///         ]
+    ///         .into_iter()
+    ///         .map(|(key, value)| (key.to_string(), value.to_string()))
+    ///         .collect(),
+    ///         phone_number: None,
+    ///         parcel_id: None,
+    ///         itinerary_id: None,
+    ///         locale: None,
+    ///     };
+    ///     let response = client
+    ///         .send_notification_batch(contact::NotificationBatchRequest {
+    ///             notifications: vec![notification("Alice"), notification("Bob")],
+    ///         })
+    ///         .await?;
+    ///     println!("RESPONSE={:?}", response.into_inner());
+    ///     Ok(())
+    /// }
+    /// ```
+    async fn send_notification_batch(
+        &self,
+        request: Self::NotificationBatchRequest,
+    ) -> Result<tonic::Response<Self::NotificationBatchResponse>, tonic::Status>;
 }
@@ -43,6 +43,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let data = SignupRequest {
             display_name: "abcdef12".to_string(),
             email: "example@aetheric.nl".to_string(),
+            locale: Some("nl-BE".to_string()),
         };
 
         let data_str = serde_json::to_string(&data).unwrap();
@@ -61,6 +62,40 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!("{}: {}", uri, result_str);
     }
 
+    // PUT /contact/preferences/{user_id}
+    {
+        let data = UserPreferences {
+            email: true,
+            sms: false,
+            marketing: false,
+            language: Some("nl".to_string()),
+            timezone: Some("Europe/Amsterdam".to_string()),
+            quiet_hours: Some(QuietHours {
+                start: "22:00".to_string(),
+                end: "07:00".to_string(),
+            }),
+        };
+
+        let data_str = serde_json::to_string(&data).unwrap();
+        let uri = format!(
+            "{}/contact/preferences/{}",
+            url,
+            lib_common::uuid::Uuid::new_v4()
+        );
+        let req = Request::builder()
+            .method(Method::PUT)
+            .uri(uri.clone())
+            .header("content-type", "application/json")
+            .body(Body::from(data_str))
+            .unwrap();
+
+        let resp = client.request(req).await;
+        let (success, result_str) = evaluate(resp, StatusCode::OK);
+        ok &= success;
+
+        println!("{}: {}", uri, result_str);
+    }
+
     if ok {
         println!("\u{1F9c1} All endpoints responded!");
     } else {
@@ -13,4 +13,78 @@ pub struct SignupRequest {
 
     /// The display name to use
     pub display_name: String,
+
+    /// Language of the verification email, as a language tag (e.g. `nl-BE`)
+    pub locale: Option<String>,
+}
+
+/// Daily period without text messages and marketing,
+/// in the timezone of the user
+#[derive(Debug, Clone)]
+#[derive(Deserialize, Serialize)]
+#[derive(ToSchema, IntoParams)]
+pub struct QuietHours {
+    /// Start of the quiet hours, as `HH:MM`
+    pub start: String,
+
+    /// End of the quiet hours, as `HH:MM`. Before the start when the
+    /// quiet hours span midnight, e.g. `22:00` to `07:00`
+    pub end: String,
+}
+
+/// Notification preferences of a user
+#[derive(Debug, Clone)]
+#[derive(Deserialize, Serialize)]
+#[derive(ToSchema, IntoParams)]
+pub struct UserPreferences {
+    /// Send emails
+    pub email: bool,
+
+    /// Send text messages
+    pub sms: bool,
+
+    /// Send marketing. Transactional messages, e.g. booking confirmations,
+    /// are sent regardless.
+    pub marketing: bool,
+
+    /// Preferred language, as a language tag (e.g. `nl-BE`)
+    pub language: Option<String>,
+
+    /// Preferred timezone, as an IANA timezone name (e.g. `Europe/Amsterdam`)
+    pub timezone: Option<String>,
+
+    /// Daily period without text messages and marketing
+    pub quiet_hours: Option<QuietHours>,
+}
+
+/// Query of the unsubscribe link in marketing emails
+#[derive(Debug, Clone)]
+#[derive(Deserialize, Serialize)]
+#[derive(ToSchema, IntoParams)]
+pub struct UnsubscribeQuery {
+    /// Signed token identifying the user, from the unsubscribe link
+    pub token: String,
+}
+
+/// Query of the verification link sent on signup, and the form its page posts
+#[derive(Debug, Clone)]
+#[derive(Deserialize, Serialize)]
+#[derive(ToSchema, IntoParams)]
+pub struct VerifyQuery {
+    /// Signed single-use token identifying the user and email address,
+    /// from the verification link
+    pub token: String,
+}
+
+/// Request for another verification email
+#[derive(Debug, Clone)]
+#[derive(Deserialize, Serialize)]
+#[derive(ToSchema, IntoParams)]
+pub struct ResendVerificationRequest {
+    /// Signed token of a previous verification link of the user, also when
+    /// expired. The email is sent to the current address of the user.
+    pub token: String,
+
+    /// Language of the verification email, as a language tag (e.g. `nl-BE`)
+    pub locale: Option<String>,
 }
@@ -8,9 +8,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let server_config = tonic_build::configure()
         .type_attribute("ReadyRequest", "#[derive(Eq, Copy)]")
         .type_attribute("ReadyResponse", "#[derive(Eq, Copy)]")
-        .type_attribute("CargoConfirmationResponse", "#[derive(Eq, Copy)]");
+        .type_attribute("CargoConfirmationResponse", "#[derive(Eq)]");
     let client_config = server_config.clone();
 
+    // Confirmation results are stored for idempotent retries
+    let server_config = server_config.type_attribute(
+        "CargoConfirmationResponse",
+        "#[derive(serde::Serialize, serde::Deserialize)]",
+    );
+
     client_config
         .client_mod_attribute("grpc", "#[cfg(not(tarpaulin_include))]")
         .build_server(false)
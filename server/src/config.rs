@@ -29,6 +29,84 @@ pub struct Config {
     pub rest_cors_allowed_origin: String,
     /// postmark token
     pub postmark_token: String,
+    /// username Postmark authenticates webhook calls with
+    pub postmark_webhook_username: String,
+    /// password Postmark authenticates webhook calls with, the webhook is disabled when empty
+    pub postmark_webhook_password: String,
+    /// email backend to use (`postmark`, `smtp` or `stub`)
+    pub email_backend: String,
+    /// host of the SMTP relay
+    pub smtp_host: String,
+    /// port of the SMTP relay
+    pub smtp_port: u16,
+    /// SMTP transport security (`none`, `starttls` or `tls`)
+    pub smtp_tls: String,
+    /// SMTP username, authentication is disabled when empty
+    pub smtp_username: String,
+    /// SMTP password
+    pub smtp_password: String,
+    /// SMS backend to use (`none`, `twilio` or `stub`)
+    pub sms_backend: String,
+    /// base url of the Twilio compatible API
+    pub twilio_api_url: String,
+    /// Twilio account SID
+    pub twilio_account_sid: String,
+    /// Twilio auth token
+    pub twilio_auth_token: String,
+    /// phone number text messages are sent from
+    pub twilio_from_number: String,
+    /// maximum number of delivery attempts, including the first one
+    pub retry_max_attempts: u32,
+    /// delay in milliseconds before the first retry, doubled for every retry
+    pub retry_base_delay_ms: u64,
+    /// maximum delay in milliseconds between two delivery attempts
+    pub retry_max_delay_ms: u64,
+    /// Valkey (Redis) connection, keys are kept in memory when no url is set
+    #[serde(default)]
+    pub redis: deadpool_redis::Config,
+    /// how long in seconds a cargo confirmation result is returned for retried requests
+    pub idempotency_window_secs: u64,
+    /// how long in days delivery records are kept
+    pub delivery_log_retention_days: u64,
+    /// how long in hours an arrived parcel can be picked up
+    pub pickup_window_hours: u64,
+    /// maximum number of notifications in a batch request
+    pub batch_max_size: u32,
+    /// maximum number of batch notifications processed at once
+    pub batch_concurrency: u16,
+    /// delay in milliseconds between two delivery status checks of a status stream
+    pub status_poll_interval_ms: u64,
+    /// how long in seconds a delivery status stream waits for a final status
+    pub status_watch_timeout_secs: u64,
+    /// comma separated times before the origin timeslot start pickup reminders are sent,
+    /// e.g. `24h,1h`, reminders are disabled when empty
+    pub reminder_lead_times: String,
+    /// delay in seconds between two checks for due reminders
+    pub reminder_poll_interval_secs: u64,
+    /// key signing the tokens of unsubscribe and verification links, both are disabled when empty
+    pub token_signing_key: String,
+    /// public url of the unsubscribe endpoint, the token is added as query parameter
+    pub unsubscribe_url: String,
+    /// how long in days an unsubscribe link stays valid
+    pub unsubscribe_token_ttl_days: u64,
+    /// public url of the email verification endpoint, the token is added as query parameter
+    pub verification_url: String,
+    /// how long in hours an email verification link stays valid
+    pub verification_token_ttl_hours: u64,
+    /// refuse cargo confirmations to email addresses that aren't verified,
+    /// instead of flagging them in the response
+    pub require_verified_email: bool,
+    /// prefix of invoice numbers, followed by the year and a sequence per year
+    pub invoice_prefix: String,
+    /// AMQP (RabbitMQ) connection, the queue consumer is disabled when no url is set
+    #[serde(default)]
+    pub amqp: deadpool_lapin::Config,
+    /// name of the queue notification jobs are consumed from
+    pub amqp_queue: String,
+    /// maximum number of unacknowledged jobs processed at once
+    pub amqp_prefetch: u16,
+    /// delay in milliseconds before a failed job is retried
+    pub amqp_retry_delay_ms: u32,
 }
 
 impl Default for Config {
@@ -51,6 +129,43 @@ impl Config {
             rest_concurrency_limit_per_service: 5,
             rest_cors_allowed_origin: String::from("http://localhost:3000"),
             postmark_token: String::from("fake_token"),
+            postmark_webhook_username: String::from("postmark"),
+            postmark_webhook_password: String::from(""),
+            email_backend: String::from("postmark"),
+            smtp_host: String::from("localhost"),
+            smtp_port: 587,
+            smtp_tls: String::from("starttls"),
+            smtp_username: String::from(""),
+            smtp_password: String::from(""),
+            sms_backend: String::from("none"),
+            twilio_api_url: String::from("https://api.twilio.com"),
+            twilio_account_sid: String::from(""),
+            twilio_auth_token: String::from(""),
+            twilio_from_number: String::from(""),
+            retry_max_attempts: 3,
+            retry_base_delay_ms: 500,
+            retry_max_delay_ms: 5000,
+            redis: deadpool_redis::Config::default(),
+            idempotency_window_secs: 86400,
+            delivery_log_retention_days: 365,
+            pickup_window_hours: 48,
+            batch_max_size: 1000,
+            batch_concurrency: 8,
+            status_poll_interval_ms: 1000,
+            status_watch_timeout_secs: 900,
+            reminder_lead_times: String::from("24h,1h"),
+            reminder_poll_interval_secs: 60,
+            token_signing_key: String::from(""),
+            unsubscribe_url: String::from("http://localhost:8000/contact/unsubscribe"),
+            unsubscribe_token_ttl_days: 90,
+            verification_url: String::from("http://localhost:8000/contact/verify"),
+            verification_token_ttl_hours: 24,
+            require_verified_email: false,
+            invoice_prefix: String::from("AET-"),
+            amqp: deadpool_lapin::Config::default(),
+            amqp_queue: String::from("contact.notifications"),
+            amqp_prefetch: 10,
+            amqp_retry_delay_ms: 30000,
         }
     }
 
@@ -77,6 +192,71 @@ impl Config {
                 default_config.rest_cors_allowed_origin,
             )?
             .set_default("postmark_token", default_config.postmark_token)?
+            .set_default(
+                "postmark_webhook_username",
+                default_config.postmark_webhook_username,
+            )?
+            .set_default(
+                "postmark_webhook_password",
+                default_config.postmark_webhook_password,
+            )?
+            .set_default("email_backend", default_config.email_backend)?
+            .set_default("smtp_host", default_config.smtp_host)?
+            .set_default("smtp_port", default_config.smtp_port)?
+            .set_default("smtp_tls", default_config.smtp_tls)?
+            .set_default("smtp_username", default_config.smtp_username)?
+            .set_default("smtp_password", default_config.smtp_password)?
+            .set_default("sms_backend", default_config.sms_backend)?
+            .set_default("twilio_api_url", default_config.twilio_api_url)?
+            .set_default("twilio_account_sid", default_config.twilio_account_sid)?
+            .set_default("twilio_auth_token", default_config.twilio_auth_token)?
+            .set_default("twilio_from_number", default_config.twilio_from_number)?
+            .set_default("retry_max_attempts", default_config.retry_max_attempts)?
+            .set_default("retry_base_delay_ms", default_config.retry_base_delay_ms)?
+            .set_default("retry_max_delay_ms", default_config.retry_max_delay_ms)?
+            .set_default(
+                "idempotency_window_secs",
+                default_config.idempotency_window_secs,
+            )?
+            .set_default(
+                "delivery_log_retention_days",
+                default_config.delivery_log_retention_days,
+            )?
+            .set_default("pickup_window_hours", default_config.pickup_window_hours)?
+            .set_default("batch_max_size", default_config.batch_max_size)?
+            .set_default("batch_concurrency", default_config.batch_concurrency)?
+            .set_default(
+                "status_poll_interval_ms",
+                default_config.status_poll_interval_ms,
+            )?
+            .set_default(
+                "status_watch_timeout_secs",
+                default_config.status_watch_timeout_secs,
+            )?
+            .set_default("reminder_lead_times", default_config.reminder_lead_times)?
+            .set_default(
+                "reminder_poll_interval_secs",
+                default_config.reminder_poll_interval_secs,
+            )?
+            .set_default("token_signing_key", default_config.token_signing_key)?
+            .set_default("unsubscribe_url", default_config.unsubscribe_url)?
+            .set_default(
+                "unsubscribe_token_ttl_days",
+                default_config.unsubscribe_token_ttl_days,
+            )?
+            .set_default("verification_url", default_config.verification_url)?
+            .set_default(
+                "verification_token_ttl_hours",
+                default_config.verification_token_ttl_hours,
+            )?
+            .set_default(
+                "require_verified_email",
+                default_config.require_verified_email,
+            )?
+            .set_default("invoice_prefix", default_config.invoice_prefix)?
+            .set_default("amqp_queue", default_config.amqp_queue)?
+            .set_default("amqp_prefetch", default_config.amqp_prefetch)?
+            .set_default("amqp_retry_delay_ms", default_config.amqp_retry_delay_ms)?
             .add_source(Environment::default().separator("__"))
             .build()?
             .try_deserialize()
@@ -102,6 +282,52 @@ mod tests {
         assert_eq!(config.rest_concurrency_limit_per_service, 5);
         assert_eq!(config.rest_request_limit_per_second, 2);
         assert_eq!(config.postmark_token, String::from("fake_token"));
+        assert_eq!(config.postmark_webhook_username, String::from("postmark"));
+        assert_eq!(config.postmark_webhook_password, String::from(""));
+        assert_eq!(config.email_backend, String::from("postmark"));
+        assert_eq!(config.smtp_host, String::from("localhost"));
+        assert_eq!(config.smtp_port, 587);
+        assert_eq!(config.smtp_tls, String::from("starttls"));
+        assert_eq!(config.smtp_username, String::from(""));
+        assert_eq!(config.smtp_password, String::from(""));
+        assert_eq!(config.sms_backend, String::from("none"));
+        assert_eq!(
+            config.twilio_api_url,
+            String::from("https://api.twilio.com")
+        );
+        assert_eq!(config.twilio_account_sid, String::from(""));
+        assert_eq!(config.twilio_auth_token, String::from(""));
+        assert_eq!(config.twilio_from_number, String::from(""));
+        assert_eq!(config.retry_max_attempts, 3);
+        assert_eq!(config.retry_base_delay_ms, 500);
+        assert_eq!(config.retry_max_delay_ms, 5000);
+        assert!(config.redis.url.is_none());
+        assert_eq!(config.idempotency_window_secs, 86400);
+        assert_eq!(config.delivery_log_retention_days, 365);
+        assert_eq!(config.pickup_window_hours, 48);
+        assert_eq!(config.batch_max_size, 1000);
+        assert_eq!(config.batch_concurrency, 8);
+        assert_eq!(config.status_poll_interval_ms, 1000);
+        assert_eq!(config.status_watch_timeout_secs, 900);
+        assert_eq!(config.reminder_lead_times, String::from("24h,1h"));
+        assert_eq!(config.reminder_poll_interval_secs, 60);
+        assert_eq!(config.token_signing_key, String::from(""));
+        assert_eq!(
+            config.unsubscribe_url,
+            String::from("http://localhost:8000/contact/unsubscribe")
+        );
+        assert_eq!(config.unsubscribe_token_ttl_days, 90);
+        assert_eq!(
+            config.verification_url,
+            String::from("http://localhost:8000/contact/verify")
+        );
+        assert_eq!(config.verification_token_ttl_hours, 24);
+        assert!(!config.require_verified_email);
+        assert_eq!(config.invoice_prefix, String::from("AET-"));
+        assert!(config.amqp.url.is_none());
+        assert_eq!(config.amqp_queue, String::from("contact.notifications"));
+        assert_eq!(config.amqp_prefetch, 10);
+        assert_eq!(config.amqp_retry_delay_ms, 30000);
         assert_eq!(
             config.rest_cors_allowed_origin,
             String::from("http://localhost:3000")
@@ -127,6 +353,49 @@ mod tests {
             "https://allowed.origin.host:443",
         );
         std::env::set_var("POSTMARK_TOKEN", "test_token");
+        std::env::set_var("POSTMARK_WEBHOOK_USERNAME", "test_user");
+        std::env::set_var("POSTMARK_WEBHOOK_PASSWORD", "test_password");
+        std::env::set_var("EMAIL_BACKEND", "stub");
+        std::env::set_var("SMTP_HOST", "smtp.aetheric.nl");
+        std::env::set_var("SMTP_PORT", "465");
+        std::env::set_var("SMTP_TLS", "tls");
+        std::env::set_var("SMTP_USERNAME", "test_user");
+        std::env::set_var("SMTP_PASSWORD", "test_password");
+        std::env::set_var("SMS_BACKEND", "stub");
+        std::env::set_var("TWILIO_API_URL", "http://localhost:8080");
+        std::env::set_var("TWILIO_ACCOUNT_SID", "test_sid");
+        std::env::set_var("TWILIO_AUTH_TOKEN", "test_auth_token");
+        std::env::set_var("TWILIO_FROM_NUMBER", "+31600000000");
+        std::env::set_var("RETRY_MAX_ATTEMPTS", "5");
+        std::env::set_var("RETRY_BASE_DELAY_MS", "100");
+        std::env::set_var("RETRY_MAX_DELAY_MS", "2000");
+        std::env::set_var("REDIS__URL", "redis://test_valkey:6379");
+        std::env::set_var("IDEMPOTENCY_WINDOW_SECS", "3600");
+        std::env::set_var("DELIVERY_LOG_RETENTION_DAYS", "30");
+        std::env::set_var("PICKUP_WINDOW_HOURS", "24");
+        std::env::set_var("BATCH_MAX_SIZE", "100");
+        std::env::set_var("BATCH_CONCURRENCY", "4");
+        std::env::set_var("STATUS_POLL_INTERVAL_MS", "250");
+        std::env::set_var("STATUS_WATCH_TIMEOUT_SECS", "60");
+        std::env::set_var("REMINDER_LEAD_TIMES", "2h");
+        std::env::set_var("REMINDER_POLL_INTERVAL_SECS", "30");
+        std::env::set_var("TOKEN_SIGNING_KEY", "test_signing_key");
+        std::env::set_var(
+            "UNSUBSCRIBE_URL",
+            "https://contact.aetheric.nl/contact/unsubscribe",
+        );
+        std::env::set_var("UNSUBSCRIBE_TOKEN_TTL_DAYS", "30");
+        std::env::set_var(
+            "VERIFICATION_URL",
+            "https://contact.aetheric.nl/contact/verify",
+        );
+        std::env::set_var("VERIFICATION_TOKEN_TTL_HOURS", "48");
+        std::env::set_var("REQUIRE_VERIFIED_EMAIL", "true");
+        std::env::set_var("INVOICE_PREFIX", "INV/");
+        std::env::set_var("AMQP__URL", "amqp://test_rabbitmq:5672");
+        std::env::set_var("AMQP_QUEUE", "test.notifications");
+        std::env::set_var("AMQP_PREFETCH", "25");
+        std::env::set_var("AMQP_RETRY_DELAY_MS", "5000");
 
         let config = Config::try_from_env();
         assert!(config.is_ok());
@@ -140,6 +409,58 @@ mod tests {
         assert_eq!(config.rest_concurrency_limit_per_service, 255);
         assert_eq!(config.rest_request_limit_per_second, 255);
         assert_eq!(config.postmark_token, String::from("test_token"));
+        assert_eq!(config.postmark_webhook_username, String::from("test_user"));
+        assert_eq!(
+            config.postmark_webhook_password,
+            String::from("test_password")
+        );
+        assert_eq!(config.email_backend, String::from("stub"));
+        assert_eq!(config.smtp_host, String::from("smtp.aetheric.nl"));
+        assert_eq!(config.smtp_port, 465);
+        assert_eq!(config.smtp_tls, String::from("tls"));
+        assert_eq!(config.smtp_username, String::from("test_user"));
+        assert_eq!(config.smtp_password, String::from("test_password"));
+        assert_eq!(config.sms_backend, String::from("stub"));
+        assert_eq!(config.twilio_api_url, String::from("http://localhost:8080"));
+        assert_eq!(config.twilio_account_sid, String::from("test_sid"));
+        assert_eq!(config.twilio_auth_token, String::from("test_auth_token"));
+        assert_eq!(config.twilio_from_number, String::from("+31600000000"));
+        assert_eq!(config.retry_max_attempts, 5);
+        assert_eq!(config.retry_base_delay_ms, 100);
+        assert_eq!(config.retry_max_delay_ms, 2000);
+        assert_eq!(
+            config.redis.url,
+            Some(String::from("redis://test_valkey:6379"))
+        );
+        assert_eq!(config.idempotency_window_secs, 3600);
+        assert_eq!(config.delivery_log_retention_days, 30);
+        assert_eq!(config.pickup_window_hours, 24);
+        assert_eq!(config.batch_max_size, 100);
+        assert_eq!(config.batch_concurrency, 4);
+        assert_eq!(config.status_poll_interval_ms, 250);
+        assert_eq!(config.status_watch_timeout_secs, 60);
+        assert_eq!(config.reminder_lead_times, String::from("2h"));
+        assert_eq!(config.reminder_poll_interval_secs, 30);
+        assert_eq!(config.token_signing_key, String::from("test_signing_key"));
+        assert_eq!(
+            config.unsubscribe_url,
+            String::from("https://contact.aetheric.nl/contact/unsubscribe")
+        );
+        assert_eq!(config.unsubscribe_token_ttl_days, 30);
+        assert_eq!(
+            config.verification_url,
+            String::from("https://contact.aetheric.nl/contact/verify")
+        );
+        assert_eq!(config.verification_token_ttl_hours, 48);
+        assert!(config.require_verified_email);
+        assert_eq!(config.invoice_prefix, String::from("INV/"));
+        assert_eq!(
+            config.amqp.url,
+            Some(String::from("amqp://test_rabbitmq:5672"))
+        );
+        assert_eq!(config.amqp_queue, String::from("test.notifications"));
+        assert_eq!(config.amqp_prefetch, 25);
+        assert_eq!(config.amqp_retry_delay_ms, 5000);
         assert_eq!(
             config.rest_cors_allowed_origin,
             String::from("https://allowed.origin.host:443")
@@ -0,0 +1,654 @@
+//! Email delivery backends
+
+pub mod postmark;
+pub mod smtp;
+pub mod stub;
+
+use super::retry::{self, DeliveryOutcome, RetryPolicy};
+use super::{DeliveryError, DeliveryReceipt};
+use crate::locale::Locale;
+use crate::templates;
+use crate::Config;
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt::{self, Debug, Formatter};
+use std::str::FromStr;
+use tokio::sync::OnceCell;
+
+/// Email backend shared by all handlers
+pub static EMAIL_BACKEND: OnceCell<Box<dyn EmailBackend>> = OnceCell::const_new();
+
+/// Values to fill in the fields of a template
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TemplateModel(Map<String, Value>);
+
+impl TemplateModel {
+    /// Inserts a field value, replacing any previous value for the key
+    pub fn insert<V: Serialize>(&mut self, key: &str, value: V) {
+        match serde_json::to_value(value) {
+            Ok(value) => {
+                self.0.insert(key.to_string(), value);
+            }
+            Err(e) => delivery_warn!("could not serialize template field {}: {}", key, e),
+        }
+    }
+
+    /// Returns the value of a field, if present
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.0.get(key)
+    }
+
+    /// Iterates over all fields in the model
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.0.iter()
+    }
+}
+
+/// Rendered content of an email
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailBody {
+    /// Subject line
+    pub subject: String,
+
+    /// HTML part
+    pub html: String,
+
+    /// Plain text part
+    pub text: String,
+}
+
+/// A custom header of an email
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailHeader {
+    /// Header name, e.g. `List-Unsubscribe`
+    pub name: String,
+
+    /// Header value
+    pub value: String,
+}
+
+impl EmailHeader {
+    /// Creates a header
+    pub fn new(name: &str, value: impl Into<String>) -> Self {
+        EmailHeader {
+            name: name.to_string(),
+            value: value.into(),
+        }
+    }
+}
+
+/// A file attached to an email
+#[derive(Clone, PartialEq, Eq)]
+pub struct EmailAttachment {
+    /// File name, e.g. `receipt.pdf`
+    pub name: String,
+
+    /// MIME type, e.g. `application/pdf`
+    pub content_type: String,
+
+    /// File content
+    pub content: Vec<u8>,
+}
+
+impl Debug for EmailAttachment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // don't dump file contents into logs
+        f.debug_struct("EmailAttachment")
+            .field("name", &self.name)
+            .field("content_type", &self.content_type)
+            .field("size", &self.content.len())
+            .finish()
+    }
+}
+
+/// An email message
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmailMessage {
+    /// Sender address
+    pub from: String,
+
+    /// Recipient address
+    pub to: String,
+
+    /// Template name, see [`templates::TEMPLATES`]
+    pub template: String,
+
+    /// Values for the template fields
+    pub model: TemplateModel,
+
+    /// Locale selecting the template translation
+    pub locale: Locale,
+
+    /// Rendered content, required by backends without provider-side templates
+    pub body: Option<EmailBody>,
+
+    /// Custom headers, e.g. `List-Unsubscribe`
+    pub headers: Vec<EmailHeader>,
+
+    /// Attached files, e.g. a PDF receipt
+    pub attachments: Vec<EmailAttachment>,
+}
+
+/// Interface every email provider needs to implement
+#[tonic::async_trait]
+pub trait EmailBackend: Debug + Send + Sync {
+    /// Name of the backend, used for logging
+    fn name(&self) -> &'static str;
+
+    /// True if the provider renders templates itself.
+    /// Other backends receive a locally rendered body.
+    fn supports_templates(&self) -> bool {
+        false
+    }
+
+    /// Sends a message, returning the provider's message ID
+    async fn send(&self, message: &EmailMessage) -> Result<DeliveryReceipt, DeliveryError>;
+
+    /// Maximum number of messages handed to [`send_batch`](Self::send_batch) at once
+    fn max_batch_size(&self) -> usize {
+        1
+    }
+
+    /// Sends several messages, returning a result per message in the same order.
+    /// Backends without a batch API send the messages one by one.
+    async fn send_batch(
+        &self,
+        messages: &[EmailMessage],
+    ) -> Vec<Result<DeliveryReceipt, DeliveryError>> {
+        let mut results = Vec::with_capacity(messages.len());
+        for message in messages {
+            results.push(self.send(message).await);
+        }
+
+        results
+    }
+}
+
+/// Available email backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailBackendKind {
+    /// Postmark HTTP API
+    Postmark,
+
+    /// SMTP relay
+    Smtp,
+
+    /// Logs messages without sending them
+    Stub,
+}
+
+impl FromStr for EmailBackendKind {
+    type Err = DeliveryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "postmark" => Ok(EmailBackendKind::Postmark),
+            "smtp" => Ok(EmailBackendKind::Smtp),
+            "stub" => Ok(EmailBackendKind::Stub),
+            other => Err(DeliveryError::Configuration(format!(
+                "unknown email backend: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Creates the email backend selected in the provided configuration
+pub fn new_backend(config: &Config) -> Result<Box<dyn EmailBackend>, DeliveryError> {
+    let backend: Box<dyn EmailBackend> = match config.email_backend.parse()? {
+        EmailBackendKind::Postmark => Box::new(postmark::PostmarkBackend::new(
+            config.postmark_token.clone(),
+        )?),
+        EmailBackendKind::Smtp => Box::new(smtp::SmtpBackend::new(config)?),
+        EmailBackendKind::Stub => Box::new(stub::StubBackend::default()),
+    };
+
+    delivery_info!("using email backend: {}", backend.name());
+    Ok(backend)
+}
+
+/// Renders the message body locally when the backend can't render templates
+pub fn render_body(
+    backend: &dyn EmailBackend,
+    message: &mut EmailMessage,
+) -> Result<(), DeliveryError> {
+    if backend.supports_templates() || message.body.is_some() {
+        return Ok(());
+    }
+
+    let body = templates::get_renderer()
+        .and_then(|renderer| renderer.render(&message.template, &message.locale, &message.model))
+        .map_err(|e| DeliveryError::Message(e.to_string()))?;
+
+    message.body = Some(body);
+    Ok(())
+}
+
+/// Masks the local part of an email address for display, e.g. `a***e@aetheric.nl`
+pub fn mask_address(address: &str) -> String {
+    let (local, domain) = address.rsplit_once('@').unwrap_or((address, ""));
+    let chars: Vec<char> = local.chars().collect();
+    let masked = match chars.as_slice() {
+        [] | [_] => "***".to_string(),
+        [first, _] => format!("{}***", first),
+        [first, .., last] => format!("{}***{}", first, last),
+    };
+
+    if domain.is_empty() {
+        masked
+    } else {
+        format!("{}@{}", masked, domain)
+    }
+}
+
+/// Sends a message with the provided backend, rendering the body first if needed.
+/// Transient failures are retried as allowed by the policy.
+pub async fn deliver(
+    backend: &dyn EmailBackend,
+    mut message: EmailMessage,
+    policy: &RetryPolicy,
+) -> DeliveryOutcome {
+    if let Err(e) = render_body(backend, &mut message) {
+        return DeliveryOutcome {
+            result: Err(e),
+            attempts: 0,
+        };
+    }
+
+    retry::retry(policy, || backend.send(&message)).await
+}
+
+/// Sends one batch of messages, resending the messages that failed
+/// with a retryable error as allowed by the policy
+async fn deliver_chunk(
+    backend: &dyn EmailBackend,
+    chunk: Vec<(usize, EmailMessage)>,
+    policy: &RetryPolicy,
+) -> Vec<(usize, DeliveryOutcome)> {
+    let mut outcomes = Vec::with_capacity(chunk.len());
+    let (mut indices, mut messages): (Vec<usize>, Vec<EmailMessage>) = chunk.into_iter().unzip();
+    let mut attempts = 0;
+
+    while !messages.is_empty() {
+        attempts += 1;
+        let mut results = backend.send_batch(&messages).await.into_iter();
+        let mut retry_indices = vec![];
+        let mut retry_messages = vec![];
+
+        for (index, message) in indices.into_iter().zip(messages) {
+            let result = results.next().unwrap_or_else(|| {
+                Err(DeliveryError::Transport(
+                    "no result returned for message".to_string(),
+                ))
+            });
+
+            match result {
+                Err(e) if e.is_retryable() && attempts < policy.max_attempts => {
+                    delivery_warn!("batch attempt {} failed for {}: {}", attempts, index, e);
+                    retry_indices.push(index);
+                    retry_messages.push(message);
+                }
+                result => outcomes.push((index, DeliveryOutcome { result, attempts })),
+            }
+        }
+
+        if !retry_messages.is_empty() {
+            tokio::time::sleep(policy.backoff(attempts)).await;
+        }
+
+        indices = retry_indices;
+        messages = retry_messages;
+    }
+
+    outcomes
+}
+
+/// Sends many messages with the provided backend, returning an outcome per
+/// message in the same order. Messages are handed to the backend in batches
+/// of at most [`EmailBackend::max_batch_size`] messages, with at most
+/// `concurrency` batches in flight. A failed message doesn't affect the others.
+pub async fn deliver_batch(
+    backend: &dyn EmailBackend,
+    messages: Vec<EmailMessage>,
+    policy: &RetryPolicy,
+    concurrency: usize,
+) -> Vec<DeliveryOutcome> {
+    let mut outcomes = vec![];
+    let mut pending = vec![];
+    for (index, mut message) in messages.into_iter().enumerate() {
+        match render_body(backend, &mut message) {
+            Ok(()) => pending.push((index, message)),
+            Err(e) => outcomes.push((
+                index,
+                DeliveryOutcome {
+                    result: Err(e),
+                    attempts: 0,
+                },
+            )),
+        }
+    }
+
+    let batch_size = backend.max_batch_size().max(1);
+    let mut chunks = vec![];
+    while pending.len() > batch_size {
+        let rest = pending.split_off(batch_size);
+        chunks.push(pending);
+        pending = rest;
+    }
+    chunks.push(pending);
+
+    let delivered: Vec<Vec<(usize, DeliveryOutcome)>> = stream::iter(chunks)
+        .map(|chunk| deliver_chunk(backend, chunk, policy))
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+
+    outcomes.extend(delivered.into_iter().flatten());
+    outcomes.sort_by_key(|(index, _)| *index);
+    outcomes.into_iter().map(|(_, outcome)| outcome).collect()
+}
+
+/// Returns EMAIL_BACKEND, the email backend selected through a Config
+/// object generated from environment variables.
+/// Initializes EMAIL_BACKEND if it hasn't been initialized yet.
+pub async fn get_backend() -> Result<&'static dyn EmailBackend, DeliveryError> {
+    EMAIL_BACKEND
+        .get_or_try_init(|| async move {
+            let config = Config::try_from_env().unwrap_or_default();
+            new_backend(&config)
+        })
+        .await
+        .map(|backend| backend.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_model_insert() {
+        let mut model = TemplateModel::default();
+        model.insert("name", "Alice");
+        model.insert("weight", 1.5);
+        model.insert("details", vec!["a", "b"]);
+
+        assert_eq!(model.get("name"), Some(&Value::from("Alice")));
+        assert_eq!(model.get("weight"), Some(&Value::from(1.5)));
+        assert_eq!(model.get("details"), Some(&Value::from(vec!["a", "b"])));
+        assert_eq!(model.get("missing"), None);
+        assert_eq!(model.iter().count(), 3);
+
+        model.insert("name", "Bob");
+        assert_eq!(model.get("name"), Some(&Value::from("Bob")));
+    }
+
+    #[test]
+    fn test_email_attachment_debug() {
+        let attachment = EmailAttachment {
+            name: "receipt.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            content: b"%PDF-1.3".to_vec(),
+        };
+        assert_eq!(
+            format!("{:?}", attachment),
+            r#"EmailAttachment { name: "receipt.pdf", content_type: "application/pdf", size: 8 }"#
+        );
+    }
+
+    #[test]
+    fn test_mask_address() {
+        assert_eq!(mask_address("alice@aetheric.nl"), "a***e@aetheric.nl");
+        assert_eq!(mask_address("al@aetheric.nl"), "a***@aetheric.nl");
+        assert_eq!(mask_address("a@aetheric.nl"), "***@aetheric.nl");
+        assert_eq!(mask_address("@aetheric.nl"), "***@aetheric.nl");
+        assert_eq!(mask_address("not an address"), "n***s");
+    }
+
+    #[test]
+    fn test_email_backend_kind_from_str() {
+        assert_eq!(
+            "postmark".parse::<EmailBackendKind>().unwrap(),
+            EmailBackendKind::Postmark
+        );
+        assert_eq!(
+            "smtp".parse::<EmailBackendKind>().unwrap(),
+            EmailBackendKind::Smtp
+        );
+        assert_eq!(
+            "Stub".parse::<EmailBackendKind>().unwrap(),
+            EmailBackendKind::Stub
+        );
+        assert!("carrier-pigeon".parse::<EmailBackendKind>().is_err());
+    }
+
+    #[test]
+    fn test_render_body() {
+        let mut message = EmailMessage {
+            from: "info@aetheric.nl".to_string(),
+            to: "test@aetheric.nl".to_string(),
+            template: "unknown".to_string(),
+            model: TemplateModel::default(),
+            locale: Locale::default(),
+            body: None,
+            headers: vec![],
+            attachments: vec![],
+        };
+
+        let postmark = postmark::PostmarkBackend::new("token".to_string()).unwrap();
+        render_body(&postmark, &mut message).unwrap();
+        assert_eq!(message.body, None);
+
+        let stub = stub::StubBackend::default();
+        let error = render_body(&stub, &mut message).unwrap_err();
+        assert!(matches!(error, DeliveryError::Message(_)));
+
+        let body = EmailBody {
+            subject: "subject".to_string(),
+            html: "html".to_string(),
+            text: "text".to_string(),
+        };
+        message.body = Some(body.clone());
+        render_body(&stub, &mut message).unwrap();
+        assert_eq!(message.body, Some(body));
+    }
+
+    #[tokio::test]
+    async fn test_deliver() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: std::time::Duration::from_millis(1),
+            max_delay: std::time::Duration::from_millis(1),
+        };
+        let mut message = EmailMessage {
+            from: "info@aetheric.nl".to_string(),
+            to: "test@aetheric.nl".to_string(),
+            template: "unknown".to_string(),
+            model: TemplateModel::default(),
+            locale: Locale::default(),
+            body: None,
+            headers: vec![],
+            attachments: vec![],
+        };
+
+        let stub = stub::StubBackend::default();
+        let outcome = deliver(&stub, message.clone(), &policy).await;
+        assert_eq!(outcome.attempts, 0);
+        assert!(matches!(outcome.result, Err(DeliveryError::Message(_))));
+
+        message.body = Some(EmailBody {
+            subject: "subject".to_string(),
+            html: "html".to_string(),
+            text: "text".to_string(),
+        });
+        let outcome = deliver(&stub, message, &policy).await;
+        assert_eq!(outcome.attempts, 1);
+        assert!(outcome.result.is_ok());
+        assert_eq!(stub.sent().len(), 1);
+
+        ut_info!("Success.");
+    }
+
+    /// Accepts messages in batches of two, refusing addresses without a
+    /// local part and failing the first attempt for addresses starting with "flaky"
+    #[derive(Debug, Default)]
+    struct BatchBackend {
+        batches: std::sync::Mutex<Vec<Vec<String>>>,
+    }
+
+    #[tonic::async_trait]
+    impl EmailBackend for BatchBackend {
+        fn name(&self) -> &'static str {
+            "batch"
+        }
+
+        fn max_batch_size(&self) -> usize {
+            2
+        }
+
+        async fn send(&self, _message: &EmailMessage) -> Result<DeliveryReceipt, DeliveryError> {
+            Err(DeliveryError::Message(
+                "messages are sent in batches".to_string(),
+            ))
+        }
+
+        async fn send_batch(
+            &self,
+            messages: &[EmailMessage],
+        ) -> Vec<Result<DeliveryReceipt, DeliveryError>> {
+            let mut batches = self.batches.lock().unwrap();
+            let retried = batches.iter().flatten().cloned().collect::<Vec<_>>();
+            batches.push(messages.iter().map(|m| m.to.clone()).collect());
+
+            messages
+                .iter()
+                .map(|message| {
+                    if message.to.starts_with('@') {
+                        Err(DeliveryError::Provider {
+                            code: 300,
+                            message: "Invalid email request".to_string(),
+                        })
+                    } else if message.to.starts_with("flaky") && !retried.contains(&message.to) {
+                        Err(DeliveryError::Unavailable("rate limited".to_string()))
+                    } else {
+                        Ok(DeliveryReceipt {
+                            message_id: format!("id-{}", message.to),
+                        })
+                    }
+                })
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deliver_batch() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            base_delay: std::time::Duration::from_millis(1),
+            max_delay: std::time::Duration::from_millis(1),
+        };
+        let message = |to: &str| EmailMessage {
+            from: "info@aetheric.nl".to_string(),
+            to: to.to_string(),
+            template: "unknown".to_string(),
+            model: TemplateModel::default(),
+            locale: Locale::default(),
+            body: Some(EmailBody {
+                subject: "subject".to_string(),
+                html: "html".to_string(),
+                text: "text".to_string(),
+            }),
+            headers: vec![],
+            attachments: vec![],
+        };
+
+        let mut unrendered = message("unrendered@aetheric.nl");
+        unrendered.body = None;
+        let messages = vec![
+            message("alice@aetheric.nl"),
+            unrendered,
+            message("@aetheric.nl"),
+            message("flaky@aetheric.nl"),
+            message("bob@aetheric.nl"),
+        ];
+
+        let backend = BatchBackend::default();
+        let outcomes = deliver_batch(&backend, messages, &policy, 2).await;
+        assert_eq!(outcomes.len(), 5);
+
+        assert_eq!(outcomes[0].attempts, 1);
+        assert_eq!(
+            outcomes[0].result,
+            Ok(DeliveryReceipt {
+                message_id: "id-alice@aetheric.nl".to_string()
+            })
+        );
+        assert_eq!(outcomes[1].attempts, 0);
+        assert!(matches!(outcomes[1].result, Err(DeliveryError::Message(_))));
+        assert_eq!(outcomes[2].attempts, 1);
+        assert!(matches!(
+            outcomes[2].result,
+            Err(DeliveryError::Provider { code: 300, .. })
+        ));
+        assert_eq!(outcomes[3].attempts, 2);
+        assert!(outcomes[3].result.is_ok());
+        assert_eq!(outcomes[4].attempts, 1);
+        assert!(outcomes[4].result.is_ok());
+
+        // two batches of two, then the retried message on its own
+        let mut batches = backend.batches.lock().unwrap().clone();
+        assert_eq!(batches.len(), 3);
+        assert_eq!(batches.pop(), Some(vec!["flaky@aetheric.nl".to_string()]));
+        batches.sort();
+        assert_eq!(
+            batches,
+            vec![
+                vec!["alice@aetheric.nl".to_string(), "@aetheric.nl".to_string()],
+                vec![
+                    "flaky@aetheric.nl".to_string(),
+                    "bob@aetheric.nl".to_string()
+                ],
+            ]
+        );
+
+        let stub = stub::StubBackend::default();
+        let outcomes = deliver_batch(&stub, vec![message("alice@aetheric.nl")], &policy, 2).await;
+        assert_eq!(outcomes.len(), 1);
+        assert!(outcomes[0].result.is_ok());
+        assert_eq!(stub.sent().len(), 1);
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_new_backend() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let mut config = Config::default();
+        config.email_backend = "stub".to_string();
+        assert_eq!(new_backend(&config).unwrap().name(), "stub");
+
+        config.email_backend = "smtp".to_string();
+        assert_eq!(new_backend(&config).unwrap().name(), "smtp");
+
+        config.email_backend = "postmark".to_string();
+        assert_eq!(new_backend(&config).unwrap().name(), "postmark");
+
+        config.postmark_token = String::new();
+        let error = new_backend(&config).unwrap_err();
+        assert!(matches!(error, DeliveryError::Configuration(_)));
+
+        config.email_backend = "unknown".to_string();
+        let error = new_backend(&config).unwrap_err();
+        assert!(matches!(error, DeliveryError::Configuration(_)));
+
+        ut_info!("Success.");
+    }
+}
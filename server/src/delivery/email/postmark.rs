@@ -0,0 +1,553 @@
+//! Postmark email backend
+
+use super::{EmailBackend, EmailMessage};
+use crate::delivery::{DeliveryError, DeliveryReceipt};
+use crate::templates;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use postmark::api::email::{
+    Attachment, Header, SendEmailBatchWithTemplatesRequest, SendEmailWithTemplateRequest,
+    TemplateModel,
+};
+use postmark::POSTMARK_API_URL;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Debug, Formatter};
+
+/// Postmark error code returned while the API is in maintenance
+const POSTMARK_MAINTENANCE: i64 = 100;
+
+/// Maximum number of messages Postmark accepts in a single batch
+const POSTMARK_MAX_BATCH_SIZE: usize = 500;
+
+/// Path of the Postmark endpoint sending a templated email
+const POSTMARK_EMAIL_PATH: &str = "/email/withTemplate";
+
+/// Path of the Postmark endpoint sending a batch of templated emails
+const POSTMARK_BATCH_PATH: &str = "/email/batchWithTemplates";
+
+/// Result of a message, or error of a refused batch
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkResult {
+    error_code: i64,
+    message: String,
+    #[serde(rename = "MessageID", default)]
+    message_id: String,
+}
+
+/// Sends templated emails through the Postmark HTTP API
+pub struct PostmarkBackend {
+    /// Emails are posted directly, to tell apart emails Postmark
+    /// never accepted from answers that could not be read
+    http: reqwest::Client,
+    email_url: String,
+    batch_url: String,
+    token: String,
+}
+
+impl Debug for PostmarkBackend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // don't leak the token into logs
+        f.debug_struct("PostmarkBackend").finish_non_exhaustive()
+    }
+}
+
+impl PostmarkBackend {
+    /// Creates a new Postmark backend using the provided server token
+    pub fn new(token: String) -> Result<Self, DeliveryError> {
+        Self::with_url(token, POSTMARK_API_URL)
+    }
+
+    /// Creates a new Postmark backend for the API at `url`
+    fn with_url(token: String, url: &str) -> Result<Self, DeliveryError> {
+        if token.is_empty() {
+            return Err(DeliveryError::Configuration(
+                "Postmark token not found".to_string(),
+            ));
+        }
+
+        let url = url.trim_end_matches('/');
+        Ok(PostmarkBackend {
+            http: reqwest::Client::new(),
+            email_url: format!("{}{}", url, POSTMARK_EMAIL_PATH),
+            batch_url: format!("{}{}", url, POSTMARK_BATCH_PATH),
+            token,
+        })
+    }
+
+    /// Posts a request to Postmark. Only a request Postmark didn't accept
+    /// fails with a retryable error: the connection failed, or Postmark
+    /// answered with a server error or `429 Too Many Requests`. A request
+    /// that failed after it was sent fails as unconfirmed, as the emails
+    /// may have been sent.
+    async fn post(
+        &self,
+        url: &str,
+        request: &impl Serialize,
+    ) -> Result<reqwest::Response, DeliveryError> {
+        let response = self
+            .http
+            .post(url)
+            .header("Accept", "application/json")
+            .header("X-Postmark-Server-Token", &self.token)
+            .json(request)
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_connect() {
+                    DeliveryError::Transport(e.to_string())
+                } else {
+                    DeliveryError::Unconfirmed(e.to_string())
+                }
+            })?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            return Err(DeliveryError::Unavailable(format!("HTTP {}", status)));
+        }
+
+        Ok(response)
+    }
+
+    /// Posts a batch, returning the result of every message.
+    /// Errors are classified as in [`Self::post`]; an answer to an accepted
+    /// batch that can't be read fails as unconfirmed.
+    async fn post_batch(
+        &self,
+        messages: &[EmailMessage],
+    ) -> Result<Vec<PostmarkResult>, DeliveryError> {
+        let request = SendEmailBatchWithTemplatesRequest {
+            messages: messages.iter().map(template_request).collect(),
+        };
+
+        let response = self.post(&self.batch_url, &request).await?;
+        let status = response.status();
+        if !status.is_success() {
+            // e.g. an invalid token, the whole batch is refused
+            let error =
+                response
+                    .json::<PostmarkResult>()
+                    .await
+                    .map_err(|e| DeliveryError::Provider {
+                        code: status.as_u16() as i64,
+                        message: e.to_string(),
+                    })?;
+            delivery_error!(
+                "Postmark refused batch: {} {}",
+                error.error_code,
+                error.message
+            );
+            return Err(DeliveryError::Provider {
+                code: error.error_code,
+                message: error.message,
+            });
+        }
+
+        let results = response
+            .json::<Vec<PostmarkResult>>()
+            .await
+            .map_err(|e| DeliveryError::Unconfirmed(e.to_string()))?;
+
+        // Postmark answers with a result per message, in the same order
+        if results.len() != messages.len() {
+            return Err(DeliveryError::Unconfirmed(format!(
+                "{} results for {} messages",
+                results.len(),
+                messages.len()
+            )));
+        }
+
+        Ok(results)
+    }
+}
+
+#[tonic::async_trait]
+impl EmailBackend for PostmarkBackend {
+    fn name(&self) -> &'static str {
+        "postmark"
+    }
+
+    fn supports_templates(&self) -> bool {
+        true
+    }
+
+    /// Sends a single email, its errors are classified as in [`Self::post`]
+    async fn send(&self, message: &EmailMessage) -> Result<DeliveryReceipt, DeliveryError> {
+        let response = self
+            .post(&self.email_url, &template_request(message))
+            .await?;
+
+        // a refused email is answered with the error of the message
+        let status = response.status();
+        let result = response.json::<PostmarkResult>().await.map_err(|e| {
+            if status.is_success() {
+                DeliveryError::Unconfirmed(e.to_string())
+            } else {
+                DeliveryError::Provider {
+                    code: status.as_u16() as i64,
+                    message: e.to_string(),
+                }
+            }
+        })?;
+
+        receipt(result.error_code, result.message, result.message_id)
+    }
+
+    fn max_batch_size(&self) -> usize {
+        POSTMARK_MAX_BATCH_SIZE
+    }
+
+    async fn send_batch(
+        &self,
+        messages: &[EmailMessage],
+    ) -> Vec<Result<DeliveryReceipt, DeliveryError>> {
+        match self.post_batch(messages).await {
+            Ok(results) => results
+                .into_iter()
+                .map(|result| receipt(result.error_code, result.message, result.message_id))
+                .collect(),
+            Err(error) => messages.iter().map(|_| Err(error.clone())).collect(),
+        }
+    }
+}
+
+/// Builds the Postmark request for a message, using the provider alias of its template
+/// in the language of the message
+fn template_request(message: &EmailMessage) -> SendEmailWithTemplateRequest {
+    let alias = templates::find(&message.template)
+        .map(|spec| spec.provider_alias_for(&message.locale))
+        .unwrap_or_else(|| message.template.clone());
+
+    let mut model = TemplateModel::default();
+    for (key, value) in message.model.iter() {
+        model.insert(key.as_str(), value.clone());
+    }
+
+    let mut request = SendEmailWithTemplateRequest::builder()
+        .from(message.from.clone())
+        .to(message.to.clone())
+        .template_model(model)
+        .template_alias(&alias)
+        .build();
+
+    if !message.headers.is_empty() {
+        request.headers = Some(
+            message
+                .headers
+                .iter()
+                .map(|header| Header {
+                    name: header.name.clone(),
+                    value: header.value.clone(),
+                })
+                .collect(),
+        );
+    }
+
+    if !message.attachments.is_empty() {
+        request.attachments = Some(
+            message
+                .attachments
+                .iter()
+                .map(|attachment| Attachment {
+                    name: attachment.name.clone(),
+                    content: STANDARD.encode(&attachment.content),
+                    content_type: attachment.content_type.clone(),
+                    content_id: None,
+                })
+                .collect(),
+        );
+    }
+
+    request
+}
+
+/// Turns the Postmark result of a single message into a receipt
+fn receipt(
+    error_code: i64,
+    message: String,
+    message_id: String,
+) -> Result<DeliveryReceipt, DeliveryError> {
+    match error_code {
+        0 => Ok(DeliveryReceipt { message_id }),
+        POSTMARK_MAINTENANCE => Err(DeliveryError::Unavailable(message)),
+        code => {
+            delivery_error!("Postmark refused email: {} {}", code, message);
+            Err(DeliveryError::Provider { code, message })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delivery::email::{EmailAttachment, EmailHeader};
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::response::{IntoResponse, Response};
+    use axum::{routing, Json, Router};
+    use serde_json::{json, Value};
+
+    /// Postmark stand-in on the loopback interface, returns its base URL.
+    /// The recipient of the (first) message selects the answer.
+    async fn postmark_stand_in() -> String {
+        async fn email(headers: HeaderMap, Json(body): Json<Value>) -> Response {
+            if headers.get("X-Postmark-Server-Token").unwrap() != "token" {
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    Json(json!({ "ErrorCode": 10, "Message": "Bad or missing API token" })),
+                )
+                    .into_response();
+            }
+
+            match body["To"].as_str().unwrap() {
+                "busy@aetheric.nl" => StatusCode::TOO_MANY_REQUESTS.into_response(),
+                "down@aetheric.nl" => StatusCode::BAD_GATEWAY.into_response(),
+                "garbled@aetheric.nl" => (StatusCode::OK, "<html>").into_response(),
+                to if to.starts_with('@') => (
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    Json(json!({ "ErrorCode": 300, "Message": "Invalid email request" })),
+                )
+                    .into_response(),
+                _ => Json(json!({
+                    "ErrorCode": 0,
+                    "Message": "OK",
+                    "MessageID": "message-0"
+                }))
+                .into_response(),
+            }
+        }
+
+        async fn batch(headers: HeaderMap, Json(body): Json<Value>) -> Response {
+            if headers.get("X-Postmark-Server-Token").unwrap() != "token" {
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    Json(json!({ "ErrorCode": 10, "Message": "Bad or missing API token" })),
+                )
+                    .into_response();
+            }
+
+            let messages = body["Messages"].as_array().unwrap();
+            match messages[0]["To"].as_str().unwrap() {
+                "busy@aetheric.nl" => StatusCode::TOO_MANY_REQUESTS.into_response(),
+                "down@aetheric.nl" => StatusCode::BAD_GATEWAY.into_response(),
+                "garbled@aetheric.nl" => (StatusCode::OK, "<html>").into_response(),
+                _ => Json(
+                    messages
+                        .iter()
+                        .enumerate()
+                        .map(|(index, message)| match message["To"].as_str() {
+                            Some(to) if to.starts_with('@') => json!({
+                                "ErrorCode": 300,
+                                "Message": "Invalid email request"
+                            }),
+                            _ => json!({
+                                "ErrorCode": 0,
+                                "Message": "OK",
+                                "MessageID": format!("message-{}", index)
+                            }),
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .into_response(),
+            }
+        }
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route(POSTMARK_EMAIL_PATH, routing::post(email))
+            .route(POSTMARK_BATCH_PATH, routing::post(batch));
+
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        format!("http://{}", address)
+    }
+
+    fn message(to: &str) -> EmailMessage {
+        EmailMessage {
+            from: "info@aetheric.nl".to_string(),
+            to: to.to_string(),
+            template: "parcel-arrival".to_string(),
+            model: crate::delivery::email::TemplateModel::default(),
+            locale: Default::default(),
+            body: None,
+            headers: vec![],
+            attachments: vec![],
+        }
+    }
+
+    /// Server on the loopback interface that hangs up on every request
+    /// after reading it, returns its base URL
+    async fn hang_up_stand_in() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buffer = [0; 4096];
+                let _ = tokio::io::AsyncReadExt::read(&mut socket, &mut buffer).await;
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    #[tokio::test]
+    async fn test_postmark_backend_send() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let url = postmark_stand_in().await;
+        let backend = PostmarkBackend::with_url("token".to_string(), &url).unwrap();
+
+        let result = backend.send(&message("alice@aetheric.nl")).await;
+        assert_eq!(
+            result,
+            Ok(DeliveryReceipt {
+                message_id: "message-0".to_string()
+            })
+        );
+        let result = backend.send(&message("@aetheric.nl")).await;
+        assert!(matches!(
+            result,
+            Err(DeliveryError::Provider { code: 300, .. })
+        ));
+
+        // Postmark didn't accept the email, it can be sent again
+        let result = backend.send(&message("busy@aetheric.nl")).await;
+        assert!(matches!(result, Err(DeliveryError::Unavailable(_))));
+        let result = backend.send(&message("down@aetheric.nl")).await;
+        assert!(matches!(result, Err(DeliveryError::Unavailable(_))));
+
+        // the email may have been sent, so it isn't retried
+        let result = backend.send(&message("garbled@aetheric.nl")).await;
+        assert!(matches!(result, Err(DeliveryError::Unconfirmed(_))));
+        let hang_up =
+            PostmarkBackend::with_url("token".to_string(), &hang_up_stand_in().await).unwrap();
+        let result = hang_up.send(&message("alice@aetheric.nl")).await;
+        assert!(matches!(result, Err(DeliveryError::Unconfirmed(_))));
+
+        let backend = PostmarkBackend::with_url("wrong".to_string(), &url).unwrap();
+        let result = backend.send(&message("alice@aetheric.nl")).await;
+        assert!(matches!(
+            result,
+            Err(DeliveryError::Provider { code: 10, .. })
+        ));
+
+        // nothing listens on the discard port
+        let backend = PostmarkBackend::with_url("token".to_string(), "http://127.0.0.1:9").unwrap();
+        let result = backend.send(&message("alice@aetheric.nl")).await;
+        assert!(matches!(result, Err(DeliveryError::Transport(_))));
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_postmark_backend_send_batch() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let url = postmark_stand_in().await;
+        let backend = PostmarkBackend::with_url("token".to_string(), &url).unwrap();
+
+        let results = backend
+            .send_batch(&[message("alice@aetheric.nl"), message("@aetheric.nl")])
+            .await;
+        assert_eq!(
+            results[0],
+            Ok(DeliveryReceipt {
+                message_id: "message-0".to_string()
+            })
+        );
+        assert!(matches!(
+            results[1],
+            Err(DeliveryError::Provider { code: 300, .. })
+        ));
+
+        // Postmark didn't accept the batch, it can be sent again
+        let results = backend.send_batch(&[message("busy@aetheric.nl")]).await;
+        assert!(matches!(results[0], Err(DeliveryError::Unavailable(_))));
+        let results = backend.send_batch(&[message("down@aetheric.nl")]).await;
+        assert!(matches!(results[0], Err(DeliveryError::Unavailable(_))));
+
+        // the batch may have been sent, so it isn't retried
+        let results = backend
+            .send_batch(&[message("garbled@aetheric.nl"), message("bob@aetheric.nl")])
+            .await;
+        assert_eq!(results.len(), 2);
+        assert!(results
+            .iter()
+            .all(|result| matches!(result, Err(e) if !e.is_retryable())));
+
+        let backend = PostmarkBackend::with_url("wrong".to_string(), &url).unwrap();
+        let results = backend.send_batch(&[message("alice@aetheric.nl")]).await;
+        assert!(matches!(
+            results[0],
+            Err(DeliveryError::Provider { code: 10, .. })
+        ));
+
+        // nothing listens on the discard port
+        let backend = PostmarkBackend::with_url("token".to_string(), "http://127.0.0.1:9").unwrap();
+        let results = backend.send_batch(&[message("alice@aetheric.nl")]).await;
+        assert!(matches!(results[0], Err(DeliveryError::Transport(_))));
+
+        ut_info!("Success.");
+    }
+
+    #[test]
+    fn test_template_request() {
+        let mut message = EmailMessage {
+            from: "info@aetheric.nl".to_string(),
+            to: "test@aetheric.nl".to_string(),
+            template: "cargo-confirmation".to_string(),
+            model: crate::delivery::email::TemplateModel::default(),
+            locale: Default::default(),
+            body: None,
+            headers: vec![],
+            attachments: vec![],
+        };
+        assert!(template_request(&message).headers.is_none());
+        assert!(template_request(&message).attachments.is_none());
+
+        message.headers.push(EmailHeader::new(
+            "List-Unsubscribe-Post",
+            "List-Unsubscribe=One-Click",
+        ));
+        let headers = template_request(&message).headers.unwrap();
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers[0].name, "List-Unsubscribe-Post");
+        assert_eq!(headers[0].value, "List-Unsubscribe=One-Click");
+
+        message.attachments.push(EmailAttachment {
+            name: "receipt.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            content: b"%PDF".to_vec(),
+        });
+        let attachments = template_request(&message).attachments.unwrap();
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].name, "receipt.pdf");
+        assert_eq!(attachments[0].content_type, "application/pdf");
+        assert_eq!(attachments[0].content, "JVBERg==");
+    }
+
+    #[test]
+    fn test_receipt() {
+        assert_eq!(
+            receipt(0, "OK".to_string(), "id".to_string()),
+            Ok(DeliveryReceipt {
+                message_id: "id".to_string()
+            })
+        );
+        assert_eq!(
+            receipt(100, "Maintenance".to_string(), String::new()),
+            Err(DeliveryError::Unavailable("Maintenance".to_string()))
+        );
+        assert_eq!(
+            receipt(406, "Inactive recipient".to_string(), String::new()),
+            Err(DeliveryError::Provider {
+                code: 406,
+                message: "Inactive recipient".to_string()
+            })
+        );
+    }
+}
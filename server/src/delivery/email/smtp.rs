@@ -0,0 +1,395 @@
+//! SMTP email backend
+
+use super::{EmailBackend, EmailMessage};
+use crate::delivery::{DeliveryError, DeliveryReceipt};
+use crate::Config;
+use lettre::message::header::{ContentType, HeaderName, HeaderValue};
+use lettre::message::{Attachment, Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::str::FromStr;
+
+/// Transport security used for the SMTP connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// Plain text connection, only use for local relays
+    None,
+
+    /// Upgrade a plain text connection with STARTTLS
+    StartTls,
+
+    /// TLS from the start of the connection (SMTPS)
+    Implicit,
+}
+
+impl FromStr for SmtpTls {
+    type Err = DeliveryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(SmtpTls::None),
+            "starttls" => Ok(SmtpTls::StartTls),
+            "tls" | "implicit" => Ok(SmtpTls::Implicit),
+            other => Err(DeliveryError::Configuration(format!(
+                "unknown SMTP TLS mode: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Sends emails through an SMTP relay
+#[derive(Debug)]
+pub struct SmtpBackend {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpBackend {
+    /// Creates a new SMTP backend from the `smtp_*` configuration options
+    pub fn new(config: &Config) -> Result<Self, DeliveryError> {
+        if config.smtp_host.is_empty() {
+            return Err(DeliveryError::Configuration(
+                "SMTP host not found".to_string(),
+            ));
+        }
+
+        let tls = match config.smtp_tls.parse()? {
+            SmtpTls::None => Tls::None,
+            SmtpTls::StartTls => Tls::Required(Self::tls_parameters(&config.smtp_host)?),
+            SmtpTls::Implicit => Tls::Wrapper(Self::tls_parameters(&config.smtp_host)?),
+        };
+
+        let mut builder =
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(config.smtp_host.clone())
+                .port(config.smtp_port)
+                .tls(tls);
+
+        if !config.smtp_username.is_empty() {
+            builder = builder.credentials(Credentials::new(
+                config.smtp_username.clone(),
+                config.smtp_password.clone(),
+            ));
+        }
+
+        Ok(SmtpBackend {
+            transport: builder.build(),
+        })
+    }
+
+    fn tls_parameters(host: &str) -> Result<TlsParameters, DeliveryError> {
+        TlsParameters::new(host.to_string())
+            .map_err(|e| DeliveryError::Configuration(format!("invalid TLS parameters: {}", e)))
+    }
+
+    /// Builds the MIME message, returning it together with its Message-ID
+    fn build_message(message: &EmailMessage) -> Result<(Message, String), DeliveryError> {
+        let from: Mailbox = message
+            .from
+            .parse()
+            .map_err(|e| DeliveryError::Message(format!("invalid sender address: {}", e)))?;
+
+        let to: Mailbox = message
+            .to
+            .parse()
+            .map_err(|e| DeliveryError::Message(format!("invalid recipient address: {}", e)))?;
+
+        let body = message.body.as_ref().ok_or_else(|| {
+            DeliveryError::Message(format!(
+                "SMTP requires a rendered body, got template {}",
+                message.template
+            ))
+        })?;
+
+        let message_id = format!(
+            "{}@{}",
+            lib_common::uuid::Uuid::new_v4(),
+            from.email.domain()
+        );
+
+        let mut builder = Message::builder()
+            .from(from)
+            .to(to)
+            .subject(body.subject.clone())
+            .message_id(Some(format!("<{}>", message_id)));
+        for header in &message.headers {
+            let name = HeaderName::new_from_ascii(header.name.clone())
+                .map_err(|e| DeliveryError::Message(format!("{}: {}", e, header.name)))?;
+            builder = builder.raw_header(HeaderValue::new(name, header.value.clone()));
+        }
+
+        let mut content = MultiPart::alternative_plain_html(body.text.clone(), body.html.clone());
+        if !message.attachments.is_empty() {
+            // attachments follow the body in a multipart/mixed message
+            let mut mixed = MultiPart::mixed().multipart(content);
+            for attachment in &message.attachments {
+                let content_type = ContentType::parse(&attachment.content_type).map_err(|e| {
+                    DeliveryError::Message(format!("{}: {}", e, attachment.content_type))
+                })?;
+                mixed = mixed.singlepart(
+                    Attachment::new(attachment.name.clone())
+                        .body(attachment.content.clone(), content_type),
+                );
+            }
+            content = mixed;
+        }
+
+        let email = builder
+            .multipart(content)
+            .map_err(|e| DeliveryError::Message(format!("could not build email: {}", e)))?;
+
+        Ok((email, message_id))
+    }
+}
+
+#[tonic::async_trait]
+impl EmailBackend for SmtpBackend {
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+
+    async fn send(&self, message: &EmailMessage) -> Result<DeliveryReceipt, DeliveryError> {
+        let (email, message_id) = Self::build_message(message)?;
+
+        match self.transport.send(email).await {
+            Ok(response) => {
+                delivery_debug!("SMTP relay accepted email: {:?}", response);
+                Ok(DeliveryReceipt { message_id })
+            }
+            Err(e) if e.is_permanent() => Err(DeliveryError::Provider {
+                code: e.status().map(u16::from).unwrap_or_default() as i64,
+                message: e.to_string(),
+            }),
+            Err(e) if e.is_transient() => Err(DeliveryError::Unavailable(e.to_string())),
+            Err(e) => Err(DeliveryError::Transport(e.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delivery::email::{EmailAttachment, EmailBody, EmailHeader, TemplateModel};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+
+    /// Minimal SMTP server accepting a single session on the loopback interface.
+    /// Replies to RCPT TO with the provided reply and returns the DATA section.
+    async fn smtp_stand_in(rcpt_reply: &'static str) -> (u16, oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (data_tx, data_rx) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut data = String::new();
+            let mut in_data = false;
+
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        writer.write_all(b"250 2.0.0 queued\r\n").await.unwrap();
+                    } else {
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    continue;
+                }
+
+                let command = line.to_uppercase();
+                let reply = if command.starts_with("EHLO") || command.starts_with("HELO") {
+                    "250 localhost\r\n"
+                } else if command.starts_with("RCPT") {
+                    rcpt_reply
+                } else if command.starts_with("DATA") {
+                    in_data = true;
+                    "354 end data with <CR><LF>.<CR><LF>\r\n"
+                } else if command.starts_with("QUIT") {
+                    writer.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    "250 OK\r\n"
+                };
+                writer.write_all(reply.as_bytes()).await.unwrap();
+            }
+
+            let _ = data_tx.send(data);
+        });
+
+        (port, data_rx)
+    }
+
+    fn smtp_config(port: u16) -> Config {
+        let mut config = Config::default();
+        config.smtp_host = "127.0.0.1".to_string();
+        config.smtp_port = port;
+        config.smtp_tls = "none".to_string();
+        config
+    }
+
+    fn message() -> EmailMessage {
+        EmailMessage {
+            from: "info@aetheric.nl".to_string(),
+            to: "test@aetheric.nl".to_string(),
+            template: "cargo-confirmation".to_string(),
+            model: TemplateModel::default(),
+            locale: Default::default(),
+            body: Some(EmailBody {
+                subject: "Your parcel is booked".to_string(),
+                html: "<p>Hello there</p>".to_string(),
+                text: "Hello there".to_string(),
+            }),
+            headers: vec![],
+            attachments: vec![],
+        }
+    }
+
+    #[test]
+    fn test_smtp_tls_from_str() {
+        assert_eq!("none".parse::<SmtpTls>().unwrap(), SmtpTls::None);
+        assert_eq!("STARTTLS".parse::<SmtpTls>().unwrap(), SmtpTls::StartTls);
+        assert_eq!("tls".parse::<SmtpTls>().unwrap(), SmtpTls::Implicit);
+        assert_eq!("implicit".parse::<SmtpTls>().unwrap(), SmtpTls::Implicit);
+        assert!("ssl3".parse::<SmtpTls>().is_err());
+    }
+
+    #[test]
+    fn test_smtp_backend_new() {
+        let mut config = Config::default();
+        config.smtp_host = String::new();
+        assert!(matches!(
+            SmtpBackend::new(&config).unwrap_err(),
+            DeliveryError::Configuration(_)
+        ));
+
+        config.smtp_host = "smtp.aetheric.nl".to_string();
+        config.smtp_tls = "carrier-pigeon".to_string();
+        assert!(matches!(
+            SmtpBackend::new(&config).unwrap_err(),
+            DeliveryError::Configuration(_)
+        ));
+
+        for tls in ["none", "starttls", "tls"] {
+            config.smtp_tls = tls.to_string();
+            assert_eq!(SmtpBackend::new(&config).unwrap().name(), "smtp");
+        }
+    }
+
+    #[test]
+    fn test_smtp_build_message() {
+        let (_, message_id) = SmtpBackend::build_message(&message()).unwrap();
+        assert!(message_id.ends_with("@aetheric.nl"));
+
+        let mut invalid = message();
+        invalid.to = "not an address".to_string();
+        assert!(matches!(
+            SmtpBackend::build_message(&invalid).unwrap_err(),
+            DeliveryError::Message(_)
+        ));
+
+        let mut template_only = message();
+        template_only.body = None;
+        assert!(matches!(
+            SmtpBackend::build_message(&template_only).unwrap_err(),
+            DeliveryError::Message(_)
+        ));
+
+        let mut invalid_header = message();
+        invalid_header.headers.push(EmailHeader::new(
+            "List Unsubscribe",
+            "<https://aetheric.nl>",
+        ));
+        assert_eq!(
+            SmtpBackend::build_message(&invalid_header).unwrap_err(),
+            DeliveryError::Message("invalid header name: List Unsubscribe".to_string())
+        );
+
+        let mut invalid_attachment = message();
+        invalid_attachment.attachments.push(EmailAttachment {
+            name: "receipt.pdf".to_string(),
+            content_type: "pdf".to_string(),
+            content: vec![],
+        });
+        assert!(matches!(
+            SmtpBackend::build_message(&invalid_attachment).unwrap_err(),
+            DeliveryError::Message(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_smtp_backend_send() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let (port, data_rx) = smtp_stand_in("250 OK\r\n").await;
+        let backend = SmtpBackend::new(&smtp_config(port)).unwrap();
+
+        let mut message = message();
+        message.headers.push(EmailHeader::new(
+            "List-Unsubscribe-Post",
+            "List-Unsubscribe=One-Click",
+        ));
+        let receipt = backend.send(&message).await.unwrap();
+        let data = data_rx.await.unwrap();
+        assert!(data.contains(&format!("Message-ID: <{}>", receipt.message_id)));
+        assert!(data.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+        assert!(data.contains("Subject: Your parcel is booked"));
+        assert!(data.contains("To: test@aetheric.nl"));
+        assert!(data.contains("Hello there"));
+        assert!(!data.contains("multipart/mixed"));
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_smtp_backend_send_attachment() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let (port, data_rx) = smtp_stand_in("250 OK\r\n").await;
+        let backend = SmtpBackend::new(&smtp_config(port)).unwrap();
+
+        let mut message = message();
+        message.attachments.push(EmailAttachment {
+            name: "receipt.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            content: b"%PDF-1.3".to_vec(),
+        });
+        backend.send(&message).await.unwrap();
+        let data = data_rx.await.unwrap();
+        assert!(data.contains("Content-Type: multipart/mixed"));
+        assert!(data.contains("Content-Type: multipart/alternative"));
+        assert!(data.contains("Content-Type: application/pdf"));
+        assert!(data.contains("Content-Disposition: attachment; filename=\"receipt.pdf\""));
+        assert!(data.contains("Hello there"));
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_smtp_backend_rejected() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let (port, _) = smtp_stand_in("550 5.1.1 no such user\r\n").await;
+        let backend = SmtpBackend::new(&smtp_config(port)).unwrap();
+
+        let error = backend.send(&message()).await.unwrap_err();
+        assert!(matches!(error, DeliveryError::Provider { code: 550, .. }));
+        assert!(!error.is_retryable());
+
+        let (port, _) = smtp_stand_in("452 4.2.2 mailbox full\r\n").await;
+        let backend = SmtpBackend::new(&smtp_config(port)).unwrap();
+
+        let error = backend.send(&message()).await.unwrap_err();
+        assert!(matches!(error, DeliveryError::Unavailable(_)));
+        assert!(error.is_retryable());
+
+        ut_info!("Success.");
+    }
+}
@@ -0,0 +1,72 @@
+//! Stub email backend, logs messages instead of sending them
+
+use super::{EmailBackend, EmailMessage};
+use crate::delivery::{DeliveryError, DeliveryReceipt};
+use std::sync::Mutex;
+
+/// Accepts every message without contacting a provider.
+/// Useful for local development and for testing the notification flow.
+#[derive(Debug, Default)]
+pub struct StubBackend {
+    sent: Mutex<Vec<EmailMessage>>,
+}
+
+impl StubBackend {
+    /// Returns a copy of every message accepted by this backend
+    pub fn sent(&self) -> Vec<EmailMessage> {
+        match self.sent.lock() {
+            Ok(sent) => sent.clone(),
+            Err(e) => e.into_inner().clone(),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl EmailBackend for StubBackend {
+    fn name(&self) -> &'static str {
+        "stub"
+    }
+
+    async fn send(&self, message: &EmailMessage) -> Result<DeliveryReceipt, DeliveryError> {
+        delivery_warn!("(STUB) not sending email to {}.", message.to);
+        delivery_debug!("(STUB) message: {:?}", message);
+
+        let message_id = lib_common::uuid::Uuid::new_v4().to_string();
+        match self.sent.lock() {
+            Ok(mut sent) => sent.push(message.clone()),
+            Err(e) => e.into_inner().push(message.clone()),
+        }
+
+        Ok(DeliveryReceipt { message_id })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delivery::email::TemplateModel;
+
+    #[tokio::test]
+    async fn test_stub_backend_send() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let backend = StubBackend::default();
+        let message = EmailMessage {
+            from: "info@aetheric.nl".to_string(),
+            to: "test@aetheric.nl".to_string(),
+            template: "demo-confirmation".to_string(),
+            model: TemplateModel::default(),
+            locale: Default::default(),
+            body: None,
+            headers: vec![],
+            attachments: vec![],
+        };
+
+        let receipt = backend.send(&message).await.unwrap();
+        lib_common::uuid::to_uuid(&receipt.message_id).unwrap();
+        assert_eq!(backend.sent(), vec![message]);
+
+        ut_info!("Success.");
+    }
+}
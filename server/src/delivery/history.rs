@@ -0,0 +1,708 @@
+//! Delivery history, a log of every notification sent to a user
+
+use super::{DeliveryError, DeliveryReceipt};
+use crate::store::{self, Store, StoreError};
+use crate::Config;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+/// Delivery log shared by all handlers
+static DELIVERY_LOG: OnceCell<DeliveryLog<'static>> = OnceCell::const_new();
+
+/// Namespace of delivery records in the store
+const NAMESPACE: &str = "delivery";
+
+/// Namespace of the delivery record indexes in the store
+const INDEX_NAMESPACE: &str = "delivery-index";
+
+/// Namespace of the provider message IDs in the store
+const MESSAGE_NAMESPACE: &str = "delivery-message";
+
+/// Channel a notification was sent through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    /// Email
+    Email,
+
+    /// Text message
+    Sms,
+}
+
+impl Display for Channel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Channel::Email => write!(f, "email"),
+            Channel::Sms => write!(f, "sms"),
+        }
+    }
+}
+
+/// Delivery status of a notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Accepted from the notification queue, not handed to the provider yet
+    Queued,
+
+    /// Accepted by the provider
+    Sent,
+
+    /// Not accepted by the provider
+    Failed,
+
+    /// Delivered to the recipient's mail server
+    Delivered,
+
+    /// Returned by the recipient's mail server
+    Bounced,
+
+    /// Marked as spam by the recipient
+    Complained,
+}
+
+impl DeliveryStatus {
+    /// Returns true if no further status is expected on any channel.
+    /// A delivered email can still be marked as spam later, which isn't waited for.
+    pub fn is_final(&self) -> bool {
+        !matches!(self, DeliveryStatus::Queued | DeliveryStatus::Sent)
+    }
+}
+
+/// A notification sent, or attempted to be sent, to a user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeliveryRecord {
+    /// Record ID
+    pub id: String,
+
+    /// ID of the user the notification was sent to
+    pub user_id: String,
+
+    /// Channel the notification was sent through
+    pub channel: Channel,
+
+    /// Email address or phone number of the recipient
+    pub recipient: String,
+
+    /// Template name, see [`crate::templates::TEMPLATES`]
+    pub template: String,
+
+    /// Message ID assigned by the provider, if accepted
+    pub message_id: Option<String>,
+
+    /// Current delivery status
+    pub status: DeliveryStatus,
+
+    /// Error reported by the provider, if any
+    pub error: Option<String>,
+
+    /// Related parcel, if any
+    pub parcel_id: Option<String>,
+
+    /// Related itinerary, if any
+    pub itinerary_id: Option<String>,
+
+    /// When the notification was handed to the provider
+    pub created_at: DateTime<Utc>,
+
+    /// When the status last changed
+    pub updated_at: DateTime<Utc>,
+}
+
+impl DeliveryRecord {
+    /// Creates a record for the result of a delivery
+    pub fn new(
+        user_id: &str,
+        channel: Channel,
+        recipient: &str,
+        template: &str,
+        result: &Result<DeliveryReceipt, DeliveryError>,
+    ) -> Self {
+        let now = Utc::now();
+        let (message_id, status, error) = match result {
+            Ok(receipt) => (Some(receipt.message_id.clone()), DeliveryStatus::Sent, None),
+            Err(e) => (None, DeliveryStatus::Failed, Some(e.to_string())),
+        };
+
+        DeliveryRecord {
+            id: lib_common::uuid::Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            channel,
+            recipient: recipient.to_string(),
+            template: template.to_string(),
+            message_id,
+            status,
+            error,
+            parcel_id: None,
+            itinerary_id: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+impl DeliveryRecord {
+    /// Creates a record for a notification accepted from the notification queue.
+    /// The recipient is filled in once the notification is sent.
+    pub fn queued(id: &str, channel: Channel, template: &str) -> Self {
+        let now = Utc::now();
+        DeliveryRecord {
+            id: id.to_string(),
+            user_id: String::new(),
+            channel,
+            recipient: String::new(),
+            template: template.to_string(),
+            message_id: None,
+            status: DeliveryStatus::Queued,
+            error: None,
+            parcel_id: None,
+            itinerary_id: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Returns true if no further status is expected.
+    /// Text messages stay sent, the SMS provider doesn't report their delivery.
+    pub fn is_final(&self) -> bool {
+        self.status.is_final()
+            || (self.channel == Channel::Sms && self.status == DeliveryStatus::Sent)
+    }
+}
+
+/// Criteria to search delivery records, all provided criteria must match
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeliveryQuery {
+    /// Only records of this user
+    pub user_id: Option<String>,
+
+    /// Only records related to this itinerary
+    pub itinerary_id: Option<String>,
+
+    /// Only records created at or after this time
+    pub from: Option<DateTime<Utc>>,
+
+    /// Only records created at or before this time
+    pub to: Option<DateTime<Utc>>,
+
+    /// Maximum number of records, newest first
+    pub limit: usize,
+}
+
+/// Keeps delivery records in a store, indexed by user, itinerary and time
+#[derive(Debug, Clone, Copy)]
+pub struct DeliveryLog<'a> {
+    store: &'a dyn Store,
+    retention: Duration,
+}
+
+impl<'a> DeliveryLog<'a> {
+    /// Keeps records in `store` for `retention`
+    pub fn new(store: &'a dyn Store, retention: Duration) -> Self {
+        DeliveryLog { store, retention }
+    }
+
+    fn record_key(id: &str) -> String {
+        store::key(NAMESPACE, id)
+    }
+
+    fn index_key(index: &str) -> String {
+        store::key(INDEX_NAMESPACE, index)
+    }
+
+    fn message_key(message_id: &str) -> String {
+        store::key(MESSAGE_NAMESPACE, message_id)
+    }
+
+    /// Indexes a record belongs to
+    fn indexes(record: &DeliveryRecord) -> Vec<String> {
+        let mut indexes = vec![
+            Self::index_key("all"),
+            Self::index_key(&format!("user:{}", record.user_id)),
+        ];
+
+        if let Some(itinerary_id) = &record.itinerary_id {
+            indexes.push(Self::index_key(&format!("itinerary:{}", itinerary_id)));
+        }
+
+        indexes
+    }
+
+    /// Index to search for a query, the most selective one available
+    fn query_index(query: &DeliveryQuery) -> String {
+        match (&query.user_id, &query.itinerary_id) {
+            (_, Some(itinerary_id)) => Self::index_key(&format!("itinerary:{}", itinerary_id)),
+            (Some(user_id), None) => Self::index_key(&format!("user:{}", user_id)),
+            (None, None) => Self::index_key("all"),
+        }
+    }
+
+    /// Writes a record without updating the indexes
+    async fn save(&self, record: &DeliveryRecord) -> Result<(), StoreError> {
+        let value = serde_json::to_string(record).map_err(|e| StoreError::Value(e.to_string()))?;
+        self.store
+            .set(&Self::record_key(&record.id), &value, Some(self.retention))
+            .await
+    }
+
+    /// Stores a new record, or replaces an existing record with the same ID
+    pub async fn record(&self, record: &DeliveryRecord) -> Result<(), StoreError> {
+        self.save(record).await?;
+
+        if let Some(message_id) = &record.message_id {
+            self.store
+                .set(
+                    &Self::message_key(message_id),
+                    &record.id,
+                    Some(self.retention),
+                )
+                .await?;
+        }
+
+        // records expire after the retention, so do their index entries
+        let score = record.created_at.timestamp_millis();
+        let expired = Utc::now().timestamp_millis() - self.retention.as_millis() as i64;
+        for index in Self::indexes(record) {
+            self.store.zadd(&index, score, &record.id).await?;
+            self.store
+                .zrem_range_by_score(&index, i64::MIN, expired)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Stores a queued record unless a record with its ID exists, e.g. when
+    /// the job was delivered again. Queued records aren't indexed, the record
+    /// of the sent notification replaces them under the same ID.
+    /// Returns true if the record was stored.
+    pub async fn record_queued(&self, record: &DeliveryRecord) -> Result<bool, StoreError> {
+        let value = serde_json::to_string(record).map_err(|e| StoreError::Value(e.to_string()))?;
+        self.store
+            .set_nx(&Self::record_key(&record.id), &value, Some(self.retention))
+            .await
+    }
+
+    /// Finishes a record that is still queued once its job is done without
+    /// sending a notification under its ID: it fails with the error of the
+    /// job, or is removed when the job succeeded, e.g. as the notification
+    /// was sent before.
+    pub async fn finish_queued(&self, id: &str, error: Option<String>) -> Result<(), StoreError> {
+        let Some(mut record) = self.get(id).await? else {
+            return Ok(());
+        };
+
+        if record.status != DeliveryStatus::Queued {
+            return Ok(());
+        }
+
+        match error {
+            Some(error) => {
+                record.status = DeliveryStatus::Failed;
+                record.error = Some(error);
+                record.updated_at = Utc::now();
+                self.save(&record).await
+            }
+            None => self.store.del(&Self::record_key(id)).await,
+        }
+    }
+
+    /// Returns a record by ID, if present
+    pub async fn get(&self, id: &str) -> Result<Option<DeliveryRecord>, StoreError> {
+        let Some(value) = self.store.get(&Self::record_key(id)).await? else {
+            return Ok(None);
+        };
+
+        serde_json::from_str(&value)
+            .map(Some)
+            .map_err(|e| StoreError::Value(e.to_string()))
+    }
+
+    /// Returns the record of a provider message ID, if present
+    pub async fn find_by_message_id(
+        &self,
+        message_id: &str,
+    ) -> Result<Option<DeliveryRecord>, StoreError> {
+        match self.store.get(&Self::message_key(message_id)).await? {
+            Some(id) => self.get(&id).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Updates the status of the record of a provider message ID.
+    /// Returns the updated record, or None if the message ID is unknown.
+    pub async fn update_status(
+        &self,
+        message_id: &str,
+        status: DeliveryStatus,
+        error: Option<String>,
+    ) -> Result<Option<DeliveryRecord>, StoreError> {
+        let Some(mut record) = self.find_by_message_id(message_id).await? else {
+            return Ok(None);
+        };
+
+        record.status = status;
+        record.error = error;
+        record.updated_at = Utc::now();
+        self.save(&record).await?;
+
+        Ok(Some(record))
+    }
+
+    /// Returns the records matching the query, newest first.
+    ///
+    /// The index is read a page of `limit` IDs at a time, so only the
+    /// newest records are loaded. Further pages are only read when records
+    /// expired or don't match the user of an itinerary query.
+    pub async fn query(&self, query: &DeliveryQuery) -> Result<Vec<DeliveryRecord>, StoreError> {
+        let min = query.from.map_or(i64::MIN, |from| from.timestamp_millis());
+        let max = query.to.map_or(i64::MAX, |to| to.timestamp_millis());
+        let index = Self::query_index(query);
+
+        let mut records = vec![];
+        let mut offset = 0;
+        while records.len() < query.limit {
+            let ids = self
+                .store
+                .zrevrange_by_score(&index, min, max, offset, query.limit)
+                .await?;
+            offset += ids.len();
+
+            for id in &ids {
+                if records.len() >= query.limit {
+                    break;
+                }
+
+                let Some(record) = self.get(id).await? else {
+                    continue;
+                };
+
+                if query
+                    .user_id
+                    .as_ref()
+                    .is_some_and(|user_id| *user_id != record.user_id)
+                {
+                    continue;
+                }
+
+                records.push(record);
+            }
+
+            if ids.len() < query.limit {
+                break;
+            }
+        }
+
+        Ok(records)
+    }
+}
+
+/// Returns DELIVERY_LOG, kept in the shared store with the retention of a
+/// Config object generated from environment variables.
+/// Initializes DELIVERY_LOG if it hasn't been initialized yet.
+pub async fn get_log() -> Result<&'static DeliveryLog<'static>, StoreError> {
+    DELIVERY_LOG
+        .get_or_try_init(|| async move {
+            let config = Config::try_from_env().unwrap_or_default();
+            let retention = Duration::from_secs(config.delivery_log_retention_days * 24 * 60 * 60);
+            let store = store::get_store().await?;
+            if !store.persistent() {
+                delivery_error!(
+                    "delivery log kept in a {} store, it is lost on restart \
+                    and not shared between instances.",
+                    store.name()
+                );
+            }
+            Ok::<_, StoreError>(DeliveryLog::new(store, retention))
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::memory::MemoryStore;
+    use chrono::TimeZone;
+
+    fn record(user_id: &str, itinerary_id: Option<&str>, minute: u32) -> DeliveryRecord {
+        let receipt = Ok(DeliveryReceipt {
+            message_id: format!("{}-{}", user_id, minute),
+        });
+
+        let mut record = DeliveryRecord::new(
+            user_id,
+            Channel::Email,
+            "alice@aetheric.nl",
+            "cargo-confirmation",
+            &receipt,
+        );
+        record.itinerary_id = itinerary_id.map(str::to_string);
+        record.created_at = Utc.with_ymd_and_hms(2024, 1, 1, 10, minute, 0).unwrap();
+        record
+    }
+
+    fn minutes(records: &[DeliveryRecord]) -> Vec<String> {
+        records
+            .iter()
+            .map(|record| record.message_id.clone().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_delivery_status_is_final() {
+        assert!(!DeliveryStatus::Queued.is_final());
+        assert!(!DeliveryStatus::Sent.is_final());
+        assert!(DeliveryStatus::Failed.is_final());
+        assert!(DeliveryStatus::Delivered.is_final());
+        assert!(DeliveryStatus::Bounced.is_final());
+        assert!(DeliveryStatus::Complained.is_final());
+    }
+
+    #[test]
+    fn test_delivery_record_new() {
+        let record = DeliveryRecord::new(
+            "user",
+            Channel::Sms,
+            "+31611111111",
+            "cargo-confirmation",
+            &Err(DeliveryError::Transport("timeout".to_string())),
+        );
+        assert_eq!(record.status, DeliveryStatus::Failed);
+        assert_eq!(record.message_id, None);
+        assert_eq!(record.error, Some("Transport error: timeout".to_string()));
+        assert_eq!(record.created_at, record.updated_at);
+        lib_common::uuid::to_uuid(&record.id).unwrap();
+
+        let mut record = DeliveryRecord::new(
+            "user",
+            Channel::Email,
+            "alice@aetheric.nl",
+            "cargo-confirmation",
+            &Ok(DeliveryReceipt {
+                message_id: "id".to_string(),
+            }),
+        );
+        assert_eq!(record.status, DeliveryStatus::Sent);
+        assert_eq!(record.message_id, Some("id".to_string()));
+        assert_eq!(record.error, None);
+        assert!(!record.is_final());
+
+        // no delivery reports are received for text messages
+        record.channel = Channel::Sms;
+        assert!(record.is_final());
+
+        let record = DeliveryRecord::queued("job", Channel::Sms, "cargo-confirmation");
+        assert_eq!(record.id, "job");
+        assert_eq!(record.status, DeliveryStatus::Queued);
+        assert!(!record.is_final());
+    }
+
+    #[tokio::test]
+    async fn test_delivery_log_queued() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let log = DeliveryLog::new(&store, Duration::from_secs(60));
+        let queued = DeliveryRecord::queued("job-1", Channel::Email, "cargo-confirmation");
+        assert!(log.record_queued(&queued).await.unwrap());
+
+        // a redelivered job keeps its record
+        let mut sent = record("alice", None, 0);
+        sent.id = "job-1".to_string();
+        log.record(&sent).await.unwrap();
+        assert!(!log.record_queued(&queued).await.unwrap());
+        assert_eq!(log.get("job-1").await.unwrap().unwrap(), sent);
+
+        // a sent record is not finished again
+        log.finish_queued("job-1", Some("failed".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(log.get("job-1").await.unwrap().unwrap(), sent);
+
+        let queued = DeliveryRecord::queued("job-2", Channel::Email, "cargo-confirmation");
+        log.record_queued(&queued).await.unwrap();
+        log.finish_queued("job-2", Some("Parcel data not found".to_string()))
+            .await
+            .unwrap();
+        let failed = log.get("job-2").await.unwrap().unwrap();
+        assert_eq!(failed.status, DeliveryStatus::Failed);
+        assert_eq!(failed.error.as_deref(), Some("Parcel data not found"));
+
+        let queued = DeliveryRecord::queued("job-3", Channel::Email, "cargo-confirmation");
+        log.record_queued(&queued).await.unwrap();
+        log.finish_queued("job-3", None).await.unwrap();
+        assert_eq!(log.get("job-3").await.unwrap(), None);
+
+        // queued records are only found by ID
+        let query = DeliveryQuery {
+            limit: 10,
+            ..Default::default()
+        };
+        assert_eq!(minutes(&log.query(&query).await.unwrap()), ["alice-0"]);
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_delivery_log() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let log = DeliveryLog::new(&store, Duration::from_secs(100 * 365 * 24 * 60 * 60));
+
+        let records = [
+            record("alice", Some("trip-1"), 0),
+            record("alice", Some("trip-2"), 10),
+            record("bob", Some("trip-1"), 20),
+            record("alice", None, 30),
+        ];
+        for record in &records {
+            log.record(record).await.unwrap();
+        }
+
+        assert_eq!(log.get(&records[0].id).await.unwrap().unwrap(), records[0]);
+        assert_eq!(log.get("unknown").await.unwrap(), None);
+
+        let query = DeliveryQuery {
+            limit: 10,
+            ..Default::default()
+        };
+        let found = log.query(&query).await.unwrap();
+        assert_eq!(
+            minutes(&found),
+            ["alice-30", "bob-20", "alice-10", "alice-0"]
+        );
+
+        let found = log
+            .query(&DeliveryQuery {
+                user_id: Some("alice".to_string()),
+                ..query.clone()
+            })
+            .await
+            .unwrap();
+        assert_eq!(minutes(&found), ["alice-30", "alice-10", "alice-0"]);
+
+        let found = log
+            .query(&DeliveryQuery {
+                itinerary_id: Some("trip-1".to_string()),
+                ..query.clone()
+            })
+            .await
+            .unwrap();
+        assert_eq!(minutes(&found), ["bob-20", "alice-0"]);
+
+        let found = log
+            .query(&DeliveryQuery {
+                user_id: Some("alice".to_string()),
+                itinerary_id: Some("trip-1".to_string()),
+                ..query.clone()
+            })
+            .await
+            .unwrap();
+        assert_eq!(minutes(&found), ["alice-0"]);
+
+        let found = log
+            .query(&DeliveryQuery {
+                from: Some(Utc.with_ymd_and_hms(2024, 1, 1, 10, 10, 0).unwrap()),
+                to: Some(Utc.with_ymd_and_hms(2024, 1, 1, 10, 20, 0).unwrap()),
+                ..query.clone()
+            })
+            .await
+            .unwrap();
+        assert_eq!(minutes(&found), ["bob-20", "alice-10"]);
+
+        let found = log
+            .query(&DeliveryQuery {
+                limit: 1,
+                ..query.clone()
+            })
+            .await
+            .unwrap();
+        assert_eq!(minutes(&found), ["alice-30"]);
+
+        // the newest record of the itinerary is bob's, so a second page is read
+        let found = log
+            .query(&DeliveryQuery {
+                user_id: Some("alice".to_string()),
+                itinerary_id: Some("trip-1".to_string()),
+                limit: 1,
+                ..query
+            })
+            .await
+            .unwrap();
+        assert_eq!(minutes(&found), ["alice-0"]);
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_delivery_log_update_status() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let log = DeliveryLog::new(&store, Duration::from_secs(100 * 365 * 24 * 60 * 60));
+
+        let record = record("alice", Some("trip-1"), 0);
+        log.record(&record).await.unwrap();
+
+        let found = log.find_by_message_id("alice-0").await.unwrap().unwrap();
+        assert_eq!(found, record);
+        assert_eq!(log.find_by_message_id("unknown").await.unwrap(), None);
+
+        let updated = log
+            .update_status(
+                "alice-0",
+                DeliveryStatus::Bounced,
+                Some("Unknown user".to_string()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.id, record.id);
+        assert_eq!(updated.status, DeliveryStatus::Bounced);
+        assert_eq!(updated.error, Some("Unknown user".to_string()));
+        assert!(updated.updated_at >= record.updated_at);
+        assert_eq!(log.get(&record.id).await.unwrap().unwrap(), updated);
+
+        let none = log
+            .update_status("unknown", DeliveryStatus::Delivered, None)
+            .await
+            .unwrap();
+        assert_eq!(none, None);
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_delivery_log_retention() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let log = DeliveryLog::new(&store, Duration::from_secs(60 * 60));
+
+        let old = record("alice", None, 0);
+        let mut new = record("alice", None, 1);
+        new.created_at = Utc::now();
+
+        log.record(&old).await.unwrap();
+        log.record(&new).await.unwrap();
+
+        let found = log
+            .query(&DeliveryQuery {
+                limit: 10,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(found, vec![new]);
+
+        ut_info!("Success.");
+    }
+}
@@ -0,0 +1,130 @@
+//! Delivery
+//! provides pluggable backends to deliver notifications to users
+
+#[macro_use]
+pub mod macros;
+pub mod email;
+pub mod history;
+pub mod preferences;
+pub mod retry;
+pub mod sms;
+pub mod suppression;
+pub mod unsubscribe;
+pub mod verification;
+
+use std::fmt::{self, Display, Formatter};
+
+/// Receipt returned by a backend after a message was accepted for delivery
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeliveryReceipt {
+    /// Message ID assigned by the provider
+    pub message_id: String,
+}
+
+/// Errors reported by delivery backends
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeliveryError {
+    /// The backend is missing configuration or is misconfigured
+    Configuration(String),
+
+    /// The message could not be handed over to the provider
+    Transport(String),
+
+    /// The message is invalid and can't be delivered as composed
+    Message(String),
+
+    /// The provider is temporarily unable to accept the message,
+    /// e.g. during maintenance or when rate limiting
+    Unavailable(String),
+
+    /// The provider received the message but its answer could not be read,
+    /// so the message may have been sent and is not sent again
+    Unconfirmed(String),
+
+    /// The provider refused the message
+    Provider {
+        /// Provider specific error code
+        code: i64,
+
+        /// Provider supplied error message
+        message: String,
+    },
+}
+
+impl DeliveryError {
+    /// Returns true if sending the same message again may succeed
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            DeliveryError::Transport(_) | DeliveryError::Unavailable(_)
+        )
+    }
+}
+
+impl std::error::Error for DeliveryError {}
+
+impl Display for DeliveryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DeliveryError::Configuration(e) => write!(f, "Invalid configuration: {}", e),
+            DeliveryError::Transport(e) => write!(f, "Transport error: {}", e),
+            DeliveryError::Message(e) => write!(f, "Invalid message: {}", e),
+            DeliveryError::Unavailable(e) => write!(f, "Provider unavailable: {}", e),
+            DeliveryError::Unconfirmed(e) => write!(f, "Delivery not confirmed: {}", e),
+            DeliveryError::Provider { code, message } => {
+                write!(f, "Provider error {}: {}", code, message)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delivery_error_display() {
+        assert_eq!(
+            DeliveryError::Configuration("no token".to_string()).to_string(),
+            "Invalid configuration: no token"
+        );
+        assert_eq!(
+            DeliveryError::Transport("timeout".to_string()).to_string(),
+            "Transport error: timeout"
+        );
+        assert_eq!(
+            DeliveryError::Message("no recipient".to_string()).to_string(),
+            "Invalid message: no recipient"
+        );
+        assert_eq!(
+            DeliveryError::Unavailable("rate limited".to_string()).to_string(),
+            "Provider unavailable: rate limited"
+        );
+        assert_eq!(
+            DeliveryError::Unconfirmed("invalid response".to_string()).to_string(),
+            "Delivery not confirmed: invalid response"
+        );
+        assert_eq!(
+            DeliveryError::Provider {
+                code: 406,
+                message: "Inactive recipient".to_string()
+            }
+            .to_string(),
+            "Provider error 406: Inactive recipient"
+        );
+    }
+
+    #[test]
+    fn test_delivery_error_is_retryable() {
+        assert!(DeliveryError::Transport("timeout".to_string()).is_retryable());
+        assert!(DeliveryError::Unavailable("maintenance".to_string()).is_retryable());
+        assert!(!DeliveryError::Configuration("no token".to_string()).is_retryable());
+        assert!(!DeliveryError::Message("no recipient".to_string()).is_retryable());
+        assert!(!DeliveryError::Unconfirmed("invalid response".to_string()).is_retryable());
+        assert!(!DeliveryError::Provider {
+            code: 406,
+            message: "Inactive recipient".to_string()
+        }
+        .is_retryable());
+    }
+}
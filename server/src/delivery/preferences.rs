@@ -0,0 +1,519 @@
+//! Notification preferences of users:
+//! enabled channels, language, timezone and quiet hours
+
+use crate::locale::{is_language_tag, Locale};
+use crate::store::{self, Store, StoreError};
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+
+/// Namespace of user preferences in the store
+const NAMESPACE: &str = "preferences";
+
+/// What a notification is about, which decides what a user can opt out of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    /// Messages about the user's own bookings, e.g. confirmations.
+    /// They are sent even when the user opted out of marketing.
+    Transactional,
+
+    /// Promotional messages, only sent to users who didn't opt out
+    Marketing,
+}
+
+/// Channels a notification is sent through
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Channels {
+    /// Send an email
+    pub email: bool,
+
+    /// Send a text message
+    pub sms: bool,
+}
+
+impl Channels {
+    /// Email only, the channel of notifications without a text message part
+    pub const EMAIL: Channels = Channels {
+        email: true,
+        sms: false,
+    };
+}
+
+/// Daily period in which no text messages or marketing are sent,
+/// in the timezone of the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuietHours {
+    /// Start of the quiet hours
+    pub start: NaiveTime,
+
+    /// End of the quiet hours, before the start when they span midnight
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    /// Returns true if `time` falls within the quiet hours
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// Invalid preferences
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PreferenceError {
+    /// The language is not a language tag, e.g. `nl-BE`
+    Language(String),
+
+    /// The timezone is not a known IANA timezone, e.g. `Europe/Amsterdam`
+    Timezone(String),
+
+    /// The quiet hours are invalid
+    QuietHours(String),
+}
+
+impl std::error::Error for PreferenceError {}
+
+impl Display for PreferenceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PreferenceError::Language(e) => write!(f, "Invalid language tag: {}", e),
+            PreferenceError::Timezone(e) => write!(f, "Unknown timezone: {}", e),
+            PreferenceError::QuietHours(e) => write!(f, "Invalid quiet hours: {}", e),
+        }
+    }
+}
+
+/// Why the preferences of a user don't allow a notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptOut {
+    /// The user opted out of marketing
+    Marketing,
+
+    /// Marketing isn't sent during the quiet hours of the user
+    QuietHours,
+
+    /// The user disabled every requested channel
+    Channels,
+}
+
+impl Display for OptOut {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            OptOut::Marketing => write!(f, "User opted out of marketing"),
+            OptOut::QuietHours => write!(f, "Within the quiet hours of the user"),
+            OptOut::Channels => write!(f, "User disabled the requested channels"),
+        }
+    }
+}
+
+/// Notification preferences of a user.
+/// Users without stored preferences get every channel, in English and UTC.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    /// Emails are enabled
+    pub email: bool,
+
+    /// Text messages are enabled
+    pub sms: bool,
+
+    /// Marketing is enabled
+    pub marketing: bool,
+
+    /// Preferred language, as a language tag
+    pub language: Option<String>,
+
+    /// Preferred timezone, as an IANA timezone name
+    pub timezone: Option<String>,
+
+    /// Daily period without text messages and marketing
+    pub quiet_hours: Option<QuietHours>,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Preferences {
+            email: true,
+            sms: true,
+            marketing: true,
+            language: None,
+            timezone: None,
+            quiet_hours: None,
+        }
+    }
+}
+
+impl Preferences {
+    /// Checks the language, timezone and quiet hours
+    pub fn validate(&self) -> Result<(), PreferenceError> {
+        if let Some(language) = &self.language {
+            if !is_language_tag(language) {
+                return Err(PreferenceError::Language(language.clone()));
+            }
+        }
+
+        if let Some(timezone) = &self.timezone {
+            timezone
+                .parse::<Tz>()
+                .map_err(|_| PreferenceError::Timezone(timezone.clone()))?;
+        }
+
+        if let Some(quiet_hours) = &self.quiet_hours {
+            if quiet_hours.start == quiet_hours.end {
+                return Err(PreferenceError::QuietHours(
+                    "start and end are the same".to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Locale of the user, the default language if none or an invalid language is set
+    pub fn locale(&self) -> Locale {
+        Locale::resolve(None, self.language.as_deref())
+    }
+
+    /// Timezone of the user, UTC if none or an unknown timezone is set
+    pub fn tz(&self) -> Tz {
+        self.timezone
+            .as_deref()
+            .and_then(|timezone| timezone.parse().ok())
+            .unwrap_or(Tz::UTC)
+    }
+
+    /// Returns true if `now` falls within the quiet hours of the user
+    pub fn is_quiet(&self, now: DateTime<Utc>) -> bool {
+        self.quiet_hours
+            .is_some_and(|quiet_hours| quiet_hours.contains(now.with_timezone(&self.tz()).time()))
+    }
+
+    /// Returns which of the `requested` channels a notification may be sent through.
+    ///
+    /// Marketing is refused when the user opted out of it, or during the quiet
+    /// hours. No text messages are sent during the quiet hours.
+    /// A transactional message is never refused: when the user disabled every
+    /// requested channel, it is sent by email if requested, by SMS otherwise.
+    pub fn channels(
+        &self,
+        kind: MessageKind,
+        requested: Channels,
+        now: DateTime<Utc>,
+    ) -> Result<Channels, OptOut> {
+        let quiet = self.is_quiet(now);
+        if kind == MessageKind::Marketing {
+            if !self.marketing {
+                return Err(OptOut::Marketing);
+            }
+
+            if quiet {
+                return Err(OptOut::QuietHours);
+            }
+        }
+
+        let allowed = Channels {
+            email: requested.email && self.email,
+            sms: requested.sms && self.sms && !quiet,
+        };
+        if allowed.email || allowed.sms {
+            return Ok(allowed);
+        }
+
+        match kind {
+            MessageKind::Transactional => Ok(Channels {
+                email: requested.email,
+                sms: !requested.email && requested.sms,
+            }),
+            MessageKind::Marketing => Err(OptOut::Channels),
+        }
+    }
+}
+
+/// Keeps the preferences of users in a store.
+/// Preferences don't expire, they are removed explicitly.
+#[derive(Debug, Clone, Copy)]
+pub struct PreferenceStore<'a> {
+    store: &'a dyn Store,
+}
+
+impl<'a> PreferenceStore<'a> {
+    /// Keeps preferences in `store`
+    pub fn new(store: &'a dyn Store) -> Self {
+        PreferenceStore { store }
+    }
+
+    /// Returns the preferences of a user, if any were set
+    pub async fn get(&self, user_id: &str) -> Result<Option<Preferences>, StoreError> {
+        let Some(value) = self.store.get(&store::key(NAMESPACE, user_id)).await? else {
+            return Ok(None);
+        };
+
+        serde_json::from_str(&value)
+            .map(Some)
+            .map_err(|e| StoreError::Value(e.to_string()))
+    }
+
+    /// Sets the preferences of a user
+    pub async fn set(&self, user_id: &str, preferences: &Preferences) -> Result<(), StoreError> {
+        let value =
+            serde_json::to_string(preferences).map_err(|e| StoreError::Value(e.to_string()))?;
+        self.store
+            .set(&store::key(NAMESPACE, user_id), &value, None)
+            .await
+    }
+
+    /// Removes the preferences of a user, reverting to the defaults
+    pub async fn remove(&self, user_id: &str) -> Result<(), StoreError> {
+        self.store.del(&store::key(NAMESPACE, user_id)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::memory::MemoryStore;
+
+    const BOTH: Channels = Channels {
+        email: true,
+        sms: true,
+    };
+
+    fn time(value: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(value, "%H:%M").unwrap()
+    }
+
+    fn at(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().into()
+    }
+
+    fn quiet(start: &str, end: &str) -> QuietHours {
+        QuietHours {
+            start: time(start),
+            end: time(end),
+        }
+    }
+
+    #[test]
+    fn test_quiet_hours_contains() {
+        let night = quiet("22:00", "07:00");
+        assert!(night.contains(time("22:00")));
+        assert!(night.contains(time("23:59")));
+        assert!(night.contains(time("00:00")));
+        assert!(night.contains(time("06:59")));
+        assert!(!night.contains(time("07:00")));
+        assert!(!night.contains(time("12:00")));
+
+        let lunch = quiet("12:00", "13:00");
+        assert!(lunch.contains(time("12:30")));
+        assert!(!lunch.contains(time("13:00")));
+        assert!(!lunch.contains(time("11:59")));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Preferences::default().validate(), Ok(()));
+
+        let mut preferences = Preferences {
+            language: Some("nl-BE".to_string()),
+            timezone: Some("Europe/Amsterdam".to_string()),
+            quiet_hours: Some(quiet("22:00", "07:00")),
+            ..Default::default()
+        };
+        assert_eq!(preferences.validate(), Ok(()));
+
+        for language in ["zh-Hant-TW", "en", "fil"] {
+            preferences.language = Some(language.to_string());
+            assert_eq!(preferences.validate(), Ok(()));
+        }
+
+        for language in ["", "dutch", "n", "nl_BE", "nl-", "nl-BE!"] {
+            preferences.language = Some(language.to_string());
+            assert_eq!(
+                preferences.validate(),
+                Err(PreferenceError::Language(language.to_string()))
+            );
+        }
+        preferences.language = None;
+
+        preferences.timezone = Some("Europe/Nowhere".to_string());
+        assert_eq!(
+            preferences.validate().unwrap_err().to_string(),
+            "Unknown timezone: Europe/Nowhere"
+        );
+        preferences.timezone = None;
+
+        preferences.quiet_hours = Some(quiet("22:00", "22:00"));
+        assert_eq!(
+            preferences.validate().unwrap_err().to_string(),
+            "Invalid quiet hours: start and end are the same"
+        );
+    }
+
+    #[test]
+    fn test_tz() {
+        let mut preferences = Preferences::default();
+        assert_eq!(preferences.tz(), Tz::UTC);
+
+        preferences.timezone = Some("Europe/Amsterdam".to_string());
+        assert_eq!(preferences.tz(), Tz::Europe__Amsterdam);
+
+        preferences.timezone = Some("Europe/Nowhere".to_string());
+        assert_eq!(preferences.tz(), Tz::UTC);
+    }
+
+    #[test]
+    fn test_locale() {
+        let mut preferences = Preferences::default();
+        assert_eq!(preferences.locale().tag(), "en");
+
+        preferences.language = Some("nl-be".to_string());
+        assert_eq!(preferences.locale().tag(), "nl-BE");
+
+        preferences.language = Some("dutch".to_string());
+        assert_eq!(preferences.locale().tag(), "en");
+    }
+
+    #[test]
+    fn test_is_quiet() {
+        let mut preferences = Preferences {
+            quiet_hours: Some(quiet("22:00", "07:00")),
+            ..Default::default()
+        };
+        assert!(preferences.is_quiet(at("2024-06-01T23:00:00Z")));
+        assert!(!preferences.is_quiet(at("2024-06-01T21:00:00Z")));
+
+        // 21:00 UTC is 23:00 in Amsterdam during summer time
+        preferences.timezone = Some("Europe/Amsterdam".to_string());
+        assert!(preferences.is_quiet(at("2024-06-01T21:00:00Z")));
+        assert!(!preferences.is_quiet(at("2024-06-01T05:00:00Z")));
+
+        preferences.quiet_hours = None;
+        assert!(!preferences.is_quiet(at("2024-06-01T21:00:00Z")));
+    }
+
+    #[test]
+    fn test_channels() {
+        let day = at("2024-06-01T12:00:00Z");
+        let night = at("2024-06-01T23:00:00Z");
+        let email = Channels::EMAIL;
+        let sms = Channels {
+            email: false,
+            sms: true,
+        };
+
+        let mut preferences = Preferences::default();
+        for kind in [MessageKind::Transactional, MessageKind::Marketing] {
+            assert_eq!(preferences.channels(kind, BOTH, day), Ok(BOTH));
+            assert_eq!(preferences.channels(kind, email, day), Ok(email));
+        }
+
+        // opting out of marketing doesn't stop transactional messages
+        preferences.marketing = false;
+        assert_eq!(
+            preferences.channels(MessageKind::Marketing, BOTH, day),
+            Err(OptOut::Marketing)
+        );
+        assert_eq!(
+            preferences.channels(MessageKind::Transactional, BOTH, day),
+            Ok(BOTH)
+        );
+
+        // no text messages or marketing during the quiet hours
+        preferences.marketing = true;
+        preferences.quiet_hours = Some(quiet("22:00", "07:00"));
+        assert_eq!(
+            preferences.channels(MessageKind::Marketing, BOTH, night),
+            Err(OptOut::QuietHours)
+        );
+        assert_eq!(
+            preferences.channels(MessageKind::Transactional, BOTH, night),
+            Ok(email)
+        );
+        assert_eq!(
+            preferences.channels(MessageKind::Transactional, BOTH, day),
+            Ok(BOTH)
+        );
+        preferences.quiet_hours = None;
+
+        // disabled channels are left out
+        preferences.sms = false;
+        assert_eq!(
+            preferences.channels(MessageKind::Marketing, BOTH, day),
+            Ok(email)
+        );
+        assert_eq!(
+            preferences.channels(MessageKind::Marketing, sms, day),
+            Err(OptOut::Channels)
+        );
+
+        // transactional messages fall back to a requested channel
+        preferences.email = false;
+        assert_eq!(
+            preferences.channels(MessageKind::Transactional, BOTH, day),
+            Ok(email)
+        );
+        assert_eq!(
+            preferences.channels(MessageKind::Transactional, sms, day),
+            Ok(sms)
+        );
+        assert_eq!(
+            preferences.channels(MessageKind::Marketing, BOTH, day),
+            Err(OptOut::Channels)
+        );
+    }
+
+    #[test]
+    fn test_opt_out_display() {
+        assert_eq!(OptOut::Marketing.to_string(), "User opted out of marketing");
+        assert_eq!(
+            OptOut::QuietHours.to_string(),
+            "Within the quiet hours of the user"
+        );
+        assert_eq!(
+            OptOut::Channels.to_string(),
+            "User disabled the requested channels"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_preference_store() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let preferences = PreferenceStore::new(&store);
+        assert_eq!(preferences.get("user").await.unwrap(), None);
+
+        let stored = Preferences {
+            sms: false,
+            language: Some("nl".to_string()),
+            quiet_hours: Some(quiet("22:00", "07:00")),
+            ..Default::default()
+        };
+        preferences.set("user", &stored).await.unwrap();
+        assert_eq!(preferences.get("user").await.unwrap(), Some(stored));
+        assert_eq!(preferences.get("other").await.unwrap(), None);
+
+        preferences.remove("user").await.unwrap();
+        assert_eq!(preferences.get("user").await.unwrap(), None);
+
+        // preferences stored before a field was added get its default
+        store
+            .set(&store::key(NAMESPACE, "old"), r#"{"sms":false}"#, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            preferences.get("old").await.unwrap(),
+            Some(Preferences {
+                sms: false,
+                ..Default::default()
+            })
+        );
+
+        ut_info!("Success.");
+    }
+}
@@ -0,0 +1,203 @@
+//! Retries transient delivery failures with exponential backoff and jitter
+
+use super::{DeliveryError, DeliveryReceipt};
+use crate::Config;
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+/// Retry policy shared by all handlers
+static RETRY_POLICY: OnceCell<RetryPolicy> = OnceCell::const_new();
+
+/// Bounds on how often and how quickly a delivery is retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one
+    pub max_attempts: u32,
+
+    /// Delay before the first retry, doubled for every following retry
+    pub base_delay: Duration,
+
+    /// Upper bound for the delay between two attempts
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Creates a policy from the `retry_*` configuration options
+    pub fn new(config: &Config) -> Self {
+        RetryPolicy {
+            max_attempts: config.retry_max_attempts.max(1),
+            base_delay: Duration::from_millis(config.retry_base_delay_ms),
+            max_delay: Duration::from_millis(config.retry_max_delay_ms),
+        }
+    }
+
+    /// Upper bound of the delay after the given (1 based) failed attempt
+    pub fn max_backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+
+    /// Delay after the given failed attempt.
+    /// Half of the delay is random so that callers failing at the same
+    /// moment don't all retry at the same moment.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let half = self.max_backoff(attempt) / 2;
+        let jitter = rand::thread_rng().gen_range(0..=half.as_millis() as u64);
+        half + Duration::from_millis(jitter)
+    }
+}
+
+/// Final result of a delivery, including retries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeliveryOutcome {
+    /// Result of the last attempt
+    pub result: Result<DeliveryReceipt, DeliveryError>,
+
+    /// Number of attempts made, 0 if the message was never handed to the provider
+    pub attempts: u32,
+}
+
+impl DeliveryOutcome {
+    /// True if the delivery was attempted more than once
+    pub fn retried(&self) -> bool {
+        self.attempts > 1
+    }
+}
+
+/// Runs a delivery operation, retrying retryable errors as allowed by the policy
+pub async fn retry<F, Fut>(policy: &RetryPolicy, mut operation: F) -> DeliveryOutcome
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<DeliveryReceipt, DeliveryError>>,
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let result = operation().await;
+
+        match &result {
+            Err(e) if e.is_retryable() && attempts < policy.max_attempts => {
+                let delay = policy.backoff(attempts);
+                delivery_warn!(
+                    "attempt {} of {} failed, retrying in {:?}: {}",
+                    attempts,
+                    policy.max_attempts,
+                    delay,
+                    e
+                );
+                tokio::time::sleep(delay).await;
+            }
+            _ => return DeliveryOutcome { result, attempts },
+        }
+    }
+}
+
+/// Returns RETRY_POLICY, created from a Config object generated from
+/// environment variables on first use.
+pub async fn get_policy() -> &'static RetryPolicy {
+    RETRY_POLICY
+        .get_or_init(|| async move {
+            let config = Config::try_from_env().unwrap_or_default();
+            RetryPolicy::new(&config)
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_millis(2),
+            max_delay: Duration::from_millis(5),
+        }
+    }
+
+    /// Fails with the given errors in order, then succeeds
+    async fn run(policy: &RetryPolicy, errors: Vec<DeliveryError>) -> DeliveryOutcome {
+        let calls = AtomicU32::new(0);
+        retry(policy, || {
+            let call = calls.fetch_add(1, Ordering::SeqCst) as usize;
+            let result = match errors.get(call) {
+                Some(e) => Err(e.clone()),
+                None => Ok(DeliveryReceipt {
+                    message_id: format!("id-{}", call),
+                }),
+            };
+            async move { result }
+        })
+        .await
+    }
+
+    #[test]
+    fn test_retry_policy_new() {
+        let mut config = Config::default();
+        config.retry_max_attempts = 0;
+        config.retry_base_delay_ms = 100;
+        config.retry_max_delay_ms = 1000;
+
+        let policy = RetryPolicy::new(&config);
+        assert_eq!(policy.max_attempts, 1);
+        assert_eq!(policy.base_delay, Duration::from_millis(100));
+        assert_eq!(policy.max_delay, Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+        };
+
+        assert_eq!(policy.max_backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.max_backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.max_backoff(4), Duration::from_millis(800));
+        assert_eq!(policy.max_backoff(5), Duration::from_millis(1000));
+        assert_eq!(policy.max_backoff(64), Duration::from_millis(1000));
+
+        for attempt in 1..10 {
+            let delay = policy.backoff(attempt);
+            assert!(delay >= policy.max_backoff(attempt) / 2);
+            assert!(delay <= policy.max_backoff(attempt));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let outcome = run(&policy(3), vec![]).await;
+        assert_eq!(outcome.attempts, 1);
+        assert!(!outcome.retried());
+        assert!(outcome.result.is_ok());
+
+        let transport = DeliveryError::Transport("connection reset".to_string());
+        let unavailable = DeliveryError::Unavailable("maintenance".to_string());
+        let outcome = run(&policy(3), vec![transport.clone(), unavailable]).await;
+        assert_eq!(outcome.attempts, 3);
+        assert!(outcome.retried());
+        assert_eq!(outcome.result.unwrap().message_id, "id-2");
+
+        let outcome = run(&policy(2), vec![transport.clone(); 3]).await;
+        assert_eq!(outcome.attempts, 2);
+        assert_eq!(outcome.result, Err(transport));
+
+        let permanent = DeliveryError::Provider {
+            code: 406,
+            message: "Inactive recipient".to_string(),
+        };
+        let outcome = run(&policy(3), vec![permanent.clone()]).await;
+        assert_eq!(outcome.attempts, 1);
+        assert!(!outcome.retried());
+        assert_eq!(outcome.result, Err(permanent));
+
+        ut_info!("Success.");
+    }
+}
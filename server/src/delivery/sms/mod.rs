@@ -0,0 +1,138 @@
+//! SMS delivery backends
+
+pub mod stub;
+pub mod twilio;
+
+use super::{DeliveryError, DeliveryReceipt};
+use crate::Config;
+use std::fmt::Debug;
+use std::str::FromStr;
+use tokio::sync::OnceCell;
+
+/// SMS backend shared by all handlers, `None` if SMS is disabled
+pub static SMS_BACKEND: OnceCell<Option<Box<dyn SmsBackend>>> = OnceCell::const_new();
+
+/// A text message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmsMessage {
+    /// Recipient phone number in E.164 format
+    pub to: String,
+
+    /// Message text
+    pub text: String,
+}
+
+/// Interface every SMS provider needs to implement
+#[tonic::async_trait]
+pub trait SmsBackend: Debug + Send + Sync {
+    /// Name of the backend, used for logging
+    fn name(&self) -> &'static str;
+
+    /// Sends a text message, returning the provider's message ID
+    async fn send(&self, message: &SmsMessage) -> Result<DeliveryReceipt, DeliveryError>;
+}
+
+/// Available SMS backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmsBackendKind {
+    /// SMS notifications are disabled
+    None,
+
+    /// Twilio compatible HTTP API
+    Twilio,
+
+    /// Logs messages without sending them
+    Stub,
+}
+
+impl FromStr for SmsBackendKind {
+    type Err = DeliveryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" | "" => Ok(SmsBackendKind::None),
+            "twilio" => Ok(SmsBackendKind::Twilio),
+            "stub" => Ok(SmsBackendKind::Stub),
+            other => Err(DeliveryError::Configuration(format!(
+                "unknown SMS backend: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Creates the SMS backend selected in the provided configuration
+pub fn new_backend(config: &Config) -> Result<Option<Box<dyn SmsBackend>>, DeliveryError> {
+    let backend: Box<dyn SmsBackend> = match config.sms_backend.parse()? {
+        SmsBackendKind::None => {
+            delivery_info!("SMS notifications disabled.");
+            return Ok(None);
+        }
+        SmsBackendKind::Twilio => Box::new(twilio::TwilioBackend::new(config)?),
+        SmsBackendKind::Stub => Box::new(stub::StubBackend::default()),
+    };
+
+    delivery_info!("using SMS backend: {}", backend.name());
+    Ok(Some(backend))
+}
+
+/// Returns SMS_BACKEND, the SMS backend selected through a Config
+/// object generated from environment variables.
+/// Initializes SMS_BACKEND if it hasn't been initialized yet.
+pub async fn get_backend() -> Result<Option<&'static dyn SmsBackend>, DeliveryError> {
+    SMS_BACKEND
+        .get_or_try_init(|| async move {
+            let config = Config::try_from_env().unwrap_or_default();
+            new_backend(&config)
+        })
+        .await
+        .map(|backend| backend.as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sms_backend_kind_from_str() {
+        assert_eq!(
+            "none".parse::<SmsBackendKind>().unwrap(),
+            SmsBackendKind::None
+        );
+        assert_eq!("".parse::<SmsBackendKind>().unwrap(), SmsBackendKind::None);
+        assert_eq!(
+            "Twilio".parse::<SmsBackendKind>().unwrap(),
+            SmsBackendKind::Twilio
+        );
+        assert_eq!(
+            "stub".parse::<SmsBackendKind>().unwrap(),
+            SmsBackendKind::Stub
+        );
+        assert!("carrier-pigeon".parse::<SmsBackendKind>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_new_backend() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let mut config = Config::default();
+        config.sms_backend = "none".to_string();
+        assert!(new_backend(&config).unwrap().is_none());
+
+        config.sms_backend = "stub".to_string();
+        assert_eq!(new_backend(&config).unwrap().unwrap().name(), "stub");
+
+        config.sms_backend = "twilio".to_string();
+        config.twilio_account_sid = "AC123".to_string();
+        config.twilio_auth_token = "token".to_string();
+        config.twilio_from_number = "+31600000000".to_string();
+        assert_eq!(new_backend(&config).unwrap().unwrap().name(), "twilio");
+
+        config.twilio_auth_token = String::new();
+        let error = new_backend(&config).unwrap_err();
+        assert!(matches!(error, DeliveryError::Configuration(_)));
+
+        ut_info!("Success.");
+    }
+}
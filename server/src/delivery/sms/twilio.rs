@@ -0,0 +1,238 @@
+//! Twilio compatible SMS backend
+
+use super::{SmsBackend, SmsMessage};
+use crate::delivery::{DeliveryError, DeliveryReceipt};
+use crate::Config;
+use serde::Deserialize;
+use std::fmt::{self, Debug, Formatter};
+
+/// Successful response of the Messages resource
+#[derive(Debug, Deserialize)]
+struct MessageResponse {
+    sid: String,
+}
+
+/// Error response of the Twilio API
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    code: Option<i64>,
+    message: String,
+}
+
+/// Sends text messages through the Twilio Messages API,
+/// or any provider exposing a compatible API.
+pub struct TwilioBackend {
+    client: reqwest::Client,
+    url: String,
+    account_sid: String,
+    auth_token: String,
+    from_number: String,
+}
+
+impl Debug for TwilioBackend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // don't leak the auth token into logs
+        f.debug_struct("TwilioBackend")
+            .field("url", &self.url)
+            .field("from_number", &self.from_number)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TwilioBackend {
+    /// Creates a new Twilio backend from the `twilio_*` configuration options
+    pub fn new(config: &Config) -> Result<Self, DeliveryError> {
+        for (name, value) in [
+            ("account SID", &config.twilio_account_sid),
+            ("auth token", &config.twilio_auth_token),
+            ("from number", &config.twilio_from_number),
+        ] {
+            if value.is_empty() {
+                return Err(DeliveryError::Configuration(format!(
+                    "Twilio {} not found",
+                    name
+                )));
+            }
+        }
+
+        let url = format!(
+            "{}/2010-04-01/Accounts/{}/Messages.json",
+            config.twilio_api_url.trim_end_matches('/'),
+            config.twilio_account_sid
+        );
+
+        Ok(TwilioBackend {
+            client: reqwest::Client::new(),
+            url,
+            account_sid: config.twilio_account_sid.clone(),
+            auth_token: config.twilio_auth_token.clone(),
+            from_number: config.twilio_from_number.clone(),
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl SmsBackend for TwilioBackend {
+    fn name(&self) -> &'static str {
+        "twilio"
+    }
+
+    async fn send(&self, message: &SmsMessage) -> Result<DeliveryReceipt, DeliveryError> {
+        let params = [
+            ("To", message.to.as_str()),
+            ("From", self.from_number.as_str()),
+            ("Body", message.text.as_str()),
+        ];
+
+        let response = self
+            .client
+            .post(&self.url)
+            .basic_auth(&self.account_sid, Some(&self.auth_token))
+            .form(&params)
+            .send()
+            .await
+            .map_err(|e| DeliveryError::Transport(e.to_string()))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error = response
+                .json::<ErrorResponse>()
+                .await
+                .map_err(|e| DeliveryError::Transport(format!("HTTP {}: {}", status, e)))?;
+
+            if status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+                return Err(DeliveryError::Unavailable(error.message));
+            }
+
+            delivery_error!("Twilio refused SMS: {:?}", error);
+            return Err(DeliveryError::Provider {
+                code: error.code.unwrap_or(status.as_u16() as i64),
+                message: error.message,
+            });
+        }
+
+        let response = response
+            .json::<MessageResponse>()
+            .await
+            .map_err(|e| DeliveryError::Transport(e.to_string()))?;
+
+        Ok(DeliveryReceipt {
+            message_id: response.sid,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::{Form, Path};
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::{routing, Json, Router};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+
+    /// Twilio stand-in on the loopback interface, returns its base URL
+    async fn twilio_stand_in() -> String {
+        async fn messages(
+            Path(sid): Path<String>,
+            headers: HeaderMap,
+            Form(params): Form<HashMap<String, String>>,
+        ) -> (StatusCode, Json<Value>) {
+            if sid != "AC123" || !headers.contains_key("authorization") {
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    Json(json!({ "code": 20003, "message": "Authenticate", "status": 401 })),
+                );
+            }
+
+            if params.get("Body").is_some_and(|body| body == "busy") {
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    Json(json!({ "code": 20429, "message": "Too Many Requests", "status": 429 })),
+                );
+            }
+
+            if !params.get("To").is_some_and(|to| to.starts_with('+')) {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({
+                        "code": 21211,
+                        "message": "The 'To' number is not a valid phone number.",
+                        "status": 400
+                    })),
+                );
+            }
+
+            (
+                StatusCode::CREATED,
+                Json(json!({ "sid": "SM0001", "status": "queued", "body": params["Body"] })),
+            )
+        }
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = Router::new().route(
+            "/2010-04-01/Accounts/:sid/Messages.json",
+            routing::post(messages),
+        );
+
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        format!("http://{}", address)
+    }
+
+    fn twilio_config(url: String) -> Config {
+        let mut config = Config::default();
+        config.twilio_api_url = url;
+        config.twilio_account_sid = "AC123".to_string();
+        config.twilio_auth_token = "token".to_string();
+        config.twilio_from_number = "+31600000000".to_string();
+        config
+    }
+
+    #[test]
+    fn test_twilio_backend_new() {
+        let config = twilio_config("https://api.twilio.com/".to_string());
+        let backend = TwilioBackend::new(&config).unwrap();
+        assert_eq!(
+            backend.url,
+            "https://api.twilio.com/2010-04-01/Accounts/AC123/Messages.json"
+        );
+        assert!(!format!("{:?}", backend).contains("token"));
+
+        let mut config = twilio_config("https://api.twilio.com".to_string());
+        config.twilio_from_number = String::new();
+        assert!(matches!(
+            TwilioBackend::new(&config).unwrap_err(),
+            DeliveryError::Configuration(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_twilio_backend_send() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let backend = TwilioBackend::new(&twilio_config(twilio_stand_in().await)).unwrap();
+        let mut message = SmsMessage {
+            to: "+31611111111".to_string(),
+            text: "Hello there".to_string(),
+        };
+
+        let receipt = backend.send(&message).await.unwrap();
+        assert_eq!(receipt.message_id, "SM0001");
+
+        message.to = "0611111111".to_string();
+        let error = backend.send(&message).await.unwrap_err();
+        assert!(matches!(error, DeliveryError::Provider { code: 21211, .. }));
+
+        message.text = "busy".to_string();
+        let error = backend.send(&message).await.unwrap_err();
+        assert!(matches!(error, DeliveryError::Unavailable(_)));
+
+        ut_info!("Success.");
+    }
+}
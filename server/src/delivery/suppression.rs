@@ -0,0 +1,98 @@
+//! Suppression list, email addresses no more emails are sent to
+
+use crate::store::{self, Store, StoreError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Namespace of suppressed addresses in the store
+const NAMESPACE: &str = "undeliverable";
+
+/// Why and since when an email address is undeliverable
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Suppression {
+    /// Reason reported by the provider, e.g. the bounce description
+    pub reason: String,
+
+    /// When the address was suppressed
+    pub created_at: DateTime<Utc>,
+}
+
+/// Keeps the undeliverable email addresses in a store.
+/// Suppressions don't expire, they are removed explicitly.
+#[derive(Debug, Clone, Copy)]
+pub struct Suppressions<'a> {
+    store: &'a dyn Store,
+}
+
+impl<'a> Suppressions<'a> {
+    /// Keeps suppressed addresses in `store`
+    pub fn new(store: &'a dyn Store) -> Self {
+        Suppressions { store }
+    }
+
+    /// Email addresses are case insensitive in practice
+    fn key(email: &str) -> String {
+        store::key(NAMESPACE, &email.trim().to_lowercase())
+    }
+
+    /// Marks an email address as undeliverable
+    pub async fn suppress(&self, email: &str, reason: &str) -> Result<(), StoreError> {
+        let suppression = Suppression {
+            reason: reason.to_string(),
+            created_at: Utc::now(),
+        };
+
+        let value =
+            serde_json::to_string(&suppression).map_err(|e| StoreError::Value(e.to_string()))?;
+        self.store.set(&Self::key(email), &value, None).await
+    }
+
+    /// Returns the suppression of an email address, if it is undeliverable
+    pub async fn get(&self, email: &str) -> Result<Option<Suppression>, StoreError> {
+        let Some(value) = self.store.get(&Self::key(email)).await? else {
+            return Ok(None);
+        };
+
+        serde_json::from_str(&value)
+            .map(Some)
+            .map_err(|e| StoreError::Value(e.to_string()))
+    }
+
+    /// Marks an email address as deliverable again
+    pub async fn remove(&self, email: &str) -> Result<(), StoreError> {
+        self.store.del(&Self::key(email)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::memory::MemoryStore;
+
+    #[tokio::test]
+    async fn test_suppressions() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let suppressions = Suppressions::new(&store);
+        assert_eq!(suppressions.get("alice@aetheric.nl").await.unwrap(), None);
+
+        suppressions
+            .suppress("Alice@Aetheric.nl", "Unknown user")
+            .await
+            .unwrap();
+        let suppression = suppressions
+            .get("alice@aetheric.nl")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(suppression.reason, "Unknown user");
+        assert_eq!(suppressions.get("bob@aetheric.nl").await.unwrap(), None);
+
+        suppressions.remove(" alice@aetheric.nl").await.unwrap();
+        assert_eq!(suppressions.get("alice@aetheric.nl").await.unwrap(), None);
+
+        ut_info!("Success.");
+    }
+}
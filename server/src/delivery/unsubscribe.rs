@@ -0,0 +1,187 @@
+//! One-click unsubscribe links, embedded in the template model
+//! and announced with the `List-Unsubscribe` headers (RFC 8058)
+
+use super::email::{EmailHeader, EmailMessage};
+use super::preferences::MessageKind;
+use crate::templates::TemplateSpec;
+use crate::token::{TokenError, TokenSigner};
+use crate::Config;
+use chrono::{DateTime, Duration, Utc};
+use tokio::sync::OnceCell;
+
+/// Unsubscribe links shared by all handlers, none when no signing key is configured
+static UNSUBSCRIBE: OnceCell<Option<Unsubscribe>> = OnceCell::const_new();
+
+/// Purpose of unsubscribe tokens, see [`TokenSigner`]
+const PURPOSE: &str = "unsubscribe";
+
+/// Template model field holding the unsubscribe link
+pub const MODEL_FIELD: &str = "unsubscribe_url";
+
+/// Longest validity of an unsubscribe link, ten years
+const MAX_TTL_DAYS: u64 = 3650;
+
+/// Issues and verifies the unsubscribe links of users
+#[derive(Debug, Clone)]
+pub struct Unsubscribe {
+    signer: TokenSigner,
+    url: String,
+    ttl: Duration,
+}
+
+impl Unsubscribe {
+    /// Creates the unsubscribe links from the `token_signing_key` and
+    /// `unsubscribe_*` configuration options, none when no key is set
+    pub fn new(config: &Config) -> Option<Self> {
+        let Some(signer) = TokenSigner::new(&config.token_signing_key) else {
+            delivery_warn!("no token signing key set, unsubscribe links are disabled.");
+            return None;
+        };
+
+        let days = config.unsubscribe_token_ttl_days.min(MAX_TTL_DAYS);
+        Some(Unsubscribe {
+            signer,
+            url: config.unsubscribe_url.clone(),
+            // the capped validity always fits
+            ttl: Duration::try_days(days as i64).unwrap_or_else(Duration::zero),
+        })
+    }
+
+    /// Returns the unsubscribe link of a user, valid until the configured time after `now`
+    pub fn link(&self, user_id: &str, now: DateTime<Utc>) -> String {
+        let expires_at = now.checked_add_signed(self.ttl).unwrap_or(now);
+        format!(
+            "{}?token={}",
+            self.url,
+            self.signer.sign(PURPOSE, user_id, expires_at)
+        )
+    }
+
+    /// Returns the user an unsubscribe token was issued to
+    pub fn verify(&self, token: &str, now: DateTime<Utc>) -> Result<String, TokenError> {
+        self.signer.verify(PURPOSE, token, now)
+    }
+
+    /// Adds the unsubscribe link of a user to the template model of a message,
+    /// and the headers mail clients show a one-click unsubscribe button for.
+    /// Unsubscribing only opts out of marketing, so transactional messages
+    /// don't get a link.
+    pub fn add_to(
+        &self,
+        message: &mut EmailMessage,
+        template: &TemplateSpec,
+        user_id: &str,
+        now: DateTime<Utc>,
+    ) {
+        if template.kind != MessageKind::Marketing {
+            return;
+        }
+
+        let link = self.link(user_id, now);
+        message
+            .headers
+            .push(EmailHeader::new("List-Unsubscribe", format!("<{}>", link)));
+        message.headers.push(EmailHeader::new(
+            "List-Unsubscribe-Post",
+            "List-Unsubscribe=One-Click",
+        ));
+        message.model.insert(MODEL_FIELD, link);
+    }
+}
+
+/// Returns UNSUBSCRIBE, created from a Config object generated from
+/// environment variables on first use.
+pub async fn get_unsubscribe() -> Option<&'static Unsubscribe> {
+    UNSUBSCRIBE
+        .get_or_init(|| async move {
+            let config = Config::try_from_env().unwrap_or_default();
+            Unsubscribe::new(&config)
+        })
+        .await
+        .as_ref()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delivery::email::TemplateModel;
+    use crate::templates::{CARGO_CONFIRMATION, ROUTE_ANNOUNCEMENT};
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.token_signing_key = "secret".to_string();
+        config.unsubscribe_url = "https://contact.aetheric.nl/contact/unsubscribe".to_string();
+        config.unsubscribe_token_ttl_days = 30;
+        config
+    }
+
+    fn now() -> DateTime<Utc> {
+        "2024-06-01T12:00:00Z".parse().unwrap()
+    }
+
+    #[test]
+    fn test_unsubscribe_new() {
+        let mut config = config();
+        let unsubscribe = Unsubscribe::new(&config).unwrap();
+        assert_eq!(unsubscribe.ttl, Duration::try_days(30).unwrap());
+
+        config.unsubscribe_token_ttl_days = u64::MAX;
+        let unsubscribe = Unsubscribe::new(&config).unwrap();
+        assert_eq!(unsubscribe.ttl, Duration::try_days(3650).unwrap());
+
+        config.token_signing_key = String::new();
+        assert!(Unsubscribe::new(&config).is_none());
+    }
+
+    #[test]
+    fn test_link_and_verify() {
+        let unsubscribe = Unsubscribe::new(&config()).unwrap();
+        let link = unsubscribe.link("user", now());
+        let token = link
+            .strip_prefix("https://contact.aetheric.nl/contact/unsubscribe?token=")
+            .unwrap();
+
+        assert_eq!(unsubscribe.verify(token, now()), Ok("user".to_string()));
+        let expiry = now() + Duration::try_days(30).unwrap();
+        assert_eq!(unsubscribe.verify(token, expiry), Err(TokenError::Expired));
+
+        // tokens issued for another purpose are refused
+        let signer = TokenSigner::new("secret").unwrap();
+        let other = signer.sign("verify", "user", expiry);
+        assert_eq!(
+            unsubscribe.verify(&other, now()),
+            Err(TokenError::Signature)
+        );
+    }
+
+    #[test]
+    fn test_add_to() {
+        let unsubscribe = Unsubscribe::new(&config()).unwrap();
+        let mut message = EmailMessage {
+            from: "info@aetheric.nl".to_string(),
+            to: "alice@aetheric.nl".to_string(),
+            template: "cargo-confirmation".to_string(),
+            model: TemplateModel::default(),
+            locale: Default::default(),
+            body: None,
+            headers: vec![],
+            attachments: vec![],
+        };
+        // transactional messages can't be unsubscribed from
+        unsubscribe.add_to(&mut message, &CARGO_CONFIRMATION, "user", now());
+        assert!(message.model.get(MODEL_FIELD).is_none());
+        assert!(message.headers.is_empty());
+
+        unsubscribe.add_to(&mut message, &ROUTE_ANNOUNCEMENT, "user", now());
+
+        let link = unsubscribe.link("user", now());
+        assert_eq!(message.model.get(MODEL_FIELD).unwrap(), &link);
+        assert_eq!(
+            message.headers,
+            vec![
+                EmailHeader::new("List-Unsubscribe", format!("<{}>", link)),
+                EmailHeader::new("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+            ]
+        );
+    }
+}
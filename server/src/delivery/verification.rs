@@ -0,0 +1,462 @@
+//! Email address verification, with signed single-use links sent on signup
+
+use crate::store::{self, Store, StoreError};
+use crate::token::{TokenError, TokenSigner};
+use crate::Config;
+use chrono::{DateTime, Duration, Utc};
+use std::fmt::{self, Display, Formatter};
+use tokio::sync::OnceCell;
+
+/// Verification links shared by all handlers, none when no signing key is configured
+static VERIFICATION: OnceCell<Option<Verification>> = OnceCell::const_new();
+
+/// Whether cargo confirmations require a verified address
+static REQUIRED: OnceCell<bool> = OnceCell::const_new();
+
+/// Purpose of verification tokens, see [`TokenSigner`]
+const PURPOSE: &str = "verify";
+
+/// Namespace of verified addresses in the store
+const NAMESPACE: &str = "verified";
+
+/// Namespace of used verification tokens in the store
+const USED_NAMESPACE: &str = "verification_used";
+
+/// Longest validity of a verification link, thirty days
+const MAX_TTL_HOURS: u64 = 720;
+
+/// Namespace of the resend cooldowns of users in the store
+const RESEND_NAMESPACE: &str = "verification_resend";
+
+/// Days an expired verification token can still request another email
+const RESEND_WINDOW_DAYS: i64 = 30;
+
+/// Seconds a user has to wait before requesting another verification email
+const RESEND_COOLDOWN_SECS: u64 = 60;
+
+/// Reasons a verification link is refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerificationError {
+    /// The token is malformed, forged or expired
+    Token(TokenError),
+
+    /// The token was used before
+    Used,
+
+    /// Another verification email was requested less than a cooldown ago
+    TooSoon,
+
+    /// The store could not be reached
+    Store(StoreError),
+}
+
+impl std::error::Error for VerificationError {}
+
+impl Display for VerificationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            VerificationError::Token(e) => write!(f, "{}", e),
+            VerificationError::Used => write!(f, "Token already used"),
+            VerificationError::TooSoon => write!(f, "Verification email requested too soon"),
+            VerificationError::Store(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// A user and the email address they verified
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedAddress {
+    /// User ID
+    pub user_id: String,
+
+    /// Verified email address
+    pub email: String,
+}
+
+/// Keeps the verified email address of every user in a store.
+/// Verified addresses don't expire.
+#[derive(Debug, Clone, Copy)]
+pub struct VerifiedAddresses<'a> {
+    store: &'a dyn Store,
+}
+
+impl<'a> VerifiedAddresses<'a> {
+    /// Keeps verified addresses in `store`
+    pub fn new(store: &'a dyn Store) -> Self {
+        VerifiedAddresses { store }
+    }
+
+    /// Email addresses are case insensitive in practice
+    fn normalize(email: &str) -> String {
+        email.trim().to_lowercase()
+    }
+
+    /// Marks the email address of a user as verified
+    pub async fn set(&self, user_id: &str, email: &str) -> Result<(), StoreError> {
+        self.store
+            .set(
+                &store::key(NAMESPACE, user_id),
+                &Self::normalize(email),
+                None,
+            )
+            .await
+    }
+
+    /// Returns true if `email` is the verified address of the user.
+    /// Changing the address of a user makes it unverified.
+    pub async fn is_verified(&self, user_id: &str, email: &str) -> Result<bool, StoreError> {
+        let verified = self.store.get(&store::key(NAMESPACE, user_id)).await?;
+        Ok(verified == Some(Self::normalize(email)))
+    }
+}
+
+/// Issues and confirms the verification links of email addresses
+#[derive(Debug, Clone)]
+pub struct Verification {
+    signer: TokenSigner,
+    url: String,
+    ttl: Duration,
+}
+
+impl Verification {
+    /// Creates the verification links from the `token_signing_key` and
+    /// `verification_*` configuration options, none when no key is set
+    pub fn new(config: &Config) -> Option<Self> {
+        let Some(signer) = TokenSigner::new(&config.token_signing_key) else {
+            delivery_warn!("no token signing key set, email verification is disabled.");
+            return None;
+        };
+
+        let hours = config.verification_token_ttl_hours.min(MAX_TTL_HOURS);
+        Some(Verification {
+            signer,
+            url: config.verification_url.clone(),
+            // the capped validity always fits
+            ttl: Duration::try_hours(hours as i64).unwrap_or_else(Duration::zero),
+        })
+    }
+
+    /// How long a verification link stays valid
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Returns the verification link of the email address of a user,
+    /// valid until the configured time after `now`
+    pub fn link(&self, user_id: &str, email: &str, now: DateTime<Utc>) -> String {
+        let expires_at = now.checked_add_signed(self.ttl).unwrap_or(now);
+        let subject = format!("{}:{}", user_id, email.trim());
+        format!(
+            "{}?token={}",
+            self.url,
+            self.signer.sign(PURPOSE, &subject, expires_at)
+        )
+    }
+
+    /// Marks the address in a verification token as verified.
+    /// Every token can be used once.
+    pub async fn confirm(
+        &self,
+        store: &dyn Store,
+        token: &str,
+        now: DateTime<Utc>,
+    ) -> Result<VerifiedAddress, VerificationError> {
+        let subject = self
+            .signer
+            .verify(PURPOSE, token, now)
+            .map_err(VerificationError::Token)?;
+
+        // user IDs don't contain colons, email addresses may
+        let (user_id, email) = subject
+            .split_once(':')
+            .ok_or(VerificationError::Token(TokenError::Malformed))?;
+
+        // kept until the token expired anyway
+        let used_key = store::key(USED_NAMESPACE, token.trim());
+        let ttl = self.ttl.to_std().ok();
+        let claimed = store
+            .set_nx(&used_key, user_id, ttl)
+            .await
+            .map_err(VerificationError::Store)?;
+
+        if !claimed {
+            return Err(VerificationError::Used);
+        }
+
+        if let Err(e) = VerifiedAddresses::new(store).set(user_id, email).await {
+            // let the user try again
+            let _ = store.del(&used_key).await;
+            return Err(VerificationError::Store(e));
+        }
+
+        Ok(VerifiedAddress {
+            user_id: user_id.to_string(),
+            email: email.to_string(),
+        })
+    }
+
+    /// Returns the user of a verification token, who requests another
+    /// verification email. Expired tokens are accepted up to
+    /// [`RESEND_WINDOW_DAYS`] days after their expiry, as an expired link is
+    /// the usual reason to request another one.
+    pub fn resend_user(
+        &self,
+        token: &str,
+        now: DateTime<Utc>,
+    ) -> Result<String, VerificationError> {
+        let window = Duration::try_days(RESEND_WINDOW_DAYS).unwrap_or_else(Duration::zero);
+        let subject = self
+            .signer
+            .verify(
+                PURPOSE,
+                token,
+                now.checked_sub_signed(window).unwrap_or(now),
+            )
+            .map_err(VerificationError::Token)?;
+
+        subject
+            .split_once(':')
+            .map(|(user_id, _)| user_id.to_string())
+            .ok_or(VerificationError::Token(TokenError::Malformed))
+    }
+
+    /// Starts the resend cooldown of a user, refusing another verification
+    /// email within [`RESEND_COOLDOWN_SECS`] seconds of the previous one
+    pub async fn claim_resend(
+        &self,
+        store: &dyn Store,
+        user_id: &str,
+    ) -> Result<(), VerificationError> {
+        let claimed = store
+            .set_nx(
+                &store::key(RESEND_NAMESPACE, user_id),
+                "1",
+                Some(std::time::Duration::from_secs(RESEND_COOLDOWN_SECS)),
+            )
+            .await
+            .map_err(VerificationError::Store)?;
+
+        if claimed {
+            Ok(())
+        } else {
+            Err(VerificationError::TooSoon)
+        }
+    }
+
+    /// Ends the resend cooldown of a user, when no email could be sent
+    pub async fn release_resend(&self, store: &dyn Store, user_id: &str) -> Result<(), StoreError> {
+        store.del(&store::key(RESEND_NAMESPACE, user_id)).await
+    }
+}
+
+/// Returns VERIFICATION, created from a Config object generated from
+/// environment variables on first use.
+pub async fn get_verification() -> Option<&'static Verification> {
+    VERIFICATION
+        .get_or_init(|| async move {
+            let config = Config::try_from_env().unwrap_or_default();
+            Verification::new(&config)
+        })
+        .await
+        .as_ref()
+}
+
+/// Returns REQUIRED, the `require_verified_email` configuration option.
+/// Addresses can't be verified while verification is disabled,
+/// so they are not required then.
+pub async fn get_required() -> bool {
+    *REQUIRED
+        .get_or_init(|| async move {
+            let config = Config::try_from_env().unwrap_or_default();
+            config.require_verified_email && get_verification().await.is_some()
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::memory::MemoryStore;
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.token_signing_key = "secret".to_string();
+        config.verification_url = "https://contact.aetheric.nl/contact/verify".to_string();
+        config.verification_token_ttl_hours = 24;
+        config
+    }
+
+    fn now() -> DateTime<Utc> {
+        "2024-06-01T12:00:00Z".parse().unwrap()
+    }
+
+    fn token(link: &str) -> &str {
+        link.strip_prefix("https://contact.aetheric.nl/contact/verify?token=")
+            .unwrap()
+    }
+
+    #[test]
+    fn test_verification_new() {
+        let mut config = config();
+        let verification = Verification::new(&config).unwrap();
+        assert_eq!(verification.ttl(), Duration::try_hours(24).unwrap());
+
+        config.verification_token_ttl_hours = u64::MAX;
+        let verification = Verification::new(&config).unwrap();
+        assert_eq!(verification.ttl(), Duration::try_hours(720).unwrap());
+
+        config.token_signing_key = String::new();
+        assert!(Verification::new(&config).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_confirm() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let addresses = VerifiedAddresses::new(&store);
+        let verification = Verification::new(&config()).unwrap();
+        let link = verification.link("user", "Alice@Aetheric.nl", now());
+        assert!(!addresses
+            .is_verified("user", "alice@aetheric.nl")
+            .await
+            .unwrap());
+
+        let verified = verification
+            .confirm(&store, token(&link), now())
+            .await
+            .unwrap();
+        assert_eq!(
+            verified,
+            VerifiedAddress {
+                user_id: "user".to_string(),
+                email: "Alice@Aetheric.nl".to_string(),
+            }
+        );
+        assert!(addresses
+            .is_verified("user", "alice@aetheric.nl")
+            .await
+            .unwrap());
+        assert!(!addresses
+            .is_verified("user", "bob@aetheric.nl")
+            .await
+            .unwrap());
+        assert!(!addresses
+            .is_verified("other", "alice@aetheric.nl")
+            .await
+            .unwrap());
+
+        // tokens can be used once
+        let error = verification
+            .confirm(&store, token(&link), now())
+            .await
+            .unwrap_err();
+        assert_eq!(error, VerificationError::Used);
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_confirm_refused() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let verification = Verification::new(&config()).unwrap();
+        let link = verification.link("user", "alice@aetheric.nl", now());
+
+        let expired = now() + Duration::try_hours(24).unwrap();
+        let error = verification
+            .confirm(&store, token(&link), expired)
+            .await
+            .unwrap_err();
+        assert_eq!(error, VerificationError::Token(TokenError::Expired));
+
+        let error = verification
+            .confirm(&store, "token", now())
+            .await
+            .unwrap_err();
+        assert_eq!(error, VerificationError::Token(TokenError::Malformed));
+
+        // tokens issued for another purpose are refused
+        let signer = TokenSigner::new("secret").unwrap();
+        let other = signer.sign("unsubscribe", "user:alice@aetheric.nl", expired);
+        let error = verification
+            .confirm(&store, &other, now())
+            .await
+            .unwrap_err();
+        assert_eq!(error, VerificationError::Token(TokenError::Signature));
+
+        // refused tokens aren't used up
+        verification
+            .confirm(&store, token(&link), now())
+            .await
+            .unwrap();
+
+        ut_info!("Success.");
+    }
+
+    #[test]
+    fn test_resend_user() {
+        let verification = Verification::new(&config()).unwrap();
+        let link = verification.link("user", "alice@aetheric.nl", now());
+        assert_eq!(
+            verification.resend_user(token(&link), now()).unwrap(),
+            "user"
+        );
+
+        // expired links can request another one for a while
+        let expired = now() + Duration::try_days(30).unwrap();
+        assert_eq!(
+            verification.resend_user(token(&link), expired).unwrap(),
+            "user"
+        );
+        let error = verification
+            .resend_user(token(&link), expired + Duration::try_days(1).unwrap())
+            .unwrap_err();
+        assert_eq!(error, VerificationError::Token(TokenError::Expired));
+
+        let signer = TokenSigner::new("secret").unwrap();
+        let other = signer.sign("unsubscribe", "user:alice@aetheric.nl", expired);
+        let error = verification.resend_user(&other, now()).unwrap_err();
+        assert_eq!(error, VerificationError::Token(TokenError::Signature));
+    }
+
+    #[tokio::test]
+    async fn test_claim_resend() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let verification = Verification::new(&config()).unwrap();
+        verification.claim_resend(&store, "user").await.unwrap();
+        let error = verification.claim_resend(&store, "user").await.unwrap_err();
+        assert_eq!(error, VerificationError::TooSoon);
+
+        // other users have their own cooldown
+        verification.claim_resend(&store, "other").await.unwrap();
+
+        verification.release_resend(&store, "user").await.unwrap();
+        verification.claim_resend(&store, "user").await.unwrap();
+
+        ut_info!("Success.");
+    }
+
+    #[test]
+    fn test_verification_error_display() {
+        assert_eq!(
+            VerificationError::Token(TokenError::Expired).to_string(),
+            "Token expired"
+        );
+        assert_eq!(VerificationError::Used.to_string(), "Token already used");
+        assert_eq!(
+            VerificationError::TooSoon.to_string(),
+            "Verification email requested too soon"
+        );
+        assert_eq!(
+            VerificationError::Store(StoreError::Backend("down".to_string())).to_string(),
+            "Store error: down"
+        );
+    }
+}
@@ -1,28 +1,48 @@
 //! Cargo-related handlers
 
+use super::notify::{self, Recipient};
+use crate::delivery::email::{
+    mask_address, EmailAttachment, EmailBackend, EmailMessage, TemplateModel,
+};
+use crate::delivery::history::DeliveryLog;
+use crate::delivery::preferences::{Channels, PreferenceStore};
+use crate::delivery::retry::RetryPolicy;
+use crate::delivery::sms::SmsMessage;
+use crate::delivery::suppression::Suppressions;
+use crate::delivery::verification::VerifiedAddresses;
 use crate::grpc::client::GrpcClients;
+use crate::grpc::server::{
+    CancellationReason, CargoCancellationRequest, CargoCancellationResponse,
+};
 use crate::grpc::server::{CargoConfirmationRequest, CargoConfirmationResponse};
+use crate::grpc::server::{DeliveryChannel, FailureReason};
+use crate::locale::Locale;
+use crate::receipt::pdf::{self, ReceiptDocument};
+use crate::receipt::{self, Receipt};
+use crate::scheduler::{lead_time_text, Reminder, ReminderSchedule};
+use crate::store::idempotency::{Claim, Idempotency};
+use crate::store::invoice::{InvoiceError, InvoiceNumbers};
+use crate::templates::{CARGO_CANCELLATION, CARGO_CONFIRMATION, PICKUP_REMINDER};
+use chrono_tz::Tz;
 use geo_types::{Coord, LineString};
 use lib_common::time::{DateTime, Duration, Utc};
 use polyline;
-use postmark::api::email::*;
-use postmark::reqwest::PostmarkClient;
-use postmark::*;
+use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::fmt::{self, Display, Formatter};
+use std::future::Future;
 use svc_storage_client_grpc::prelude::{flight_plan, vertiport};
 use svc_storage_client_grpc::prelude::{AdvancedSearchFilter, Id};
 use svc_storage_client_grpc::simple_service::Client as _;
 use svc_storage_client_grpc::simple_service_linked::Client;
-use tokio::sync::OnceCell;
 use tonic::Status;
 
-/// Postmark token
-pub static POSTMARK_TOKEN: OnceCell<String> = OnceCell::const_new();
-
 // TODO(R5): no-reply@aetheric.nl
 /// Aetheric's email address
-const AETHERIC_EMAIL_ADDRESS: &str = "info@aetheric.nl";
+pub(crate) const AETHERIC_EMAIL_ADDRESS: &str = "info@aetheric.nl";
+
+/// Date and time format used in notifications, with the zone abbreviation
+pub(crate) const DT_FORMAT: &str = "%Y-%m-%d %H:%M %Z";
 
 #[derive(Debug)]
 struct PlanData {
@@ -38,49 +58,164 @@ struct PlanData {
     path: Vec<Coord>,
 }
 
-/// Parcel information needed for a confirmation email
-struct ParcelData {
+/// Parcel information needed for a notification
+pub(crate) struct ParcelData {
+    /// Itinerary the parcel is booked on
+    pub(crate) itinerary_id: String,
+
     /// Weight in kilograms
-    weight_kg: f32,
+    pub(crate) weight_kg: f32,
 
     /// pickup flight plan
-    origin_vertiport_id: String,
+    pub(crate) origin_vertiport_id: String,
 
     /// Dropoff flight plan
-    target_vertiport_id: String,
+    pub(crate) target_vertiport_id: String,
 
     /// origin timeslot start
-    origin_timeslot_start: DateTime<Utc>,
+    pub(crate) origin_timeslot_start: DateTime<Utc>,
 
     /// target timeslot end
-    target_timeslot_end: DateTime<Utc>,
+    pub(crate) target_timeslot_end: DateTime<Utc>,
 
     /// origin latitude
-    origin_latitude: f64,
+    pub(crate) origin_latitude: f64,
 
     /// origin longitude
-    origin_longitude: f64,
+    pub(crate) origin_longitude: f64,
 
     /// target latitude
-    target_latitude: f64,
+    pub(crate) target_latitude: f64,
 
     /// target longitude
-    target_longitude: f64,
+    pub(crate) target_longitude: f64,
 
     /// Full path
-    polyline: String,
+    pub(crate) polyline: String,
+}
+
+/// Vertiport information shown to the customer
+pub(crate) struct VertiportData {
+    pub(crate) name: String,
+    pub(crate) address: String,
+
+    /// Local timezone, if the schedule of the vertiport names one
+    pub(crate) timezone: Option<Tz>,
+}
+
+impl VertiportData {
+    /// Formats a time in the local timezone of the vertiport,
+    /// or in `fallback` if its timezone is unknown
+    pub(crate) fn local_time(&self, time: DateTime<Utc>, fallback: Tz) -> String {
+        time.with_timezone(&self.timezone.unwrap_or(fallback))
+            .format(DT_FORMAT)
+            .to_string()
+    }
+}
+
+/// Returns the timezone of an iCalendar schedule, from its first `TZID` parameter,
+/// e.g. `DTSTART;TZID=Europe/Amsterdam:20240101T080000`.
+/// Schedules in UTC don't name a timezone.
+fn schedule_timezone(schedule: &str) -> Option<Tz> {
+    let (_, rest) = schedule.split_once("TZID=")?;
+    let name = rest
+        .split(|c: char| c == ':' || c == ';' || c.is_whitespace())
+        .next()?
+        .trim_matches('"');
+
+    match name.parse() {
+        Ok(tz) => Some(tz),
+        Err(_) => {
+            grpc_warn!("unknown timezone in vertiport schedule: {}", name);
+            None
+        }
+    }
+}
+
+/// User information needed to address a notification
+pub(crate) struct UserData {
+    pub(crate) name: String,
+    pub(crate) email: String,
 }
 
-struct VertiportData {
-    name: String,
-    address: String,
+/// A booked parcel and the user who booked it
+struct BookingData {
+    user_id: String,
+    parcel_id: String,
+    itinerary_id: String,
+    user: UserData,
+    parcel: ParcelData,
+    origin_vertiport: VertiportData,
+    target_vertiport: VertiportData,
 }
 
-struct UserData {
-    name: String,
-    email: String,
+/// Everything needed to compose a confirmation email
+struct ConfirmationData {
+    booking: BookingData,
+    invoice_id: String,
+    phone_number: Option<String>,
+
+    /// Locale requested by the caller, the user's preferred language applies otherwise
+    locale: Option<String>,
+
+    /// Prices of the booking
+    receipt: Receipt,
+
+    /// When the confirmation was composed, the date of the invoice
+    issued_at: DateTime<Utc>,
+}
+
+/// Everything needed to compose a cancellation email
+struct CancellationData {
+    booking: BookingData,
+    reason: CancellationReason,
+    refund_amount_cents: u64,
+    currency: String,
+}
+
+impl BookingData {
+    /// Recipient of notifications about the booking, for the delivery log
+    fn recipient(&self) -> Recipient {
+        Recipient {
+            user_id: self.user_id.clone(),
+            parcel_id: Some(self.parcel_id.clone()),
+            itinerary_id: Some(self.itinerary_id.clone()),
+            delivery_id: None,
+        }
+    }
+
+    /// Formats the customer facing pickup and dropoff times of the parcel,
+    /// see [`pickup_dropoff_times`]
+    fn pickup_dropoff_times(&self, tz: Tz) -> Result<(String, String), Status> {
+        pickup_dropoff_times(
+            &self.parcel,
+            &self.origin_vertiport,
+            &self.target_vertiport,
+            tz,
+        )
+    }
 }
 
+impl ConfirmationData {
+    /// Channels requested for the confirmation,
+    /// email and a text message if the customer provided a phone number
+    fn channels(&self) -> Channels {
+        Channels {
+            email: true,
+            sms: self.phone_number.is_some(),
+        }
+    }
+
+    /// Date and time of the invoice, in the provided timezone
+    fn invoice_date(&self, tz: Tz) -> String {
+        self.issued_at
+            .with_timezone(&tz)
+            .format(DT_FORMAT)
+            .to_string()
+    }
+}
+
+/// A line of the receipt in the template model
 #[derive(Serialize)]
 struct Details {
     amount: String,
@@ -178,6 +313,7 @@ impl TryFrom<vertiport::Object> for VertiportData {
         object
             .data
             .map(|data| VertiportData {
+                timezone: data.schedule.as_deref().and_then(schedule_timezone),
                 name: data.name,
                 address: data.description,
             })
@@ -203,7 +339,10 @@ async fn get_plan_data(clients: &GrpcClients, flight_plan_id: &str) -> Result<Pl
 
 #[cfg(not(tarpaulin_include))]
 // no_coverage: (Rnever) not unit testable, only integration tests
-async fn get_parcel_data(clients: &GrpcClients, parcel_id: &str) -> Result<ParcelData, Status> {
+pub(crate) async fn get_parcel_data(
+    clients: &GrpcClients,
+    parcel_id: &str,
+) -> Result<ParcelData, Status> {
     let parcel_data = clients
         .storage
         .parcel
@@ -290,6 +429,7 @@ async fn get_parcel_data(clients: &GrpcClients, parcel_id: &str) -> Result<Parce
     let polyline = polyline::encode_coordinates(LineString::new(path), 5).unwrap_or("".to_string());
 
     let parcel = ParcelData {
+        itinerary_id: parcel_data.itinerary_id,
         weight_kg: (parcel_data.weight_grams as f32) / 1000.0,
         origin_vertiport_id,
         target_vertiport_id,
@@ -307,7 +447,7 @@ async fn get_parcel_data(clients: &GrpcClients, parcel_id: &str) -> Result<Parce
 
 #[cfg(not(tarpaulin_include))]
 // no_coverage: (Rnever) not unit testable, only integration tests
-async fn get_vertiport_data(
+pub(crate) async fn get_vertiport_data(
     clients: &GrpcClients,
     vertiport_id: &str,
 ) -> Result<VertiportData, Status> {
@@ -325,7 +465,30 @@ async fn get_vertiport_data(
 
 #[cfg(not(tarpaulin_include))]
 // no_coverage: (Rnever) not unit testable, only integration tests
-async fn get_user_data(clients: &GrpcClients, user_id: &str) -> Result<UserData, Status> {
+pub(crate) async fn get_itinerary_user_id(
+    clients: &GrpcClients,
+    itinerary_id: &str,
+) -> Result<String, Status> {
+    clients
+        .storage
+        .itinerary
+        .get_by_id(Id {
+            id: itinerary_id.to_string(),
+        })
+        .await
+        .map_err(|e| Status::internal(format!("Could not get itinerary: {}", e)))?
+        .into_inner()
+        .data
+        .map(|data| data.user_id)
+        .ok_or_else(|| Status::internal("Itinerary data not found"))
+}
+
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+pub(crate) async fn get_user_data(
+    clients: &GrpcClients,
+    user_id: &str,
+) -> Result<UserData, Status> {
     let user_data = clients
         .storage
         .user
@@ -338,11 +501,12 @@ async fn get_user_data(clients: &GrpcClients, user_id: &str) -> Result<UserData,
         .data
         .ok_or_else(|| Status::internal("User data not found"))?;
 
+    // the templates leave out an empty name
     let name = user_data
         .display_name
         .split_whitespace()
         .next()
-        .unwrap_or("there") // hello there!
+        .unwrap_or_default()
         .to_string();
 
     let email = if user_data.email.is_empty() {
@@ -354,127 +518,1430 @@ async fn get_user_data(clients: &GrpcClients, user_id: &str) -> Result<UserData,
     Ok(UserData { name, email })
 }
 
-/// Sends a confirmation email to the user
+/// Formats the customer facing pickup and dropoff times of a parcel,
+/// in the local time of the origin and target vertiport.
+/// Times at a vertiport of unknown timezone are shown in the user's timezone `tz`.
+pub(crate) fn pickup_dropoff_times(
+    parcel: &ParcelData,
+    origin_vertiport: &VertiportData,
+    target_vertiport: &VertiportData,
+    tz: Tz,
+) -> Result<(String, String), Status> {
+    let padding = Duration::try_minutes(10)
+        .ok_or_else(|| Status::internal("Could not create time padding"))?;
+
+    let pickup_time = origin_vertiport.local_time(parcel.origin_timeslot_start + padding, tz);
+    let dropoff_time = target_vertiport.local_time(parcel.target_timeslot_end - padding, tz);
+
+    Ok((pickup_time, dropoff_time))
+}
+
+/// Fills in the confirmation template for the collected data,
+/// with numbers formatted for the locale and times in local time
+fn confirmation_model(
+    data: &ConfirmationData,
+    locale: &Locale,
+    tz: Tz,
+) -> Result<TemplateModel, Status> {
+    let booking = &data.booking;
+    let (pickup_time, dropoff_time) = booking.pickup_dropoff_times(tz)?;
+
+    let receipt = &data.receipt;
+    let lines: Vec<Details> = receipt
+        .lines
+        .iter()
+        .map(|line| Details {
+            description: line.description.clone(),
+            amount: receipt.format_amount(line.amount, locale),
+        })
+        .collect();
+
+    let mut model = TemplateModel::default();
+    model.insert("customer_name", &booking.user.name);
+    model.insert("customer_dropoff_time", dropoff_time);
+    model.insert("customer_pickup_time", pickup_time);
+    model.insert(
+        "parcel_weight_kg",
+        locale.format_weight_kg(booking.parcel.weight_kg.into()),
+    );
+    model.insert("origin_vertiport_name", &booking.origin_vertiport.name);
+    model.insert(
+        "origin_vertiport_address",
+        &booking.origin_vertiport.address,
+    );
+    model.insert("target_vertiport_name", &booking.target_vertiport.name);
+    model.insert(
+        "target_vertiport_address",
+        &booking.target_vertiport.address,
+    );
+    model.insert("origin_latitude", booking.parcel.origin_latitude);
+    model.insert("origin_longitude", booking.parcel.origin_longitude);
+    model.insert("target_latitude", booking.parcel.target_latitude);
+    model.insert("target_longitude", booking.parcel.target_longitude);
+    model.insert("encoded_polyline", &booking.parcel.polyline);
+    model.insert("invoice_id", &data.invoice_id);
+    model.insert("invoice_date", data.invoice_date(tz));
+    model.insert("receipt_lines", lines);
+    model.insert("subtotal", receipt.format_amount(receipt.subtotal, locale));
+    model.insert("tax_rate", receipt.format_tax_rate(locale));
+    model.insert("tax", receipt.format_amount(receipt.tax, locale));
+    model.insert("total_price", receipt.format_amount(receipt.total, locale));
+    model.insert("currency", &receipt.currency);
+
+    Ok(model)
+}
+
+/// Renders the PDF receipt of the booking, in the user's language
+fn receipt_attachment(
+    data: &ConfirmationData,
+    locale: &Locale,
+    tz: Tz,
+) -> Result<EmailAttachment, Status> {
+    let booking = &data.booking;
+    let invoice_date = data.invoice_date(tz);
+    let parcel_weight_kg = locale.format_weight_kg(booking.parcel.weight_kg.into());
+    let document = ReceiptDocument {
+        invoice_id: &data.invoice_id,
+        invoice_date: &invoice_date,
+        customer_name: &booking.user.name,
+        parcel_weight_kg: &parcel_weight_kg,
+        origin_vertiport_name: &booking.origin_vertiport.name,
+        target_vertiport_name: &booking.target_vertiport.name,
+        receipt: &data.receipt,
+    };
+
+    let content = pdf::render(&document, locale)
+        .map_err(|e| Status::internal(format!("Could not render receipt: {}", e)))?;
+
+    Ok(EmailAttachment {
+        name: pdf::file_name(&data.invoice_id),
+        content_type: pdf::CONTENT_TYPE.to_string(),
+        content,
+    })
+}
+
+/// Composes the confirmation email for the collected data, in the user's language,
+/// with the PDF receipt attached
+fn confirmation_message(
+    data: &ConfirmationData,
+    locale: &Locale,
+    tz: Tz,
+) -> Result<EmailMessage, Status> {
+    Ok(EmailMessage {
+        from: AETHERIC_EMAIL_ADDRESS.to_string(),
+        to: data.booking.user.email.clone(),
+        template: CARGO_CONFIRMATION.name.to_string(),
+        model: confirmation_model(data, locale, tz)?,
+        locale: locale.clone(),
+        body: None,
+        headers: vec![],
+        attachments: vec![receipt_attachment(data, locale, tz)?],
+    })
+}
+
+/// Composes the confirmation text message in the user's language,
+/// if the customer provided a phone number
+fn confirmation_sms(
+    data: &ConfirmationData,
+    locale: &Locale,
+    tz: Tz,
+) -> Result<Option<SmsMessage>, Status> {
+    let Some(phone_number) = data.phone_number.as_ref() else {
+        return Ok(None);
+    };
+
+    let model = confirmation_model(data, locale, tz)?;
+    let text = crate::templates::get_renderer()
+        .and_then(|renderer| renderer.render_sms(CARGO_CONFIRMATION.name, locale, &model))
+        .map_err(|e| Status::internal(format!("Could not render text message: {}", e)))?;
+
+    Ok(Some(SmsMessage {
+        to: phone_number.clone(),
+        text,
+    }))
+}
+
+/// Hands the confirmation email to the delivery backend.
+/// The number of attempts is reported in the response, or in the
+/// `x-delivery-attempts` and `x-failure-reason` metadata of the error status.
+async fn send_confirmation(
+    backend: &dyn EmailBackend,
+    log: &DeliveryLog<'_>,
+    data: &ConfirmationData,
+    recipient: &Recipient,
+    message: EmailMessage,
+    policy: &RetryPolicy,
+) -> Result<CargoConfirmationResponse, Status> {
+    let address = mask_address(&message.to);
+    let sent = notify::send_email(backend, log, recipient, message, policy).await?;
+
+    Ok(CargoConfirmationResponse {
+        success: true,
+        attempts: sent.attempts,
+        message_id: Some(sent.message_id),
+        channels: vec![DeliveryChannel::Email as i32],
+        recipient: address,
+        invoice_id: data.invoice_id.clone(),
+        failure_reason: FailureReason::None as i32,
+        // set by the caller, see notify::check_verified
+        email_verified: false,
+    })
+}
+
+/// Response of a confirmation that is only sent by text message,
+/// as the user disabled emails
+fn sms_only_response(data: &ConfirmationData) -> CargoConfirmationResponse {
+    CargoConfirmationResponse {
+        success: true,
+        attempts: 0,
+        message_id: None,
+        channels: vec![],
+        recipient: String::new(),
+        invoice_id: data.invoice_id.clone(),
+        failure_reason: FailureReason::None as i32,
+        email_verified: false,
+    }
+}
+
+/// Adds the outcome of the confirmation text message to the response.
+/// A failed text message only fails the confirmation when no email was sent.
+fn add_sms_outcome(response: &mut CargoConfirmationResponse, sent: bool) -> Result<(), Status> {
+    if sent {
+        response.push_channels(DeliveryChannel::Sms);
+    } else if response.channels.is_empty() {
+        return Err(notify::with_failure_reason(
+            Status::internal("Could not send text message"),
+            FailureReason::SmsFailed,
+        ));
+    } else {
+        response.set_failure_reason(FailureReason::SmsFailed);
+    }
+
+    Ok(())
+}
+
+/// Returns the idempotency key of a request,
+/// the parcel ID and itinerary ID unless the caller provided a key
+fn idempotency_key(request: &CargoConfirmationRequest) -> String {
+    match request.idempotency_key.as_deref() {
+        Some(key) if !key.is_empty() => key.to_string(),
+        _ => format!("{}:{}", request.parcel_id, request.itinerary_id),
+    }
+}
+
+/// Runs the notification unless a request with the same key completed
+/// within the idempotency window, in which case its result is returned.
+pub(crate) async fn run_idempotent<T, F>(
+    idempotency: &Idempotency<'_>,
+    key: &str,
+    notification: F,
+) -> Result<T, Status>
+where
+    T: Serialize + DeserializeOwned,
+    F: Future<Output = Result<T, Status>>,
+{
+    match idempotency.claim(key).await {
+        Ok(Claim::New) => (),
+        Ok(Claim::Done(response)) => {
+            grpc_info!("returning previous result for idempotency key {}.", key);
+            return Ok(response);
+        }
+        Ok(Claim::InProgress) => {
+            return Err(Status::aborted(format!(
+                "Request with idempotency key {} is in progress",
+                key
+            )));
+        }
+        Err(e) => {
+            // a duplicate email is better than a lost notification
+            grpc_warn!("idempotency not available, sending anyway: {}", e);
+            return notification.await;
+        }
+    }
+
+    let result = notification.await;
+    let stored = match &result {
+        Ok(response) => idempotency.complete(key, response).await,
+        Err(_) => idempotency.release(key).await,
+    };
+
+    if let Err(e) = stored {
+        grpc_warn!("could not store result for idempotency key {}: {}", key, e);
+    }
+
+    result
+}
+
+/// Sends a confirmation email, and text message if requested, to the user.
+/// Retries of a request within the idempotency window return the first result.
 #[cfg(not(tarpaulin_include))]
 // no_coverage: (Rnever) not unit testable, only integration tests
 pub async fn cargo_confirmation(
     request: CargoConfirmationRequest,
 ) -> Result<CargoConfirmationResponse, Status> {
     grpc_info!("entry.");
+    confirm(request, None).await
+}
 
-    let padding = Duration::try_minutes(10)
-        .ok_or_else(|| Status::internal("Could not create time padding"))?;
-
-    let dt_format = "%Y-%m-%d %H:%M UTC%z";
+/// Sends the confirmation of a job from the notification queue,
+/// recording its delivery under the ID of the queued job.
+/// See [`cargo_confirmation`].
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+pub async fn queued_cargo_confirmation(
+    request: CargoConfirmationRequest,
+    delivery_id: String,
+) -> Result<CargoConfirmationResponse, Status> {
+    confirm(request, Some(delivery_id)).await
+}
 
-    let clients = crate::grpc::client::get_clients().await;
+/// Sends the confirmation unless the request was handled
+/// within the idempotency window
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+async fn confirm(
+    request: CargoConfirmationRequest,
+    delivery_id: Option<String>,
+) -> Result<CargoConfirmationResponse, Status> {
+    let window = crate::store::idempotency::get_window().await;
+    if window.is_zero() {
+        return send_cargo_confirmation(request, delivery_id).await;
+    }
 
-    let parcel_data = get_parcel_data(clients, &request.parcel_id).await?;
+    let store = crate::store::get_store()
+        .await
+        .map_err(|e| Status::internal(format!("Store not available: {}", e)))?;
+    let idempotency = Idempotency::new(store, CARGO_CONFIRMATION.name, window);
+    let key = idempotency_key(&request);
+
+    run_idempotent(
+        &idempotency,
+        &key,
+        send_cargo_confirmation(request, delivery_id),
+    )
+    .await
+}
 
-    let origin_vertiport_data =
-        get_vertiport_data(clients, &parcel_data.origin_vertiport_id).await?;
+/// Gathers a booked parcel, its vertiports and its user from svc-storage
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+async fn get_booking_data(
+    clients: &GrpcClients,
+    parcel_id: String,
+    itinerary_id: String,
+) -> Result<BookingData, Status> {
+    let parcel = get_parcel_data(clients, &parcel_id).await?;
+    let origin_vertiport = get_vertiport_data(clients, &parcel.origin_vertiport_id).await?;
+    let target_vertiport = get_vertiport_data(clients, &parcel.target_vertiport_id).await?;
+
+    let user_id = get_itinerary_user_id(clients, &itinerary_id).await?;
+    let user = get_user_data(clients, &user_id).await?;
+
+    Ok(BookingData {
+        user_id,
+        parcel_id,
+        itinerary_id,
+        user,
+        parcel,
+        origin_vertiport,
+        target_vertiport,
+    })
+}
 
-    let target_vertiport_data =
-        get_vertiport_data(clients, &parcel_data.target_vertiport_id).await?;
+/// Computes the receipt of a confirmation from the requested prices
+fn request_receipt(request: &CargoConfirmationRequest) -> Result<Receipt, Status> {
+    if request.price_lines.is_empty() {
+        // deprecated, kept until svc-cargo sends price lines
+        grpc_warn!(
+            "deprecated confirmation without price lines for parcel {}, sending an empty receipt.",
+            request.parcel_id
+        );
+    }
 
-    let dropoff_time = (parcel_data.target_timeslot_end - padding)
-        .format(dt_format)
-        .to_string();
+    let lines = request
+        .price_lines
+        .iter()
+        .map(|line| (line.description.as_str(), line.amount.as_str()));
 
-    let pickup_time = (parcel_data.origin_timeslot_start + padding)
-        .format(dt_format)
-        .to_string();
+    Receipt::new(lines, &request.tax_rate, &request.currency)
+        .map_err(|e| Status::invalid_argument(e.to_string()))
+}
 
-    let user_id = clients
-        .storage
-        .itinerary
-        .get_by_id(Id {
-            id: request.itinerary_id,
+/// Returns the invoice number of a booking, assigned on its first confirmation
+async fn invoice_number(
+    invoices: &InvoiceNumbers<'_>,
+    booking: &BookingData,
+) -> Result<String, Status> {
+    let key = format!("{}:{}", booking.parcel_id, booking.itinerary_id);
+    invoices
+        .assign(&key, Utc::now())
+        .await
+        .map_err(|e| match e {
+            InvoiceError::InProgress(_) => Status::aborted(e.to_string()),
+            InvoiceError::Store(_) => {
+                Status::internal(format!("Invoice number not available: {}", e))
+            }
         })
+}
+
+/// Gathers the data needed to compose a confirmation from svc-storage,
+/// and the invoice number of the booking
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+async fn get_confirmation_data(
+    clients: &GrpcClients,
+    invoices: &InvoiceNumbers<'_>,
+    request: CargoConfirmationRequest,
+    receipt: Receipt,
+) -> Result<ConfirmationData, Status> {
+    let booking = get_booking_data(clients, request.parcel_id, request.itinerary_id).await?;
+    let invoice_id = invoice_number(invoices, &booking).await?;
+
+    Ok(ConfirmationData {
+        booking,
+        invoice_id,
+        issued_at: Utc::now(),
+        phone_number: request.phone_number.filter(|number| !number.is_empty()),
+        locale: request.locale,
+        receipt,
+    })
+}
+
+/// Gathers the confirmation data and sends the confirmation
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+async fn send_cargo_confirmation(
+    request: CargoConfirmationRequest,
+    delivery_id: Option<String>,
+) -> Result<CargoConfirmationResponse, Status> {
+    let receipt = request_receipt(&request)?;
+    let clients = crate::grpc::client::get_clients().await;
+    let backend = crate::delivery::email::get_backend().await.map_err(|e| {
+        let status = Status::internal(format!("Email backend not available: {}", e));
+        notify::with_failure_reason(status, FailureReason::Configuration)
+    })?;
+    let sms_backend = crate::delivery::sms::get_backend().await.map_err(|e| {
+        let status = Status::internal(format!("SMS backend not available: {}", e));
+        notify::with_failure_reason(status, FailureReason::Configuration)
+    })?;
+    let log = crate::delivery::history::get_log()
         .await
-        .map_err(|e| Status::internal(format!("Could not get itinerary: {}", e)))?
-        .into_inner()
-        .data
-        .ok_or_else(|| Status::internal("Itinerary data not found"))?
-        .user_id;
-
-    let user_data = get_user_data(clients, &user_id).await?;
-    let postmark_token = POSTMARK_TOKEN
-        .get()
-        .ok_or_else(|| Status::internal("Postmark token not found"))?;
-
-    // TODO(R5): Get these from svc-cargo. Not needed for demo.
-    let flight_price = 0.0;
-    let network_fee = 0.0;
-    let tax = 0.0;
-    let details = vec![
-        Details {
-            description: "Network Fee".to_string(),
-            amount: format!("{:.2}", network_fee),
-        },
-        Details {
-            description: "VAT".to_string(),
-            amount: format!("{:.2}", tax),
-        },
-    ];
-
-    let total_price = format!("{:.2}", flight_price + network_fee + tax);
-    let flight_price = format!("{:.2}", flight_price);
-    let currency = "EUR".to_string();
-    let invoice_date = Utc::now().format(dt_format).to_string();
-    // TODO(R5): no actual payments in demo
-    let invoice_id = rand::random::<u16>().to_string();
-
-    let client = PostmarkClient::builder()
-        .base_url(POSTMARK_API_URL)
-        .token(postmark_token.to_string())
-        .build();
+        .map_err(|e| Status::internal(format!("Delivery log not available: {}", e)))?;
+
+    let store = crate::store::get_store()
+        .await
+        .map_err(|e| Status::internal(format!("Store not available: {}", e)))?;
+
+    let invoices = InvoiceNumbers::new(store, crate::store::invoice::get_prefix().await);
+    let data = get_confirmation_data(clients, &invoices, request, receipt)
+        .await
+        .map_err(|status| notify::with_failure_reason(status, FailureReason::DataUnavailable))?;
+    let preferences =
+        notify::get_preferences(&PreferenceStore::new(store), &data.booking.user_id).await;
+    let channels = notify::check_channels(&preferences, CARGO_CONFIRMATION.kind, data.channels())?;
+    let locale = notify::resolve_locale(data.locale.as_deref(), &preferences)?;
+    let tz = preferences.tz();
+
+    // the first notification sent is recorded under the delivery ID
+    let mut recipient = data.booking.recipient();
+    recipient.delivery_id = delivery_id;
+
+    let mut response = if channels.email {
+        notify::check_deliverable(&Suppressions::new(store), &data.booking.user.email).await?;
+        let verified = notify::check_verified(
+            &VerifiedAddresses::new(store),
+            &data.booking.user_id,
+            &data.booking.user.email,
+            crate::delivery::verification::get_required().await,
+        )
+        .await?;
+        let message = confirmation_message(&data, &locale, tz)?;
+        let policy = crate::delivery::retry::get_policy().await;
+        let mut response =
+            send_confirmation(backend, log, &data, &recipient, message, policy).await?;
+        response.email_verified = verified;
+        recipient.delivery_id = None;
+        response
+    } else {
+        sms_only_response(&data)
+    };
+
+    if let Some(sms) = confirmation_sms(&data, &locale, tz)?.filter(|_| channels.sms) {
+        let sent =
+            notify::send_sms(sms_backend, log, &recipient, CARGO_CONFIRMATION.name, sms).await;
+        add_sms_outcome(&mut response, sent)?;
+    }
+
+    let lead_times = crate::scheduler::get_lead_times().await;
+    schedule_reminders(&ReminderSchedule::new(store), &data, lead_times).await;
+
+    Ok(response)
+}
+
+/// Registers the pickup reminders of a confirmed booking.
+/// Failing to register them is logged but does not fail the confirmation.
+async fn schedule_reminders(
+    schedule: &ReminderSchedule<'_>,
+    data: &ConfirmationData,
+    lead_times: &[std::time::Duration],
+) {
+    let booking = &data.booking;
+    let reminders = Reminder::for_booking(
+        &booking.parcel_id,
+        &booking.itinerary_id,
+        data.phone_number.as_deref(),
+        data.locale.as_deref(),
+        booking.parcel.origin_timeslot_start,
+        lead_times,
+        Utc::now(),
+    );
+
+    for reminder in reminders {
+        if let Err(e) = schedule.schedule(&reminder).await {
+            grpc_warn!("could not schedule reminder {}: {}", reminder.id, e);
+        }
+    }
+}
+
+/// Cancels the pending pickup reminders of a cancelled itinerary.
+/// Failing to cancel them is logged but does not fail the cancellation.
+async fn cancel_reminders(schedule: &ReminderSchedule<'_>, itinerary_id: &str) {
+    match schedule.cancel(itinerary_id).await {
+        Ok(0) => (),
+        Ok(cancelled) => grpc_info!(
+            "cancelled {} reminders of itinerary {}.",
+            cancelled,
+            itinerary_id
+        ),
+        Err(e) => grpc_warn!(
+            "could not cancel reminders of itinerary {}: {}",
+            itinerary_id,
+            e
+        ),
+    }
+}
+
+/// Composes the pickup reminder email, and text message if the customer
+/// provided a phone number on confirmation, in the user's language
+fn reminder_messages(
+    booking: &BookingData,
+    reminder: &Reminder,
+    locale: &Locale,
+    tz: Tz,
+) -> Result<(EmailMessage, Option<SmsMessage>), Status> {
+    let (pickup_time, _) = booking.pickup_dropoff_times(tz)?;
 
     let mut model = TemplateModel::default();
-    model.insert("customer_name", user_data.name);
-    model.insert("customer_dropoff_time", dropoff_time);
+    model.insert("customer_name", &booking.user.name);
+    model.insert("lead_time", lead_time_text(reminder.lead_time(), locale));
     model.insert("customer_pickup_time", pickup_time);
-    model.insert("parcel_weight_kg", format!("{:.2}", parcel_data.weight_kg));
-    model.insert("origin_vertiport_name", origin_vertiport_data.name);
-    model.insert("origin_vertiport_address", origin_vertiport_data.address);
-    model.insert("target_vertiport_name", target_vertiport_data.name);
-    model.insert("target_vertiport_address", target_vertiport_data.address);
-    model.insert("origin_latitude", parcel_data.origin_latitude);
-    model.insert("origin_longitude", parcel_data.origin_longitude);
-    model.insert("target_latitude", parcel_data.target_latitude);
-    model.insert("target_longitude", parcel_data.target_longitude);
-    model.insert("encoded_polyline", parcel_data.polyline);
-    model.insert("invoice_id", invoice_id);
-    model.insert("invoice_date", invoice_date);
-    model.insert("flight_price", flight_price);
-    model.insert("receipt_add_details", details);
-    model.insert("currency", currency);
-    model.insert("total_price", total_price);
-
-    let response = SendEmailWithTemplateRequest::builder()
-        .from(AETHERIC_EMAIL_ADDRESS)
-        .to(user_data.email)
-        .template_model(model)
-        .template_alias("demo-confirmation")
-        .build()
-        .execute(&client)
+    model.insert("origin_vertiport_name", &booking.origin_vertiport.name);
+    model.insert(
+        "origin_vertiport_address",
+        &booking.origin_vertiport.address,
+    );
+    model.insert("target_vertiport_name", &booking.target_vertiport.name);
+
+    let sms = match reminder.phone_number.as_ref() {
+        Some(phone_number) => {
+            let text = crate::templates::get_renderer()
+                .and_then(|renderer| renderer.render_sms(PICKUP_REMINDER.name, locale, &model))
+                .map_err(|e| Status::internal(format!("Could not render text message: {}", e)))?;
+            Some(SmsMessage {
+                to: phone_number.clone(),
+                text,
+            })
+        }
+        None => None,
+    };
+
+    let message = EmailMessage {
+        from: AETHERIC_EMAIL_ADDRESS.to_string(),
+        to: booking.user.email.clone(),
+        template: PICKUP_REMINDER.name.to_string(),
+        model,
+        locale: locale.clone(),
+        body: None,
+        headers: vec![],
+        attachments: vec![],
+    };
+
+    Ok((message, sms))
+}
+
+/// Sends a due pickup reminder to the user who booked the parcel.
+/// The booking is looked up again, so the reminder shows the current pickup time;
+/// reminders of bookings whose origin timeslot has started are skipped.
+/// The preferences of the user are checked when the reminder is due.
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+pub(crate) async fn pickup_reminder(reminder: &Reminder) -> Result<(), Status> {
+    grpc_info!("entry.");
+
+    let clients = crate::grpc::client::get_clients().await;
+    let backend = crate::delivery::email::get_backend().await.map_err(|e| {
+        let status = Status::internal(format!("Email backend not available: {}", e));
+        notify::with_failure_reason(status, FailureReason::Configuration)
+    })?;
+    let sms_backend = crate::delivery::sms::get_backend().await.map_err(|e| {
+        let status = Status::internal(format!("SMS backend not available: {}", e));
+        notify::with_failure_reason(status, FailureReason::Configuration)
+    })?;
+    let log = crate::delivery::history::get_log()
+        .await
+        .map_err(|e| Status::internal(format!("Delivery log not available: {}", e)))?;
+
+    let booking = get_booking_data(
+        clients,
+        reminder.parcel_id.clone(),
+        reminder.itinerary_id.clone(),
+    )
+    .await
+    .map_err(|status| notify::with_failure_reason(status, FailureReason::DataUnavailable))?;
+
+    if booking.parcel.origin_timeslot_start <= Utc::now() {
+        grpc_info!("skipping reminder {}, pickup has started.", reminder.id);
+        return Ok(());
+    }
+
+    let store = crate::store::get_store()
         .await
-        .map_err(|e| Status::internal(format!("Could not send email: {}", e)))?;
+        .map_err(|e| Status::internal(format!("Store not available: {}", e)))?;
+    let preferences = notify::get_preferences(&PreferenceStore::new(store), &booking.user_id).await;
+    let requested = Channels {
+        email: true,
+        sms: reminder.phone_number.is_some(),
+    };
+    let channels = notify::check_channels(&preferences, PICKUP_REMINDER.kind, requested)?;
+    let locale = notify::resolve_locale(reminder.locale.as_deref(), &preferences)?;
+
+    let (message, sms) = reminder_messages(&booking, reminder, &locale, preferences.tz())?;
+    let recipient = booking.recipient();
+    if channels.email {
+        notify::check_deliverable(&Suppressions::new(store), &booking.user.email).await?;
+        let policy = crate::delivery::retry::get_policy().await;
+        notify::send_email(backend, log, &recipient, message, policy).await?;
+    }
 
-    let success = response.error_code == 0;
-    if !success {
-        grpc_error!("Could not send email: {:?}", response);
-        return Err(Status::internal("Could not send email"));
+    if let Some(sms) = sms.filter(|_| channels.sms) {
+        notify::send_sms(sms_backend, log, &recipient, PICKUP_REMINDER.name, sms).await;
     }
 
-    grpc_info!("success={}.", success);
-    Ok(CargoConfirmationResponse { success })
+    Ok(())
+}
+
+/// Formats a refund given in the minor units of its currency, for the locale,
+/// e.g. `12,50` for 1250 EUR cents and `1.250` for 1250 JPY in `nl`
+fn format_refund(amount: u64, currency: &str, locale: &Locale) -> String {
+    receipt::format_amount(
+        receipt::from_minor_units(amount, currency),
+        currency,
+        locale,
+    )
+}
+
+/// Returns the ISO 4217 currency code of a refund, EUR if none was provided
+fn refund_currency(currency: &str) -> Result<String, Status> {
+    receipt::currency_code(currency).map_err(|e| Status::invalid_argument(e.to_string()))
+}
+
+/// Composes the cancellation email for the collected data, in the user's language
+fn cancellation_message(
+    data: &CancellationData,
+    locale: &Locale,
+    tz: Tz,
+) -> Result<EmailMessage, Status> {
+    let booking = &data.booking;
+    let (pickup_time, _) = booking.pickup_dropoff_times(tz)?;
+
+    let mut model = TemplateModel::default();
+    model.insert("customer_name", &booking.user.name);
+    model.insert("customer_pickup_time", pickup_time);
+    model.insert("origin_vertiport_name", &booking.origin_vertiport.name);
+    model.insert("target_vertiport_name", &booking.target_vertiport.name);
+    // the templates explain the reason in the language of the message
+    model.insert("cancellation_reason", data.reason.as_str_name());
+    model.insert("refund", data.refund_amount_cents > 0);
+    model.insert(
+        "refund_amount",
+        format_refund(data.refund_amount_cents, &data.currency, locale),
+    );
+    model.insert("currency", &data.currency);
+
+    Ok(EmailMessage {
+        from: AETHERIC_EMAIL_ADDRESS.to_string(),
+        to: booking.user.email.clone(),
+        template: CARGO_CANCELLATION.name.to_string(),
+        model,
+        locale: locale.clone(),
+        body: None,
+        headers: vec![],
+        attachments: vec![],
+    })
+}
+
+/// Sends a cancellation email to the user who booked the itinerary
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+pub async fn cargo_cancellation(
+    request: CargoCancellationRequest,
+) -> Result<CargoCancellationResponse, Status> {
+    grpc_info!("entry.");
+    let currency = refund_currency(&request.currency)?;
+    let reason = request.reason();
+
+    let store = crate::store::get_store()
+        .await
+        .map_err(|e| Status::internal(format!("Store not available: {}", e)))?;
+    cancel_reminders(&ReminderSchedule::new(store), &request.itinerary_id).await;
+
+    let clients = crate::grpc::client::get_clients().await;
+    let backend = crate::delivery::email::get_backend().await.map_err(|e| {
+        let status = Status::internal(format!("Email backend not available: {}", e));
+        notify::with_failure_reason(status, FailureReason::Configuration)
+    })?;
+    let log = crate::delivery::history::get_log()
+        .await
+        .map_err(|e| Status::internal(format!("Delivery log not available: {}", e)))?;
+
+    let booking = get_booking_data(clients, request.parcel_id, request.itinerary_id)
+        .await
+        .map_err(|status| notify::with_failure_reason(status, FailureReason::DataUnavailable))?;
+
+    let preferences = notify::get_preferences(&PreferenceStore::new(store), &booking.user_id).await;
+    notify::check_channels(&preferences, CARGO_CANCELLATION.kind, Channels::EMAIL)?;
+    notify::check_deliverable(&Suppressions::new(store), &booking.user.email).await?;
+    let locale = notify::resolve_locale(request.locale.as_deref(), &preferences)?;
+
+    let data = CancellationData {
+        booking,
+        reason,
+        refund_amount_cents: request.refund_amount_cents,
+        currency,
+    };
+    let message = cancellation_message(&data, &locale, preferences.tz())?;
+    let recipient = mask_address(&message.to);
+    let policy = crate::delivery::retry::get_policy().await;
+    let sent = notify::send_email(backend, log, &data.booking.recipient(), message, policy).await?;
+
+    Ok(CargoCancellationResponse {
+        success: true,
+        attempts: sent.attempts,
+        message_id: Some(sent.message_id),
+        recipient,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::delivery::email::stub::StubBackend;
+    use crate::delivery::history::{Channel, DeliveryQuery, DeliveryRecord, DeliveryStatus};
+    use crate::delivery::{DeliveryError, DeliveryReceipt};
+    use crate::grpc::api::notify::{ATTEMPTS_METADATA_KEY, FAILURE_REASON_METADATA_KEY};
+    use crate::grpc::server::PriceLine;
+    use crate::store::memory::MemoryStore;
     use svc_storage_client_grpc::prelude::{GeoLineStringZ, GeoPointZ};
+    use tonic::Code;
+
+    fn booking_data() -> BookingData {
+        BookingData {
+            user_id: "user".to_string(),
+            parcel_id: "parcel".to_string(),
+            itinerary_id: "itinerary".to_string(),
+            user: UserData {
+                name: "Alice".to_string(),
+                email: "alice@aetheric.nl".to_string(),
+            },
+            parcel: ParcelData {
+                itinerary_id: "itinerary".to_string(),
+                weight_kg: 1.5,
+                origin_vertiport_id: "origin".to_string(),
+                target_vertiport_id: "target".to_string(),
+                origin_timeslot_start: "2024-01-01T10:00:00Z".parse().unwrap(),
+                target_timeslot_end: "2024-01-01T11:00:00Z".parse().unwrap(),
+                origin_latitude: 52.37,
+                origin_longitude: 4.89,
+                target_latitude: 52.09,
+                target_longitude: 5.12,
+                polyline: "_p~iF~ps|U".to_string(),
+            },
+            origin_vertiport: VertiportData {
+                name: "Amsterdam".to_string(),
+                address: "Dam 1".to_string(),
+                timezone: Some(Tz::Europe__Amsterdam),
+            },
+            target_vertiport: VertiportData {
+                name: "Utrecht".to_string(),
+                address: "Domplein 1".to_string(),
+                timezone: Some(Tz::Europe__Amsterdam),
+            },
+        }
+    }
+
+    fn confirmation_data() -> ConfirmationData {
+        ConfirmationData {
+            booking: booking_data(),
+            invoice_id: "AET-2024-000042".to_string(),
+            issued_at: "2024-01-01T09:00:00Z".parse().unwrap(),
+            phone_number: Some("+31611111111".to_string()),
+            locale: None,
+            receipt: Receipt::new([("Flight", "40.00"), ("Network fee", "2.50")], "21", "EUR")
+                .unwrap(),
+        }
+    }
+
+    /// Fails every message with the provided error
+    #[derive(Debug)]
+    struct FailingBackend(DeliveryError);
+
+    #[tonic::async_trait]
+    impl EmailBackend for FailingBackend {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        async fn send(&self, _message: &EmailMessage) -> Result<DeliveryReceipt, DeliveryError> {
+            Err(self.0.clone())
+        }
+    }
+
+    fn delivery_log(store: &MemoryStore) -> DeliveryLog<'_> {
+        DeliveryLog::new(store, std::time::Duration::from_secs(60 * 60))
+    }
+
+    async fn recorded(log: &DeliveryLog<'_>) -> Vec<DeliveryRecord> {
+        log.query(&DeliveryQuery {
+            user_id: Some("user".to_string()),
+            limit: 10,
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+    }
+
+    fn retry_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 2,
+            base_delay: std::time::Duration::from_millis(1),
+            max_delay: std::time::Duration::from_millis(1),
+        }
+    }
+
+    #[test]
+    fn test_schedule_timezone() {
+        assert_eq!(
+            schedule_timezone(
+                "DTSTART;TZID=Europe/Amsterdam:20240101T080000;DURATION:PT14H\n\
+                RRULE:FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR"
+            ),
+            Some(Tz::Europe__Amsterdam)
+        );
+        assert_eq!(
+            schedule_timezone("DTSTART;TZID=\"America/New_York\":20240101T080000"),
+            Some(Tz::America__New_York)
+        );
+        assert_eq!(
+            schedule_timezone("DTSTART:20240101T080000Z;DURATION:PT24H"),
+            None
+        );
+        assert_eq!(
+            schedule_timezone("DTSTART;TZID=Europe/Nowhere:20240101T080000"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_pickup_dropoff_times() {
+        let mut booking = booking_data();
+        let (pickup_time, dropoff_time) = booking.pickup_dropoff_times(Tz::UTC).unwrap();
+        assert_eq!(pickup_time, "2024-01-01 11:10 CET");
+        assert_eq!(dropoff_time, "2024-01-01 11:50 CET");
+
+        // summer time
+        booking.parcel.origin_timeslot_start = "2024-07-01T10:00:00Z".parse().unwrap();
+        booking.parcel.target_timeslot_end = "2024-07-01T11:00:00Z".parse().unwrap();
+        let (pickup_time, _) = booking.pickup_dropoff_times(Tz::UTC).unwrap();
+        assert_eq!(pickup_time, "2024-07-01 12:10 CEST");
+
+        // vertiports without a timezone fall back to the user's timezone
+        booking.origin_vertiport.timezone = Some(Tz::America__New_York);
+        booking.target_vertiport.timezone = None;
+        let (pickup_time, dropoff_time) = booking.pickup_dropoff_times(Tz::UTC).unwrap();
+        assert_eq!(pickup_time, "2024-07-01 06:10 EDT");
+        assert_eq!(dropoff_time, "2024-07-01 10:50 UTC");
+
+        let (_, dropoff_time) = booking.pickup_dropoff_times(Tz::Asia__Kolkata).unwrap();
+        assert_eq!(dropoff_time, "2024-07-01 16:20 IST");
+    }
+
+    #[test]
+    fn test_confirmation_message() {
+        let message =
+            confirmation_message(&confirmation_data(), &Locale::default(), Tz::UTC).unwrap();
+        assert_eq!(message.from, AETHERIC_EMAIL_ADDRESS);
+        assert_eq!(message.to, "alice@aetheric.nl");
+        assert_eq!(message.template, "cargo-confirmation");
+        CARGO_CONFIRMATION.validate(&message.model).unwrap();
+
+        let field = |key: &str| message.model.get(key).unwrap().clone();
+        assert_eq!(field("customer_name"), "Alice");
+        assert_eq!(field("customer_pickup_time"), "2024-01-01 11:10 CET");
+        assert_eq!(field("customer_dropoff_time"), "2024-01-01 11:50 CET");
+        assert_eq!(field("parcel_weight_kg"), "1.50");
+        assert_eq!(field("origin_vertiport_name"), "Amsterdam");
+        assert_eq!(field("target_vertiport_address"), "Domplein 1");
+        assert_eq!(field("invoice_id"), "AET-2024-000042");
+        assert_eq!(field("invoice_date"), "2024-01-01 09:00 UTC");
+        assert_eq!(field("receipt_lines")[0]["description"], "Flight");
+        assert_eq!(field("receipt_lines")[0]["amount"], "40.00");
+        assert_eq!(field("subtotal"), "42.50");
+        assert_eq!(field("tax_rate"), "21");
+        assert_eq!(field("tax"), "8.93");
+        assert_eq!(field("total_price"), "51.43");
+        assert_eq!(field("currency"), "EUR");
+
+        assert_eq!(message.attachments.len(), 1);
+        let attachment = &message.attachments[0];
+        assert_eq!(attachment.name, "receipt-AET-2024-000042.pdf");
+        assert_eq!(attachment.content_type, "application/pdf");
+        assert!(attachment.content.starts_with(b"%PDF-"));
+
+        let locale = Locale::parse("nl-BE").unwrap();
+        let message = confirmation_message(&confirmation_data(), &locale, Tz::UTC).unwrap();
+        assert_eq!(message.locale, locale);
+        CARGO_CONFIRMATION.validate(&message.model).unwrap();
+
+        let field = |key: &str| message.model.get(key).unwrap().clone();
+        assert_eq!(field("parcel_weight_kg"), "1,50");
+        assert_eq!(field("receipt_lines")[1]["amount"], "2,50");
+        assert_eq!(field("tax"), "8,93");
+        assert_eq!(field("total_price"), "51,43");
+
+        let body = crate::templates::get_renderer()
+            .unwrap()
+            .render(&message.template, &message.locale, &message.model)
+            .unwrap();
+        assert!(body.text.contains("Btw (21%): 8,93 EUR"));
+    }
+
+    #[test]
+    fn test_confirmation_sms() {
+        let mut data = confirmation_data();
+        let sms = confirmation_sms(&data, &Locale::default(), Tz::UTC)
+            .unwrap()
+            .unwrap();
+        assert_eq!(sms.to, "+31611111111");
+        assert_eq!(
+            sms.text,
+            "Aetheric: your parcel (1.50 kg) is booked. \
+            Pickup at Amsterdam 2024-01-01 11:10 CET, \
+            dropoff at Utrecht 2024-01-01 11:50 CET."
+        );
+
+        let locale = Locale::parse("nl").unwrap();
+        let sms = confirmation_sms(&data, &locale, Tz::UTC).unwrap().unwrap();
+        assert_eq!(
+            sms.text,
+            "Aetheric: je pakket (1,50 kg) is geboekt. \
+            Ophalen bij Amsterdam 2024-01-01 11:10 CET, \
+            afleveren bij Utrecht 2024-01-01 11:50 CET."
+        );
+
+        data.phone_number = None;
+        assert_eq!(confirmation_sms(&data, &locale, Tz::UTC).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_send_confirmation() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let log = delivery_log(&store);
+        let data = confirmation_data();
+        let backend = StubBackend::default();
+        let message = confirmation_message(&data, &Locale::default(), Tz::UTC).unwrap();
+        let response = send_confirmation(
+            &backend,
+            &log,
+            &data,
+            &data.booking.recipient(),
+            message.clone(),
+            &retry_policy(),
+        )
+        .await
+        .unwrap();
+        assert!(response.success);
+        assert_eq!(response.attempts, 1);
+        assert!(response.message_id.is_some());
+        assert_eq!(
+            response.channels().collect::<Vec<_>>(),
+            vec![DeliveryChannel::Email]
+        );
+        assert_eq!(response.recipient, "a***e@aetheric.nl");
+        assert_eq!(response.invoice_id, "AET-2024-000042");
+        assert_eq!(response.failure_reason(), FailureReason::None);
+
+        let sent = backend.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].model, message.model);
+        let body = sent[0].body.as_ref().unwrap();
+        assert!(body.text.contains("Hi Alice,"));
+
+        let backend = FailingBackend(DeliveryError::Provider {
+            code: 406,
+            message: "Inactive recipient".to_string(),
+        });
+        let error = send_confirmation(
+            &backend,
+            &log,
+            &data,
+            &data.booking.recipient(),
+            message.clone(),
+            &retry_policy(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code(), Code::Internal);
+        assert_eq!(error.metadata().get(ATTEMPTS_METADATA_KEY).unwrap(), "1");
+        assert_eq!(
+            error.metadata().get(FAILURE_REASON_METADATA_KEY).unwrap(),
+            "FAILURE_REASON_REJECTED"
+        );
+
+        let backend = FailingBackend(DeliveryError::Transport("connection reset".to_string()));
+        let error = send_confirmation(
+            &backend,
+            &log,
+            &data,
+            &data.booking.recipient(),
+            message,
+            &retry_policy(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code(), Code::Internal);
+        assert_eq!(error.metadata().get(ATTEMPTS_METADATA_KEY).unwrap(), "2");
+        assert_eq!(
+            error.metadata().get(FAILURE_REASON_METADATA_KEY).unwrap(),
+            "FAILURE_REASON_UNAVAILABLE"
+        );
+
+        let records = recorded(&log).await;
+        assert_eq!(records.len(), 3);
+        assert!(records.iter().all(|record| record.channel == Channel::Email
+            && record.parcel_id.as_deref() == Some("parcel")
+            && record.itinerary_id.as_deref() == Some("itinerary")));
+        let sent = records
+            .iter()
+            .filter(|record| record.status == DeliveryStatus::Sent)
+            .count();
+        assert_eq!(sent, 1);
+
+        ut_info!("Success.");
+    }
+
+    #[test]
+    fn test_add_sms_outcome() {
+        let mut response = CargoConfirmationResponse {
+            success: true,
+            channels: vec![DeliveryChannel::Email as i32],
+            ..Default::default()
+        };
+
+        add_sms_outcome(&mut response, true).unwrap();
+        assert_eq!(
+            response.channels().collect::<Vec<_>>(),
+            vec![DeliveryChannel::Email, DeliveryChannel::Sms]
+        );
+        assert_eq!(response.failure_reason(), FailureReason::None);
+
+        let mut response = CargoConfirmationResponse {
+            success: true,
+            channels: vec![DeliveryChannel::Email as i32],
+            ..Default::default()
+        };
+        add_sms_outcome(&mut response, false).unwrap();
+        assert_eq!(
+            response.channels().collect::<Vec<_>>(),
+            vec![DeliveryChannel::Email]
+        );
+        assert_eq!(response.failure_reason(), FailureReason::SmsFailed);
+
+        // without an email, a failed text message fails the confirmation
+        let mut response = sms_only_response(&confirmation_data());
+        assert_eq!(response.invoice_id, "AET-2024-000042");
+        add_sms_outcome(&mut response, true).unwrap();
+        assert_eq!(
+            response.channels().collect::<Vec<_>>(),
+            vec![DeliveryChannel::Sms]
+        );
+
+        let mut response = sms_only_response(&confirmation_data());
+        let error = add_sms_outcome(&mut response, false).unwrap_err();
+        assert_eq!(error.message(), "Could not send text message");
+        assert_eq!(
+            error.metadata().get(FAILURE_REASON_METADATA_KEY).unwrap(),
+            "FAILURE_REASON_SMS_FAILED"
+        );
+    }
+
+    #[test]
+    fn test_confirmation_channels() {
+        let mut data = confirmation_data();
+        assert_eq!(
+            data.channels(),
+            Channels {
+                email: true,
+                sms: true
+            }
+        );
+
+        data.phone_number = None;
+        assert_eq!(
+            data.channels(),
+            Channels {
+                email: true,
+                sms: false
+            }
+        );
+    }
+
+    #[test]
+    fn test_reminder_messages() {
+        let booking = booking_data();
+        let mut reminder = Reminder::for_booking(
+            &booking.parcel_id,
+            &booking.itinerary_id,
+            Some("+31611111111"),
+            None,
+            booking.parcel.origin_timeslot_start,
+            &[std::time::Duration::from_secs(60 * 60)],
+            booking.parcel.origin_timeslot_start - Duration::try_hours(2).unwrap(),
+        )
+        .pop()
+        .unwrap();
+
+        let (message, sms) =
+            reminder_messages(&booking, &reminder, &Locale::default(), Tz::UTC).unwrap();
+        assert_eq!(message.to, "alice@aetheric.nl");
+        assert_eq!(message.template, "pickup-reminder");
+        PICKUP_REMINDER.validate(&message.model).unwrap();
+
+        let field = |key: &str| message.model.get(key).unwrap().clone();
+        assert_eq!(field("lead_time"), "in 1 hour");
+        assert_eq!(field("customer_pickup_time"), "2024-01-01 11:10 CET");
+
+        let sms = sms.unwrap();
+        assert_eq!(sms.to, "+31611111111");
+        assert_eq!(
+            sms.text,
+            "Aetheric: your parcel pickup at Amsterdam, Dam 1 is in 1 hour \
+            (2024-01-01 11:10 CET)."
+        );
+
+        let locale = Locale::parse("nl-BE").unwrap();
+        let (message, sms) = reminder_messages(&booking, &reminder, &locale, Tz::UTC).unwrap();
+        assert_eq!(message.locale, locale);
+        assert_eq!(message.model.get("lead_time").unwrap(), "over 1 uur");
+        assert_eq!(
+            sms.unwrap().text,
+            "Aetheric: je pakket wordt over 1 uur opgehaald bij Amsterdam, Dam 1 \
+            (2024-01-01 11:10 CET)."
+        );
+
+        reminder.phone_number = None;
+        let (_, sms) = reminder_messages(&booking, &reminder, &locale, Tz::UTC).unwrap();
+        assert!(sms.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_schedule_and_cancel_reminders() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let schedule = ReminderSchedule::new(&store);
+        let lead_times = [
+            std::time::Duration::from_secs(24 * 60 * 60),
+            std::time::Duration::from_secs(60 * 60),
+        ];
+
+        // the origin timeslot of the test booking has passed
+        schedule_reminders(&schedule, &confirmation_data(), &lead_times).await;
+        let later = Utc::now() + Duration::try_days(2).unwrap();
+        assert!(schedule.take_due(later).await.unwrap().is_empty());
+
+        let mut data = confirmation_data();
+        data.booking.parcel.origin_timeslot_start = Utc::now() + Duration::try_hours(30).unwrap();
+        data.locale = Some("nl-BE".to_string());
+        schedule_reminders(&schedule, &data, &lead_times).await;
+        cancel_reminders(&schedule, "other-itinerary").await;
+
+        let due = schedule
+            .take_due(Utc::now() + Duration::try_hours(7).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].itinerary_id, "itinerary");
+        assert_eq!(due[0].lead_minutes, 24 * 60);
+        assert_eq!(due[0].phone_number, Some("+31611111111".to_string()));
+        assert_eq!(due[0].locale, Some("nl-BE".to_string()));
+
+        cancel_reminders(&schedule, "itinerary").await;
+        assert!(schedule.take_due(later).await.unwrap().is_empty());
+
+        ut_info!("Success.");
+    }
+
+    #[test]
+    fn test_cancellation_message() {
+        let data = CancellationData {
+            booking: booking_data(),
+            reason: CancellationReason::Weather,
+            refund_amount_cents: 1250,
+            currency: "EUR".to_string(),
+        };
+        let message = cancellation_message(&data, &Locale::default(), Tz::UTC).unwrap();
+        assert_eq!(message.from, AETHERIC_EMAIL_ADDRESS);
+        assert_eq!(message.to, "alice@aetheric.nl");
+        assert_eq!(message.template, "cargo-cancellation");
+        CARGO_CANCELLATION.validate(&message.model).unwrap();
+
+        let field = |key: &str| message.model.get(key).unwrap().clone();
+        assert_eq!(field("customer_name"), "Alice");
+        assert_eq!(field("customer_pickup_time"), "2024-01-01 11:10 CET");
+        assert_eq!(field("origin_vertiport_name"), "Amsterdam");
+        assert_eq!(field("cancellation_reason"), "CANCELLATION_REASON_WEATHER");
+        assert_eq!(field("refund"), true);
+        assert_eq!(field("refund_amount"), "12.50");
+        assert_eq!(field("currency"), "EUR");
+
+        let body = crate::templates::get_renderer()
+            .unwrap()
+            .render(&message.template, &message.locale, &message.model)
+            .unwrap();
+        assert!(body.text.contains("12.50 EUR will be refunded"));
+
+        let locale = Locale::parse("nl-BE").unwrap();
+        let message = cancellation_message(&data, &locale, Tz::UTC).unwrap();
+        let body = crate::templates::get_renderer()
+            .unwrap()
+            .render(&message.template, &message.locale, &message.model)
+            .unwrap();
+        assert!(body.text.contains("Hallo Alice,"));
+        assert!(body
+            .text
+            .contains("Het weer laat niet toe dat we je pakket veilig vliegen."));
+        assert!(body
+            .text
+            .contains("12,50 EUR wordt teruggestort via je oorspronkelijke betaalmethode."));
+    }
+
+    #[test]
+    fn test_cancellation_reasons() {
+        let renderer = crate::templates::get_renderer().unwrap();
+        let explanation = |reason: CancellationReason, locale: &Locale| {
+            let data = CancellationData {
+                booking: booking_data(),
+                reason,
+                refund_amount_cents: 0,
+                currency: "EUR".to_string(),
+            };
+            let message = cancellation_message(&data, locale, Tz::UTC).unwrap();
+            let body = renderer
+                .render(&message.template, &message.locale, &message.model)
+                .unwrap();
+            body.text.lines().nth(3).unwrap().to_string()
+        };
+
+        let en = Locale::default();
+        let nl = Locale::parse("nl").unwrap();
+        assert_eq!(
+            explanation(CancellationReason::CustomerRequest, &en),
+            "Your booking was cancelled at your request."
+        );
+        assert_eq!(
+            explanation(CancellationReason::CustomerRequest, &nl),
+            "Je boeking is op je verzoek geannuleerd."
+        );
+
+        // every reason the proto defines has its own text in every language
+        let unspecified = explanation(CancellationReason::Unspecified, &en);
+        for value in 1.. {
+            let Ok(reason) = CancellationReason::try_from(value) else {
+                break;
+            };
+            assert_ne!(explanation(reason, &en), unspecified);
+            assert_ne!(explanation(reason, &nl), explanation(reason, &en));
+        }
+    }
+
+    #[test]
+    fn test_format_refund() {
+        let en = Locale::default();
+        assert_eq!(format_refund(0, "EUR", &en), "0.00");
+        assert_eq!(format_refund(5, "EUR", &en), "0.05");
+        assert_eq!(format_refund(1250, "EUR", &en), "12.50");
+        assert_eq!(format_refund(123456, "EUR", &en), "1,234.56");
+        assert_eq!(format_refund(1250, "JPY", &en), "1,250");
+        assert_eq!(format_refund(1250, "KWD", &en), "1.250");
+
+        let nl = Locale::parse("nl-BE").unwrap();
+        assert_eq!(format_refund(1250, "EUR", &nl), "12,50");
+        assert_eq!(format_refund(123456, "EUR", &nl), "1.234,56");
+    }
+
+    #[test]
+    fn test_refund_currency() {
+        assert_eq!(refund_currency("").unwrap(), "EUR");
+        assert_eq!(refund_currency(" usd ").unwrap(), "USD");
+
+        let error = refund_currency("EURO").unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument);
+        assert_eq!(error.message(), "Invalid currency: EURO");
+        assert!(refund_currency("E1R").is_err());
+    }
+
+    #[test]
+    fn test_request_receipt() {
+        let mut request = CargoConfirmationRequest {
+            parcel_id: "parcel".to_string(),
+            itinerary_id: "itinerary".to_string(),
+            phone_number: None,
+            idempotency_key: None,
+            locale: None,
+            price_lines: vec![PriceLine {
+                description: "Flight".to_string(),
+                amount: "40.00".to_string(),
+            }],
+            tax_rate: "9".to_string(),
+            currency: "usd".to_string(),
+        };
+        let receipt = request_receipt(&request).unwrap();
+        assert_eq!(receipt.currency, "USD");
+        assert_eq!(receipt.total.to_string(), "43.60");
+
+        request.price_lines[0].amount = "40.001".to_string();
+        let error = request_receipt(&request).unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument);
+        assert_eq!(error.message(), "Invalid amount: 40.001");
+
+        // deprecated, confirmations without price lines get an empty receipt
+        request.price_lines.clear();
+        request.tax_rate = String::new();
+        request.currency = String::new();
+        assert_eq!(request_receipt(&request).unwrap(), Receipt::default());
+    }
+
+    #[test]
+    fn test_idempotency_key() {
+        let mut request = CargoConfirmationRequest {
+            parcel_id: "parcel".to_string(),
+            itinerary_id: "itinerary".to_string(),
+            phone_number: None,
+            idempotency_key: None,
+            locale: None,
+            price_lines: vec![],
+            tax_rate: String::new(),
+            currency: String::new(),
+        };
+        assert_eq!(idempotency_key(&request), "parcel:itinerary");
+
+        request.idempotency_key = Some(String::new());
+        assert_eq!(idempotency_key(&request), "parcel:itinerary");
+
+        request.idempotency_key = Some("retry-1".to_string());
+        assert_eq!(idempotency_key(&request), "retry-1");
+    }
+
+    #[tokio::test]
+    async fn test_invoice_number() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let invoices = InvoiceNumbers::new(&store, "AET-");
+        let booking = booking_data();
+        let year = Utc::now().format("%Y");
+
+        let number = invoice_number(&invoices, &booking).await.unwrap();
+        assert_eq!(number, format!("AET-{}-000001", year));
+        // resent confirmations keep the number
+        assert_eq!(invoice_number(&invoices, &booking).await.unwrap(), number);
+
+        let mut booking = booking_data();
+        booking.itinerary_id = "other".to_string();
+        let number = invoice_number(&invoices, &booking).await.unwrap();
+        assert_eq!(number, format!("AET-{}-000002", year));
+
+        booking.itinerary_id = "pending".to_string();
+        store
+            .set(
+                &crate::store::key("invoice", "parcel:pending"),
+                "pending",
+                None,
+            )
+            .await
+            .unwrap();
+        let error = invoice_number(&invoices, &booking).await.unwrap_err();
+        assert_eq!(error.code(), Code::Aborted);
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_run_idempotent() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let idempotency = Idempotency::new(&store, "test", std::time::Duration::from_secs(60));
+        let response = CargoConfirmationResponse {
+            success: true,
+            attempts: 2,
+            message_id: Some("message".to_string()),
+            channels: vec![DeliveryChannel::Email as i32],
+            recipient: "a***e@aetheric.nl".to_string(),
+            invoice_id: "AET-2024-000042".to_string(),
+            failure_reason: FailureReason::None as i32,
+            email_verified: true,
+        };
+
+        // a failed confirmation can be retried
+        let error = run_idempotent(&idempotency, "a", async {
+            Err(Status::internal("Could not send email"))
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(error.code(), Code::Internal);
+
+        let result = run_idempotent(&idempotency, "a", async { Ok(response.clone()) }).await;
+        assert_eq!(result.unwrap(), response);
+
+        // a completed confirmation is not sent again
+        let result = run_idempotent(&idempotency, "a", async {
+            panic!("confirmation sent twice");
+        })
+        .await;
+        assert_eq!(result.unwrap(), response);
+
+        // a confirmation in progress is not sent concurrently
+        assert_eq!(
+            idempotency
+                .claim::<CargoConfirmationResponse>("b")
+                .await
+                .unwrap(),
+            Claim::New
+        );
+        let error = run_idempotent(&idempotency, "b", async { Ok(response.clone()) })
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), Code::Aborted);
+
+        ut_info!("Success.");
+    }
 
     #[test]
     fn test_try_from_flight_plan_object() {
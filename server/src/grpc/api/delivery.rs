@@ -0,0 +1,608 @@
+//! Delivery-related handlers
+
+use crate::delivery::history::{
+    self, Channel, DeliveryLog, DeliveryQuery, DeliveryRecord, DeliveryStatus,
+};
+use crate::delivery::DeliveryError;
+use crate::grpc::server::{self as grpc, DeliveryQueryRequest, DeliveryQueryResponse};
+use crate::grpc::server::{DeliveryStatusUpdate, DeliveryWatchRequest};
+use crate::store::StoreError;
+use crate::Config;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, Stream};
+use prost_types::Timestamp;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use tokio::time::{sleep, Instant};
+use tonic::Status;
+
+/// Number of deliveries returned when the request doesn't set a limit
+const DEFAULT_LIMIT: u32 = 100;
+
+/// Maximum number of deliveries returned for a single request
+const MAX_LIMIT: u32 = 1000;
+
+/// Settings of the delivery status streams
+static WATCH_OPTIONS: OnceCell<WatchOptions> = OnceCell::const_new();
+
+/// Stream of delivery status updates returned by the `watchDelivery` RPC
+pub type DeliveryStatusStream =
+    Pin<Box<dyn Stream<Item = Result<DeliveryStatusUpdate, Status>> + Send>>;
+
+/// How a delivery status stream checks for status changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchOptions {
+    /// Delay between two status checks
+    pub interval: Duration,
+
+    /// How long to wait for a final status
+    pub timeout: Duration,
+}
+
+impl WatchOptions {
+    /// Creates the options from the `status_*` configuration options
+    pub fn new(config: &Config) -> Self {
+        WatchOptions {
+            interval: Duration::from_millis(config.status_poll_interval_ms),
+            timeout: Duration::from_secs(config.status_watch_timeout_secs),
+        }
+    }
+}
+
+/// Returns WATCH_OPTIONS, created from a Config object generated from
+/// environment variables on first use.
+pub async fn get_watch_options() -> &'static WatchOptions {
+    WATCH_OPTIONS
+        .get_or_init(|| async move {
+            let config = Config::try_from_env().unwrap_or_default();
+            WatchOptions::new(&config)
+        })
+        .await
+}
+
+/// Where a delivery status stream is at
+#[derive(Debug)]
+enum WatchState {
+    /// A status to send to the client
+    Update(DeliveryRecord),
+
+    /// Waiting for the status of a record to change
+    Poll {
+        id: String,
+        status: DeliveryStatus,
+        deadline: Instant,
+    },
+
+    /// Stream ended
+    Done,
+}
+
+impl From<Channel> for grpc::DeliveryChannel {
+    fn from(channel: Channel) -> Self {
+        match channel {
+            Channel::Email => grpc::DeliveryChannel::Email,
+            Channel::Sms => grpc::DeliveryChannel::Sms,
+        }
+    }
+}
+
+impl From<DeliveryStatus> for grpc::DeliveryStatus {
+    fn from(status: DeliveryStatus) -> Self {
+        match status {
+            DeliveryStatus::Queued => grpc::DeliveryStatus::Queued,
+            DeliveryStatus::Sent => grpc::DeliveryStatus::Sent,
+            DeliveryStatus::Failed => grpc::DeliveryStatus::Failed,
+            DeliveryStatus::Delivered => grpc::DeliveryStatus::Delivered,
+            DeliveryStatus::Bounced => grpc::DeliveryStatus::Bounced,
+            DeliveryStatus::Complained => grpc::DeliveryStatus::Complained,
+        }
+    }
+}
+
+impl From<&DeliveryError> for grpc::FailureReason {
+    fn from(error: &DeliveryError) -> Self {
+        match error {
+            DeliveryError::Configuration(_) => grpc::FailureReason::Configuration,
+            DeliveryError::Transport(_)
+            | DeliveryError::Unavailable(_)
+            | DeliveryError::Unconfirmed(_) => grpc::FailureReason::Unavailable,
+            DeliveryError::Message(_) | DeliveryError::Provider { .. } => {
+                grpc::FailureReason::Rejected
+            }
+        }
+    }
+}
+
+/// Converts a date and time to a protobuf timestamp
+pub fn to_timestamp(datetime: DateTime<Utc>) -> Timestamp {
+    Timestamp {
+        seconds: datetime.timestamp(),
+        nanos: datetime.timestamp_subsec_nanos() as i32,
+    }
+}
+
+/// Converts a protobuf timestamp to a date and time
+pub fn from_timestamp(timestamp: &Timestamp) -> Result<DateTime<Utc>, Status> {
+    u32::try_from(timestamp.nanos)
+        .ok()
+        .and_then(|nanos| DateTime::from_timestamp(timestamp.seconds, nanos))
+        .ok_or_else(|| Status::invalid_argument(format!("Invalid timestamp: {}", timestamp)))
+}
+
+impl From<DeliveryRecord> for grpc::Delivery {
+    fn from(record: DeliveryRecord) -> Self {
+        grpc::Delivery {
+            id: record.id,
+            user_id: record.user_id,
+            channel: grpc::DeliveryChannel::from(record.channel) as i32,
+            recipient: record.recipient,
+            template: record.template,
+            message_id: record.message_id,
+            status: grpc::DeliveryStatus::from(record.status) as i32,
+            error: record.error,
+            parcel_id: record.parcel_id,
+            itinerary_id: record.itinerary_id,
+            created_at: Some(to_timestamp(record.created_at)),
+            updated_at: Some(to_timestamp(record.updated_at)),
+        }
+    }
+}
+
+impl TryFrom<DeliveryQueryRequest> for DeliveryQuery {
+    type Error = Status;
+
+    fn try_from(request: DeliveryQueryRequest) -> Result<Self, Self::Error> {
+        let query = DeliveryQuery {
+            user_id: request.user_id.filter(|id| !id.is_empty()),
+            itinerary_id: request.itinerary_id.filter(|id| !id.is_empty()),
+            from: request.from.as_ref().map(from_timestamp).transpose()?,
+            to: request.to.as_ref().map(from_timestamp).transpose()?,
+            limit: request.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize,
+        };
+
+        if let (Some(from), Some(to)) = (query.from, query.to) {
+            if from > to {
+                return Err(Status::invalid_argument(
+                    "Query start time is after its end time",
+                ));
+            }
+        }
+
+        Ok(query)
+    }
+}
+
+/// Returns the notifications sent to a user, for an itinerary or within a time range
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+pub async fn query_deliveries(
+    request: DeliveryQueryRequest,
+) -> Result<DeliveryQueryResponse, Status> {
+    grpc_info!("entry.");
+
+    let query = DeliveryQuery::try_from(request)?;
+    let log = history::get_log()
+        .await
+        .map_err(|e| Status::internal(format!("Delivery log not available: {}", e)))?;
+
+    let records = log.query(&query).await.map_err(|e| {
+        grpc_error!("Could not query delivery log: {}", e);
+        Status::internal(format!("Could not query delivery log: {}", e))
+    })?;
+
+    Ok(DeliveryQueryResponse {
+        deliveries: records.into_iter().map(grpc::Delivery::from).collect(),
+    })
+}
+
+/// Finds the delivery record of a notification ID, either the provider
+/// message ID returned when the notification was sent, or the record ID
+pub async fn find_delivery(
+    log: DeliveryLog<'_>,
+    notification_id: &str,
+) -> Result<DeliveryRecord, Status> {
+    if notification_id.is_empty() {
+        return Err(Status::invalid_argument("Missing notification ID"));
+    }
+
+    let error = |e: StoreError| {
+        grpc_error!("Could not read delivery log: {}", e);
+        Status::internal(format!("Could not read delivery log: {}", e))
+    };
+
+    if let Some(record) = log
+        .find_by_message_id(notification_id)
+        .await
+        .map_err(error)?
+    {
+        return Ok(record);
+    }
+
+    log.get(notification_id)
+        .await
+        .map_err(error)?
+        .ok_or_else(|| Status::not_found(format!("Unknown notification: {}", notification_id)))
+}
+
+/// Returns the update to send for a record, and where the stream is at after it
+fn status_update(record: DeliveryRecord, deadline: Instant) -> (DeliveryStatusUpdate, WatchState) {
+    let done = record.is_final();
+    let next = if done {
+        WatchState::Done
+    } else {
+        WatchState::Poll {
+            id: record.id.clone(),
+            status: record.status,
+            deadline,
+        }
+    };
+
+    let update = DeliveryStatusUpdate {
+        delivery: Some(grpc::Delivery::from(record)),
+        done,
+    };
+    (update, next)
+}
+
+/// Streams the status of a delivery record, starting with its current status.
+/// The record is checked for changes every interval, only changes are sent.
+/// The stream ends after a final status, or with a DEADLINE_EXCEEDED error
+/// when no final status was reached within the timeout.
+pub fn watch<'a>(
+    log: DeliveryLog<'a>,
+    record: DeliveryRecord,
+    options: WatchOptions,
+) -> impl Stream<Item = Result<DeliveryStatusUpdate, Status>> + Send + 'a {
+    let deadline = Instant::now() + options.timeout;
+    stream::unfold(WatchState::Update(record), move |state| async move {
+        match state {
+            WatchState::Done => None,
+            WatchState::Update(record) => {
+                let (update, next) = status_update(record, deadline);
+                Some((Ok(update), next))
+            }
+            WatchState::Poll {
+                id,
+                status,
+                deadline,
+            } => loop {
+                sleep(options.interval).await;
+
+                match log.get(&id).await {
+                    Err(e) => {
+                        grpc_error!("Could not read delivery log: {}", e);
+                        let error = Status::internal(format!("Could not read delivery log: {}", e));
+                        return Some((Err(error), WatchState::Done));
+                    }
+                    Ok(None) => {
+                        let error = Status::not_found(format!("Delivery {} expired", id));
+                        return Some((Err(error), WatchState::Done));
+                    }
+                    Ok(Some(record)) if record.status != status => {
+                        let (update, next) = status_update(record, deadline);
+                        return Some((Ok(update), next));
+                    }
+                    Ok(Some(_)) => (),
+                }
+
+                if Instant::now() >= deadline {
+                    let error = Status::deadline_exceeded(format!(
+                        "No final status of delivery {} within {} seconds",
+                        id,
+                        options.timeout.as_secs()
+                    ));
+                    return Some((Err(error), WatchState::Done));
+                }
+            },
+        }
+    })
+}
+
+/// Streams the status transitions of a notification until it reaches a final status
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+pub async fn watch_delivery(request: DeliveryWatchRequest) -> Result<DeliveryStatusStream, Status> {
+    grpc_info!("entry.");
+
+    let log = history::get_log()
+        .await
+        .map_err(|e| Status::internal(format!("Delivery log not available: {}", e)))?;
+    let record = find_delivery(*log, &request.notification_id).await?;
+    let options = get_watch_options().await;
+
+    Ok(Box::pin(watch(*log, record, *options)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delivery::DeliveryReceipt;
+    use crate::store::memory::MemoryStore;
+    use chrono::TimeZone;
+    use futures::StreamExt;
+
+    fn sent_record(message_id: &str) -> DeliveryRecord {
+        DeliveryRecord::new(
+            "alice",
+            Channel::Email,
+            "alice@aetheric.nl",
+            "cargo-confirmation",
+            &Ok(DeliveryReceipt {
+                message_id: message_id.to_string(),
+            }),
+        )
+    }
+
+    fn watch_options(timeout_ms: u64) -> WatchOptions {
+        WatchOptions {
+            interval: Duration::from_millis(5),
+            timeout: Duration::from_millis(timeout_ms),
+        }
+    }
+
+    fn statuses(
+        updates: &[Result<DeliveryStatusUpdate, Status>],
+    ) -> Vec<(grpc::DeliveryStatus, bool)> {
+        updates
+            .iter()
+            .map(|update| {
+                let update = update.as_ref().unwrap();
+                (update.delivery.as_ref().unwrap().status(), update.done)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_timestamp_conversion() {
+        let datetime = Utc.with_ymd_and_hms(2024, 1, 1, 10, 0, 0).unwrap();
+        let timestamp = to_timestamp(datetime);
+        assert_eq!(timestamp.seconds, 1704103200);
+        assert_eq!(timestamp.nanos, 0);
+        assert_eq!(from_timestamp(&timestamp).unwrap(), datetime);
+
+        let invalid = Timestamp {
+            seconds: 0,
+            nanos: -1,
+        };
+        assert_eq!(
+            from_timestamp(&invalid).unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_delivery_query_try_from() {
+        let request = DeliveryQueryRequest {
+            user_id: Some("alice".to_string()),
+            itinerary_id: Some(String::new()),
+            from: None,
+            to: None,
+            limit: None,
+        };
+        let query = DeliveryQuery::try_from(request.clone()).unwrap();
+        assert_eq!(query.user_id, Some("alice".to_string()));
+        assert_eq!(query.itinerary_id, None);
+        assert_eq!(query.limit, DEFAULT_LIMIT as usize);
+
+        let query = DeliveryQuery::try_from(DeliveryQueryRequest {
+            limit: Some(u32::MAX),
+            ..request.clone()
+        })
+        .unwrap();
+        assert_eq!(query.limit, MAX_LIMIT as usize);
+
+        let from = Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let error = DeliveryQuery::try_from(DeliveryQueryRequest {
+            from: Some(to_timestamp(from)),
+            to: Some(to_timestamp(to)),
+            ..request
+        })
+        .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[test]
+    fn test_failure_reason_from_delivery_error() {
+        let reason = |error: DeliveryError| grpc::FailureReason::from(&error);
+        assert_eq!(
+            reason(DeliveryError::Configuration("no token".to_string())),
+            grpc::FailureReason::Configuration
+        );
+        assert_eq!(
+            reason(DeliveryError::Transport("timeout".to_string())),
+            grpc::FailureReason::Unavailable
+        );
+        assert_eq!(
+            reason(DeliveryError::Unavailable("maintenance".to_string())),
+            grpc::FailureReason::Unavailable
+        );
+        assert_eq!(
+            reason(DeliveryError::Unconfirmed("invalid response".to_string())),
+            grpc::FailureReason::Unavailable
+        );
+        assert_eq!(
+            reason(DeliveryError::Message("no body".to_string())),
+            grpc::FailureReason::Rejected
+        );
+        assert_eq!(
+            reason(DeliveryError::Provider {
+                code: 406,
+                message: "Inactive recipient".to_string()
+            }),
+            grpc::FailureReason::Rejected
+        );
+    }
+
+    #[test]
+    fn test_delivery_from_record() {
+        let mut record = DeliveryRecord::new(
+            "alice",
+            Channel::Sms,
+            "+31611111111",
+            "cargo-confirmation",
+            &Ok(DeliveryReceipt {
+                message_id: "SM0001".to_string(),
+            }),
+        );
+        record.itinerary_id = Some("trip".to_string());
+
+        let delivery = grpc::Delivery::from(record.clone());
+        assert_eq!(delivery.id, record.id);
+        assert_eq!(delivery.channel(), grpc::DeliveryChannel::Sms);
+        assert_eq!(delivery.status(), grpc::DeliveryStatus::Sent);
+        assert_eq!(delivery.message_id, Some("SM0001".to_string()));
+        assert_eq!(delivery.itinerary_id, Some("trip".to_string()));
+        assert_eq!(delivery.created_at, Some(to_timestamp(record.created_at)));
+    }
+
+    #[test]
+    fn test_watch_options_new() {
+        let mut config = Config::default();
+        config.status_poll_interval_ms = 250;
+        config.status_watch_timeout_secs = 60;
+        let options = WatchOptions::new(&config);
+        assert_eq!(options.interval, Duration::from_millis(250));
+        assert_eq!(options.timeout, Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn test_find_delivery() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let log = DeliveryLog::new(&store, Duration::from_secs(60));
+        let record = sent_record("msg-1");
+        log.record(&record).await.unwrap();
+
+        assert_eq!(find_delivery(log, "msg-1").await.unwrap(), record);
+        assert_eq!(find_delivery(log, &record.id).await.unwrap(), record);
+        assert_eq!(
+            find_delivery(log, "unknown").await.unwrap_err().code(),
+            tonic::Code::NotFound
+        );
+        assert_eq!(
+            find_delivery(log, "").await.unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_watch() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let log = DeliveryLog::new(&store, Duration::from_secs(60));
+        let record = sent_record("msg-1");
+        log.record(&record).await.unwrap();
+
+        // the status changes while the stream is running, unchanged checks are skipped
+        let updates = watch(log, record, watch_options(5000)).collect::<Vec<_>>();
+        let webhook = async {
+            sleep(Duration::from_millis(30)).await;
+            log.update_status("msg-1", DeliveryStatus::Delivered, None)
+                .await
+                .unwrap()
+                .unwrap();
+        };
+        let (updates, _) = tokio::join!(updates, webhook);
+        assert_eq!(
+            statuses(&updates),
+            [
+                (grpc::DeliveryStatus::Sent, false),
+                (grpc::DeliveryStatus::Delivered, true)
+            ]
+        );
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_watch_final_status() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let log = DeliveryLog::new(&store, Duration::from_secs(60));
+        let record = DeliveryRecord::new(
+            "alice",
+            Channel::Email,
+            "alice@aetheric.nl",
+            "cargo-confirmation",
+            &Err(DeliveryError::Transport("timeout".to_string())),
+        );
+        log.record(&record).await.unwrap();
+
+        let updates: Vec<_> = watch(log, record, watch_options(5000)).collect().await;
+        assert_eq!(statuses(&updates), [(grpc::DeliveryStatus::Failed, true)]);
+
+        // text messages have no delivery reports
+        let mut record = sent_record("SM0001");
+        record.channel = Channel::Sms;
+        log.record(&record).await.unwrap();
+        let updates: Vec<_> = watch(log, record, watch_options(5000)).collect().await;
+        assert_eq!(statuses(&updates), [(grpc::DeliveryStatus::Sent, true)]);
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_watch_queued() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let log = DeliveryLog::new(&store, Duration::from_secs(60));
+        let record = DeliveryRecord::queued("p1:i1", Channel::Email, "cargo-confirmation");
+        log.record_queued(&record).await.unwrap();
+
+        // the sent email replaces the queued record
+        let updates = watch(log, record, watch_options(5000)).collect::<Vec<_>>();
+        let job = async {
+            sleep(Duration::from_millis(30)).await;
+            let mut record = sent_record("msg-1");
+            record.id = "p1:i1".to_string();
+            log.record(&record).await.unwrap();
+            sleep(Duration::from_millis(30)).await;
+            log.update_status("msg-1", DeliveryStatus::Delivered, None)
+                .await
+                .unwrap()
+                .unwrap();
+        };
+        let (updates, _) = tokio::join!(updates, job);
+        assert_eq!(
+            statuses(&updates),
+            [
+                (grpc::DeliveryStatus::Queued, false),
+                (grpc::DeliveryStatus::Sent, false),
+                (grpc::DeliveryStatus::Delivered, true)
+            ]
+        );
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_watch_timeout() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let log = DeliveryLog::new(&store, Duration::from_secs(60));
+        let record = sent_record("msg-1");
+        log.record(&record).await.unwrap();
+
+        let mut updates: Vec<_> = watch(log, record, watch_options(20)).collect().await;
+        assert_eq!(updates.len(), 2);
+        let error = updates.pop().unwrap().unwrap_err();
+        assert_eq!(error.code(), tonic::Code::DeadlineExceeded);
+        assert_eq!(statuses(&updates), [(grpc::DeliveryStatus::Sent, false)]);
+
+        ut_info!("Success.");
+    }
+}
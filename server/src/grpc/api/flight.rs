@@ -0,0 +1,527 @@
+//! Flight-related handlers
+
+use super::cargo::AETHERIC_EMAIL_ADDRESS;
+use super::cargo::{get_itinerary_user_id, get_parcel_data, get_user_data, get_vertiport_data};
+use super::cargo::{pickup_dropoff_times, ParcelData, UserData, VertiportData};
+use super::delivery::from_timestamp;
+use super::notify::{self, EmailSent, Recipient};
+use crate::delivery::email::{EmailBackend, EmailMessage, TemplateModel};
+use crate::delivery::history::DeliveryLog;
+use crate::delivery::preferences::{Channels, PreferenceStore};
+use crate::delivery::retry::RetryPolicy;
+use crate::delivery::suppression::Suppressions;
+use crate::grpc::client::GrpcClients;
+use crate::grpc::server::NotificationResult;
+use crate::grpc::server::{FailureReason, FlightDelayRequest, FlightDelayResponse};
+use crate::locale::Locale;
+use crate::scheduler::ReminderSchedule;
+use crate::store::Store;
+use crate::templates::FLIGHT_DELAY;
+use chrono_tz::Tz;
+use lib_common::time::{DateTime, Utc};
+use prost_types::Timestamp;
+use svc_storage_client_grpc::prelude::AdvancedSearchFilter;
+use svc_storage_client_grpc::simple_service_linked::Client as _;
+use tonic::Status;
+
+/// Old and new times of a rescheduled flight
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FlightDelay {
+    flight_plan_id: String,
+    old_origin_timeslot_start: DateTime<Utc>,
+    new_origin_timeslot_start: DateTime<Utc>,
+    old_target_timeslot_end: DateTime<Utc>,
+    new_target_timeslot_end: DateTime<Utc>,
+}
+
+/// A parcel on the rescheduled flight
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct ParcelLeg {
+    parcel_id: String,
+
+    /// The parcel is picked up by this flight
+    acquire: bool,
+
+    /// The parcel is delivered by this flight
+    deliver: bool,
+}
+
+/// Everything needed to compose a delay notification for one parcel
+struct DelayNotice {
+    recipient: Recipient,
+    user: UserData,
+    parcel: ParcelData,
+    origin_vertiport: VertiportData,
+    target_vertiport: VertiportData,
+}
+
+/// Converts a required request timestamp
+fn required(timestamp: Option<&Timestamp>, name: &str) -> Result<DateTime<Utc>, Status> {
+    timestamp
+        .ok_or_else(|| Status::invalid_argument(format!("Missing {}", name)))
+        .and_then(from_timestamp)
+}
+
+impl TryFrom<FlightDelayRequest> for FlightDelay {
+    type Error = Status;
+
+    fn try_from(request: FlightDelayRequest) -> Result<Self, Self::Error> {
+        if request.flight_plan_id.is_empty() {
+            return Err(Status::invalid_argument("Missing flight plan ID"));
+        }
+
+        let delay = FlightDelay {
+            old_origin_timeslot_start: required(
+                request.old_origin_timeslot_start.as_ref(),
+                "old origin timeslot start",
+            )?,
+            new_origin_timeslot_start: required(
+                request.new_origin_timeslot_start.as_ref(),
+                "new origin timeslot start",
+            )?,
+            old_target_timeslot_end: required(
+                request.old_target_timeslot_end.as_ref(),
+                "old target timeslot end",
+            )?,
+            new_target_timeslot_end: required(
+                request.new_target_timeslot_end.as_ref(),
+                "new target timeslot end",
+            )?,
+            flight_plan_id: request.flight_plan_id,
+        };
+
+        if delay.new_target_timeslot_end <= delay.new_origin_timeslot_start {
+            return Err(Status::invalid_argument(
+                "New target timeslot end must be after the new origin timeslot start",
+            ));
+        }
+
+        Ok(delay)
+    }
+}
+
+impl FlightDelay {
+    /// Applies the new times to a parcel picked up or delivered by the flight
+    fn reschedule(&self, parcel: &mut ParcelData, leg: &ParcelLeg) {
+        if leg.acquire {
+            parcel.origin_timeslot_start = self.new_origin_timeslot_start;
+        }
+
+        if leg.deliver {
+            parcel.target_timeslot_end = self.new_target_timeslot_end;
+        }
+    }
+}
+
+/// Merges the `flight_plan_parcel` rows of the same parcel, keeping the order
+fn merge_legs(rows: impl IntoIterator<Item = ParcelLeg>) -> Vec<ParcelLeg> {
+    let mut legs: Vec<ParcelLeg> = vec![];
+    for row in rows {
+        match legs.iter_mut().find(|leg| leg.parcel_id == row.parcel_id) {
+            Some(leg) => {
+                leg.acquire |= row.acquire;
+                leg.deliver |= row.deliver;
+            }
+            None => legs.push(row),
+        }
+    }
+
+    legs
+}
+
+/// Composes the delay notification email for a parcel, in the user's language.
+/// Departure and arrival times are in the local time of the parcel's
+/// origin and target vertiport, or in the user's timezone `tz`.
+fn delay_message(
+    notice: &DelayNotice,
+    delay: &FlightDelay,
+    locale: &Locale,
+    tz: Tz,
+) -> Result<EmailMessage, Status> {
+    let origin = &notice.origin_vertiport;
+    let target = &notice.target_vertiport;
+    let (pickup_time, dropoff_time) = pickup_dropoff_times(&notice.parcel, origin, target, tz)?;
+
+    let mut model = TemplateModel::default();
+    model.insert("customer_name", &notice.user.name);
+    model.insert("customer_pickup_time", pickup_time);
+    model.insert("customer_dropoff_time", dropoff_time);
+    model.insert("origin_vertiport_name", &origin.name);
+    model.insert("origin_vertiport_address", &origin.address);
+    model.insert("target_vertiport_name", &target.name);
+    model.insert("target_vertiport_address", &target.address);
+    model.insert(
+        "old_departure_time",
+        origin.local_time(delay.old_origin_timeslot_start, tz),
+    );
+    model.insert(
+        "new_departure_time",
+        origin.local_time(delay.new_origin_timeslot_start, tz),
+    );
+    model.insert(
+        "old_arrival_time",
+        target.local_time(delay.old_target_timeslot_end, tz),
+    );
+    model.insert(
+        "new_arrival_time",
+        target.local_time(delay.new_target_timeslot_end, tz),
+    );
+
+    Ok(EmailMessage {
+        from: AETHERIC_EMAIL_ADDRESS.to_string(),
+        to: notice.user.email.clone(),
+        template: FLIGHT_DELAY.name.to_string(),
+        model,
+        locale: locale.clone(),
+        body: None,
+        headers: vec![],
+        attachments: vec![],
+    })
+}
+
+/// Reports the outcome of the notification about a parcel
+fn notification_result(parcel_id: &str, result: Result<EmailSent, Status>) -> NotificationResult {
+    match result {
+        Ok(sent) => NotificationResult {
+            parcel_id: parcel_id.to_string(),
+            success: true,
+            message_id: Some(sent.message_id),
+            failure_reason: FailureReason::None as i32,
+        },
+        Err(status) => {
+            grpc_warn!(
+                "could not notify parcel {} of delay: {}",
+                parcel_id,
+                status.message()
+            );
+
+            NotificationResult {
+                parcel_id: parcel_id.to_string(),
+                success: false,
+                message_id: None,
+                failure_reason: notify::failure_reason(&status) as i32,
+            }
+        }
+    }
+}
+
+/// Returns the parcels on a flight from svc-storage
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+async fn get_parcel_legs(
+    clients: &GrpcClients,
+    flight_plan_id: &str,
+) -> Result<Vec<ParcelLeg>, Status> {
+    let filter = AdvancedSearchFilter::search_equals(
+        "flight_plan_id".to_string(),
+        flight_plan_id.to_string(),
+    );
+
+    let rows = clients
+        .storage
+        .flight_plan_parcel
+        .search(filter)
+        .await
+        .map_err(|e| Status::internal(format!("Could not get flight plan parcels: {}", e)))?
+        .into_inner()
+        .list
+        .into_iter()
+        .map(|row| ParcelLeg {
+            parcel_id: row.parcel_id,
+            acquire: row.acquire,
+            deliver: row.deliver,
+        });
+
+    Ok(merge_legs(rows))
+}
+
+/// Gathers the data needed to compose a delay notification from svc-storage
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+async fn get_delay_notice(
+    clients: &GrpcClients,
+    leg: &ParcelLeg,
+    delay: &FlightDelay,
+) -> Result<DelayNotice, Status> {
+    let mut parcel = get_parcel_data(clients, &leg.parcel_id).await?;
+    delay.reschedule(&mut parcel, leg);
+
+    let origin_vertiport = get_vertiport_data(clients, &parcel.origin_vertiport_id).await?;
+    let target_vertiport = get_vertiport_data(clients, &parcel.target_vertiport_id).await?;
+    let user_id = get_itinerary_user_id(clients, &parcel.itinerary_id).await?;
+    let user = get_user_data(clients, &user_id).await?;
+
+    Ok(DelayNotice {
+        recipient: Recipient {
+            user_id,
+            parcel_id: Some(leg.parcel_id.clone()),
+            itinerary_id: Some(parcel.itinerary_id.clone()),
+            delivery_id: None,
+        },
+        user,
+        parcel,
+        origin_vertiport,
+        target_vertiport,
+    })
+}
+
+/// Moves the pickup reminders of a parcel picked up by the delayed flight.
+/// Failing to move them is logged but does not fail the notification.
+async fn reschedule_reminders(
+    schedule: &ReminderSchedule<'_>,
+    leg: &ParcelLeg,
+    parcel: &ParcelData,
+) {
+    if !leg.acquire {
+        return;
+    }
+
+    let result = schedule
+        .reschedule(
+            &leg.parcel_id,
+            &parcel.itinerary_id,
+            parcel.origin_timeslot_start,
+            Utc::now(),
+        )
+        .await;
+    match result {
+        Ok(count) => grpc_debug!(
+            "rescheduled {} reminder(s) of parcel {}.",
+            count,
+            leg.parcel_id
+        ),
+        Err(e) => grpc_warn!(
+            "could not reschedule reminders of parcel {}: {}",
+            leg.parcel_id,
+            e
+        ),
+    }
+}
+
+/// Notifies the customer of a single parcel of the delay
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+async fn notify_parcel(
+    clients: &GrpcClients,
+    store: &dyn Store,
+    backend: &dyn EmailBackend,
+    log: &DeliveryLog<'_>,
+    policy: &RetryPolicy,
+    leg: &ParcelLeg,
+    delay: &FlightDelay,
+) -> Result<EmailSent, Status> {
+    let notice = get_delay_notice(clients, leg, delay)
+        .await
+        .map_err(|status| notify::with_failure_reason(status, FailureReason::DataUnavailable))?;
+    reschedule_reminders(&ReminderSchedule::new(store), leg, &notice.parcel).await;
+
+    let preferences =
+        notify::get_preferences(&PreferenceStore::new(store), &notice.recipient.user_id).await;
+    notify::check_channels(&preferences, FLIGHT_DELAY.kind, Channels::EMAIL)?;
+    notify::check_deliverable(&Suppressions::new(store), &notice.user.email).await?;
+    let message = delay_message(&notice, delay, &preferences.locale(), preferences.tz())?;
+    notify::send_email(backend, log, &notice.recipient, message, policy).await
+}
+
+/// Notifies the customers of every parcel on a rescheduled flight
+/// of their new pickup and dropoff times.
+/// A failure to notify one customer doesn't stop the others.
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+pub async fn flight_delay_notification(
+    request: FlightDelayRequest,
+) -> Result<FlightDelayResponse, Status> {
+    grpc_info!("entry.");
+    let delay = FlightDelay::try_from(request)?;
+
+    let clients = crate::grpc::client::get_clients().await;
+    let backend = crate::delivery::email::get_backend()
+        .await
+        .map_err(|e| Status::internal(format!("Email backend not available: {}", e)))?;
+    let log = crate::delivery::history::get_log()
+        .await
+        .map_err(|e| Status::internal(format!("Delivery log not available: {}", e)))?;
+    let store = crate::store::get_store()
+        .await
+        .map_err(|e| Status::internal(format!("Store not available: {}", e)))?;
+    let policy = crate::delivery::retry::get_policy().await;
+
+    let legs = get_parcel_legs(clients, &delay.flight_plan_id).await?;
+    let mut results = vec![];
+    for leg in legs.iter() {
+        let result = notify_parcel(clients, store, backend, log, policy, leg, &delay).await;
+        results.push(notification_result(&leg.parcel_id, result));
+    }
+
+    let notified = results.iter().filter(|result| result.success).count() as u32;
+    grpc_info!(
+        "notified {} of {} parcel(s) on flight plan {}.",
+        notified,
+        results.len(),
+        delay.flight_plan_id
+    );
+
+    Ok(FlightDelayResponse { notified, results })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grpc::api::delivery::to_timestamp;
+    use tonic::Code;
+
+    fn time(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    fn request() -> FlightDelayRequest {
+        FlightDelayRequest {
+            flight_plan_id: "flight".to_string(),
+            old_origin_timeslot_start: Some(to_timestamp(time("2024-01-01T10:00:00Z"))),
+            new_origin_timeslot_start: Some(to_timestamp(time("2024-01-01T10:30:00Z"))),
+            old_target_timeslot_end: Some(to_timestamp(time("2024-01-01T11:00:00Z"))),
+            new_target_timeslot_end: Some(to_timestamp(time("2024-01-01T11:30:00Z"))),
+        }
+    }
+
+    fn parcel() -> ParcelData {
+        ParcelData {
+            itinerary_id: "itinerary".to_string(),
+            weight_kg: 1.5,
+            origin_vertiport_id: "origin".to_string(),
+            target_vertiport_id: "target".to_string(),
+            origin_timeslot_start: time("2024-01-01T10:00:00Z"),
+            target_timeslot_end: time("2024-01-01T11:00:00Z"),
+            origin_latitude: 52.37,
+            origin_longitude: 4.89,
+            target_latitude: 52.09,
+            target_longitude: 5.12,
+            polyline: "_p~iF~ps|U".to_string(),
+        }
+    }
+
+    fn leg(parcel_id: &str, acquire: bool, deliver: bool) -> ParcelLeg {
+        ParcelLeg {
+            parcel_id: parcel_id.to_string(),
+            acquire,
+            deliver,
+        }
+    }
+
+    #[test]
+    fn test_try_from_flight_delay_request() {
+        let delay = FlightDelay::try_from(request()).unwrap();
+        assert_eq!(delay.flight_plan_id, "flight");
+        assert_eq!(
+            delay.new_origin_timeslot_start,
+            time("2024-01-01T10:30:00Z")
+        );
+        assert_eq!(delay.new_target_timeslot_end, time("2024-01-01T11:30:00Z"));
+
+        let mut invalid = request();
+        invalid.flight_plan_id = String::new();
+        let error = FlightDelay::try_from(invalid).unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument);
+
+        let mut invalid = request();
+        invalid.new_origin_timeslot_start = None;
+        let error = FlightDelay::try_from(invalid).unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument);
+        assert_eq!(error.message(), "Missing new origin timeslot start");
+
+        let mut invalid = request();
+        invalid.new_target_timeslot_end = invalid.new_origin_timeslot_start.clone();
+        let error = FlightDelay::try_from(invalid).unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument);
+    }
+
+    #[test]
+    fn test_reschedule() {
+        let delay = FlightDelay::try_from(request()).unwrap();
+
+        let mut data = parcel();
+        delay.reschedule(&mut data, &leg("parcel", true, true));
+        assert_eq!(data.origin_timeslot_start, time("2024-01-01T10:30:00Z"));
+        assert_eq!(data.target_timeslot_end, time("2024-01-01T11:30:00Z"));
+
+        // a later flight delivers the parcel
+        let mut data = parcel();
+        delay.reschedule(&mut data, &leg("parcel", true, false));
+        assert_eq!(data.origin_timeslot_start, time("2024-01-01T10:30:00Z"));
+        assert_eq!(data.target_timeslot_end, time("2024-01-01T11:00:00Z"));
+    }
+
+    #[test]
+    fn test_merge_legs() {
+        let legs = merge_legs(vec![
+            leg("a", true, false),
+            leg("b", false, true),
+            leg("a", false, true),
+        ]);
+        assert_eq!(legs, vec![leg("a", true, true), leg("b", false, true)]);
+    }
+
+    #[test]
+    fn test_delay_message() {
+        let delay = FlightDelay::try_from(request()).unwrap();
+        let mut notice = DelayNotice {
+            recipient: Recipient::default(),
+            user: UserData {
+                name: "Alice".to_string(),
+                email: "alice@aetheric.nl".to_string(),
+            },
+            parcel: parcel(),
+            origin_vertiport: VertiportData {
+                name: "Amsterdam".to_string(),
+                address: "Dam 1".to_string(),
+                timezone: Some(Tz::Europe__Amsterdam),
+            },
+            target_vertiport: VertiportData {
+                name: "Utrecht".to_string(),
+                address: "Domplein 1".to_string(),
+                timezone: None,
+            },
+        };
+        delay.reschedule(&mut notice.parcel, &leg("parcel", true, true));
+
+        let message =
+            delay_message(&notice, &delay, &Locale::default(), Tz::Europe__London).unwrap();
+        assert_eq!(message.from, AETHERIC_EMAIL_ADDRESS);
+        assert_eq!(message.to, "alice@aetheric.nl");
+        assert_eq!(message.template, "flight-delay");
+        FLIGHT_DELAY.validate(&message.model).unwrap();
+
+        let field = |key: &str| message.model.get(key).unwrap().clone();
+        assert_eq!(field("customer_name"), "Alice");
+        // departures in the origin's timezone, arrivals in the user's
+        assert_eq!(field("customer_pickup_time"), "2024-01-01 11:40 CET");
+        assert_eq!(field("customer_dropoff_time"), "2024-01-01 11:20 GMT");
+        assert_eq!(field("old_departure_time"), "2024-01-01 11:00 CET");
+        assert_eq!(field("new_departure_time"), "2024-01-01 11:30 CET");
+        assert_eq!(field("old_arrival_time"), "2024-01-01 11:00 GMT");
+        assert_eq!(field("new_arrival_time"), "2024-01-01 11:30 GMT");
+        assert_eq!(field("target_vertiport_name"), "Utrecht");
+    }
+
+    #[test]
+    fn test_notification_result() {
+        let result = notification_result(
+            "parcel",
+            Ok(EmailSent {
+                message_id: "message".to_string(),
+                attempts: 1,
+            }),
+        );
+        assert!(result.success);
+        assert_eq!(result.message_id, Some("message".to_string()));
+        assert_eq!(result.failure_reason(), FailureReason::None);
+
+        let status =
+            notify::with_failure_reason(Status::internal("error"), FailureReason::Undeliverable);
+        let result = notification_result("parcel", Err(status));
+        assert!(!result.success);
+        assert_eq!(result.parcel_id, "parcel");
+        assert_eq!(result.message_id, None);
+        assert_eq!(result.failure_reason(), FailureReason::Undeliverable);
+    }
+}
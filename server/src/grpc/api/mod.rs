@@ -1,3 +1,8 @@
 //! API handlers
 
 pub mod cargo;
+pub mod delivery;
+pub mod flight;
+pub mod notification;
+pub mod notify;
+pub mod parcel;
@@ -0,0 +1,1125 @@
+//! Generic notification handler, sends any template to a user
+
+use super::cargo::{get_user_data, AETHERIC_EMAIL_ADDRESS};
+use super::notify::{self, EmailSent, Recipient};
+use crate::delivery::email::{mask_address, EmailBackend, EmailMessage, TemplateModel};
+use crate::delivery::history::DeliveryLog;
+use crate::delivery::preferences::{Channels, PreferenceStore};
+use crate::delivery::retry::{DeliveryOutcome, RetryPolicy};
+use crate::delivery::sms::{SmsBackend, SmsMessage};
+use crate::delivery::suppression::Suppressions;
+use crate::grpc::client::GrpcClients;
+use crate::grpc::server::{ChannelPreference, DeliveryChannel, FailureReason};
+use crate::grpc::server::{NotificationBatchRequest, NotificationBatchResponse};
+use crate::grpc::server::{NotificationBatchResult, NotificationRequest, NotificationResponse};
+use crate::locale::Locale;
+use crate::templates::TemplateSpec;
+use crate::Config;
+use futures::stream::{self, StreamExt};
+use tokio::sync::OnceCell;
+use tonic::{Code, Status};
+
+/// Batch limits shared by all batch requests
+static BATCH_LIMITS: OnceCell<BatchLimits> = OnceCell::const_new();
+
+/// Bounds on the size and processing of notification batches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchLimits {
+    /// Maximum number of notifications in a batch
+    pub max_size: usize,
+
+    /// Maximum number of notifications processed at once
+    pub concurrency: usize,
+}
+
+impl BatchLimits {
+    /// Creates the limits from the `batch_*` configuration options
+    pub fn new(config: &Config) -> Self {
+        BatchLimits {
+            max_size: config.batch_max_size as usize,
+            concurrency: usize::from(config.batch_concurrency).max(1),
+        }
+    }
+}
+
+/// Returns BATCH_LIMITS, created from a Config object generated from
+/// environment variables on first use.
+pub async fn get_batch_limits() -> &'static BatchLimits {
+    BATCH_LIMITS
+        .get_or_init(|| async move {
+            let config = Config::try_from_env().unwrap_or_default();
+            BatchLimits::new(&config)
+        })
+        .await
+}
+
+/// A validated notification request
+#[derive(Debug, Clone)]
+struct Notification {
+    recipient: Recipient,
+    template: &'static TemplateSpec,
+    channel: ChannelPreference,
+    model: TemplateModel,
+    phone_number: Option<String>,
+    locale: Locale,
+}
+
+impl Notification {
+    /// True if the notification is sent by email
+    fn email(&self) -> bool {
+        matches!(
+            self.channel,
+            ChannelPreference::Email | ChannelPreference::EmailAndSms
+        )
+    }
+
+    /// True if the notification is sent by text message
+    fn sms(&self) -> bool {
+        matches!(
+            self.channel,
+            ChannelPreference::Sms | ChannelPreference::EmailAndSms
+        )
+    }
+
+    /// Channels requested for the notification
+    fn channels(&self) -> Channels {
+        Channels {
+            email: self.email(),
+            sms: self.sms(),
+        }
+    }
+
+    /// Limits the notification to the channels allowed by the user's preferences
+    fn restrict(&mut self, allowed: Channels) {
+        self.channel = match (allowed.email, allowed.sms) {
+            (true, true) => ChannelPreference::EmailAndSms,
+            (false, true) => ChannelPreference::Sms,
+            _ => ChannelPreference::Email,
+        };
+    }
+}
+
+impl TryFrom<NotificationRequest> for Notification {
+    type Error = Status;
+
+    fn try_from(request: NotificationRequest) -> Result<Self, Self::Error> {
+        if request.user_id.is_empty() {
+            return Err(Status::invalid_argument("Missing user ID"));
+        }
+
+        let template = crate::templates::find_generic(&request.template).ok_or_else(|| {
+            match crate::templates::find(&request.template) {
+                Some(_) => Status::invalid_argument(format!(
+                    "Template {} can only be sent by its own RPC",
+                    request.template
+                )),
+                None => Status::invalid_argument(format!("Unknown template: {}", request.template)),
+            }
+        })?;
+
+        let channel = ChannelPreference::try_from(request.channel).map_err(|_| {
+            Status::invalid_argument(format!("Invalid channel preference: {}", request.channel))
+        })?;
+
+        let mut model = TemplateModel::default();
+        for (key, value) in request.model {
+            model.insert(&key, value);
+        }
+
+        template
+            .validate(&model)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let notification = Notification {
+            recipient: Recipient {
+                user_id: request.user_id,
+                parcel_id: request.parcel_id.filter(|id| !id.is_empty()),
+                itinerary_id: request.itinerary_id.filter(|id| !id.is_empty()),
+                delivery_id: None,
+            },
+            template,
+            channel,
+            model,
+            phone_number: request.phone_number.filter(|number| !number.is_empty()),
+            // resolved with the user's preferences, see prepare
+            locale: Locale::default(),
+        };
+
+        if notification.sms() {
+            if notification.phone_number.is_none() {
+                return Err(Status::invalid_argument(
+                    "Missing phone number for text message",
+                ));
+            }
+
+            if template.sms.is_none() {
+                return Err(Status::invalid_argument(format!(
+                    "Template {} can't be sent by text message",
+                    template.name
+                )));
+            }
+        }
+
+        Ok(notification)
+    }
+}
+
+/// A validated notification, with its email if one was requested
+#[derive(Debug, Clone)]
+struct PreparedNotification {
+    notification: Notification,
+    email: Option<EmailMessage>,
+}
+
+/// Composes the email of a notification to the user's address
+fn email_message(notification: &Notification, email: &str) -> EmailMessage {
+    EmailMessage {
+        from: AETHERIC_EMAIL_ADDRESS.to_string(),
+        to: email.to_string(),
+        template: notification.template.name.to_string(),
+        model: notification.model.clone(),
+        locale: notification.locale.clone(),
+        body: None,
+        headers: vec![],
+        attachments: vec![],
+    }
+}
+
+/// Renders the text message of a notification, if one was requested
+fn sms_message(notification: &Notification) -> Result<Option<SmsMessage>, Status> {
+    let Some(phone_number) = notification
+        .phone_number
+        .as_ref()
+        .filter(|_| notification.sms())
+    else {
+        return Ok(None);
+    };
+
+    let text = crate::templates::get_renderer()
+        .and_then(|renderer| {
+            renderer.render_sms(
+                notification.template.name,
+                &notification.locale,
+                &notification.model,
+            )
+        })
+        .map_err(|e| Status::internal(format!("Could not render text message: {}", e)))?;
+
+    Ok(Some(SmsMessage {
+        to: phone_number.clone(),
+        text,
+    }))
+}
+
+/// Response for a notification whose email was sent
+fn email_response(address: &str, sent: EmailSent) -> NotificationResponse {
+    let mut response = NotificationResponse {
+        attempts: sent.attempts,
+        message_id: Some(sent.message_id),
+        recipient: mask_address(address),
+        ..Default::default()
+    };
+    response.push_channels(DeliveryChannel::Email);
+    response
+}
+
+/// Sends the text message of a notification, if one was requested,
+/// and completes the response. A failed text message only fails the
+/// notification when it was the sole channel, next to email it is
+/// reported with [`FailureReason::SmsFailed`].
+async fn send_text(
+    notification: &Notification,
+    mut response: NotificationResponse,
+    sms_backend: Option<&dyn SmsBackend>,
+    log: &DeliveryLog<'_>,
+) -> Result<NotificationResponse, Status> {
+    if let Some(sms) = sms_message(notification)? {
+        let sent = notify::send_sms(
+            sms_backend,
+            log,
+            &notification.recipient,
+            notification.template.name,
+            sms,
+        )
+        .await;
+
+        match (sent, notification.email()) {
+            (true, _) => response.push_channels(DeliveryChannel::Sms),
+            (false, true) => response.set_failure_reason(FailureReason::SmsFailed),
+            (false, false) => {
+                return Err(notify::with_failure_reason(
+                    Status::internal("Could not send text message"),
+                    FailureReason::SmsFailed,
+                ))
+            }
+        }
+    }
+
+    response.success = true;
+    Ok(response)
+}
+
+/// Sends a notification through the requested channels.
+/// A failed email fails the notification, see [`send_text`] for text messages.
+async fn dispatch(
+    prepared: PreparedNotification,
+    backend: &dyn EmailBackend,
+    sms_backend: Option<&dyn SmsBackend>,
+    log: &DeliveryLog<'_>,
+    policy: &RetryPolicy,
+) -> Result<NotificationResponse, Status> {
+    let notification = prepared.notification;
+    let response = match prepared.email {
+        Some(message) => {
+            let address = message.to.clone();
+            let sent =
+                notify::send_email(backend, log, &notification.recipient, message, policy).await?;
+            email_response(&address, sent)
+        }
+        None => NotificationResponse::default(),
+    };
+
+    send_text(&notification, response, sms_backend, log).await
+}
+
+/// Completes a notification of a batch whose email, if any, was delivered
+async fn complete(
+    prepared: Result<Notification, Status>,
+    email: Option<(DeliveryOutcome, String)>,
+    backend: &dyn EmailBackend,
+    sms_backend: Option<&dyn SmsBackend>,
+    log: &DeliveryLog<'_>,
+) -> Result<NotificationResponse, Status> {
+    let notification = prepared?;
+    let response = match email {
+        Some((outcome, address)) => {
+            let sent = notify::email_result(
+                backend,
+                log,
+                &notification.recipient,
+                &address,
+                notification.template.name,
+                outcome,
+            )
+            .await?;
+            email_response(&address, sent)
+        }
+        None => NotificationResponse::default(),
+    };
+
+    send_text(&notification, response, sms_backend, log).await
+}
+
+/// Sends a batch of notifications, returning a result per notification
+/// in the same order. The emails are handed to the backend together, using
+/// its batch API if it has one. Text messages are sent with at most
+/// `concurrency` at once. A failed notification doesn't affect the others.
+async fn send_batch(
+    prepared: Vec<Result<PreparedNotification, Status>>,
+    backend: &dyn EmailBackend,
+    sms_backend: Option<&dyn SmsBackend>,
+    log: &DeliveryLog<'_>,
+    policy: &RetryPolicy,
+    concurrency: usize,
+) -> Vec<Result<NotificationResponse, Status>> {
+    let mut emails = vec![];
+    let mut pending = vec![];
+    for item in prepared {
+        pending.push(item.map(|prepared| {
+            let has_email = prepared.email.is_some();
+            emails.extend(prepared.email);
+            (prepared.notification, has_email)
+        }));
+    }
+
+    let addresses: Vec<String> = emails.iter().map(|message| message.to.clone()).collect();
+    let mut delivered = crate::delivery::email::deliver_batch(backend, emails, policy, concurrency)
+        .await
+        .into_iter()
+        .zip(addresses);
+
+    let mut tasks = vec![];
+    for item in pending {
+        let email = match &item {
+            Ok((_, true)) => delivered.next(),
+            _ => None,
+        };
+        tasks.push((item.map(|(notification, _)| notification), email));
+    }
+
+    stream::iter(tasks)
+        .map(|(item, email)| complete(item, email, backend, sms_backend, log))
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
+}
+
+/// Refuses empty batches and batches over the size limit
+fn check_batch_size(size: usize, limits: &BatchLimits) -> Result<(), Status> {
+    if size == 0 {
+        return Err(Status::invalid_argument("Empty notification batch"));
+    }
+
+    if size > limits.max_size {
+        return Err(Status::invalid_argument(format!(
+            "Notification batch of {} exceeds the limit of {}",
+            size, limits.max_size
+        )));
+    }
+
+    Ok(())
+}
+
+/// Outcome of a single notification of a batch
+fn batch_result(
+    index: usize,
+    user_id: String,
+    result: Result<NotificationResponse, Status>,
+) -> NotificationBatchResult {
+    match result {
+        Ok(response) => NotificationBatchResult {
+            index: index as u32,
+            user_id,
+            success: response.success,
+            failure_reason: response.failure_reason,
+            channels: response.channels,
+            message_id: response.message_id,
+            error: String::new(),
+        },
+        Err(status) => {
+            let reason = match status.code() {
+                Code::InvalidArgument => FailureReason::InvalidRequest,
+                _ => notify::failure_reason(&status),
+            };
+
+            NotificationBatchResult {
+                index: index as u32,
+                user_id,
+                success: false,
+                channels: vec![],
+                message_id: None,
+                failure_reason: reason as i32,
+                error: status.message().to_string(),
+            }
+        }
+    }
+}
+
+/// Collects the outcome of every notification of a batch
+fn batch_response(
+    user_ids: Vec<String>,
+    results: Vec<Result<NotificationResponse, Status>>,
+) -> NotificationBatchResponse {
+    let results: Vec<NotificationBatchResult> = user_ids
+        .into_iter()
+        .zip(results)
+        .enumerate()
+        .map(|(index, (user_id, result))| batch_result(index, user_id, result))
+        .collect();
+
+    let sent = results.iter().filter(|result| result.success).count() as u32;
+    NotificationBatchResponse {
+        sent,
+        failed: results.len() as u32 - sent,
+        results,
+    }
+}
+
+/// Validates a notification, limits it to the channels allowed by the user's
+/// preferences and composes its email, if one is sent, to the address of the
+/// user in svc-storage
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+async fn prepare(
+    clients: &GrpcClients,
+    preferences: &PreferenceStore<'_>,
+    suppressions: &Suppressions<'_>,
+    request: NotificationRequest,
+) -> Result<PreparedNotification, Status> {
+    let requested_locale = request.locale.clone();
+    let mut notification = Notification::try_from(request)?;
+    let user_preferences =
+        notify::get_preferences(preferences, &notification.recipient.user_id).await;
+    notification.locale = notify::resolve_locale(requested_locale.as_deref(), &user_preferences)?;
+    let allowed = notify::check_channels(
+        &user_preferences,
+        notification.template.kind,
+        notification.channels(),
+    )?;
+    notification.restrict(allowed);
+
+    if !notification.email() {
+        return Ok(PreparedNotification {
+            notification,
+            email: None,
+        });
+    }
+
+    let user = get_user_data(clients, &notification.recipient.user_id)
+        .await
+        .map_err(|status| notify::with_failure_reason(status, FailureReason::DataUnavailable))?;
+    notify::check_deliverable(suppressions, &user.email).await?;
+
+    let mut email = email_message(&notification, &user.email);
+    notify::add_unsubscribe(
+        &mut email,
+        notification.template,
+        &notification.recipient.user_id,
+    )
+    .await;
+    Ok(PreparedNotification {
+        notification,
+        email: Some(email),
+    })
+}
+
+/// Sends any template to a user, by email, text message or both.
+/// The model must provide exactly the fields declared by the template.
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+pub async fn send_notification(
+    request: NotificationRequest,
+) -> Result<NotificationResponse, Status> {
+    grpc_info!("entry.");
+    let clients = crate::grpc::client::get_clients().await;
+    let backend = crate::delivery::email::get_backend().await.map_err(|e| {
+        let status = Status::internal(format!("Email backend not available: {}", e));
+        notify::with_failure_reason(status, FailureReason::Configuration)
+    })?;
+    let sms_backend = crate::delivery::sms::get_backend().await.map_err(|e| {
+        let status = Status::internal(format!("SMS backend not available: {}", e));
+        notify::with_failure_reason(status, FailureReason::Configuration)
+    })?;
+    let log = crate::delivery::history::get_log()
+        .await
+        .map_err(|e| Status::internal(format!("Delivery log not available: {}", e)))?;
+    let store = crate::store::get_store()
+        .await
+        .map_err(|e| Status::internal(format!("Store not available: {}", e)))?;
+
+    let prepared = prepare(
+        clients,
+        &PreferenceStore::new(store),
+        &Suppressions::new(store),
+        request,
+    )
+    .await?;
+    let policy = crate::delivery::retry::get_policy().await;
+    dispatch(prepared, backend, sms_backend, log, policy).await
+}
+
+/// Sends many notifications at once, e.g. to everyone booked on a grounded
+/// aircraft. Every notification is validated and sent independently and
+/// reported in the response, a failed notification doesn't fail the batch.
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+pub async fn send_notification_batch(
+    request: NotificationBatchRequest,
+) -> Result<NotificationBatchResponse, Status> {
+    grpc_info!("entry.");
+    let limits = get_batch_limits().await;
+    check_batch_size(request.notifications.len(), limits)?;
+
+    let clients = crate::grpc::client::get_clients().await;
+    let backend = crate::delivery::email::get_backend().await.map_err(|e| {
+        let status = Status::internal(format!("Email backend not available: {}", e));
+        notify::with_failure_reason(status, FailureReason::Configuration)
+    })?;
+    let sms_backend = crate::delivery::sms::get_backend().await.map_err(|e| {
+        let status = Status::internal(format!("SMS backend not available: {}", e));
+        notify::with_failure_reason(status, FailureReason::Configuration)
+    })?;
+    let log = crate::delivery::history::get_log()
+        .await
+        .map_err(|e| Status::internal(format!("Delivery log not available: {}", e)))?;
+    let store = crate::store::get_store()
+        .await
+        .map_err(|e| Status::internal(format!("Store not available: {}", e)))?;
+    let preferences = PreferenceStore::new(store);
+    let suppressions = Suppressions::new(store);
+
+    let user_ids: Vec<String> = request
+        .notifications
+        .iter()
+        .map(|notification| notification.user_id.clone())
+        .collect();
+    let prepared: Vec<Result<PreparedNotification, Status>> = stream::iter(request.notifications)
+        .map(|notification| prepare(clients, &preferences, &suppressions, notification))
+        .buffered(limits.concurrency)
+        .collect()
+        .await;
+
+    let policy = crate::delivery::retry::get_policy().await;
+    let results = send_batch(
+        prepared,
+        backend,
+        sms_backend,
+        log,
+        policy,
+        limits.concurrency,
+    )
+    .await;
+
+    let response = batch_response(user_ids, results);
+    grpc_info!(
+        "notification batch sent={}, failed={}.",
+        response.sent,
+        response.failed
+    );
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delivery::email::stub::StubBackend;
+    use crate::delivery::history::{Channel, DeliveryQuery};
+    use crate::delivery::unsubscribe::Unsubscribe;
+    use crate::delivery::{DeliveryError, DeliveryReceipt};
+    use crate::store::memory::MemoryStore;
+    use crate::templates::ROUTE_ANNOUNCEMENT;
+
+    fn request() -> NotificationRequest {
+        let model = [
+            ("customer_name", "Alice"),
+            ("parcel_weight_kg", "1.50"),
+            ("target_vertiport_name", "Utrecht"),
+            ("target_vertiport_address", "Domplein 1"),
+            ("pickup_window_start", "2024-01-01 11:00 CET"),
+            ("pickup_window_end", "2024-01-03 11:00 CET"),
+        ];
+
+        NotificationRequest {
+            user_id: "user".to_string(),
+            template: "parcel-arrival".to_string(),
+            channel: ChannelPreference::EmailAndSms as i32,
+            model: model
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            phone_number: Some("+31611111111".to_string()),
+            parcel_id: Some("parcel".to_string()),
+            itinerary_id: Some(String::new()),
+            locale: None,
+        }
+    }
+
+    fn retry_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            base_delay: std::time::Duration::from_millis(1),
+            max_delay: std::time::Duration::from_millis(1),
+        }
+    }
+
+    #[test]
+    fn test_try_from_notification_request() {
+        let notification = Notification::try_from(request()).unwrap();
+        assert_eq!(notification.recipient.user_id, "user");
+        assert_eq!(notification.recipient.parcel_id, Some("parcel".to_string()));
+        assert_eq!(notification.recipient.itinerary_id, None);
+        assert_eq!(notification.template.name, "parcel-arrival");
+        assert!(notification.email());
+        assert!(notification.sms());
+        assert_eq!(notification.model.get("customer_name").unwrap(), "Alice");
+
+        let mut request = request();
+        request.channel = ChannelPreference::Email as i32;
+        request.phone_number = None;
+        let notification = Notification::try_from(request).unwrap();
+        assert!(notification.email());
+        assert!(!notification.sms());
+
+        let invalid = |request: NotificationRequest| {
+            let error = Notification::try_from(request).unwrap_err();
+            assert_eq!(error.code(), Code::InvalidArgument);
+            error.message().to_string()
+        };
+
+        let mut missing_user = request();
+        missing_user.user_id = String::new();
+        assert_eq!(invalid(missing_user), "Missing user ID");
+
+        let mut unknown_template = request();
+        unknown_template.template = "welcome".to_string();
+        assert_eq!(invalid(unknown_template), "Unknown template: welcome");
+
+        let mut own_rpc_template = request();
+        own_rpc_template.template = "cargo-confirmation".to_string();
+        assert_eq!(
+            invalid(own_rpc_template),
+            "Template cargo-confirmation can only be sent by its own RPC"
+        );
+
+        let mut invalid_channel = request();
+        invalid_channel.channel = 42;
+        assert_eq!(invalid(invalid_channel), "Invalid channel preference: 42");
+
+        let mut invalid_model = request();
+        invalid_model.model.remove("pickup_window_end");
+        invalid_model
+            .model
+            .insert("customer_title".to_string(), "Dr.".to_string());
+        assert_eq!(
+            invalid(invalid_model),
+            "Invalid template model: parcel-arrival: \
+            missing pickup_window_end; unknown customer_title"
+        );
+
+        let mut missing_phone_number = request();
+        missing_phone_number.phone_number = Some(String::new());
+        assert_eq!(
+            invalid(missing_phone_number),
+            "Missing phone number for text message"
+        );
+
+        let mut email_only = request();
+        email_only.template = "flight-delay".to_string();
+        email_only.model = [
+            "customer_name",
+            "customer_pickup_time",
+            "customer_dropoff_time",
+            "origin_vertiport_name",
+            "origin_vertiport_address",
+            "target_vertiport_name",
+            "target_vertiport_address",
+            "old_departure_time",
+            "new_departure_time",
+            "old_arrival_time",
+            "new_arrival_time",
+        ]
+        .into_iter()
+        .map(|key| (key.to_string(), String::new()))
+        .collect();
+        assert_eq!(
+            invalid(email_only),
+            "Template flight-delay can't be sent by text message"
+        );
+    }
+
+    #[test]
+    fn test_restrict() {
+        let mut notification = Notification::try_from(request()).unwrap();
+        assert_eq!(
+            notification.channels(),
+            Channels {
+                email: true,
+                sms: true
+            }
+        );
+
+        notification.restrict(Channels::EMAIL);
+        assert_eq!(notification.channel, ChannelPreference::Email);
+        assert_eq!(notification.channels(), Channels::EMAIL);
+
+        notification.restrict(Channels {
+            email: false,
+            sms: true,
+        });
+        assert_eq!(notification.channel, ChannelPreference::Sms);
+        assert!(!notification.email());
+        assert!(notification.sms());
+    }
+
+    #[test]
+    fn test_email_message() {
+        let notification = Notification::try_from(request()).unwrap();
+        let message = email_message(&notification, "alice@aetheric.nl");
+        assert_eq!(message.from, AETHERIC_EMAIL_ADDRESS);
+        assert_eq!(message.to, "alice@aetheric.nl");
+        assert_eq!(message.template, "parcel-arrival");
+        assert_eq!(message.model, notification.model);
+        assert_eq!(message.locale, Locale::default());
+    }
+
+    #[test]
+    fn test_sms_message() {
+        let mut notification = Notification::try_from(request()).unwrap();
+        let sms = sms_message(&notification).unwrap().unwrap();
+        assert_eq!(sms.to, "+31611111111");
+        assert_eq!(
+            sms.text,
+            "Aetheric: your parcel has arrived at Utrecht, Domplein 1. \
+            Pickup until 2024-01-03 11:00 CET."
+        );
+
+        notification.locale = Locale::parse("nl-BE").unwrap();
+        let sms = sms_message(&notification).unwrap().unwrap();
+        assert_eq!(
+            sms.text,
+            "Aetheric: je pakket is aangekomen in Utrecht, Domplein 1. \
+            Ophalen kan tot 2024-01-03 11:00 CET."
+        );
+
+        notification.channel = ChannelPreference::Email;
+        assert_eq!(sms_message(&notification).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let log = DeliveryLog::new(&store, std::time::Duration::from_secs(60 * 60));
+        let backend = StubBackend::default();
+        let sms_backend = crate::delivery::sms::stub::StubBackend::default();
+
+        let notification = Notification::try_from(request()).unwrap();
+        let email = email_message(&notification, "alice@aetheric.nl");
+        let prepared =
+            |notification: &Notification, email: Option<&EmailMessage>| PreparedNotification {
+                notification: notification.clone(),
+                email: email.cloned(),
+            };
+        let response = dispatch(
+            prepared(&notification, Some(&email)),
+            &backend,
+            Some(&sms_backend),
+            &log,
+            &retry_policy(),
+        )
+        .await
+        .unwrap();
+        assert!(response.success);
+        assert_eq!(response.attempts, 1);
+        assert!(response.message_id.is_some());
+        assert_eq!(
+            response.channels().collect::<Vec<_>>(),
+            vec![DeliveryChannel::Email, DeliveryChannel::Sms]
+        );
+        assert_eq!(response.recipient, "a***e@aetheric.nl");
+        assert_eq!(response.failure_reason(), FailureReason::None);
+        assert_eq!(backend.sent().len(), 1);
+        assert_eq!(sms_backend.sent().len(), 1);
+
+        // text message failed next to the email
+        let response = dispatch(
+            prepared(&notification, Some(&email)),
+            &backend,
+            None,
+            &log,
+            &retry_policy(),
+        )
+        .await
+        .unwrap();
+        assert!(response.success);
+        assert_eq!(
+            response.channels().collect::<Vec<_>>(),
+            vec![DeliveryChannel::Email]
+        );
+        assert_eq!(response.failure_reason(), FailureReason::SmsFailed);
+
+        // text message only
+        let mut notification = notification;
+        notification.channel = ChannelPreference::Sms;
+        let response = dispatch(
+            prepared(&notification, None),
+            &backend,
+            Some(&sms_backend),
+            &log,
+            &retry_policy(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.attempts, 0);
+        assert_eq!(response.message_id, None);
+        assert_eq!(
+            response.channels().collect::<Vec<_>>(),
+            vec![DeliveryChannel::Sms]
+        );
+        assert_eq!(response.recipient, "");
+
+        let error = dispatch(
+            prepared(&notification, None),
+            &backend,
+            None,
+            &log,
+            &retry_policy(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code(), Code::Internal);
+        assert_eq!(notify::failure_reason(&error), FailureReason::SmsFailed);
+
+        let records = log
+            .query(&DeliveryQuery {
+                user_id: Some("user".to_string()),
+                limit: 10,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 4);
+        let sms = records
+            .iter()
+            .filter(|record| record.channel == Channel::Sms)
+            .count();
+        assert_eq!(sms, 2);
+        assert!(records
+            .iter()
+            .all(|record| record.template == "parcel-arrival"
+                && record.parcel_id.as_deref() == Some("parcel")));
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribe_marketing() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let log = DeliveryLog::new(&store, std::time::Duration::from_secs(60 * 60));
+        let preferences = PreferenceStore::new(&store);
+        let backend = StubBackend::default();
+        let mut config = Config::default();
+        config.token_signing_key = "secret".to_string();
+        let unsubscribe = Unsubscribe::new(&config).unwrap();
+        let now = chrono::Utc::now();
+
+        let model = [
+            ("customer_name", "Alice"),
+            ("origin_vertiport_name", "Amsterdam"),
+            ("target_vertiport_name", "Utrecht"),
+            ("first_flight_date", "2024-06-01"),
+        ];
+        let request = NotificationRequest {
+            template: "route-announcement".to_string(),
+            channel: ChannelPreference::Email as i32,
+            model: model
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            phone_number: None,
+            ..request()
+        };
+        let notification = Notification::try_from(request).unwrap();
+        let user_preferences = preferences.get("user").await.unwrap().unwrap_or_default();
+        notify::check_channels(
+            &user_preferences,
+            notification.template.kind,
+            notification.channels(),
+        )
+        .unwrap();
+
+        let mut email = email_message(&notification, "alice@aetheric.nl");
+        unsubscribe.add_to(&mut email, notification.template, "user", now);
+        let prepared = PreparedNotification {
+            notification,
+            email: Some(email),
+        };
+        dispatch(prepared, &backend, None, &log, &retry_policy())
+            .await
+            .unwrap();
+
+        // the sent email carries the link in its body and headers
+        let sent = backend.sent().remove(0);
+        let link = unsubscribe.link("user", now);
+        assert!(sent.headers.iter().any(
+            |header| header.name == "List-Unsubscribe" && header.value == format!("<{}>", link)
+        ));
+        let body = crate::templates::get_renderer()
+            .unwrap()
+            .render(&sent.template, &sent.locale, &sent.model)
+            .unwrap();
+        assert!(body.text.contains(&format!("Unsubscribe: {}", link)));
+
+        // following the link opts the user out of the next announcement
+        let token = link.split_once("?token=").unwrap().1;
+        crate::rest::api::unsubscribe::opt_out(Some(&unsubscribe), &preferences, token, now)
+            .await
+            .unwrap();
+        let user_preferences = preferences.get("user").await.unwrap().unwrap_or_default();
+        let error =
+            notify::check_channels(&user_preferences, ROUTE_ANNOUNCEMENT.kind, Channels::EMAIL)
+                .unwrap_err();
+        assert_eq!(notify::failure_reason(&error), FailureReason::OptedOut);
+
+        ut_info!("Success.");
+    }
+
+    #[test]
+    fn test_check_batch_size() {
+        let limits = BatchLimits {
+            max_size: 2,
+            concurrency: 1,
+        };
+        check_batch_size(1, &limits).unwrap();
+        check_batch_size(2, &limits).unwrap();
+
+        let error = check_batch_size(0, &limits).unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument);
+        assert_eq!(error.message(), "Empty notification batch");
+
+        let error = check_batch_size(3, &limits).unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument);
+        assert_eq!(
+            error.message(),
+            "Notification batch of 3 exceeds the limit of 2"
+        );
+    }
+
+    #[test]
+    fn test_batch_limits() {
+        let mut config = Config::default();
+        config.batch_max_size = 10;
+        config.batch_concurrency = 0;
+        assert_eq!(
+            BatchLimits::new(&config),
+            BatchLimits {
+                max_size: 10,
+                concurrency: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_batch_response() {
+        let mut sent = NotificationResponse {
+            success: true,
+            attempts: 1,
+            message_id: Some("id".to_string()),
+            recipient: "a***e@aetheric.nl".to_string(),
+            ..Default::default()
+        };
+        sent.push_channels(DeliveryChannel::Email);
+        sent.set_failure_reason(FailureReason::SmsFailed);
+
+        let response = batch_response(
+            vec!["alice".to_string(), "bob".to_string(), "carol".to_string()],
+            vec![
+                Ok(sent),
+                Err(Status::invalid_argument("Missing user ID")),
+                Err(notify::with_failure_reason(
+                    Status::failed_precondition("Email address is undeliverable"),
+                    FailureReason::Undeliverable,
+                )),
+            ],
+        );
+        assert_eq!(response.sent, 1);
+        assert_eq!(response.failed, 2);
+        assert_eq!(response.results.len(), 3);
+
+        let alice = &response.results[0];
+        assert_eq!(alice.index, 0);
+        assert_eq!(alice.user_id, "alice");
+        assert!(alice.success);
+        assert_eq!(
+            alice.channels().collect::<Vec<_>>(),
+            vec![DeliveryChannel::Email]
+        );
+        assert_eq!(alice.message_id, Some("id".to_string()));
+        assert_eq!(alice.failure_reason(), FailureReason::SmsFailed);
+        assert_eq!(alice.error, "");
+
+        let bob = &response.results[1];
+        assert_eq!(bob.index, 1);
+        assert_eq!(bob.user_id, "bob");
+        assert!(!bob.success);
+        assert_eq!(bob.failure_reason(), FailureReason::InvalidRequest);
+        assert_eq!(bob.error, "Missing user ID");
+
+        let carol = &response.results[2];
+        assert_eq!(carol.index, 2);
+        assert!(!carol.success);
+        assert_eq!(carol.channels.len(), 0);
+        assert_eq!(carol.failure_reason(), FailureReason::Undeliverable);
+        assert_eq!(carol.error, "Email address is undeliverable");
+    }
+
+    /// Refuses messages to addresses starting with "refused"
+    #[derive(Debug, Default)]
+    struct RefusingBackend(StubBackend);
+
+    #[tonic::async_trait]
+    impl EmailBackend for RefusingBackend {
+        fn name(&self) -> &'static str {
+            "refusing"
+        }
+
+        async fn send(&self, message: &EmailMessage) -> Result<DeliveryReceipt, DeliveryError> {
+            if message.to.starts_with("refused") {
+                return Err(DeliveryError::Provider {
+                    code: 406,
+                    message: "Inactive recipient".to_string(),
+                });
+            }
+
+            self.0.send(message).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_batch() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let log = DeliveryLog::new(&store, std::time::Duration::from_secs(60 * 60));
+        let backend = RefusingBackend::default();
+        let sms_backend = crate::delivery::sms::stub::StubBackend::default();
+
+        let prepared = |to: Option<&str>, channel: ChannelPreference| {
+            let mut notification = Notification::try_from(request()).unwrap();
+            notification.channel = channel;
+            let email = to.map(|to| email_message(&notification, to));
+            Ok(PreparedNotification {
+                notification,
+                email,
+            })
+        };
+
+        let results = send_batch(
+            vec![
+                prepared(Some("alice@aetheric.nl"), ChannelPreference::Email),
+                Err(Status::invalid_argument("Missing user ID")),
+                prepared(Some("refused@aetheric.nl"), ChannelPreference::EmailAndSms),
+                prepared(None, ChannelPreference::Sms),
+                prepared(Some("bob@aetheric.nl"), ChannelPreference::EmailAndSms),
+            ],
+            &backend,
+            Some(&sms_backend),
+            &log,
+            &retry_policy(),
+            2,
+        )
+        .await;
+        assert_eq!(results.len(), 5);
+
+        let alice = results[0].as_ref().unwrap();
+        assert_eq!(alice.recipient, "a***e@aetheric.nl");
+        assert_eq!(
+            alice.channels().collect::<Vec<_>>(),
+            vec![DeliveryChannel::Email]
+        );
+
+        let error = results[1].as_ref().unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument);
+
+        // no text message after a refused email
+        let error = results[2].as_ref().unwrap_err();
+        assert_eq!(notify::failure_reason(error), FailureReason::Rejected);
+
+        let sms_only = results[3].as_ref().unwrap();
+        assert_eq!(sms_only.recipient, "");
+        assert_eq!(
+            sms_only.channels().collect::<Vec<_>>(),
+            vec![DeliveryChannel::Sms]
+        );
+
+        let bob = results[4].as_ref().unwrap();
+        assert_eq!(bob.recipient, "b***b@aetheric.nl");
+        assert_eq!(
+            bob.channels().collect::<Vec<_>>(),
+            vec![DeliveryChannel::Email, DeliveryChannel::Sms]
+        );
+
+        let mut sent: Vec<String> = backend.0.sent().into_iter().map(|m| m.to).collect();
+        sent.sort();
+        assert_eq!(sent, vec!["alice@aetheric.nl", "bob@aetheric.nl"]);
+        assert_eq!(sms_backend.sent().len(), 2);
+
+        ut_info!("Success.");
+    }
+}
@@ -0,0 +1,513 @@
+//! Notification delivery shared by the handlers:
+//! preferences, suppression checks, retries, delivery log and failure reasons
+
+use crate::delivery::email::{EmailBackend, EmailMessage};
+use crate::delivery::history::{Channel, DeliveryLog, DeliveryRecord};
+use crate::delivery::preferences::{Channels, MessageKind, PreferenceStore, Preferences};
+use crate::delivery::retry::{DeliveryOutcome, RetryPolicy};
+use crate::delivery::sms::{SmsBackend, SmsMessage};
+use crate::delivery::suppression::Suppressions;
+use crate::delivery::verification::VerifiedAddresses;
+use crate::delivery::{DeliveryError, DeliveryReceipt};
+use crate::grpc::server::FailureReason;
+use crate::locale::Locale;
+use crate::templates::TemplateSpec;
+use chrono::Utc;
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::{Code, Status};
+
+/// Status metadata key reporting the number of email delivery attempts
+pub const ATTEMPTS_METADATA_KEY: &str = "x-delivery-attempts";
+
+/// Status metadata key reporting why a notification failed, see [`FailureReason`]
+pub const FAILURE_REASON_METADATA_KEY: &str = "x-failure-reason";
+
+/// User a notification is sent to, and what it relates to
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recipient {
+    /// User ID
+    pub user_id: String,
+
+    /// Related parcel, if any
+    pub parcel_id: Option<String>,
+
+    /// Related itinerary, if any
+    pub itinerary_id: Option<String>,
+
+    /// ID to record the delivery under, e.g. of a queued job.
+    /// A new ID is generated otherwise.
+    pub delivery_id: Option<String>,
+}
+
+/// Email accepted by the provider
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailSent {
+    /// Message ID assigned by the provider
+    pub message_id: String,
+
+    /// Number of delivery attempts
+    pub attempts: u32,
+}
+
+/// Adds the failure reason to the metadata of an error status
+pub fn with_failure_reason(mut status: Status, reason: FailureReason) -> Status {
+    status.metadata_mut().insert(
+        FAILURE_REASON_METADATA_KEY,
+        MetadataValue::from_static(reason.as_str_name()),
+    );
+    status
+}
+
+/// Returns the failure reason in the metadata of an error status,
+/// [`FailureReason::Internal`] if there is none
+pub fn failure_reason(status: &Status) -> FailureReason {
+    status
+        .metadata()
+        .get(FAILURE_REASON_METADATA_KEY)
+        .and_then(|value| value.to_str().ok())
+        .and_then(FailureReason::from_str_name)
+        .unwrap_or(FailureReason::Internal)
+}
+
+/// Returns the preferences of a user.
+/// If none are stored or the store is unavailable the defaults are used.
+pub async fn get_preferences(preferences: &PreferenceStore<'_>, user_id: &str) -> Preferences {
+    match preferences.get(user_id).await {
+        Ok(found) => found.unwrap_or_default(),
+        Err(e) => {
+            grpc_warn!(
+                "preferences of user {} not available, using defaults: {}",
+                user_id,
+                e
+            );
+            Preferences::default()
+        }
+    }
+}
+
+/// Returns the `requested` channels the preferences of the user allow,
+/// refuses the notification if they allow none
+pub fn check_channels(
+    preferences: &Preferences,
+    kind: MessageKind,
+    requested: Channels,
+) -> Result<Channels, Status> {
+    preferences
+        .channels(kind, requested, Utc::now())
+        .map_err(|e| {
+            grpc_info!("not sending {:?} notification: {}", kind, e);
+            with_failure_reason(
+                Status::failed_precondition(format!("Notification not allowed: {}", e)),
+                FailureReason::OptedOut,
+            )
+        })
+}
+
+/// Returns the locale of a notification: the locale requested by the caller,
+/// or the preferred language of the user when none was requested.
+/// A requested locale that isn't a language tag is refused.
+pub fn resolve_locale(
+    requested: Option<&str>,
+    preferences: &Preferences,
+) -> Result<Locale, Status> {
+    match requested.map(str::trim).filter(|tag| !tag.is_empty()) {
+        Some(tag) => Locale::parse(tag)
+            .ok_or_else(|| Status::invalid_argument(format!("Invalid locale: {}", tag))),
+        None => Ok(preferences.locale()),
+    }
+}
+
+/// Adds the unsubscribe link and headers of the user to a marketing email,
+/// unless unsubscribe links are disabled
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) depends on the global configuration, see Unsubscribe::add_to
+pub async fn add_unsubscribe(message: &mut EmailMessage, template: &TemplateSpec, user_id: &str) {
+    if let Some(unsubscribe) = crate::delivery::unsubscribe::get_unsubscribe().await {
+        unsubscribe.add_to(message, template, user_id, Utc::now());
+    }
+}
+
+/// Refuses to email an address marked undeliverable after a hard bounce.
+/// If the suppression list is unavailable the email is sent anyway.
+pub async fn check_deliverable(suppressions: &Suppressions<'_>, email: &str) -> Result<(), Status> {
+    match suppressions.get(email).await {
+        Ok(None) => Ok(()),
+        Ok(Some(suppression)) => {
+            grpc_warn!(
+                "not sending to undeliverable address {}: {}",
+                email,
+                suppression.reason
+            );
+            Err(with_failure_reason(
+                Status::failed_precondition(format!(
+                    "Email address is undeliverable: {}",
+                    suppression.reason
+                )),
+                FailureReason::Undeliverable,
+            ))
+        }
+        Err(e) => {
+            grpc_warn!("suppression list not available, sending anyway: {}", e);
+            Ok(())
+        }
+    }
+}
+
+/// Returns true if `email` is the verified address of the user.
+/// An unverified address is refused when verified addresses are `required`,
+/// and only flagged otherwise. If the verified addresses are unavailable the
+/// address counts as unverified, but the email is sent anyway.
+pub async fn check_verified(
+    addresses: &VerifiedAddresses<'_>,
+    user_id: &str,
+    email: &str,
+    required: bool,
+) -> Result<bool, Status> {
+    match addresses.is_verified(user_id, email).await {
+        Ok(true) => Ok(true),
+        Ok(false) if required => {
+            grpc_warn!("not sending to unverified address of user {}.", user_id);
+            Err(with_failure_reason(
+                Status::failed_precondition("Email address is not verified"),
+                FailureReason::Unverified,
+            ))
+        }
+        Ok(false) => {
+            grpc_info!("sending to unverified address of user {}.", user_id);
+            Ok(false)
+        }
+        Err(e) => {
+            grpc_warn!("verified addresses not available, sending anyway: {}", e);
+            Ok(false)
+        }
+    }
+}
+
+/// Records a delivery in the delivery log.
+/// The log is informational, failing to record is only logged.
+pub async fn record_delivery(
+    log: &DeliveryLog<'_>,
+    recipient: &Recipient,
+    channel: Channel,
+    address: &str,
+    template: &str,
+    result: &Result<DeliveryReceipt, DeliveryError>,
+) {
+    let mut record = DeliveryRecord::new(&recipient.user_id, channel, address, template, result);
+    record.parcel_id = recipient.parcel_id.clone();
+    record.itinerary_id = recipient.itinerary_id.clone();
+    if let Some(id) = &recipient.delivery_id {
+        record.id = id.clone();
+    }
+
+    if let Err(e) = log.record(&record).await {
+        grpc_warn!("could not record {} delivery {}: {}", channel, record.id, e);
+    }
+}
+
+/// Hands an email to the delivery backend, retrying transient failures.
+/// The number of attempts and failure reason of a failed email are reported
+/// in the `x-delivery-attempts` and `x-failure-reason` metadata of the error status.
+pub async fn send_email(
+    backend: &dyn EmailBackend,
+    log: &DeliveryLog<'_>,
+    recipient: &Recipient,
+    message: EmailMessage,
+    policy: &RetryPolicy,
+) -> Result<EmailSent, Status> {
+    let address = message.to.clone();
+    let template = message.template.clone();
+    let outcome = crate::delivery::email::deliver(backend, message, policy).await;
+    email_result(backend, log, recipient, &address, &template, outcome).await
+}
+
+/// Records the outcome of an email in the delivery log and turns it into
+/// the handler result, see [`send_email`]
+pub async fn email_result(
+    backend: &dyn EmailBackend,
+    log: &DeliveryLog<'_>,
+    recipient: &Recipient,
+    address: &str,
+    template: &str,
+    outcome: DeliveryOutcome,
+) -> Result<EmailSent, Status> {
+    let attempts = outcome.attempts;
+    record_delivery(
+        log,
+        recipient,
+        Channel::Email,
+        address,
+        template,
+        &outcome.result,
+    )
+    .await;
+
+    let receipt = outcome.result.map_err(|e| {
+        grpc_error!(
+            "Could not send email with {} after {} attempt(s): {}",
+            backend.name(),
+            attempts,
+            e
+        );
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert(ATTEMPTS_METADATA_KEY, attempts.into());
+        let status = Status::with_metadata(
+            Code::Internal,
+            format!("Could not send email: {}", e),
+            metadata,
+        );
+        with_failure_reason(status, FailureReason::from(&e))
+    })?;
+
+    grpc_info!(
+        "{} email sent with {}, message_id={}, attempts={}.",
+        template,
+        backend.name(),
+        receipt.message_id,
+        attempts
+    );
+    Ok(EmailSent {
+        message_id: receipt.message_id,
+        attempts,
+    })
+}
+
+/// Hands a text message to the delivery backend.
+/// SMS is a best effort channel next to email, failures are only logged.
+pub async fn send_sms(
+    backend: Option<&dyn SmsBackend>,
+    log: &DeliveryLog<'_>,
+    recipient: &Recipient,
+    template: &str,
+    message: SmsMessage,
+) -> bool {
+    let Some(backend) = backend else {
+        grpc_warn!("SMS requested but no SMS backend configured.");
+        return false;
+    };
+
+    let result = backend.send(&message).await;
+    record_delivery(log, recipient, Channel::Sms, &message.to, template, &result).await;
+
+    match result {
+        Ok(receipt) => {
+            grpc_info!(
+                "{} SMS sent with {}, message_id={}.",
+                template,
+                backend.name(),
+                receipt.message_id
+            );
+            true
+        }
+        Err(e) => {
+            grpc_error!("Could not send SMS with {}: {}", backend.name(), e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delivery::history::{DeliveryQuery, DeliveryStatus};
+    use crate::store::memory::MemoryStore;
+
+    fn recipient() -> Recipient {
+        Recipient {
+            user_id: "user".to_string(),
+            parcel_id: Some("parcel".to_string()),
+            itinerary_id: None,
+            delivery_id: None,
+        }
+    }
+
+    async fn recorded(log: &DeliveryLog<'_>) -> Vec<DeliveryRecord> {
+        log.query(&DeliveryQuery {
+            user_id: Some("user".to_string()),
+            limit: 10,
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn test_with_failure_reason() {
+        let status = with_failure_reason(Status::internal("error"), FailureReason::Rejected);
+        assert_eq!(status.code(), Code::Internal);
+        assert_eq!(
+            status.metadata().get(FAILURE_REASON_METADATA_KEY).unwrap(),
+            "FAILURE_REASON_REJECTED"
+        );
+        assert_eq!(failure_reason(&status), FailureReason::Rejected);
+        assert_eq!(
+            failure_reason(&Status::internal("error")),
+            FailureReason::Internal
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_preferences() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let preferences = PreferenceStore::new(&store);
+        assert_eq!(
+            get_preferences(&preferences, "user").await,
+            Preferences::default()
+        );
+
+        let stored = Preferences {
+            marketing: false,
+            ..Default::default()
+        };
+        preferences.set("user", &stored).await.unwrap();
+        assert_eq!(get_preferences(&preferences, "user").await, stored);
+
+        ut_info!("Success.");
+    }
+
+    #[test]
+    fn test_check_channels() {
+        let requested = Channels {
+            email: true,
+            sms: true,
+        };
+        let mut preferences = Preferences {
+            sms: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            check_channels(&preferences, MessageKind::Marketing, requested).unwrap(),
+            Channels {
+                email: true,
+                sms: false
+            }
+        );
+
+        preferences.marketing = false;
+        let error = check_channels(&preferences, MessageKind::Marketing, requested).unwrap_err();
+        assert_eq!(error.code(), Code::FailedPrecondition);
+        assert_eq!(
+            error.message(),
+            "Notification not allowed: User opted out of marketing"
+        );
+        assert_eq!(failure_reason(&error), FailureReason::OptedOut);
+
+        assert!(check_channels(&preferences, MessageKind::Transactional, requested).is_ok());
+    }
+
+    #[test]
+    fn test_resolve_locale() {
+        let preferences = Preferences {
+            language: Some("nl-BE".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            resolve_locale(Some("fr"), &preferences).unwrap().tag(),
+            "fr"
+        );
+        assert_eq!(resolve_locale(None, &preferences).unwrap().tag(), "nl-BE");
+        assert_eq!(
+            resolve_locale(Some(" "), &preferences).unwrap().tag(),
+            "nl-BE"
+        );
+        assert_eq!(
+            resolve_locale(None, &Preferences::default()).unwrap().tag(),
+            "en"
+        );
+
+        let error = resolve_locale(Some("français"), &preferences).unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument);
+        assert_eq!(error.message(), "Invalid locale: français");
+    }
+
+    #[tokio::test]
+    async fn test_check_deliverable() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let suppressions = Suppressions::new(&store);
+        check_deliverable(&suppressions, "alice@aetheric.nl")
+            .await
+            .unwrap();
+
+        suppressions
+            .suppress("alice@aetheric.nl", "Unknown user")
+            .await
+            .unwrap();
+        let error = check_deliverable(&suppressions, "alice@aetheric.nl")
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), Code::FailedPrecondition);
+        assert_eq!(
+            error.message(),
+            "Email address is undeliverable: Unknown user"
+        );
+        assert_eq!(
+            error.metadata().get(FAILURE_REASON_METADATA_KEY).unwrap(),
+            "FAILURE_REASON_UNDELIVERABLE"
+        );
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_check_verified() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let addresses = VerifiedAddresses::new(&store);
+        let verified = check_verified(&addresses, "user", "alice@aetheric.nl", false)
+            .await
+            .unwrap();
+        assert!(!verified);
+
+        let error = check_verified(&addresses, "user", "alice@aetheric.nl", true)
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), Code::FailedPrecondition);
+        assert_eq!(failure_reason(&error), FailureReason::Unverified);
+
+        addresses.set("user", "alice@aetheric.nl").await.unwrap();
+        let verified = check_verified(&addresses, "user", "Alice@aetheric.nl", true)
+            .await
+            .unwrap();
+        assert!(verified);
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_send_sms() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let log = DeliveryLog::new(&store, std::time::Duration::from_secs(60 * 60));
+        let sms = SmsMessage {
+            to: "+31611111111".to_string(),
+            text: "Hello".to_string(),
+        };
+        assert!(!send_sms(None, &log, &recipient(), "test", sms.clone()).await);
+        assert!(recorded(&log).await.is_empty());
+
+        let backend = crate::delivery::sms::stub::StubBackend::default();
+        assert!(send_sms(Some(&backend), &log, &recipient(), "test", sms.clone()).await);
+        assert_eq!(backend.sent(), vec![sms]);
+
+        let records = recorded(&log).await;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].channel, Channel::Sms);
+        assert_eq!(records[0].recipient, "+31611111111");
+        assert_eq!(records[0].template, "test");
+        assert_eq!(records[0].parcel_id, Some("parcel".to_string()));
+        assert_eq!(records[0].status, DeliveryStatus::Sent);
+
+        ut_info!("Success.");
+    }
+}
@@ -0,0 +1,342 @@
+//! Parcel-related handlers
+
+use super::cargo::run_idempotent;
+use super::cargo::AETHERIC_EMAIL_ADDRESS;
+use super::cargo::{get_itinerary_user_id, get_parcel_data, get_user_data, get_vertiport_data};
+use super::cargo::{ParcelData, UserData, VertiportData};
+use super::delivery::to_timestamp;
+use super::notify::{self, Recipient};
+use crate::delivery::email::{mask_address, EmailMessage, TemplateModel};
+use crate::delivery::preferences::{Channels, PreferenceStore};
+use crate::delivery::suppression::Suppressions;
+use crate::grpc::client::GrpcClients;
+use crate::grpc::server::{FailureReason, ParcelArrivalRequest, ParcelArrivalResponse};
+use crate::locale::Locale;
+use crate::store::idempotency::Idempotency;
+use crate::templates::PARCEL_ARRIVAL;
+use crate::Config;
+use chrono_tz::Tz;
+use lib_common::time::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+use tonic::Status;
+
+/// Pickup window shared by all arrival notifications
+static PICKUP_WINDOW: OnceCell<Duration> = OnceCell::const_new();
+
+/// Longest pickup window, a year
+const MAX_PICKUP_WINDOW_HOURS: u64 = 365 * 24;
+
+/// Everything needed to compose an arrival notification
+struct ArrivalData {
+    recipient: Recipient,
+    user: UserData,
+    parcel: ParcelData,
+    target_vertiport: VertiportData,
+}
+
+/// Result of an arrival notification, stored for idempotent retries
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ArrivalResult {
+    attempts: u32,
+    message_id: String,
+    recipient: String,
+    pickup_window_end: DateTime<Utc>,
+}
+
+impl From<ArrivalResult> for ParcelArrivalResponse {
+    fn from(result: ArrivalResult) -> Self {
+        ParcelArrivalResponse {
+            success: true,
+            attempts: result.attempts,
+            message_id: Some(result.message_id),
+            recipient: result.recipient,
+            pickup_window_end: Some(to_timestamp(result.pickup_window_end)),
+        }
+    }
+}
+
+/// Converts the configured pickup window, capped at [`MAX_PICKUP_WINDOW_HOURS`]
+fn pickup_window(hours: u64) -> Duration {
+    if hours > MAX_PICKUP_WINDOW_HOURS {
+        grpc_warn!(
+            "pickup window of {} hours too long, using {}.",
+            hours,
+            MAX_PICKUP_WINDOW_HOURS
+        );
+    }
+
+    // the capped window always fits
+    Duration::try_hours(hours.min(MAX_PICKUP_WINDOW_HOURS) as i64).unwrap_or_else(Duration::zero)
+}
+
+/// Returns PICKUP_WINDOW, read from a Config object generated from environment
+/// variables on first use.
+pub async fn get_pickup_window() -> Duration {
+    *PICKUP_WINDOW
+        .get_or_init(|| async move {
+            let config = Config::try_from_env().unwrap_or_default();
+            pickup_window(config.pickup_window_hours)
+        })
+        .await
+}
+
+/// Returns the time the pickup window of a parcel closes, counted
+/// from the end of the target timeslot of the delivering flight
+fn pickup_window_end(parcel: &ParcelData, window: Duration) -> Result<DateTime<Utc>, Status> {
+    parcel
+        .target_timeslot_end
+        .checked_add_signed(window)
+        .ok_or_else(|| Status::internal("Could not compute the pickup window"))
+}
+
+/// Composes the arrival email for the collected data, in the user's language.
+/// The pickup window is in the local time of the target vertiport,
+/// or in the user's timezone `tz`.
+fn arrival_message(
+    data: &ArrivalData,
+    window_end: DateTime<Utc>,
+    locale: &Locale,
+    tz: Tz,
+) -> EmailMessage {
+    let mut model = TemplateModel::default();
+    model.insert("customer_name", &data.user.name);
+    model.insert(
+        "parcel_weight_kg",
+        locale.format_weight_kg(data.parcel.weight_kg.into()),
+    );
+    model.insert("target_vertiport_name", &data.target_vertiport.name);
+    model.insert("target_vertiport_address", &data.target_vertiport.address);
+    model.insert(
+        "pickup_window_start",
+        data.target_vertiport
+            .local_time(data.parcel.target_timeslot_end, tz),
+    );
+    model.insert(
+        "pickup_window_end",
+        data.target_vertiport.local_time(window_end, tz),
+    );
+
+    EmailMessage {
+        from: AETHERIC_EMAIL_ADDRESS.to_string(),
+        to: data.user.email.clone(),
+        template: PARCEL_ARRIVAL.name.to_string(),
+        model,
+        locale: locale.clone(),
+        body: None,
+        headers: vec![],
+        attachments: vec![],
+    }
+}
+
+/// Gathers the parcel, its destination and its user from svc-storage
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+async fn get_arrival_data(clients: &GrpcClients, parcel_id: &str) -> Result<ArrivalData, Status> {
+    let parcel = get_parcel_data(clients, parcel_id).await?;
+    let target_vertiport = get_vertiport_data(clients, &parcel.target_vertiport_id).await?;
+    let user_id = get_itinerary_user_id(clients, &parcel.itinerary_id).await?;
+    let user = get_user_data(clients, &user_id).await?;
+
+    Ok(ArrivalData {
+        recipient: Recipient {
+            user_id,
+            parcel_id: Some(parcel_id.to_string()),
+            itinerary_id: Some(parcel.itinerary_id.clone()),
+            delivery_id: None,
+        },
+        user,
+        parcel,
+        target_vertiport,
+    })
+}
+
+/// Tells the customer their parcel arrived at its destination vertiport
+/// and until when it can be picked up.
+/// Retries for the same parcel within the idempotency window return the first result.
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+pub async fn parcel_arrival(
+    request: ParcelArrivalRequest,
+) -> Result<ParcelArrivalResponse, Status> {
+    grpc_info!("entry.");
+    if request.parcel_id.is_empty() {
+        return Err(Status::invalid_argument("Missing parcel ID"));
+    }
+
+    let window = crate::store::idempotency::get_window().await;
+    if window.is_zero() {
+        return send_parcel_arrival(request).await.map(Into::into);
+    }
+
+    let store = crate::store::get_store()
+        .await
+        .map_err(|e| Status::internal(format!("Store not available: {}", e)))?;
+    let idempotency = Idempotency::new(store, PARCEL_ARRIVAL.name, window);
+    let key = request.parcel_id.clone();
+
+    run_idempotent(&idempotency, &key, send_parcel_arrival(request))
+        .await
+        .map(Into::into)
+}
+
+/// Sends the arrival email of a parcel to its user
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) not unit testable, only integration tests
+async fn send_parcel_arrival(request: ParcelArrivalRequest) -> Result<ArrivalResult, Status> {
+    let clients = crate::grpc::client::get_clients().await;
+    let backend = crate::delivery::email::get_backend().await.map_err(|e| {
+        let status = Status::internal(format!("Email backend not available: {}", e));
+        notify::with_failure_reason(status, FailureReason::Configuration)
+    })?;
+    let log = crate::delivery::history::get_log()
+        .await
+        .map_err(|e| Status::internal(format!("Delivery log not available: {}", e)))?;
+
+    let data = get_arrival_data(clients, &request.parcel_id)
+        .await
+        .map_err(|status| notify::with_failure_reason(status, FailureReason::DataUnavailable))?;
+
+    let store = crate::store::get_store()
+        .await
+        .map_err(|e| Status::internal(format!("Store not available: {}", e)))?;
+    let preferences =
+        notify::get_preferences(&PreferenceStore::new(store), &data.recipient.user_id).await;
+    notify::check_channels(&preferences, PARCEL_ARRIVAL.kind, Channels::EMAIL)?;
+    notify::check_deliverable(&Suppressions::new(store), &data.user.email).await?;
+    let locale = notify::resolve_locale(request.locale.as_deref(), &preferences)?;
+
+    let window_end = pickup_window_end(&data.parcel, get_pickup_window().await)?;
+    let message = arrival_message(&data, window_end, &locale, preferences.tz());
+    let recipient = mask_address(&message.to);
+    let policy = crate::delivery::retry::get_policy().await;
+    let sent = notify::send_email(backend, log, &data.recipient, message, policy).await?;
+
+    Ok(ArrivalResult {
+        attempts: sent.attempts,
+        message_id: sent.message_id,
+        recipient,
+        pickup_window_end: window_end,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arrival_data() -> ArrivalData {
+        ArrivalData {
+            recipient: Recipient::default(),
+            user: UserData {
+                name: "Alice".to_string(),
+                email: "alice@aetheric.nl".to_string(),
+            },
+            parcel: ParcelData {
+                itinerary_id: "itinerary".to_string(),
+                weight_kg: 1.5,
+                origin_vertiport_id: "origin".to_string(),
+                target_vertiport_id: "target".to_string(),
+                origin_timeslot_start: "2024-01-01T10:00:00Z".parse().unwrap(),
+                target_timeslot_end: "2024-01-01T11:00:00Z".parse().unwrap(),
+                origin_latitude: 52.37,
+                origin_longitude: 4.89,
+                target_latitude: 52.09,
+                target_longitude: 5.12,
+                polyline: "_p~iF~ps|U".to_string(),
+            },
+            target_vertiport: VertiportData {
+                name: "Utrecht".to_string(),
+                address: "Domplein 1".to_string(),
+                timezone: Some(Tz::Europe__Amsterdam),
+            },
+        }
+    }
+
+    #[test]
+    fn test_pickup_window() {
+        assert_eq!(pickup_window(24), Duration::try_hours(24).unwrap());
+        assert_eq!(pickup_window(u64::MAX), Duration::try_days(365).unwrap());
+    }
+
+    #[test]
+    fn test_pickup_window_end() {
+        let data = arrival_data();
+        let end = pickup_window_end(&data.parcel, pickup_window(48)).unwrap();
+        assert_eq!(
+            end,
+            "2024-01-03T11:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+
+        let forever = Duration::try_days(1_000_000_000).unwrap();
+        let error = pickup_window_end(&data.parcel, forever).unwrap_err();
+        assert_eq!(error.code(), tonic::Code::Internal);
+    }
+
+    #[test]
+    fn test_arrival_message() {
+        let mut data = arrival_data();
+        let window_end = pickup_window_end(&data.parcel, pickup_window(48)).unwrap();
+        let message = arrival_message(&data, window_end, &Locale::default(), Tz::UTC);
+        assert_eq!(message.from, AETHERIC_EMAIL_ADDRESS);
+        assert_eq!(message.to, "alice@aetheric.nl");
+        assert_eq!(message.template, "parcel-arrival");
+        PARCEL_ARRIVAL.validate(&message.model).unwrap();
+
+        let field = |key: &str| message.model.get(key).unwrap().clone();
+        assert_eq!(field("customer_name"), "Alice");
+        assert_eq!(field("parcel_weight_kg"), "1.50");
+        assert_eq!(field("target_vertiport_name"), "Utrecht");
+        assert_eq!(field("target_vertiport_address"), "Domplein 1");
+        assert_eq!(field("pickup_window_start"), "2024-01-01 12:00 CET");
+        assert_eq!(field("pickup_window_end"), "2024-01-03 12:00 CET");
+
+        let locale = Locale::parse("nl-BE").unwrap();
+        let message = arrival_message(&data, window_end, &locale, Tz::UTC);
+        assert_eq!(message.locale, locale);
+        assert_eq!(message.model.get("parcel_weight_kg").unwrap(), "1,50");
+
+        data.target_vertiport.timezone = None;
+        let message = arrival_message(&data, window_end, &locale, Tz::UTC);
+        assert_eq!(
+            message.model.get("pickup_window_end").unwrap(),
+            "2024-01-03 11:00 UTC"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_arrival_idempotent() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = crate::store::memory::MemoryStore::default();
+        let idempotency = Idempotency::new(
+            &store,
+            PARCEL_ARRIVAL.name,
+            std::time::Duration::from_secs(60),
+        );
+        let result = ArrivalResult {
+            attempts: 1,
+            message_id: "message".to_string(),
+            recipient: "a***e@aetheric.nl".to_string(),
+            pickup_window_end: "2024-01-03T11:00:00Z".parse().unwrap(),
+        };
+
+        let first = run_idempotent(&idempotency, "parcel", async { Ok(result.clone()) }).await;
+        assert_eq!(first.unwrap(), result);
+
+        // a second arrival of the same parcel is not sent again
+        let second = run_idempotent::<ArrivalResult, _>(&idempotency, "parcel", async {
+            panic!("arrival sent twice");
+        })
+        .await
+        .unwrap();
+        assert_eq!(second, result);
+
+        let response = ParcelArrivalResponse::from(second);
+        assert!(response.success);
+        assert_eq!(response.message_id.as_deref(), Some("message"));
+        assert_eq!(response.pickup_window_end.unwrap().seconds, 1704279600);
+
+        ut_info!("Success.");
+    }
+}
@@ -6,7 +6,17 @@ mod grpc_server {
     tonic::include_proto!("grpc");
 }
 pub use grpc_server::rpc_service_server::{RpcService, RpcServiceServer};
-pub use grpc_server::{CargoConfirmationRequest, CargoConfirmationResponse};
+pub use grpc_server::{CancellationReason, CargoCancellationRequest, CargoCancellationResponse};
+pub use grpc_server::{CargoConfirmationRequest, CargoConfirmationResponse, PriceLine};
+pub use grpc_server::{ChannelPreference, NotificationRequest, NotificationResponse};
+pub use grpc_server::{Delivery, DeliveryChannel, DeliveryStatus, FailureReason};
+pub use grpc_server::{DeliveryQueryRequest, DeliveryQueryResponse};
+pub use grpc_server::{DeliveryStatusUpdate, DeliveryWatchRequest};
+pub use grpc_server::{FlightDelayRequest, FlightDelayResponse, NotificationResult};
+pub use grpc_server::{
+    NotificationBatchRequest, NotificationBatchResponse, NotificationBatchResult,
+};
+pub use grpc_server::{ParcelArrivalRequest, ParcelArrivalResponse};
 pub use grpc_server::{ReadyRequest, ReadyResponse};
 
 use crate::shutdown_signal;
@@ -45,6 +55,86 @@ impl RpcService for ServerImpl {
         let response = super::api::cargo::cargo_confirmation(request.into_inner()).await?;
         Ok(Response::new(response))
     }
+
+    /// Notifies the customer of a cancelled itinerary
+    async fn cargo_cancellation(
+        &self,
+        request: Request<CargoCancellationRequest>,
+    ) -> Result<Response<CargoCancellationResponse>, Status> {
+        grpc_info!("contact server.");
+        grpc_debug!("[{:?}].", request);
+        let response = super::api::cargo::cargo_cancellation(request.into_inner()).await?;
+        Ok(Response::new(response))
+    }
+
+    /// Returns the notifications sent matching the query
+    async fn query_deliveries(
+        &self,
+        request: Request<DeliveryQueryRequest>,
+    ) -> Result<Response<DeliveryQueryResponse>, Status> {
+        grpc_info!("contact server.");
+        grpc_debug!("[{:?}].", request);
+        let response = super::api::delivery::query_deliveries(request.into_inner()).await?;
+        Ok(Response::new(response))
+    }
+
+    type WatchDeliveryStream = super::api::delivery::DeliveryStatusStream;
+
+    /// Streams the status transitions of a notification until it reaches a final status
+    async fn watch_delivery(
+        &self,
+        request: Request<DeliveryWatchRequest>,
+    ) -> Result<Response<Self::WatchDeliveryStream>, Status> {
+        grpc_info!("contact server.");
+        grpc_debug!("[{:?}].", request);
+        let stream = super::api::delivery::watch_delivery(request.into_inner()).await?;
+        Ok(Response::new(stream))
+    }
+
+    /// Notifies the customers on a rescheduled flight
+    async fn flight_delay_notification(
+        &self,
+        request: Request<FlightDelayRequest>,
+    ) -> Result<Response<FlightDelayResponse>, Status> {
+        grpc_info!("contact server.");
+        grpc_debug!("[{:?}].", request);
+        let response = super::api::flight::flight_delay_notification(request.into_inner()).await?;
+        Ok(Response::new(response))
+    }
+
+    /// Notifies the customer that their parcel is ready for pickup
+    async fn parcel_arrival(
+        &self,
+        request: Request<ParcelArrivalRequest>,
+    ) -> Result<Response<ParcelArrivalResponse>, Status> {
+        grpc_info!("contact server.");
+        grpc_debug!("[{:?}].", request);
+        let response = super::api::parcel::parcel_arrival(request.into_inner()).await?;
+        Ok(Response::new(response))
+    }
+
+    /// Sends any template to a user, through the requested channels
+    async fn send_notification(
+        &self,
+        request: Request<NotificationRequest>,
+    ) -> Result<Response<NotificationResponse>, Status> {
+        grpc_info!("contact server.");
+        grpc_debug!("[{:?}].", request);
+        let response = super::api::notification::send_notification(request.into_inner()).await?;
+        Ok(Response::new(response))
+    }
+
+    /// Sends many notifications at once, reporting the outcome of each
+    async fn send_notification_batch(
+        &self,
+        request: Request<NotificationBatchRequest>,
+    ) -> Result<Response<NotificationBatchResponse>, Status> {
+        grpc_info!("contact server.");
+        grpc_debug!("[{:?}].", request);
+        let response =
+            super::api::notification::send_notification_batch(request.into_inner()).await?;
+        Ok(Response::new(response))
+    }
 }
 
 #[cfg(feature = "stub_server")]
@@ -66,7 +156,145 @@ impl RpcService for ServerImpl {
     ) -> Result<Response<CargoConfirmationResponse>, Status> {
         grpc_warn!("(MOCK) contact server.");
         grpc_debug!("(MOCK) [{:?}].", request);
-        let response = CargoConfirmationResponse { success: true };
+        let response = CargoConfirmationResponse {
+            success: true,
+            attempts: 1,
+            message_id: Some(lib_common::uuid::Uuid::new_v4().to_string()),
+            channels: vec![DeliveryChannel::Email as i32],
+            recipient: String::from("i***o@aetheric.nl"),
+            invoice_id: String::from("1"),
+            failure_reason: FailureReason::None as i32,
+            email_verified: true,
+        };
+        Ok(Response::new(response))
+    }
+
+    async fn cargo_cancellation(
+        &self,
+        request: Request<CargoCancellationRequest>,
+    ) -> Result<Response<CargoCancellationResponse>, Status> {
+        grpc_warn!("(MOCK) contact server.");
+        grpc_debug!("(MOCK) [{:?}].", request);
+        let response = CargoCancellationResponse {
+            success: true,
+            attempts: 1,
+            message_id: Some(lib_common::uuid::Uuid::new_v4().to_string()),
+            recipient: String::from("i***o@aetheric.nl"),
+        };
+        Ok(Response::new(response))
+    }
+
+    async fn query_deliveries(
+        &self,
+        request: Request<DeliveryQueryRequest>,
+    ) -> Result<Response<DeliveryQueryResponse>, Status> {
+        grpc_warn!("(MOCK) contact server.");
+        grpc_debug!("(MOCK) [{:?}].", request);
+        let response = DeliveryQueryResponse { deliveries: vec![] };
+        Ok(Response::new(response))
+    }
+
+    type WatchDeliveryStream = super::api::delivery::DeliveryStatusStream;
+
+    async fn watch_delivery(
+        &self,
+        request: Request<DeliveryWatchRequest>,
+    ) -> Result<Response<Self::WatchDeliveryStream>, Status> {
+        grpc_warn!("(MOCK) contact server.");
+        grpc_debug!("(MOCK) [{:?}].", request);
+        let now = prost_types::Timestamp::from(std::time::SystemTime::now());
+        let update = DeliveryStatusUpdate {
+            delivery: Some(Delivery {
+                id: lib_common::uuid::Uuid::new_v4().to_string(),
+                user_id: lib_common::uuid::Uuid::new_v4().to_string(),
+                channel: DeliveryChannel::Email as i32,
+                recipient: String::from("i***o@aetheric.nl"),
+                template: String::from("cargo-confirmation"),
+                message_id: Some(request.into_inner().notification_id),
+                status: DeliveryStatus::Delivered as i32,
+                error: None,
+                parcel_id: None,
+                itinerary_id: None,
+                created_at: Some(now.clone()),
+                updated_at: Some(now),
+            }),
+            done: true,
+        };
+        Ok(Response::new(Box::pin(futures::stream::iter([Ok(update)]))))
+    }
+
+    async fn flight_delay_notification(
+        &self,
+        request: Request<FlightDelayRequest>,
+    ) -> Result<Response<FlightDelayResponse>, Status> {
+        grpc_warn!("(MOCK) contact server.");
+        grpc_debug!("(MOCK) [{:?}].", request);
+        let response = FlightDelayResponse {
+            notified: 0,
+            results: vec![],
+        };
+        Ok(Response::new(response))
+    }
+
+    async fn parcel_arrival(
+        &self,
+        request: Request<ParcelArrivalRequest>,
+    ) -> Result<Response<ParcelArrivalResponse>, Status> {
+        grpc_warn!("(MOCK) contact server.");
+        grpc_debug!("(MOCK) [{:?}].", request);
+        let response = ParcelArrivalResponse {
+            success: true,
+            attempts: 1,
+            message_id: Some(lib_common::uuid::Uuid::new_v4().to_string()),
+            recipient: String::from("i***o@aetheric.nl"),
+            pickup_window_end: None,
+        };
+        Ok(Response::new(response))
+    }
+
+    async fn send_notification(
+        &self,
+        request: Request<NotificationRequest>,
+    ) -> Result<Response<NotificationResponse>, Status> {
+        grpc_warn!("(MOCK) contact server.");
+        grpc_debug!("(MOCK) [{:?}].", request);
+        let response = NotificationResponse {
+            success: true,
+            attempts: 1,
+            message_id: Some(lib_common::uuid::Uuid::new_v4().to_string()),
+            channels: vec![DeliveryChannel::Email as i32],
+            recipient: String::from("i***o@aetheric.nl"),
+            failure_reason: FailureReason::None as i32,
+        };
+        Ok(Response::new(response))
+    }
+
+    async fn send_notification_batch(
+        &self,
+        request: Request<NotificationBatchRequest>,
+    ) -> Result<Response<NotificationBatchResponse>, Status> {
+        grpc_warn!("(MOCK) contact server.");
+        grpc_debug!("(MOCK) [{:?}].", request);
+        let results: Vec<NotificationBatchResult> = request
+            .into_inner()
+            .notifications
+            .into_iter()
+            .enumerate()
+            .map(|(index, notification)| NotificationBatchResult {
+                index: index as u32,
+                user_id: notification.user_id,
+                success: true,
+                channels: vec![DeliveryChannel::Email as i32],
+                message_id: Some(lib_common::uuid::Uuid::new_v4().to_string()),
+                failure_reason: FailureReason::None as i32,
+                error: String::new(),
+            })
+            .collect();
+        let response = NotificationBatchResponse {
+            sent: results.len() as u32,
+            failed: 0,
+            results,
+        };
         Ok(Response::new(response))
     }
 }
@@ -167,6 +395,15 @@ mod tests {
             .cargo_confirmation(Request::new(CargoConfirmationRequest {
                 itinerary_id: String::from(lib_common::uuid::Uuid::new_v4()),
                 parcel_id: String::from(lib_common::uuid::Uuid::new_v4()),
+                phone_number: None,
+                idempotency_key: None,
+                locale: None,
+                price_lines: vec![PriceLine {
+                    description: String::from("Flight"),
+                    amount: String::from("40.00"),
+                }],
+                tax_rate: String::new(),
+                currency: String::new(),
             }))
             .await;
         assert!(result.is_ok());
@@ -175,4 +412,178 @@ mod tests {
 
         ut_info!("success");
     }
+
+    #[tokio::test]
+    #[cfg(feature = "stub_server")]
+    async fn test_grpc_cargo_cancellation() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("start");
+
+        let imp = ServerImpl::default();
+        let result = imp
+            .cargo_cancellation(Request::new(CargoCancellationRequest {
+                itinerary_id: String::from(lib_common::uuid::Uuid::new_v4()),
+                parcel_id: String::from(lib_common::uuid::Uuid::new_v4()),
+                reason: CancellationReason::Weather as i32,
+                refund_amount_cents: 1250,
+                currency: String::from("EUR"),
+                locale: None,
+            }))
+            .await;
+        assert!(result.is_ok());
+        let result: CargoCancellationResponse = result.unwrap().into_inner();
+        assert!(result.success);
+
+        ut_info!("success");
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "stub_server")]
+    async fn test_grpc_query_deliveries() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("start");
+
+        let imp = ServerImpl::default();
+        let result = imp
+            .query_deliveries(Request::new(DeliveryQueryRequest {
+                user_id: Some(String::from(lib_common::uuid::Uuid::new_v4())),
+                itinerary_id: None,
+                from: None,
+                to: None,
+                limit: None,
+            }))
+            .await;
+        assert!(result.is_ok());
+        let result: DeliveryQueryResponse = result.unwrap().into_inner();
+        assert!(result.deliveries.is_empty());
+
+        ut_info!("success");
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "stub_server")]
+    async fn test_grpc_watch_delivery() {
+        use futures::StreamExt;
+
+        lib_common::logger::get_log_handle().await;
+        ut_info!("start");
+
+        let imp = ServerImpl::default();
+        let result = imp
+            .watch_delivery(Request::new(DeliveryWatchRequest {
+                notification_id: String::from(lib_common::uuid::Uuid::new_v4()),
+            }))
+            .await;
+        assert!(result.is_ok());
+        let updates: Vec<_> = result.unwrap().into_inner().collect().await;
+        assert_eq!(updates.len(), 1);
+        let update = updates[0].as_ref().unwrap();
+        assert!(update.done);
+        assert_eq!(
+            update.delivery.as_ref().unwrap().status(),
+            DeliveryStatus::Delivered
+        );
+
+        ut_info!("success");
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "stub_server")]
+    async fn test_grpc_flight_delay_notification() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("start");
+
+        let imp = ServerImpl::default();
+        let result = imp
+            .flight_delay_notification(Request::new(FlightDelayRequest {
+                flight_plan_id: String::from(lib_common::uuid::Uuid::new_v4()),
+                old_origin_timeslot_start: Some(prost_types::Timestamp::default()),
+                new_origin_timeslot_start: Some(prost_types::Timestamp::default()),
+                old_target_timeslot_end: Some(prost_types::Timestamp::default()),
+                new_target_timeslot_end: Some(prost_types::Timestamp::default()),
+            }))
+            .await;
+        assert!(result.is_ok());
+        let result: FlightDelayResponse = result.unwrap().into_inner();
+        assert_eq!(result.notified, 0);
+
+        ut_info!("success");
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "stub_server")]
+    async fn test_grpc_parcel_arrival() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("start");
+
+        let imp = ServerImpl::default();
+        let result = imp
+            .parcel_arrival(Request::new(ParcelArrivalRequest {
+                parcel_id: String::from(lib_common::uuid::Uuid::new_v4()),
+                locale: None,
+            }))
+            .await;
+        assert!(result.is_ok());
+        let result: ParcelArrivalResponse = result.unwrap().into_inner();
+        assert!(result.success);
+
+        ut_info!("success");
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "stub_server")]
+    async fn test_grpc_send_notification() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("start");
+
+        let imp = ServerImpl::default();
+        let result = imp
+            .send_notification(Request::new(NotificationRequest {
+                user_id: String::from(lib_common::uuid::Uuid::new_v4()),
+                template: String::from("parcel-arrival"),
+                channel: ChannelPreference::Email as i32,
+                model: Default::default(),
+                phone_number: None,
+                parcel_id: None,
+                itinerary_id: None,
+                locale: None,
+            }))
+            .await;
+        assert!(result.is_ok());
+        let result: NotificationResponse = result.unwrap().into_inner();
+        assert!(result.success);
+
+        ut_info!("success");
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "stub_server")]
+    async fn test_grpc_send_notification_batch() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("start");
+
+        let notification = NotificationRequest {
+            user_id: String::from(lib_common::uuid::Uuid::new_v4()),
+            template: String::from("parcel-arrival"),
+            channel: ChannelPreference::Email as i32,
+            model: Default::default(),
+            phone_number: None,
+            parcel_id: None,
+            itinerary_id: None,
+            locale: None,
+        };
+
+        let imp = ServerImpl::default();
+        let result = imp
+            .send_notification_batch(Request::new(NotificationBatchRequest {
+                notifications: vec![notification.clone(), notification],
+            }))
+            .await;
+        assert!(result.is_ok());
+        let result: NotificationBatchResponse = result.unwrap().into_inner();
+        assert_eq!(result.sent, 2);
+        assert_eq!(result.results.len(), 2);
+
+        ut_info!("success");
+    }
 }
@@ -5,7 +5,15 @@
 pub mod test_util;
 
 pub mod config;
+pub mod delivery;
 pub mod grpc;
+pub mod locale;
+pub mod queue;
+pub mod receipt;
+pub mod scheduler;
+pub mod store;
+pub mod templates;
+pub mod token;
 
 pub use crate::config::Config;
 
@@ -0,0 +1,258 @@
+//! Locales
+//! select the language of messages and format numbers the way the user reads them
+
+use std::fmt::{self, Display, Formatter};
+
+/// Language of every template, used when no translation matches the locale
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+/// Languages writing `1.234,50`
+const PERIOD_GROUPING: &[&str] = &["da", "de", "el", "es", "id", "it", "nl", "pt", "ro", "tr"];
+
+/// Languages writing `1 234,50`, grouping with a no-break space
+const SPACE_GROUPING: &[&str] = &[
+    "cs", "fi", "fr", "hu", "nb", "nn", "no", "pl", "ru", "sk", "sv", "uk",
+];
+
+/// Checks a language tag has a 2 or 3 letter language subtag,
+/// followed by alphanumeric subtags, e.g. `nl`, `nl-BE` or `zh-Hant-TW`
+pub fn is_language_tag(tag: &str) -> bool {
+    let mut subtags = tag.split('-');
+    let Some(language) = subtags.next() else {
+        return false;
+    };
+
+    (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// The language and region of a user, as a language tag (e.g. `nl-BE`),
+/// selecting the template translation and the number format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+    tag: String,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale {
+            tag: DEFAULT_LANGUAGE.to_string(),
+        }
+    }
+}
+
+impl Display for Locale {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.tag)
+    }
+}
+
+impl Locale {
+    /// Parses a language tag, none if it isn't one.
+    /// The tag is normalized to its usual casing, e.g. `nl-be` becomes `nl-BE`.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let tag = tag.trim();
+        if !is_language_tag(tag) {
+            return None;
+        }
+
+        let subtags: Vec<String> = tag
+            .split('-')
+            .enumerate()
+            .map(|(index, subtag)| match (index, subtag.len()) {
+                (0, _) => subtag.to_lowercase(),
+                // region, e.g. BE
+                (_, 2) => subtag.to_uppercase(),
+                // script, e.g. Hant
+                (_, 4) => subtag[..1].to_uppercase() + &subtag[1..].to_lowercase(),
+                _ => subtag.to_lowercase(),
+            })
+            .collect();
+
+        Some(Locale {
+            tag: subtags.join("-"),
+        })
+    }
+
+    /// Resolves the locale of a message: the locale requested by the caller,
+    /// the preferred language of the user or the default language,
+    /// whichever is the first valid language tag
+    pub fn resolve(requested: Option<&str>, preferred: Option<&str>) -> Self {
+        requested
+            .into_iter()
+            .chain(preferred)
+            .find_map(Locale::parse)
+            .unwrap_or_default()
+    }
+
+    /// The language tag, e.g. `nl-BE`
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// The language subtag, e.g. `nl` for `nl-BE`
+    pub fn language(&self) -> &str {
+        self.tag.split('-').next().unwrap_or(DEFAULT_LANGUAGE)
+    }
+
+    /// Language tags to look for, from the most to the least specific and
+    /// ending with the default language, e.g. `nl-BE`, `nl`, `en`
+    pub fn fallback_chain(&self) -> Vec<String> {
+        let subtags: Vec<&str> = self.tag.split('-').collect();
+        let mut chain: Vec<String> = (1..=subtags.len())
+            .rev()
+            .map(|count| subtags[..count].join("-"))
+            .collect();
+
+        if self.language() != DEFAULT_LANGUAGE {
+            chain.push(DEFAULT_LANGUAGE.to_string());
+        }
+
+        chain
+    }
+
+    /// Looks up the strings of the locale in a table keyed by language tag,
+    /// taking the first tag of its fallback chain in the table,
+    /// e.g. the `nl` strings for `nl-BE`.
+    /// None if the table doesn't have the default language either.
+    pub fn lookup<'a, T>(&self, table: &'a [(&str, T)]) -> Option<&'a T> {
+        self.fallback_chain().iter().find_map(|tag| {
+            table
+                .iter()
+                .find(|(language, _)| language.eq_ignore_ascii_case(tag))
+                .map(|(_, strings)| strings)
+        })
+    }
+
+    /// Decimal and grouping separators of the language
+    fn separators(&self) -> (char, char) {
+        let language = self.language();
+        if PERIOD_GROUPING.contains(&language) {
+            (',', '.')
+        } else if SPACE_GROUPING.contains(&language) {
+            (',', '\u{a0}')
+        } else {
+            ('.', ',')
+        }
+    }
+
+    /// Localizes a number formatted with a period as decimal separator,
+    /// e.g. `1234.50` becomes `1,234.50` in `en` and `1.234,50` in `nl`
+    pub fn localize_number(&self, number: &str) -> String {
+        let (sign, digits) = match number.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", number),
+        };
+        let (integer, fraction) = match digits.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (digits, None),
+        };
+
+        let (decimal, group) = self.separators();
+        let mut localized = sign.to_string();
+        for (index, digit) in integer.chars().enumerate() {
+            if index > 0 && (integer.len() - index) % 3 == 0 {
+                localized.push(group);
+            }
+            localized.push(digit);
+        }
+
+        if let Some(fraction) = fraction {
+            localized.push(decimal);
+            localized.push_str(fraction);
+        }
+
+        localized
+    }
+
+    /// Formats a number with the provided number of decimals
+    pub fn format_decimal(&self, value: f64, decimals: usize) -> String {
+        self.localize_number(&format!("{:.*}", decimals, value))
+    }
+
+    /// Formats a weight in kilograms, with two decimals
+    pub fn format_weight_kg(&self, kg: f64) -> String {
+        self.format_decimal(kg, 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locale(tag: &str) -> Locale {
+        Locale::parse(tag).unwrap()
+    }
+
+    #[test]
+    fn test_is_language_tag() {
+        for tag in ["nl", "nl-BE", "zh-Hant-TW", "fil"] {
+            assert!(is_language_tag(tag), "{}", tag);
+        }
+
+        for tag in ["", "dutch", "n", "nl_BE", "nl-", "nl-BE!"] {
+            assert!(!is_language_tag(tag), "{}", tag);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(locale("nl-be").tag(), "nl-BE");
+        assert_eq!(locale(" EN ").tag(), "en");
+        assert_eq!(locale("zh-hant-tw").tag(), "zh-Hant-TW");
+        assert_eq!(locale("nl-BE").language(), "nl");
+        assert_eq!(locale("nl-BE").to_string(), "nl-BE");
+        assert_eq!(Locale::parse("Nederlands"), None);
+        assert_eq!(Locale::default().tag(), "en");
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(Locale::resolve(Some("fr"), Some("nl-BE")), locale("fr"));
+        assert_eq!(Locale::resolve(None, Some("nl-BE")), locale("nl-BE"));
+        assert_eq!(Locale::resolve(Some(""), Some("nl")), locale("nl"));
+        assert_eq!(Locale::resolve(Some("français"), None), Locale::default());
+        assert_eq!(Locale::resolve(None, None), Locale::default());
+    }
+
+    #[test]
+    fn test_fallback_chain() {
+        assert_eq!(locale("nl-BE").fallback_chain(), vec!["nl-BE", "nl", "en"]);
+        assert_eq!(
+            locale("zh-Hant-TW").fallback_chain(),
+            vec!["zh-Hant-TW", "zh-Hant", "zh", "en"]
+        );
+        assert_eq!(locale("en-GB").fallback_chain(), vec!["en-GB", "en"]);
+        assert_eq!(locale("en").fallback_chain(), vec!["en"]);
+    }
+
+    #[test]
+    fn test_lookup() {
+        let table = [("en", "Receipt"), ("nl", "Bon"), ("nl-BE", "Kasticket")];
+        assert_eq!(locale("nl-BE").lookup(&table), Some(&"Kasticket"));
+        assert_eq!(locale("nl-NL").lookup(&table), Some(&"Bon"));
+        assert_eq!(locale("fr").lookup(&table), Some(&"Receipt"));
+        assert_eq!(locale("fr").lookup(&table[1..]), None);
+    }
+
+    #[test]
+    fn test_localize_number() {
+        assert_eq!(locale("en").localize_number("1234567.50"), "1,234,567.50");
+        assert_eq!(locale("nl-BE").localize_number("1234.50"), "1.234,50");
+        assert_eq!(locale("fr").localize_number("1234.50"), "1\u{a0}234,50");
+        assert_eq!(locale("nl").localize_number("-12.5"), "-12,5");
+        assert_eq!(locale("nl").localize_number("123"), "123");
+        assert_eq!(locale("nl").localize_number("1000"), "1.000");
+    }
+
+    #[test]
+    fn test_format_decimal() {
+        assert_eq!(locale("en").format_weight_kg(1.5), "1.50");
+        assert_eq!(locale("nl").format_weight_kg(1.5), "1,50");
+        assert_eq!(locale("de").format_decimal(1234.5678, 1), "1.234,6");
+        assert_eq!(locale("en").format_decimal(0.0, 2), "0.00");
+    }
+}
@@ -31,9 +31,46 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         return generate_openapi_spec::<ApiDoc>(&target).map_err(|e| e.into());
     }
 
-    grpc::api::cargo::POSTMARK_TOKEN
-        .set(config.postmark_token.clone())
-        .map_err(|_| "Failed to set POSTMARK_TOKEN")?;
+    let store = store::new_store(&config).map_err(|e| format!("Failed to create store: {}", e))?;
+    if !store.persistent() {
+        return Err(format!(
+            "No REDIS__URL configured: invoice numbers, pickup reminders and the delivery log \
+            can't be kept in a {} store, they would be lost on restart.",
+            store.name()
+        )
+        .into());
+    }
+    store::STORE.set(store).map_err(|_| "Failed to set STORE")?;
+
+    let email_backend = delivery::email::new_backend(&config)
+        .map_err(|e| format!("Failed to create email backend: {}", e))?;
+    delivery::email::EMAIL_BACKEND
+        .set(email_backend)
+        .map_err(|_| "Failed to set EMAIL_BACKEND")?;
+
+    if config.amqp.url.is_some() {
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(e) = queue::queue_consumer(config).await {
+                log::error!("(main) Queue consumer stopped: {}", e);
+            }
+        });
+    } else {
+        info!("(main) No AMQP url configured, queue consumer disabled.");
+    }
+
+    let lead_times = scheduler::parse_lead_times(&config.reminder_lead_times)
+        .map_err(|e| format!("Failed to load reminder lead times: {}", e))?;
+    if lead_times.is_empty() {
+        info!("(main) No reminder lead times configured, pickup reminders disabled.");
+    } else {
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(e) = scheduler::reminder_scheduler(config).await {
+                log::error!("(main) Reminder scheduler stopped: {}", e);
+            }
+        });
+    }
 
     tokio::spawn(rest_server(config.clone(), None));
 
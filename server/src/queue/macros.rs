@@ -0,0 +1,4 @@
+//! log macro's for queue logging
+
+use lib_common::log_macros;
+log_macros!("queue");
@@ -0,0 +1,747 @@
+//! Queue
+//! consumes notification jobs published by other services on an AMQP queue
+
+#[macro_use]
+pub mod macros;
+
+use crate::delivery::history::{self, DeliveryRecord};
+use crate::delivery::retry::RetryPolicy;
+use crate::grpc::server::{CargoConfirmationRequest, PriceLine};
+use crate::templates::CARGO_CONFIRMATION;
+use crate::Config;
+use futures::StreamExt;
+use lapin::message::Delivery;
+use lapin::options::{
+    BasicAckOptions, BasicConsumeOptions, BasicPublishOptions, BasicQosOptions, BasicRejectOptions,
+    ConfirmSelectOptions, QueueDeclareOptions,
+};
+use lapin::types::{AMQPValue, FieldTable, LongString, ShortString};
+use lapin::{BasicProperties, Channel};
+use serde::Deserialize;
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+use tonic::{Code, Status};
+
+/// Consumer tag used to identify this service on the broker
+const CONSUMER_TAG: &str = "svc-contact";
+
+/// Header counting how often a job was sent back through the retry queue
+const RETRIES_HEADER: &str = "x-retries";
+
+/// Delivery mode of messages that survive a broker restart
+const PERSISTENT: u8 = 2;
+
+/// Delay before the first reconnect, doubled for every following attempt
+const RECONNECT_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Upper bound for the delay between two reconnects
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Notification job, published as JSON with a `type` tag, e.g.
+/// `{"type": "cargo_confirmation", "parcel_id": "...", "itinerary_id": "...", "price_lines": [...]}`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NotificationJob {
+    /// Send a cargo confirmation, equivalent to the `cargoConfirmation` RPC
+    CargoConfirmation {
+        /// Parcel ID
+        parcel_id: String,
+
+        /// Itinerary ID
+        itinerary_id: String,
+
+        /// Phone number (E.164) to send a text message confirmation to
+        #[serde(default)]
+        phone_number: Option<String>,
+
+        /// Key identifying retries of the same job
+        #[serde(default)]
+        idempotency_key: Option<String>,
+
+        /// Language tag of the confirmation, defaults to the user's preferred language
+        #[serde(default)]
+        locale: Option<String>,
+
+        /// Price lines of the receipt, excluding tax.
+        /// Deprecated without: the confirmation gets an empty receipt.
+        #[serde(default)]
+        price_lines: Vec<QueuedPriceLine>,
+
+        /// Tax rate over the price lines, in percent
+        #[serde(default)]
+        tax_rate: String,
+
+        /// ISO 4217 currency code of the price lines, defaults to EUR
+        #[serde(default)]
+        currency: String,
+    },
+}
+
+/// A price line of a queued cargo confirmation, see [`PriceLine`]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct QueuedPriceLine {
+    /// Description shown on the receipt
+    pub description: String,
+
+    /// Amount excluding tax, as a decimal number (e.g. `12.50`)
+    pub amount: String,
+}
+
+impl From<QueuedPriceLine> for PriceLine {
+    fn from(line: QueuedPriceLine) -> Self {
+        PriceLine {
+            description: line.description,
+            amount: line.amount,
+        }
+    }
+}
+
+impl NotificationJob {
+    /// Decodes a job from the payload of a queue message
+    pub fn decode(payload: &[u8]) -> Result<Self, QueueError> {
+        serde_json::from_slice(payload).map_err(|e| QueueError::Decode(e.to_string()))
+    }
+
+    /// ID the job is recorded under in the delivery log: its idempotency
+    /// key, or the parcel ID and itinerary ID when it has none.
+    /// A redelivered job keeps its ID.
+    pub fn delivery_id(&self) -> String {
+        match self {
+            NotificationJob::CargoConfirmation {
+                parcel_id,
+                itinerary_id,
+                idempotency_key,
+                ..
+            } => match idempotency_key.as_deref() {
+                Some(key) if !key.is_empty() => key.to_string(),
+                _ => format!("{}:{}", parcel_id, itinerary_id),
+            },
+        }
+    }
+
+    /// Executes the job, recording its notification under `delivery_id`
+    #[cfg(not(tarpaulin_include))]
+    // no_coverage: (Rnever) needs storage and delivery backends, only integration tests
+    pub async fn process(self, delivery_id: String) -> Result<(), Status> {
+        match self {
+            NotificationJob::CargoConfirmation {
+                parcel_id,
+                itinerary_id,
+                phone_number,
+                idempotency_key,
+                locale,
+                price_lines,
+                tax_rate,
+                currency,
+            } => {
+                let request = CargoConfirmationRequest {
+                    parcel_id,
+                    itinerary_id,
+                    phone_number,
+                    idempotency_key,
+                    locale,
+                    price_lines: price_lines.into_iter().map(PriceLine::from).collect(),
+                    tax_rate,
+                    currency,
+                };
+                crate::grpc::api::cargo::queued_cargo_confirmation(request, delivery_id)
+                    .await
+                    .map(|_| ())
+            }
+        }
+    }
+}
+
+/// Errors while consuming the notification queue
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueueError {
+    /// Could not connect to the broker or set up the queues
+    Connection(String),
+
+    /// A message payload is not a valid job
+    Decode(String),
+}
+
+impl std::error::Error for QueueError {}
+
+impl Display for QueueError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            QueueError::Connection(e) => write!(f, "Queue connection error: {}", e),
+            QueueError::Decode(e) => write!(f, "Invalid job: {}", e),
+        }
+    }
+}
+
+/// What to tell the broker after handling a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobOutcome {
+    /// Job done, remove the message from the queue
+    Ack,
+
+    /// Job failed temporarily, put the message back on the queue after a delay
+    Requeue,
+
+    /// Job can not succeed, move the message to the dead-letter queue
+    DeadLetter,
+}
+
+impl JobOutcome {
+    /// Decides the outcome of a job.
+    /// Transient failures are retried once, a message failing again after
+    /// its retry is moved to the dead-letter queue so it can't block the queue.
+    pub fn from_result(result: &Result<(), Status>, retried: bool) -> Self {
+        match result {
+            Ok(()) => JobOutcome::Ack,
+            Err(status) if is_permanent(status) => JobOutcome::DeadLetter,
+            Err(_) if retried => JobOutcome::DeadLetter,
+            Err(_) => JobOutcome::Requeue,
+        }
+    }
+
+    /// Acknowledges, retries or rejects the delivery.
+    /// A retried message is published to the retry queue of `queue`, and only
+    /// acknowledged once the broker confirmed the copy.
+    #[cfg(not(tarpaulin_include))]
+    // no_coverage: (Rnever) needs a broker connection, only integration tests
+    async fn apply(
+        self,
+        channel: &Channel,
+        queue: &str,
+        retry_delay_ms: u32,
+        delivery: &Delivery,
+    ) -> Result<(), QueueError> {
+        let connection_error = |e: lapin::Error| QueueError::Connection(e.to_string());
+        match self {
+            JobOutcome::Ack => delivery
+                .ack(BasicAckOptions::default())
+                .await
+                .map_err(connection_error),
+            JobOutcome::Requeue => {
+                let confirmation = channel
+                    .basic_publish(
+                        "",
+                        &retry_queue(queue),
+                        BasicPublishOptions::default(),
+                        &delivery.data,
+                        retry_properties(&delivery.properties, retry_delay_ms),
+                    )
+                    .await
+                    .map_err(connection_error)?
+                    .await
+                    .map_err(connection_error)?;
+                if confirmation.is_nack() {
+                    return Err(QueueError::Connection(
+                        "retry refused by the broker".to_string(),
+                    ));
+                }
+
+                delivery
+                    .ack(BasicAckOptions::default())
+                    .await
+                    .map_err(connection_error)
+            }
+            // rejecting without requeue routes the message to the dead-letter exchange
+            JobOutcome::DeadLetter => delivery
+                .reject(BasicRejectOptions { requeue: false })
+                .await
+                .map_err(connection_error),
+        }
+    }
+}
+
+/// Returns true if retrying the job will not change the result
+fn is_permanent(status: &Status) -> bool {
+    matches!(
+        status.code(),
+        Code::InvalidArgument
+            | Code::NotFound
+            | Code::FailedPrecondition
+            | Code::OutOfRange
+            | Code::Unimplemented
+    )
+}
+
+/// Name of the dead-letter queue belonging to a queue
+pub fn dead_letter_queue(queue: &str) -> String {
+    format!("{}.dead", queue)
+}
+
+/// Name of the retry queue belonging to a queue
+pub fn retry_queue(queue: &str) -> String {
+    format!("{}.retry", queue)
+}
+
+/// Queue arguments routing rejected and expired messages
+/// through the default exchange to the `target` queue
+fn dead_letter_arguments(target: &str) -> FieldTable {
+    let mut arguments = FieldTable::default();
+    arguments.insert(
+        ShortString::from("x-dead-letter-exchange"),
+        AMQPValue::LongString(LongString::from("")),
+    );
+    arguments.insert(
+        ShortString::from("x-dead-letter-routing-key"),
+        AMQPValue::LongString(LongString::from(target)),
+    );
+    arguments
+}
+
+/// Number of times a message was sent back through the retry queue
+fn retries(properties: &BasicProperties) -> u32 {
+    let value = properties.headers().as_ref().and_then(|headers| {
+        headers
+            .inner()
+            .get(&ShortString::from(RETRIES_HEADER))
+            .cloned()
+    });
+
+    match value {
+        Some(AMQPValue::LongUInt(retries)) => retries,
+        Some(AMQPValue::LongInt(retries)) => retries.max(0) as u32,
+        Some(AMQPValue::LongLongInt(retries)) => retries.clamp(0, u32::MAX as i64) as u32,
+        _ => 0,
+    }
+}
+
+/// Properties of the copy of a message published to the retry queue.
+/// The copy expires after `retry_delay_ms` milliseconds, which moves it back
+/// to the notification queue, and counts the retry in its headers.
+fn retry_properties(properties: &BasicProperties, retry_delay_ms: u32) -> BasicProperties {
+    let mut headers = properties.headers().clone().unwrap_or_default();
+    headers.insert(
+        ShortString::from(RETRIES_HEADER),
+        AMQPValue::LongUInt(retries(properties).saturating_add(1)),
+    );
+
+    properties
+        .clone()
+        .with_headers(headers)
+        .with_expiration(ShortString::from(retry_delay_ms.to_string()))
+        .with_delivery_mode(PERSISTENT)
+}
+
+/// Declares the durable notification queue, its retry queue and its dead-letter queue
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) needs a broker connection, only integration tests
+async fn declare_queues(channel: &Channel, queue: &str) -> Result<(), lapin::Error> {
+    let options = QueueDeclareOptions {
+        durable: true,
+        ..Default::default()
+    };
+
+    channel
+        .queue_declare(&dead_letter_queue(queue), options, FieldTable::default())
+        .await?;
+    channel
+        .queue_declare(&retry_queue(queue), options, dead_letter_arguments(queue))
+        .await?;
+    channel
+        .queue_declare(
+            queue,
+            options,
+            dead_letter_arguments(&dead_letter_queue(queue)),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Processes a single delivery and reports the outcome to the broker
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) needs a broker connection, only integration tests
+async fn handle_delivery(channel: Channel, queue: String, retry_delay_ms: u32, delivery: Delivery) {
+    let (delivery_id, result) = match NotificationJob::decode(&delivery.data) {
+        Ok(job) => {
+            queue_debug!("processing job {:?}.", job);
+            let delivery_id = job.delivery_id();
+            record_queued(&delivery_id).await;
+            let result = job.process(delivery_id.clone()).await;
+            (Some(delivery_id), result)
+        }
+        Err(e) => (None, Err(Status::invalid_argument(e.to_string()))),
+    };
+
+    let outcome = JobOutcome::from_result(&result, retries(&delivery.properties) > 0);
+    if let Some(delivery_id) = delivery_id {
+        finish_queued(&delivery_id, &result, outcome).await;
+    }
+    match &result {
+        Ok(()) => queue_info!("job {} done.", delivery.delivery_tag),
+        Err(e) => queue_warn!(
+            "job {} failed ({:?}): {}",
+            delivery.delivery_tag,
+            outcome,
+            e.message()
+        ),
+    }
+
+    if let Err(e) = outcome
+        .apply(&channel, &queue, retry_delay_ms, &delivery)
+        .await
+    {
+        queue_error!(
+            "could not report {:?} for job {}: {}",
+            outcome,
+            delivery.delivery_tag,
+            e
+        );
+    }
+}
+
+/// Records an accepted job as queued in the delivery log, so its status can
+/// be watched before the notification is sent.
+/// Failing to record it is logged but does not fail the job.
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) needs a broker connection, only integration tests
+async fn record_queued(delivery_id: &str) {
+    let record = DeliveryRecord::queued(
+        delivery_id,
+        history::Channel::Email,
+        CARGO_CONFIRMATION.name,
+    );
+    let recorded = match history::get_log().await {
+        Ok(log) => log.record_queued(&record).await,
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = recorded {
+        queue_warn!("could not record queued job {}: {}", delivery_id, e);
+    }
+}
+
+/// Finishes the queued record of a job that won't be processed again.
+/// A retried job stays queued.
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) needs a broker connection, only integration tests
+async fn finish_queued(delivery_id: &str, result: &Result<(), Status>, outcome: JobOutcome) {
+    let error = match (result, outcome) {
+        (_, JobOutcome::Requeue) => return,
+        (Err(e), _) => Some(e.message().to_string()),
+        (Ok(()), _) => None,
+    };
+
+    let finished = match history::get_log().await {
+        Ok(log) => log.finish_queued(delivery_id, error).await,
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = finished {
+        queue_warn!("could not finish queued job {}: {}", delivery_id, e);
+    }
+}
+
+/// Consumes notification jobs, reconnecting with backoff whenever the
+/// broker connection is lost or the consumer is cancelled.
+///
+/// At most `amqp_prefetch` jobs are unacknowledged, and thus processed, at once.
+/// Only returns when the AMQP configuration is invalid.
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) needs a broker connection, only integration tests
+pub async fn queue_consumer(config: Config) -> Result<(), QueueError> {
+    queue_debug!("entry.");
+
+    let pool = config
+        .amqp
+        .create_pool(Some(deadpool_lapin::Runtime::Tokio1))
+        .map_err(|e| QueueError::Connection(e.to_string()))?;
+    let policy = RetryPolicy {
+        max_attempts: u32::MAX,
+        base_delay: RECONNECT_BASE_DELAY,
+        max_delay: RECONNECT_MAX_DELAY,
+    };
+
+    let mut attempt = 0;
+    loop {
+        let result = consume(&pool, &config, &mut attempt).await;
+        attempt = attempt.saturating_add(1);
+        let delay = policy.backoff(attempt);
+        match result {
+            Ok(()) => queue_warn!(
+                "consumer for {} cancelled, reconnecting in {:?}.",
+                config.amqp_queue,
+                delay
+            ),
+            Err(e) => queue_error!("{}, reconnecting in {:?}.", e, delay),
+        }
+
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Consumes notification jobs until the broker connection is lost.
+/// `attempt` is reset once the consumer is subscribed, so a connection
+/// lost after a while is retried after the first backoff delay again.
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) needs a broker connection, only integration tests
+async fn consume(
+    pool: &deadpool_lapin::Pool,
+    config: &Config,
+    attempt: &mut u32,
+) -> Result<(), QueueError> {
+    let connection = pool
+        .get()
+        .await
+        .map_err(|e| QueueError::Connection(e.to_string()))?;
+    let channel = connection
+        .create_channel()
+        .await
+        .map_err(|e| QueueError::Connection(e.to_string()))?;
+
+    channel
+        .basic_qos(config.amqp_prefetch, BasicQosOptions::default())
+        .await
+        .map_err(|e| QueueError::Connection(e.to_string()))?;
+    // retried jobs are only acknowledged once the broker confirmed their copy
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await
+        .map_err(|e| QueueError::Connection(e.to_string()))?;
+    declare_queues(&channel, &config.amqp_queue)
+        .await
+        .map_err(|e| QueueError::Connection(e.to_string()))?;
+
+    let mut consumer = channel
+        .basic_consume(
+            &config.amqp_queue,
+            CONSUMER_TAG,
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .map_err(|e| QueueError::Connection(e.to_string()))?;
+
+    queue_info!(
+        "consuming {} (prefetch {}).",
+        config.amqp_queue,
+        config.amqp_prefetch
+    );
+    *attempt = 0;
+
+    while let Some(delivery) = consumer.next().await {
+        match delivery {
+            Ok(delivery) => {
+                tokio::spawn(handle_delivery(
+                    channel.clone(),
+                    config.amqp_queue.clone(),
+                    config.amqp_retry_delay_ms,
+                    delivery,
+                ));
+            }
+            Err(e) => return Err(QueueError::Connection(e.to_string())),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_job() {
+        let job = NotificationJob::decode(
+            br#"{"type": "cargo_confirmation", "parcel_id": "p1", "itinerary_id": "i1",
+                "price_lines": [{"description": "Flight", "amount": "40.00"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            job,
+            NotificationJob::CargoConfirmation {
+                parcel_id: "p1".to_string(),
+                itinerary_id: "i1".to_string(),
+                phone_number: None,
+                idempotency_key: None,
+                locale: None,
+                price_lines: vec![QueuedPriceLine {
+                    description: "Flight".to_string(),
+                    amount: "40.00".to_string(),
+                }],
+                tax_rate: String::new(),
+                currency: String::new(),
+            }
+        );
+
+        // deprecated, jobs published before price lines were added
+        let job = NotificationJob::decode(
+            br#"{"type": "cargo_confirmation", "parcel_id": "p1", "itinerary_id": "i1"}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            job,
+            NotificationJob::CargoConfirmation { price_lines, .. } if price_lines.is_empty()
+        ));
+
+        let job = NotificationJob::decode(
+            br#"{"type": "cargo_confirmation", "parcel_id": "p1", "itinerary_id": "i1", "phone_number": "+31611111111",
+                "price_lines": [{"description": "Flight", "amount": "40.00"}]}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            job,
+            NotificationJob::CargoConfirmation { phone_number: Some(number), .. } if number == "+31611111111"
+        ));
+
+        let job = NotificationJob::decode(
+            br#"{"type": "cargo_confirmation", "parcel_id": "p1", "itinerary_id": "i1", "locale": "nl-BE",
+                "price_lines": [{"description": "Flight", "amount": "40.00"}]}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            job,
+            NotificationJob::CargoConfirmation { locale: Some(locale), .. } if locale == "nl-BE"
+        ));
+
+        let job = NotificationJob::decode(
+            br#"{"type": "cargo_confirmation", "parcel_id": "p1", "itinerary_id": "i1",
+                "price_lines": [{"description": "Flight", "amount": "40.00"}],
+                "tax_rate": "21", "currency": "EUR"}"#,
+        )
+        .unwrap();
+        let NotificationJob::CargoConfirmation {
+            price_lines,
+            tax_rate,
+            currency,
+            ..
+        } = job;
+        assert_eq!(
+            price_lines,
+            vec![QueuedPriceLine {
+                description: "Flight".to_string(),
+                amount: "40.00".to_string(),
+            }]
+        );
+        assert_eq!(tax_rate, "21");
+        assert_eq!(currency, "EUR");
+
+        for payload in [
+            &b"not json"[..],
+            br#"{"type": "flight_cancellation"}"#,
+            br#"{"type": "cargo_confirmation", "parcel_id": "p1"}"#,
+        ] {
+            assert!(matches!(
+                NotificationJob::decode(payload).unwrap_err(),
+                QueueError::Decode(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_delivery_id() {
+        let job = NotificationJob::decode(
+            br#"{"type": "cargo_confirmation", "parcel_id": "p1", "itinerary_id": "i1",
+                "price_lines": [{"description": "Flight", "amount": "40.00"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(job.delivery_id(), "p1:i1");
+
+        let job = NotificationJob::decode(
+            br#"{"type": "cargo_confirmation", "parcel_id": "p1", "itinerary_id": "i1", "idempotency_key": "",
+                "price_lines": [{"description": "Flight", "amount": "40.00"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(job.delivery_id(), "p1:i1");
+
+        let job = NotificationJob::decode(
+            br#"{"type": "cargo_confirmation", "parcel_id": "p1", "itinerary_id": "i1", "idempotency_key": "order-42",
+                "price_lines": [{"description": "Flight", "amount": "40.00"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(job.delivery_id(), "order-42");
+    }
+
+    #[test]
+    fn test_job_outcome() {
+        assert_eq!(JobOutcome::from_result(&Ok(()), false), JobOutcome::Ack);
+        assert_eq!(JobOutcome::from_result(&Ok(()), true), JobOutcome::Ack);
+
+        let transient = Err(Status::internal("storage unavailable"));
+        assert_eq!(
+            JobOutcome::from_result(&transient, false),
+            JobOutcome::Requeue
+        );
+        assert_eq!(
+            JobOutcome::from_result(&transient, true),
+            JobOutcome::DeadLetter
+        );
+
+        for status in [
+            Status::invalid_argument("bad job"),
+            Status::not_found("no parcel"),
+            Status::failed_precondition("no email"),
+        ] {
+            assert_eq!(
+                JobOutcome::from_result(&Err(status), false),
+                JobOutcome::DeadLetter
+            );
+        }
+    }
+
+    #[test]
+    fn test_queue_arguments() {
+        assert_eq!(
+            dead_letter_queue("contact.notifications"),
+            "contact.notifications.dead"
+        );
+        assert_eq!(
+            retry_queue("contact.notifications"),
+            "contact.notifications.retry"
+        );
+
+        let arguments = dead_letter_arguments("contact.notifications.dead");
+        let arguments = arguments.inner();
+        assert_eq!(
+            arguments.get(&ShortString::from("x-dead-letter-exchange")),
+            Some(&AMQPValue::LongString(LongString::from("")))
+        );
+        assert_eq!(
+            arguments.get(&ShortString::from("x-dead-letter-routing-key")),
+            Some(&AMQPValue::LongString(LongString::from(
+                "contact.notifications.dead"
+            )))
+        );
+    }
+
+    #[test]
+    fn test_retry_properties() {
+        let mut headers = FieldTable::default();
+        headers.insert(
+            ShortString::from("trace-id"),
+            AMQPValue::LongString(LongString::from("trace-1")),
+        );
+        let properties = BasicProperties::default()
+            .with_content_type(ShortString::from("application/json"))
+            .with_headers(headers);
+        assert_eq!(retries(&properties), 0);
+
+        let retry = retry_properties(&properties, 30000);
+        assert_eq!(retries(&retry), 1);
+        assert_eq!(retry.expiration(), &Some(ShortString::from("30000")));
+        assert_eq!(retry.delivery_mode(), &Some(PERSISTENT));
+        assert_eq!(
+            retry.content_type(),
+            &Some(ShortString::from("application/json"))
+        );
+        let headers = retry.headers().as_ref().unwrap().inner();
+        assert_eq!(
+            headers.get(&ShortString::from("trace-id")),
+            Some(&AMQPValue::LongString(LongString::from("trace-1")))
+        );
+
+        assert_eq!(retries(&retry_properties(&retry, 30000)), 2);
+        assert_eq!(retries(&BasicProperties::default()), 0);
+    }
+
+    #[test]
+    fn test_queue_error_display() {
+        assert_eq!(
+            QueueError::Connection("refused".to_string()).to_string(),
+            "Queue connection error: refused"
+        );
+        assert_eq!(
+            QueueError::Decode("eof".to_string()).to_string(),
+            "Invalid job: eof"
+        );
+    }
+}
@@ -0,0 +1,329 @@
+//! Receipts of bookings: itemised price lines, tax and total,
+//! computed with decimal arithmetic in the minor units of the currency
+
+pub mod pdf;
+
+use crate::locale::Locale;
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// Currency of receipts and refunds that don't name one
+pub const DEFAULT_CURRENCY: &str = "EUR";
+
+/// Currencies without minor units, e.g. `1 JPY`
+const ZERO_DECIMAL_CURRENCIES: &[&str] = &[
+    "BIF", "CLP", "DJF", "GNF", "ISK", "JPY", "KMF", "KRW", "PYG", "RWF", "UGX", "VND", "VUV",
+    "XAF", "XOF", "XPF",
+];
+
+/// Currencies with three decimals, e.g. `1.500 KWD`
+const THREE_DECIMAL_CURRENCIES: &[&str] = &["BHD", "IQD", "JOD", "KWD", "LYD", "OMR", "TND"];
+
+/// Highest tax rate, in percent
+const MAX_TAX_RATE: u32 = 100;
+
+/// Reasons the prices of a receipt are refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReceiptError {
+    /// The currency is not an ISO 4217 currency code
+    Currency(String),
+
+    /// An amount is not a decimal number with at most the decimals of the currency
+    Amount(String),
+
+    /// A price line has no description
+    Description,
+
+    /// The tax rate is not a percentage from 0 to 100
+    TaxRate(String),
+
+    /// The total of the receipt is negative
+    NegativeTotal,
+}
+
+impl std::error::Error for ReceiptError {}
+
+impl Display for ReceiptError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReceiptError::Currency(currency) => write!(f, "Invalid currency: {}", currency),
+            ReceiptError::Amount(amount) => write!(f, "Invalid amount: {}", amount),
+            ReceiptError::Description => write!(f, "Missing price line description"),
+            ReceiptError::TaxRate(rate) => write!(f, "Invalid tax rate: {}", rate),
+            ReceiptError::NegativeTotal => write!(f, "Negative total"),
+        }
+    }
+}
+
+/// Returns the ISO 4217 currency code, [`DEFAULT_CURRENCY`] if none was provided
+pub fn currency_code(currency: &str) -> Result<String, ReceiptError> {
+    let currency = currency.trim().to_uppercase();
+    if currency.is_empty() {
+        return Ok(DEFAULT_CURRENCY.to_string());
+    }
+
+    if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(ReceiptError::Currency(currency));
+    }
+
+    Ok(currency)
+}
+
+/// Number of decimals of amounts in a currency, two for most currencies
+pub fn minor_units(currency: &str) -> u32 {
+    if ZERO_DECIMAL_CURRENCIES.contains(&currency) {
+        0
+    } else if THREE_DECIMAL_CURRENCIES.contains(&currency) {
+        3
+    } else {
+        2
+    }
+}
+
+/// Converts an amount in the minor units of a currency, e.g. cents, into a decimal amount
+pub fn from_minor_units(amount: u64, currency: &str) -> Decimal {
+    Decimal::from_i128_with_scale(i128::from(amount), minor_units(currency))
+}
+
+/// Formats an amount with the decimals of the currency, for the locale,
+/// e.g. `1,234.50` in `en` and `1.234,50` in `nl`
+pub fn format_amount(amount: Decimal, currency: &str, locale: &Locale) -> String {
+    let decimals = minor_units(currency) as usize;
+    locale.localize_number(&format!("{:.*}", decimals, amount))
+}
+
+/// A line on a receipt, e.g. the flight or a fee, excluding tax
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceiptLine {
+    /// Description shown on the receipt
+    pub description: String,
+
+    /// Amount excluding tax, negative for discounts
+    pub amount: Decimal,
+}
+
+/// The prices of a booking, with the tax over their sum
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Receipt {
+    /// Price lines, in the order they are shown
+    pub lines: Vec<ReceiptLine>,
+
+    /// ISO 4217 currency code
+    pub currency: String,
+
+    /// Tax rate, in percent
+    pub tax_rate: Decimal,
+
+    /// Sum of the price lines
+    pub subtotal: Decimal,
+
+    /// Tax over the subtotal, rounded to the minor units of the currency
+    pub tax: Decimal,
+
+    /// Subtotal and tax
+    pub total: Decimal,
+}
+
+impl Receipt {
+    /// Computes a receipt from `(description, amount)` price lines,
+    /// a tax rate in percent (none if empty) and a currency code
+    /// ([`DEFAULT_CURRENCY`] if empty). Amounts are decimal numbers,
+    /// e.g. `12.50`, with at most the decimals of the currency.
+    pub fn new<'a>(
+        lines: impl IntoIterator<Item = (&'a str, &'a str)>,
+        tax_rate: &str,
+        currency: &str,
+    ) -> Result<Self, ReceiptError> {
+        let currency = currency_code(currency)?;
+        let decimals = minor_units(&currency);
+
+        let lines = lines
+            .into_iter()
+            .map(|(description, amount)| {
+                let description = description.trim();
+                if description.is_empty() {
+                    return Err(ReceiptError::Description);
+                }
+
+                let amount = amount.trim();
+                match Decimal::from_str(amount) {
+                    Ok(value) if value.scale() <= decimals => Ok(ReceiptLine {
+                        description: description.to_string(),
+                        amount: value,
+                    }),
+                    _ => Err(ReceiptError::Amount(amount.to_string())),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let tax_rate = match tax_rate.trim() {
+            "" => Decimal::ZERO,
+            rate => Decimal::from_str(rate)
+                .ok()
+                .filter(|rate| !rate.is_sign_negative() && *rate <= Decimal::from(MAX_TAX_RATE))
+                .ok_or_else(|| ReceiptError::TaxRate(rate.to_string()))?,
+        };
+
+        let subtotal: Decimal = lines.iter().map(|line| line.amount).sum();
+        let tax = (subtotal * tax_rate / Decimal::ONE_HUNDRED)
+            .round_dp_with_strategy(decimals, RoundingStrategy::MidpointAwayFromZero);
+        let total = subtotal + tax;
+        if total < Decimal::ZERO {
+            return Err(ReceiptError::NegativeTotal);
+        }
+
+        Ok(Receipt {
+            lines,
+            currency,
+            tax_rate,
+            subtotal,
+            tax,
+            total,
+        })
+    }
+
+    /// Formats an amount with the decimals of the currency, for the locale,
+    /// e.g. `1,234.50` in `en` and `1.234,50` in `nl`
+    pub fn format_amount(&self, amount: Decimal, locale: &Locale) -> String {
+        format_amount(amount, &self.currency, locale)
+    }
+
+    /// Formats the tax rate without trailing zeros, for the locale, e.g. `9,5` in `nl`
+    pub fn format_tax_rate(&self, locale: &Locale) -> String {
+        locale.localize_number(&self.tax_rate.normalize().to_string())
+    }
+}
+
+impl Default for Receipt {
+    /// A receipt without price lines, in the default currency
+    fn default() -> Self {
+        Receipt {
+            lines: vec![],
+            currency: DEFAULT_CURRENCY.to_string(),
+            tax_rate: Decimal::ZERO,
+            subtotal: Decimal::ZERO,
+            tax: Decimal::ZERO,
+            total: Decimal::ZERO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    fn locale(tag: &str) -> Locale {
+        Locale::parse(tag).unwrap()
+    }
+
+    #[test]
+    fn test_currency_code() {
+        assert_eq!(currency_code("").unwrap(), "EUR");
+        assert_eq!(currency_code(" usd ").unwrap(), "USD");
+        assert_eq!(
+            currency_code("EURO").unwrap_err(),
+            ReceiptError::Currency("EURO".to_string())
+        );
+        assert!(currency_code("E1R").is_err());
+    }
+
+    #[test]
+    fn test_minor_units() {
+        assert_eq!(minor_units("EUR"), 2);
+        assert_eq!(minor_units("JPY"), 0);
+        assert_eq!(minor_units("KWD"), 3);
+
+        assert_eq!(from_minor_units(1250, "EUR").to_string(), "12.50");
+        assert_eq!(from_minor_units(1250, "JPY").to_string(), "1250");
+        assert_eq!(from_minor_units(1250, "KWD").to_string(), "1.250");
+
+        let nl = Locale::parse("nl").unwrap();
+        assert_eq!(
+            format_amount(Decimal::new(12345, 1), "EUR", &nl),
+            "1.234,50"
+        );
+        assert_eq!(format_amount(Decimal::new(1250, 0), "JPY", &nl), "1.250");
+    }
+
+    #[test]
+    fn test_receipt() {
+        let receipt = Receipt::new(
+            [
+                ("Flight", "40.00"),
+                ("Network fee", "2.5"),
+                ("Discount", "-5"),
+            ],
+            "21",
+            "eur",
+        )
+        .unwrap();
+        assert_eq!(receipt.currency, "EUR");
+        assert_eq!(receipt.lines.len(), 3);
+        assert_eq!(receipt.lines[1].description, "Network fee");
+        assert_eq!(receipt.subtotal, decimal("37.5"));
+        // 7.875 rounds up
+        assert_eq!(receipt.tax, decimal("7.88"));
+        assert_eq!(receipt.total, decimal("45.38"));
+
+        // no floating point error
+        let receipt = Receipt::new([("a", "0.10"), ("b", "0.20")], "", "").unwrap();
+        assert_eq!(receipt.total, decimal("0.3"));
+        assert_eq!(receipt.tax, Decimal::ZERO);
+        assert_eq!(receipt.currency, "EUR");
+
+        let receipt = Receipt::new([("Flight", "1000")], "10", "JPY").unwrap();
+        assert_eq!(receipt.total, decimal("1100"));
+
+        let receipt = Receipt::new(std::iter::empty(), "9", "").unwrap();
+        assert_eq!(receipt.total, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_receipt_refused() {
+        let error = Receipt::new([("Flight", "12.505")], "", "EUR").unwrap_err();
+        assert_eq!(error, ReceiptError::Amount("12.505".to_string()));
+        let error = Receipt::new([("Flight", "1.5")], "", "JPY").unwrap_err();
+        assert_eq!(error, ReceiptError::Amount("1.5".to_string()));
+        let error = Receipt::new([("Flight", "twelve")], "", "").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid amount: twelve");
+
+        let error = Receipt::new([(" ", "1.00")], "", "").unwrap_err();
+        assert_eq!(error, ReceiptError::Description);
+
+        for rate in ["-1", "101", "21%"] {
+            let error = Receipt::new([("Flight", "1.00")], rate, "").unwrap_err();
+            assert_eq!(error, ReceiptError::TaxRate(rate.to_string()));
+        }
+
+        let error = Receipt::new([("Flight", "1.00")], "", "EURO").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid currency: EURO");
+
+        let error = Receipt::new([("Refund", "-1.00")], "", "").unwrap_err();
+        assert_eq!(error, ReceiptError::NegativeTotal);
+    }
+
+    #[test]
+    fn test_format() {
+        let receipt = Receipt::new([("Flight", "1234.5")], "9.50", "EUR").unwrap();
+        assert_eq!(
+            receipt.format_amount(receipt.subtotal, &locale("en")),
+            "1,234.50"
+        );
+        assert_eq!(receipt.format_amount(receipt.tax, &locale("nl")), "117,28");
+        assert_eq!(receipt.format_tax_rate(&locale("en")), "9.5");
+        assert_eq!(receipt.format_tax_rate(&locale("nl")), "9,5");
+
+        let receipt = Receipt::new([("Flight", "1000")], "10", "JPY").unwrap();
+        assert_eq!(receipt.format_amount(receipt.total, &locale("en")), "1,100");
+        assert_eq!(receipt.format_tax_rate(&locale("en")), "10");
+
+        let receipt = Receipt::default();
+        assert_eq!(receipt.format_amount(receipt.total, &locale("en")), "0.00");
+        assert_eq!(receipt.format_tax_rate(&locale("en")), "0");
+    }
+}
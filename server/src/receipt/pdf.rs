@@ -0,0 +1,477 @@
+//! PDF receipts, written with the standard fonts every PDF reader provides,
+//! so no font files or external tools are needed to render them
+
+use super::Receipt;
+use crate::locale::{Locale, DEFAULT_LANGUAGE};
+use printpdf::{BuiltinFont, IndirectFontRef, Line, Mm, PdfDocument, Point};
+
+/// MIME type of PDF receipts
+pub const CONTENT_TYPE: &str = "application/pdf";
+
+/// A4 page width, in mm
+const PAGE_WIDTH: f32 = 210.0;
+
+/// A4 page height, in mm
+const PAGE_HEIGHT: f32 = 297.0;
+
+/// Margin around the content of a page, in mm
+const MARGIN: f32 = 20.0;
+
+/// Distance from the labels to the values of the booking details, in mm
+const VALUE_OFFSET: f32 = 45.0;
+
+/// Distance between two lines of text, in mm
+const LINE_HEIGHT: f32 = 6.0;
+
+/// Font size of regular text, in points
+const FONT_SIZE: f32 = 10.0;
+
+/// Font size of the company name, in points
+const TITLE_SIZE: f32 = 18.0;
+
+/// Font size of the document heading, in points
+const HEADING_SIZE: f32 = 14.0;
+
+/// Width of a Courier character per point of font size, in mm.
+/// Every Courier character is 0.6 em wide, so amounts can be right aligned.
+const MONO_WIDTH: f32 = 0.6 * 25.4 / 72.0;
+
+/// Longest price line description, longer descriptions are shortened
+/// so they don't run into the amounts
+const MAX_DESCRIPTION_CHARS: usize = 60;
+
+/// What a PDF receipt shows about a booking, formatted for the customer
+#[derive(Debug, Clone, Copy)]
+pub struct ReceiptDocument<'a> {
+    /// Invoice number
+    pub invoice_id: &'a str,
+
+    /// Date and time of the invoice
+    pub invoice_date: &'a str,
+
+    /// Name of the customer
+    pub customer_name: &'a str,
+
+    /// Weight of the parcel in kilograms, without unit
+    pub parcel_weight_kg: &'a str,
+
+    /// Name of the vertiport the parcel is picked up from
+    pub origin_vertiport_name: &'a str,
+
+    /// Name of the vertiport the parcel is delivered to
+    pub target_vertiport_name: &'a str,
+
+    /// Price lines and totals
+    pub receipt: &'a Receipt,
+}
+
+/// Font of a text, one of the standard PDF fonts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Style {
+    Regular,
+    Bold,
+    Mono,
+    MonoBold,
+}
+
+/// Text placed on a page, positions in mm from the bottom left corner
+#[derive(Debug, Clone, PartialEq)]
+struct Text {
+    text: String,
+    style: Style,
+    size: f32,
+    x: f32,
+    y: f32,
+}
+
+/// Content of a page
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Page {
+    texts: Vec<Text>,
+
+    /// Heights of the horizontal rules, in mm from the bottom
+    rules: Vec<f32>,
+}
+
+/// Places text line by line, starting a new page when a page is full
+#[derive(Debug)]
+struct Layout {
+    pages: Vec<Page>,
+
+    /// Baseline of the current line, in mm from the bottom
+    y: f32,
+}
+
+impl Layout {
+    fn new() -> Self {
+        Layout {
+            pages: vec![Page::default()],
+            y: PAGE_HEIGHT - MARGIN,
+        }
+    }
+
+    fn page(&mut self) -> &mut Page {
+        // there is always at least one page
+        let last = self.pages.len() - 1;
+        &mut self.pages[last]
+    }
+
+    /// Moves down, continuing on a new page below the bottom margin
+    fn next_line(&mut self, height: f32) {
+        self.y -= height;
+        if self.y < MARGIN {
+            self.pages.push(Page::default());
+            self.y = PAGE_HEIGHT - MARGIN;
+        }
+    }
+
+    /// Places text on the current line, starting at `x`
+    fn text(&mut self, text: impl Into<String>, style: Style, size: f32, x: f32) {
+        let y = self.y;
+        self.page().texts.push(Text {
+            text: text.into(),
+            style,
+            size,
+            x,
+            y,
+        });
+    }
+
+    /// Places an amount on the current line, aligned to the right margin
+    fn amount(&mut self, text: String, style: Style) {
+        let width = text.chars().count() as f32 * MONO_WIDTH * FONT_SIZE;
+        self.text(text, style, FONT_SIZE, PAGE_WIDTH - MARGIN - width);
+    }
+
+    /// Draws a horizontal rule above the current line
+    fn rule(&mut self) {
+        let y = self.y + LINE_HEIGHT * 0.7;
+        self.page().rules.push(y);
+    }
+}
+
+/// Labels of a receipt in one language
+struct Labels {
+    heading: &'static str,
+    invoice_id: &'static str,
+    invoice_date: &'static str,
+    customer_name: &'static str,
+    parcel_weight: &'static str,
+    pickup: &'static str,
+    dropoff: &'static str,
+    description: &'static str,
+    amount: &'static str,
+    subtotal: &'static str,
+    tax: &'static str,
+    total: &'static str,
+}
+
+/// Labels of a receipt in English, used when the locale has no translation
+const ENGLISH_LABELS: Labels = Labels {
+    heading: "Receipt",
+    invoice_id: "Invoice number",
+    invoice_date: "Date",
+    customer_name: "Customer",
+    parcel_weight: "Parcel weight",
+    pickup: "Pickup",
+    dropoff: "Dropoff",
+    description: "Description",
+    amount: "Amount",
+    subtotal: "Subtotal",
+    tax: "VAT",
+    total: "Total",
+};
+
+/// Labels of a receipt by language tag, see [`Locale::lookup`]
+const LABELS: &[(&str, Labels)] = &[
+    (DEFAULT_LANGUAGE, ENGLISH_LABELS),
+    (
+        "nl",
+        Labels {
+            heading: "Bon",
+            invoice_id: "Factuurnummer",
+            invoice_date: "Datum",
+            customer_name: "Klant",
+            parcel_weight: "Gewicht pakket",
+            pickup: "Ophalen",
+            dropoff: "Afleveren",
+            description: "Omschrijving",
+            amount: "Bedrag",
+            subtotal: "Subtotaal",
+            tax: "Btw",
+            total: "Totaal",
+        },
+    ),
+];
+
+impl Labels {
+    /// Labels in the language of the locale, English by default
+    fn for_locale(locale: &Locale) -> &'static Labels {
+        locale.lookup(LABELS).unwrap_or(&ENGLISH_LABELS)
+    }
+}
+
+/// Shortens a description to [`MAX_DESCRIPTION_CHARS`]
+fn shorten(description: &str) -> String {
+    if description.chars().count() <= MAX_DESCRIPTION_CHARS {
+        return description.to_string();
+    }
+
+    let mut shortened: String = description
+        .chars()
+        .take(MAX_DESCRIPTION_CHARS - 3)
+        .collect();
+    shortened.push_str("...");
+    shortened
+}
+
+/// Places the content of a receipt on pages
+fn layout(document: &ReceiptDocument<'_>, locale: &Locale) -> Vec<Page> {
+    let labels = Labels::for_locale(locale);
+    let receipt = document.receipt;
+    let mut layout = Layout::new();
+
+    layout.text("Aetheric", Style::Bold, TITLE_SIZE, MARGIN);
+    layout.next_line(LINE_HEIGHT * 1.5);
+    layout.text(labels.heading, Style::Regular, HEADING_SIZE, MARGIN);
+    layout.next_line(LINE_HEIGHT * 2.0);
+
+    let weight = format!("{} kg", document.parcel_weight_kg);
+    let details = [
+        (labels.invoice_id, document.invoice_id),
+        (labels.invoice_date, document.invoice_date),
+        (labels.customer_name, document.customer_name),
+        (labels.parcel_weight, weight.as_str()),
+        (labels.pickup, document.origin_vertiport_name),
+        (labels.dropoff, document.target_vertiport_name),
+    ];
+    for (label, value) in details {
+        layout.text(label, Style::Bold, FONT_SIZE, MARGIN);
+        layout.text(value, Style::Regular, FONT_SIZE, MARGIN + VALUE_OFFSET);
+        layout.next_line(LINE_HEIGHT);
+    }
+
+    layout.next_line(LINE_HEIGHT);
+    layout.text(labels.description, Style::Bold, FONT_SIZE, MARGIN);
+    layout.amount(
+        format!("{} ({})", labels.amount, receipt.currency),
+        Style::MonoBold,
+    );
+    layout.next_line(LINE_HEIGHT);
+    layout.rule();
+
+    for line in &receipt.lines {
+        layout.text(
+            shorten(&line.description),
+            Style::Regular,
+            FONT_SIZE,
+            MARGIN,
+        );
+        layout.amount(receipt.format_amount(line.amount, locale), Style::Mono);
+        layout.next_line(LINE_HEIGHT);
+    }
+
+    layout.rule();
+    let tax = format!("{} ({}%)", labels.tax, receipt.format_tax_rate(locale));
+    let totals = [
+        (labels.subtotal, receipt.subtotal),
+        (tax.as_str(), receipt.tax),
+    ];
+    for (label, amount) in totals {
+        layout.text(label, Style::Regular, FONT_SIZE, MARGIN);
+        layout.amount(receipt.format_amount(amount, locale), Style::Mono);
+        layout.next_line(LINE_HEIGHT);
+    }
+
+    layout.text(labels.total, Style::Bold, FONT_SIZE, MARGIN);
+    layout.amount(
+        receipt.format_amount(receipt.total, locale),
+        Style::MonoBold,
+    );
+
+    layout.pages
+}
+
+/// Writes the pages to a PDF document.
+/// Characters outside of Windows-1252 can't be shown by the standard fonts and are left out.
+fn write(title: &str, pages: &[Page]) -> Result<Vec<u8>, printpdf::Error> {
+    let (document, page, layer) =
+        PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "receipt");
+
+    let regular = document.add_builtin_font(BuiltinFont::Helvetica)?;
+    let bold = document.add_builtin_font(BuiltinFont::HelveticaBold)?;
+    let mono = document.add_builtin_font(BuiltinFont::Courier)?;
+    let mono_bold = document.add_builtin_font(BuiltinFont::CourierBold)?;
+
+    let mut layers = vec![document.get_page(page).get_layer(layer)];
+    for _ in 1..pages.len() {
+        let (page, layer) = document.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "receipt");
+        layers.push(document.get_page(page).get_layer(layer));
+    }
+
+    for (page, layer) in pages.iter().zip(layers) {
+        for text in &page.texts {
+            let font: &IndirectFontRef = match text.style {
+                Style::Regular => &regular,
+                Style::Bold => &bold,
+                Style::Mono => &mono,
+                Style::MonoBold => &mono_bold,
+            };
+            layer.use_text(text.text.clone(), text.size, Mm(text.x), Mm(text.y), font);
+        }
+
+        for y in &page.rules {
+            layer.add_line(Line {
+                points: vec![
+                    (Point::new(Mm(MARGIN), Mm(*y)), false),
+                    (Point::new(Mm(PAGE_WIDTH - MARGIN), Mm(*y)), false),
+                ],
+                is_closed: false,
+            });
+        }
+    }
+
+    document.save_to_bytes()
+}
+
+/// Renders the receipt of a booking as a PDF document, in the language of the locale
+pub fn render(document: &ReceiptDocument<'_>, locale: &Locale) -> Result<Vec<u8>, printpdf::Error> {
+    let labels = Labels::for_locale(locale);
+    let title = format!("{} {}", labels.heading, document.invoice_id);
+    write(&title, &layout(document, locale))
+}
+
+/// File name of the receipt of an invoice, e.g. `receipt-AET-2024-000042.pdf`.
+/// Characters that aren't safe in file names are replaced.
+pub fn file_name(invoice_id: &str) -> String {
+    let invoice_id: String = invoice_id
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '-',
+        })
+        .collect();
+
+    format!("receipt-{}.pdf", invoice_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locale(tag: &str) -> Locale {
+        Locale::parse(tag).unwrap()
+    }
+
+    fn document(receipt: &Receipt) -> ReceiptDocument<'_> {
+        ReceiptDocument {
+            invoice_id: "AET-2024-000042",
+            invoice_date: "2024-06-01 10:00 CEST",
+            customer_name: "Alice",
+            parcel_weight_kg: "2.50",
+            origin_vertiport_name: "Rotterdam",
+            target_vertiport_name: "Amsterdam",
+            receipt,
+        }
+    }
+
+    fn texts(pages: &[Page]) -> Vec<&str> {
+        pages
+            .iter()
+            .flat_map(|page| page.texts.iter().map(|text| text.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_layout() {
+        let receipt =
+            Receipt::new([("Flight", "1234.5"), ("Network fee", "2.50")], "21", "EUR").unwrap();
+        let pages = layout(&document(&receipt), &locale("en"));
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].rules.len(), 2);
+
+        let texts = texts(&pages);
+        for expected in [
+            "Receipt",
+            "AET-2024-000042",
+            "2024-06-01 10:00 CEST",
+            "2.50 kg",
+            "Rotterdam",
+            "Amsterdam",
+            "Amount (EUR)",
+            "Network fee",
+            "1,234.50",
+            "1,237.00",
+            "VAT (21%)",
+            "259.77",
+            "1,496.77",
+        ] {
+            assert!(texts.contains(&expected), "missing {}", expected);
+        }
+
+        // amounts end at the right margin
+        let total = pages[0].texts.last().unwrap();
+        assert_eq!(total.text, "1,496.77");
+        assert_eq!(total.style, Style::MonoBold);
+        let end = total.x + total.text.len() as f32 * MONO_WIDTH * FONT_SIZE;
+        assert!((end - (PAGE_WIDTH - MARGIN)).abs() < 0.001);
+
+        let pages = layout(&document(&receipt), &locale("nl-BE"));
+        let texts = texts(&pages);
+        for expected in [
+            "Bon",
+            "Factuurnummer",
+            "Bedrag (EUR)",
+            "1.234,50",
+            "Btw (21%)",
+        ] {
+            assert!(texts.contains(&expected), "missing {}", expected);
+        }
+    }
+
+    #[test]
+    fn test_layout_pages() {
+        let lines: Vec<String> = (0..100).map(|i| format!("Fee {}", i)).collect();
+        let receipt =
+            Receipt::new(lines.iter().map(|line| (line.as_str(), "1.00")), "", "EUR").unwrap();
+        let pages = layout(&document(&receipt), &locale("en"));
+        assert!(pages.len() > 1);
+
+        for page in &pages {
+            for text in &page.texts {
+                assert!(text.y >= MARGIN && text.y <= PAGE_HEIGHT - MARGIN);
+            }
+        }
+
+        let texts = texts(&pages);
+        assert!(texts.contains(&"Fee 99"));
+        assert_eq!(texts.last(), Some(&"100.00"));
+    }
+
+    #[test]
+    fn test_shorten() {
+        assert_eq!(shorten("Flight"), "Flight");
+        let shortened = shorten(&"a".repeat(80));
+        assert_eq!(shortened.chars().count(), MAX_DESCRIPTION_CHARS);
+        assert!(shortened.ends_with("..."));
+    }
+
+    #[test]
+    fn test_render() {
+        let receipt = Receipt::new([("Flight", "40.00")], "9", "EUR").unwrap();
+        let pdf = render(&document(&receipt), &locale("en")).unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+
+        let lines: Vec<String> = (0..100).map(|i| format!("Fee {}", i)).collect();
+        let receipt =
+            Receipt::new(lines.iter().map(|line| (line.as_str(), "1.00")), "", "").unwrap();
+        let pdf = render(&document(&receipt), &locale("nl")).unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("AET-2024-000042"), "receipt-AET-2024-000042.pdf");
+        assert_eq!(file_name("INV/2024 42"), "receipt-INV-2024-42.pdf");
+    }
+}
@@ -6,4 +6,7 @@ pub mod rest_types {
 }
 
 pub mod health;
+pub mod preferences;
+pub mod unsubscribe;
 pub mod user;
+pub mod webhook;
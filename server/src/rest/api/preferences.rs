@@ -0,0 +1,240 @@
+//! Rest API implementations of notification preferences
+/// openapi generated rest types
+pub use super::rest_types::*;
+use crate::delivery::preferences::{self, PreferenceError, PreferenceStore, Preferences};
+use axum::{extract::Path, Json};
+use chrono::NaiveTime;
+use hyper::StatusCode;
+
+/// Format of the quiet hours in the REST interface
+const TIME_FORMAT: &str = "%H:%M";
+
+impl From<Preferences> for UserPreferences {
+    fn from(preferences: Preferences) -> Self {
+        UserPreferences {
+            email: preferences.email,
+            sms: preferences.sms,
+            marketing: preferences.marketing,
+            language: preferences.language,
+            timezone: preferences.timezone,
+            quiet_hours: preferences.quiet_hours.map(|quiet_hours| QuietHours {
+                start: quiet_hours.start.format(TIME_FORMAT).to_string(),
+                end: quiet_hours.end.format(TIME_FORMAT).to_string(),
+            }),
+        }
+    }
+}
+
+/// Parses a quiet hours time
+fn parse_time(value: &str) -> Result<NaiveTime, PreferenceError> {
+    NaiveTime::parse_from_str(value, TIME_FORMAT)
+        .map_err(|_| PreferenceError::QuietHours(format!("{} is not a HH:MM time", value)))
+}
+
+impl TryFrom<UserPreferences> for Preferences {
+    type Error = PreferenceError;
+
+    fn try_from(request: UserPreferences) -> Result<Self, Self::Error> {
+        let quiet_hours = match request.quiet_hours {
+            Some(quiet_hours) => Some(preferences::QuietHours {
+                start: parse_time(&quiet_hours.start)?,
+                end: parse_time(&quiet_hours.end)?,
+            }),
+            None => None,
+        };
+
+        let preferences = Preferences {
+            email: request.email,
+            sms: request.sms,
+            marketing: request.marketing,
+            language: request.language.filter(|language| !language.is_empty()),
+            timezone: request.timezone.filter(|timezone| !timezone.is_empty()),
+            quiet_hours,
+        };
+        preferences.validate()?;
+
+        Ok(preferences)
+    }
+}
+
+/// Returns the notification preferences of a user,
+/// the defaults if the user didn't set any
+#[utoipa::path(
+    get,
+    path = "/contact/preferences/{user_id}",
+    tag = "svc-contact",
+    params(
+        ("user_id" = String, Path, description = "User ID"),
+    ),
+    responses(
+        (status = 200, description = "Request successful.", body = UserPreferences),
+        (status = 500, description = "Request unsuccessful."),
+    )
+)]
+pub async fn get_preferences(
+    Path(user_id): Path<String>,
+) -> Result<Json<UserPreferences>, StatusCode> {
+    rest_debug!("entry.");
+
+    let store = crate::store::get_store().await.map_err(|e| {
+        rest_error!("store not available: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let preferences = PreferenceStore::new(store)
+        .get(&user_id)
+        .await
+        .map_err(|e| {
+            rest_error!("could not get preferences of user {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .unwrap_or_default();
+
+    Ok(Json(preferences.into()))
+}
+
+/// Replaces the notification preferences of a user
+#[utoipa::path(
+    put,
+    path = "/contact/preferences/{user_id}",
+    tag = "svc-contact",
+    params(
+        ("user_id" = String, Path, description = "User ID"),
+    ),
+    request_body = UserPreferences,
+    responses(
+        (status = 200, description = "Preferences updated.", body = UserPreferences),
+        (status = 400, description = "Invalid language, timezone or quiet hours."),
+        (status = 500, description = "Request unsuccessful."),
+    )
+)]
+pub async fn update_preferences(
+    Path(user_id): Path<String>,
+    Json(payload): Json<UserPreferences>,
+) -> Result<Json<UserPreferences>, StatusCode> {
+    rest_debug!("entry.");
+
+    let preferences = Preferences::try_from(payload).map_err(|e| {
+        rest_warn!("invalid preferences for user {}: {}", user_id, e);
+        StatusCode::BAD_REQUEST
+    })?;
+
+    let store = crate::store::get_store().await.map_err(|e| {
+        rest_error!("store not available: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    PreferenceStore::new(store)
+        .set(&user_id, &preferences)
+        .await
+        .map_err(|e| {
+            rest_error!("could not set preferences of user {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    rest_info!("preferences of user {} updated.", user_id);
+    Ok(Json(preferences.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_preferences() -> UserPreferences {
+        UserPreferences {
+            email: true,
+            sms: false,
+            marketing: false,
+            language: Some("nl-BE".to_string()),
+            timezone: Some("Europe/Brussels".to_string()),
+            quiet_hours: Some(QuietHours {
+                start: "22:00".to_string(),
+                end: "07:30".to_string(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_try_from_user_preferences() {
+        let preferences = Preferences::try_from(user_preferences()).unwrap();
+        assert!(preferences.email);
+        assert!(!preferences.sms);
+        assert!(!preferences.marketing);
+        assert_eq!(preferences.language, Some("nl-BE".to_string()));
+        let quiet_hours = preferences.quiet_hours.unwrap();
+        assert_eq!(
+            quiet_hours.start,
+            NaiveTime::from_hms_opt(22, 0, 0).unwrap()
+        );
+        assert_eq!(quiet_hours.end, NaiveTime::from_hms_opt(7, 30, 0).unwrap());
+
+        let back = UserPreferences::from(preferences);
+        assert_eq!(back.timezone, Some("Europe/Brussels".to_string()));
+        let quiet_hours = back.quiet_hours.unwrap();
+        assert_eq!(quiet_hours.start, "22:00");
+        assert_eq!(quiet_hours.end, "07:30");
+
+        let mut empty = user_preferences();
+        empty.language = Some(String::new());
+        empty.timezone = Some(String::new());
+        let preferences = Preferences::try_from(empty).unwrap();
+        assert_eq!(preferences.language, None);
+        assert_eq!(preferences.timezone, None);
+
+        let mut invalid_time = user_preferences();
+        invalid_time.quiet_hours = Some(QuietHours {
+            start: "10pm".to_string(),
+            end: "07:00".to_string(),
+        });
+        assert_eq!(
+            Preferences::try_from(invalid_time).unwrap_err().to_string(),
+            "Invalid quiet hours: 10pm is not a HH:MM time"
+        );
+
+        let mut invalid_timezone = user_preferences();
+        invalid_timezone.timezone = Some("CEST".to_string());
+        assert_eq!(
+            Preferences::try_from(invalid_timezone).unwrap_err(),
+            PreferenceError::Timezone("CEST".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_and_update_preferences() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let user_id = lib_common::uuid::Uuid::new_v4().to_string();
+        let defaults = get_preferences(Path(user_id.clone())).await.unwrap().0;
+        assert!(defaults.email);
+        assert!(defaults.sms);
+        assert!(defaults.marketing);
+        assert_eq!(defaults.language, None);
+        assert!(defaults.quiet_hours.is_none());
+
+        let updated = update_preferences(Path(user_id.clone()), Json(user_preferences()))
+            .await
+            .unwrap()
+            .0;
+        assert!(!updated.marketing);
+
+        let stored = get_preferences(Path(user_id.clone())).await.unwrap().0;
+        assert!(!stored.sms);
+        assert!(!stored.marketing);
+        assert_eq!(stored.language, Some("nl-BE".to_string()));
+        assert_eq!(stored.quiet_hours.unwrap().end, "07:30");
+
+        let mut invalid = user_preferences();
+        invalid.language = Some("Nederlands (België)".to_string());
+        let error = update_preferences(Path(user_id.clone()), Json(invalid))
+            .await
+            .unwrap_err();
+        assert_eq!(error, StatusCode::BAD_REQUEST);
+
+        // the invalid update didn't change the stored preferences
+        let stored = get_preferences(Path(user_id)).await.unwrap().0;
+        assert_eq!(stored.language, Some("nl-BE".to_string()));
+
+        ut_info!("Success.");
+    }
+}
@@ -0,0 +1,219 @@
+//! Rest API implementations of one-click unsubscribe links
+/// openapi generated rest types
+pub use super::rest_types::*;
+use crate::delivery::preferences::PreferenceStore;
+use crate::delivery::unsubscribe::{get_unsubscribe, Unsubscribe};
+use crate::token::TokenError;
+use axum::{extract::Query, Json};
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+
+/// Confirmation returned to the user
+const CONFIRMATION: &str =
+    "You are unsubscribed from news and offers. Messages about your bookings are still sent.";
+
+/// Opts the user of an unsubscribe token out of marketing,
+/// keeping the other preferences of the user
+pub async fn opt_out(
+    unsubscribe: Option<&Unsubscribe>,
+    preferences: &PreferenceStore<'_>,
+    token: &str,
+    now: DateTime<Utc>,
+) -> Result<Json<String>, StatusCode> {
+    let Some(unsubscribe) = unsubscribe else {
+        rest_warn!("unsubscribe link used but no token signing key configured.");
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let user_id = unsubscribe.verify(token, now).map_err(|e| {
+        rest_warn!("unsubscribe token refused: {}", e);
+        match e {
+            TokenError::Expired => StatusCode::GONE,
+            TokenError::Malformed | TokenError::Signature => StatusCode::BAD_REQUEST,
+        }
+    })?;
+
+    let mut user_preferences = preferences
+        .get(&user_id)
+        .await
+        .map_err(|e| {
+            rest_error!("could not get preferences of user {}: {}", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .unwrap_or_default();
+
+    if user_preferences.marketing {
+        user_preferences.marketing = false;
+        preferences
+            .set(&user_id, &user_preferences)
+            .await
+            .map_err(|e| {
+                rest_error!("could not set preferences of user {}: {}", user_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        rest_info!("user {} unsubscribed from marketing.", user_id);
+    }
+
+    Ok(Json(CONFIRMATION.to_string()))
+}
+
+/// Opts the user out of marketing through the `List-Unsubscribe-Post`
+/// header (RFC 8058). Mail clients post `List-Unsubscribe=One-Click`
+/// as body, which is ignored.
+#[utoipa::path(
+    post,
+    path = "/contact/unsubscribe",
+    tag = "svc-contact",
+    params(UnsubscribeQuery),
+    responses(
+        (status = 200, description = "User unsubscribed.", body = String),
+        (status = 400, description = "Invalid token."),
+        (status = 404, description = "Unsubscribe links are disabled."),
+        (status = 410, description = "Token expired."),
+        (status = 500, description = "Request unsuccessful."),
+    )
+)]
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) depends on the global configuration, see opt_out
+pub async fn unsubscribe(
+    Query(query): Query<UnsubscribeQuery>,
+) -> Result<Json<String>, StatusCode> {
+    rest_debug!("entry.");
+
+    let store = crate::store::get_store().await.map_err(|e| {
+        rest_error!("store not available: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    opt_out(
+        get_unsubscribe().await,
+        &PreferenceStore::new(store),
+        &query.token,
+        Utc::now(),
+    )
+    .await
+}
+
+/// Opts the user out of marketing when the unsubscribe link in an
+/// email is opened
+#[utoipa::path(
+    get,
+    path = "/contact/unsubscribe",
+    tag = "svc-contact",
+    params(UnsubscribeQuery),
+    responses(
+        (status = 200, description = "User unsubscribed.", body = String),
+        (status = 400, description = "Invalid token."),
+        (status = 404, description = "Unsubscribe links are disabled."),
+        (status = 410, description = "Token expired."),
+        (status = 500, description = "Request unsuccessful."),
+    )
+)]
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) depends on the global configuration, see opt_out
+pub async fn unsubscribe_link(query: Query<UnsubscribeQuery>) -> Result<Json<String>, StatusCode> {
+    unsubscribe(query).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delivery::preferences::Preferences;
+    use crate::store::memory::MemoryStore;
+    use crate::Config;
+    use chrono::Duration;
+
+    fn unsubscribe() -> Unsubscribe {
+        let mut config = Config::default();
+        config.token_signing_key = "secret".to_string();
+        Unsubscribe::new(&config).unwrap()
+    }
+
+    fn token(unsubscribe: &Unsubscribe, user_id: &str, now: DateTime<Utc>) -> String {
+        let link = unsubscribe.link(user_id, now);
+        link.split_once("?token=").unwrap().1.to_string()
+    }
+
+    #[tokio::test]
+    async fn test_opt_out() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let preferences = PreferenceStore::new(&store);
+        let unsubscribe = unsubscribe();
+        let now = Utc::now();
+
+        // other preferences are kept
+        let stored = Preferences {
+            sms: false,
+            ..Default::default()
+        };
+        preferences.set("alice", &stored).await.unwrap();
+
+        let token = token(&unsubscribe, "alice", now);
+        let response = opt_out(Some(&unsubscribe), &preferences, &token, now)
+            .await
+            .unwrap();
+        assert_eq!(response.0, CONFIRMATION);
+        let stored = preferences.get("alice").await.unwrap().unwrap();
+        assert!(!stored.marketing);
+        assert!(!stored.sms);
+        assert!(stored.email);
+
+        // users without preferences get the defaults, opted out
+        let token = self::token(&unsubscribe, "bob", now);
+        opt_out(Some(&unsubscribe), &preferences, &token, now)
+            .await
+            .unwrap();
+        let stored = preferences.get("bob").await.unwrap().unwrap();
+        assert!(!stored.marketing);
+        assert!(stored.sms);
+
+        // a second click is fine
+        opt_out(Some(&unsubscribe), &preferences, &token, now)
+            .await
+            .unwrap();
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_opt_out_refused() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let preferences = PreferenceStore::new(&store);
+        let unsubscribe = unsubscribe();
+        let now = Utc::now();
+        let token = token(&unsubscribe, "alice", now);
+
+        let error = opt_out(None, &preferences, &token, now).await.unwrap_err();
+        assert_eq!(error, StatusCode::NOT_FOUND);
+
+        let error = opt_out(Some(&unsubscribe), &preferences, "token", now)
+            .await
+            .unwrap_err();
+        assert_eq!(error, StatusCode::BAD_REQUEST);
+
+        let mut config = Config::default();
+        config.token_signing_key = "other secret".to_string();
+        let other = Unsubscribe::new(&config).unwrap();
+        let error = opt_out(Some(&other), &preferences, &token, now)
+            .await
+            .unwrap_err();
+        assert_eq!(error, StatusCode::BAD_REQUEST);
+
+        let expired = now + Duration::try_days(365).unwrap();
+        let error = opt_out(Some(&unsubscribe), &preferences, &token, expired)
+            .await
+            .unwrap_err();
+        assert_eq!(error, StatusCode::GONE);
+
+        // nothing was recorded
+        assert!(preferences.get("alice").await.unwrap().is_none());
+
+        ut_info!("Success.");
+    }
+}
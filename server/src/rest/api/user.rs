@@ -1,8 +1,23 @@
 //! Rest API implementations of user-related operations
 /// openapi generated rest types
 pub use super::rest_types::*;
+use crate::delivery::email::{mask_address, EmailMessage, TemplateModel};
+use crate::delivery::verification::{
+    get_verification, Verification, VerificationError, VerifiedAddresses,
+};
+use crate::grpc::api::cargo::AETHERIC_EMAIL_ADDRESS;
+use crate::grpc::api::notify::{self, Recipient};
 use crate::grpc::client::GrpcClients;
-use axum::{extract::Extension, Json};
+use crate::locale::Locale;
+use crate::store::Store;
+use crate::templates::EMAIL_VERIFICATION;
+use crate::token::TokenError;
+use axum::{
+    extract::{Extension, Form, Query},
+    response::Html,
+    Json,
+};
+use chrono::{DateTime, Utc};
 use hyper::StatusCode;
 
 use svc_storage_client_grpc::prelude::*;
@@ -18,7 +33,87 @@ impl From<SignupRequest> for user::Data {
     }
 }
 
-/// Example REST API function
+/// The user an address is verified for, and the language of the email
+struct VerificationRequest<'a> {
+    user_id: &'a str,
+    email: &'a str,
+    display_name: &'a str,
+    locale: Option<&'a str>,
+}
+
+/// Composes the verification email of a user, in the requested language.
+/// An invalid locale doesn't fail the request, the default language is used.
+fn verification_message(
+    verification: &Verification,
+    request: &VerificationRequest,
+    now: DateTime<Utc>,
+) -> EmailMessage {
+    let name = request
+        .display_name
+        .split_whitespace()
+        .next()
+        .unwrap_or_default();
+
+    let mut model = TemplateModel::default();
+    model.insert("customer_name", name);
+    model.insert(
+        "verification_url",
+        verification.link(request.user_id, request.email, now),
+    );
+    model.insert("link_valid_hours", verification.ttl().num_hours());
+
+    EmailMessage {
+        from: AETHERIC_EMAIL_ADDRESS.to_string(),
+        to: request.email.trim().to_string(),
+        template: EMAIL_VERIFICATION.name.to_string(),
+        model,
+        locale: Locale::resolve(request.locale, None),
+        body: None,
+        headers: vec![],
+        attachments: vec![],
+    }
+}
+
+/// Sends the verification email of a user
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) depends on the global configuration, see verification_message
+async fn send_verification(
+    verification: &Verification,
+    request: &VerificationRequest<'_>,
+) -> Result<(), StatusCode> {
+    let backend = crate::delivery::email::get_backend().await.map_err(|e| {
+        rest_error!("email backend not available: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let log = crate::delivery::history::get_log().await.map_err(|e| {
+        rest_error!("delivery log not available: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let recipient = Recipient {
+        user_id: request.user_id.to_string(),
+        parcel_id: None,
+        itinerary_id: None,
+        delivery_id: None,
+    };
+    let message = verification_message(verification, request, Utc::now());
+    let policy = crate::delivery::retry::get_policy().await;
+    notify::send_email(backend, log, &recipient, message, policy)
+        .await
+        .map_err(|e| {
+            rest_warn!("could not send verification email: {}", e.message());
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    rest_info!(
+        "verification email sent to {}.",
+        mask_address(request.email)
+    );
+    Ok(())
+}
+
+/// Creates a user and sends a verification email to its address
 #[utoipa::path(
     post,
     path = "/contact/signup",
@@ -54,14 +149,301 @@ pub async fn signup(
         })?
         .id;
 
+    // failing to send the verification email does not fail the signup,
+    // the user can request another one
+    if let Some(verification) = get_verification().await {
+        let request = VerificationRequest {
+            user_id: &user_id,
+            email: &payload.email,
+            display_name: &payload.display_name,
+            locale: payload.locale.as_deref(),
+        };
+        let _ = send_verification(verification, &request).await;
+    }
+
     Ok(Json(user_id))
 }
 
+/// Returns the user requesting another verification email with the token
+/// of a previous one, and starts the resend cooldown of the user
+pub async fn authorize_resend(
+    verification: &Verification,
+    store: &dyn Store,
+    token: &str,
+    now: DateTime<Utc>,
+) -> Result<String, StatusCode> {
+    let user_id = verification.resend_user(token, now).map_err(|e| {
+        rest_warn!("verification resend refused: {}", e);
+        match e {
+            VerificationError::Token(TokenError::Expired) => StatusCode::GONE,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    })?;
+
+    verification
+        .claim_resend(store, &user_id)
+        .await
+        .map_err(|e| match e {
+            VerificationError::TooSoon => {
+                rest_warn!("verification email of user {} requested too soon.", user_id);
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            _ => {
+                rest_error!("resend cooldowns not available: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok(user_id)
+}
+
+/// Sends the verification email of a user again, e.g. when the link expired
+/// or the user changed their address. The user is identified by the token of
+/// a previous verification email, and the email is sent to the address of
+/// the user in svc-storage.
+#[utoipa::path(
+    post,
+    path = "/contact/verify/resend",
+    tag = "svc-contact",
+    request_body = ResendVerificationRequest,
+    responses(
+        (status = 200, description = "Verification email sent.", body = String),
+        (status = 401, description = "Invalid token."),
+        (status = 404, description = "Email verification is disabled."),
+        (status = 409, description = "Email address already verified."),
+        (status = 410, description = "Token expired too long ago."),
+        (status = 429, description = "Verification email requested too soon."),
+        (status = 500, description = "Request unsuccessful."),
+    )
+)]
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) depends on the global configuration, see authorize_resend
+pub async fn resend_verification(
+    Extension(grpc_clients): Extension<GrpcClients>,
+    Json(payload): Json<ResendVerificationRequest>,
+) -> Result<Json<String>, StatusCode> {
+    rest_debug!("entry.");
+
+    let Some(verification) = get_verification().await else {
+        rest_warn!("verification email requested but no token signing key configured.");
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let store = crate::store::get_store().await.map_err(|e| {
+        rest_error!("store not available: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let user_id = authorize_resend(verification, store, &payload.token, Utc::now()).await?;
+
+    let result = resend(&grpc_clients, verification, store, &user_id, &payload).await;
+    if result.is_err() {
+        // nothing was sent, so the user can try again right away
+        let _ = verification.release_resend(store, &user_id).await;
+    }
+
+    result.map(|_| Json("Verification email sent.".to_string()))
+}
+
+/// Sends the verification email of an authorized user to its address in svc-storage
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) depends on the global configuration, see verification_message
+async fn resend(
+    grpc_clients: &GrpcClients,
+    verification: &Verification,
+    store: &dyn Store,
+    user_id: &str,
+    payload: &ResendVerificationRequest,
+) -> Result<(), StatusCode> {
+    let user = grpc_clients
+        .storage
+        .user
+        .get_by_id(Id {
+            id: user_id.to_string(),
+        })
+        .await
+        .map_err(|e| {
+            rest_error!("failed to get user {}: {}.", user_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .into_inner()
+        .data
+        .ok_or_else(|| {
+            rest_error!("failed to get user {}: no user data returned.", user_id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let verified = VerifiedAddresses::new(store)
+        .is_verified(user_id, &user.email)
+        .await
+        .map_err(|e| {
+            rest_error!("verified addresses not available: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if verified {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let request = VerificationRequest {
+        user_id,
+        email: &user.email,
+        display_name: &user.display_name,
+        locale: payload.locale.as_deref(),
+    };
+    send_verification(verification, &request).await
+}
+
+/// Marks the address in a verification token as verified
+pub async fn confirm(
+    verification: Option<&Verification>,
+    store: &dyn Store,
+    token: &str,
+    now: DateTime<Utc>,
+) -> Result<Json<String>, StatusCode> {
+    let Some(verification) = verification else {
+        rest_warn!("verification link used but no token signing key configured.");
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let verified = verification.confirm(store, token, now).await.map_err(|e| {
+        rest_warn!("verification token refused: {}", e);
+        match e {
+            VerificationError::Token(TokenError::Expired) => StatusCode::GONE,
+            VerificationError::Token(_) => StatusCode::BAD_REQUEST,
+            VerificationError::Used => StatusCode::CONFLICT,
+            VerificationError::TooSoon => StatusCode::TOO_MANY_REQUESTS,
+            VerificationError::Store(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    })?;
+
+    rest_info!(
+        "email address {} of user {} verified.",
+        mask_address(&verified.email),
+        verified.user_id
+    );
+    Ok(Json("Your email address is verified.".to_string()))
+}
+
+/// Page of the verification link, asking the user to confirm. Opening the
+/// link changes nothing, as mail scanners open links before the user does:
+/// the page posts the token back to verify the address.
+pub fn landing_page(
+    verification: Option<&Verification>,
+    token: &str,
+) -> Result<Html<String>, StatusCode> {
+    if verification.is_none() {
+        rest_warn!("verification link opened but no token signing key configured.");
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    // tokens are base64url and digits separated by dots, so they are safe in HTML
+    let token = token.trim();
+    if token.is_empty()
+        || !token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        rest_warn!("verification link opened with a malformed token.");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    Ok(Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Verify your email address</title>
+</head>
+<body>
+<form method="post">
+<input type="hidden" name="token" value="{}">
+<button type="submit">Verify my email address</button>
+</form>
+</body>
+</html>
+"#,
+        token
+    )))
+}
+
+/// Shows the page of the verification link sent on signup,
+/// without verifying the address yet
+#[utoipa::path(
+    get,
+    path = "/contact/verify",
+    tag = "svc-contact",
+    params(VerifyQuery),
+    responses(
+        (status = 200, description = "Page posting the token to verify the address.", body = String, content_type = "text/html"),
+        (status = 400, description = "Invalid token."),
+        (status = 404, description = "Email verification is disabled."),
+    )
+)]
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) depends on the global configuration, see landing_page
+pub async fn verify_link(Query(query): Query<VerifyQuery>) -> Result<Html<String>, StatusCode> {
+    rest_debug!("entry.");
+    landing_page(get_verification().await, &query.token)
+}
+
+/// Confirms the email address of a user with the token of the link sent
+/// on signup, posted as form by the page of the link
+#[utoipa::path(
+    post,
+    path = "/contact/verify",
+    tag = "svc-contact",
+    request_body(content = VerifyQuery, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Email address verified.", body = String),
+        (status = 400, description = "Invalid token."),
+        (status = 404, description = "Email verification is disabled."),
+        (status = 409, description = "Token already used."),
+        (status = 410, description = "Token expired."),
+        (status = 500, description = "Request unsuccessful."),
+    )
+)]
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) depends on the global configuration, see confirm
+pub async fn verify(Form(form): Form<VerifyQuery>) -> Result<Json<String>, StatusCode> {
+    rest_debug!("entry.");
+
+    let store = crate::store::get_store().await.map_err(|e| {
+        rest_error!("store not available: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    confirm(get_verification().await, store, &form.token, Utc::now()).await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::store::memory::MemoryStore;
+    use crate::Config;
+    use chrono::Duration;
     use lib_common::uuid::to_uuid;
 
+    fn verification() -> Verification {
+        let mut config = Config::default();
+        config.token_signing_key = "secret".to_string();
+        Verification::new(&config).unwrap()
+    }
+
+    fn request() -> VerificationRequest<'static> {
+        VerificationRequest {
+            user_id: "user",
+            email: "alice@aetheric.nl ",
+            display_name: "Alice Smith",
+            locale: None,
+        }
+    }
+
+    fn token(message: &EmailMessage) -> String {
+        let url = message.model.get("verification_url").unwrap();
+        let url = url.as_str().unwrap();
+        url.split_once("?token=").unwrap().1.to_string()
+    }
+
     #[tokio::test]
     async fn test_signup_success() {
         lib_common::logger::get_log_handle().await;
@@ -75,6 +457,7 @@ mod tests {
         let payload = SignupRequest {
             display_name: "test".to_string(),
             email: "test@aetheric.nl".to_string(),
+            locale: None,
         };
 
         let id = signup(Extension(grpc_clients), Json(payload))
@@ -85,4 +468,145 @@ mod tests {
         // check UUID format
         to_uuid(&id).unwrap();
     }
+
+    #[test]
+    fn test_verification_message() {
+        let now = Utc::now();
+        let message = verification_message(&verification(), &request(), now);
+        EMAIL_VERIFICATION.validate(&message.model).unwrap();
+        assert_eq!(message.to, "alice@aetheric.nl");
+        assert_eq!(message.template, "email-verification");
+        assert_eq!(message.model.get("customer_name").unwrap(), "Alice");
+        assert_eq!(message.model.get("link_valid_hours").unwrap(), 24);
+
+        let url = message.model.get("verification_url").unwrap();
+        assert!(url
+            .as_str()
+            .unwrap()
+            .starts_with("http://localhost:8000/contact/verify?token="));
+
+        assert_eq!(message.locale, Locale::default());
+
+        let mut request = request();
+        request.display_name = "";
+        request.locale = Some("nl-be");
+        let message = verification_message(&verification(), &request, now);
+        assert_eq!(message.model.get("customer_name").unwrap(), "");
+        assert_eq!(message.locale.tag(), "nl-BE");
+
+        request.locale = Some("Nederlands");
+        let message = verification_message(&verification(), &request, now);
+        assert_eq!(message.locale, Locale::default());
+    }
+
+    #[tokio::test]
+    async fn test_confirm() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let verification = verification();
+        let now = Utc::now();
+        let message = verification_message(&verification, &request(), now);
+        let token = token(&message);
+
+        let error = confirm(None, &store, &token, now).await.unwrap_err();
+        assert_eq!(error, StatusCode::NOT_FOUND);
+
+        let error = confirm(Some(&verification), &store, "token", now)
+            .await
+            .unwrap_err();
+        assert_eq!(error, StatusCode::BAD_REQUEST);
+
+        let expired = now + Duration::try_hours(24).unwrap();
+        let error = confirm(Some(&verification), &store, &token, expired)
+            .await
+            .unwrap_err();
+        assert_eq!(error, StatusCode::GONE);
+
+        confirm(Some(&verification), &store, &token, now)
+            .await
+            .unwrap();
+        assert!(VerifiedAddresses::new(&store)
+            .is_verified("user", "alice@aetheric.nl")
+            .await
+            .unwrap());
+
+        // tokens can be used once
+        let error = confirm(Some(&verification), &store, &token, now)
+            .await
+            .unwrap_err();
+        assert_eq!(error, StatusCode::CONFLICT);
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_authorize_resend() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let verification = verification();
+        let now = Utc::now();
+        let message = verification_message(&verification, &request(), now);
+        let token = token(&message);
+
+        for token in ["", "token", "dXNlcg.1.c2lnbmF0dXJl"] {
+            let error = authorize_resend(&verification, &store, token, now)
+                .await
+                .unwrap_err();
+            assert_eq!(error, StatusCode::UNAUTHORIZED);
+        }
+
+        let expired = now + Duration::try_days(32).unwrap();
+        let error = authorize_resend(&verification, &store, &token, expired)
+            .await
+            .unwrap_err();
+        assert_eq!(error, StatusCode::GONE);
+
+        let user_id = authorize_resend(&verification, &store, &token, now)
+            .await
+            .unwrap();
+        assert_eq!(user_id, "user");
+
+        // one email per cooldown
+        let error = authorize_resend(&verification, &store, &token, now)
+            .await
+            .unwrap_err();
+        assert_eq!(error, StatusCode::TOO_MANY_REQUESTS);
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_landing_page() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let verification = verification();
+        let message = verification_message(&verification, &request(), Utc::now());
+        let token = token(&message);
+
+        let page = landing_page(Some(&verification), &token).unwrap().0;
+        assert!(page.contains(r#"<form method="post">"#));
+        assert!(page.contains(&format!(r#"name="token" value="{}""#, token)));
+
+        // opening the link doesn't verify the address
+        assert!(!VerifiedAddresses::new(&store)
+            .is_verified("user", "alice@aetheric.nl")
+            .await
+            .unwrap());
+
+        for token in ["", r#""><script>"#] {
+            let error = landing_page(Some(&verification), token).unwrap_err();
+            assert_eq!(error, StatusCode::BAD_REQUEST);
+        }
+
+        let error = landing_page(None, &token).unwrap_err();
+        assert_eq!(error, StatusCode::NOT_FOUND);
+
+        ut_info!("Success.");
+    }
 }
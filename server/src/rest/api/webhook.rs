@@ -0,0 +1,473 @@
+//! Rest API implementations of provider webhooks
+use crate::delivery::history::{DeliveryLog, DeliveryStatus};
+use crate::delivery::suppression::Suppressions;
+use crate::store::StoreError;
+use axum::{body::Bytes, extract::Extension, http::HeaderMap};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hyper::{header::AUTHORIZATION, StatusCode};
+use serde::Deserialize;
+
+/// Bounce types after which an address will never accept email
+const HARD_BOUNCE_TYPES: [&str; 2] = ["HardBounce", "BadEmailAddress"];
+
+/// Bounce types after which Postmark may still deliver the message
+const TRANSIENT_BOUNCE_TYPES: [&str; 2] = ["SoftBounce", "Transient"];
+
+/// Basic authentication credentials Postmark provides with every webhook call.
+/// The webhook is disabled when no password is configured.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WebhookCredentials {
+    /// Expected username
+    pub username: String,
+
+    /// Expected password
+    pub password: String,
+}
+
+impl WebhookCredentials {
+    /// Returns true if the request carries the expected basic authentication
+    pub fn authorize(&self, headers: &HeaderMap) -> bool {
+        if self.password.is_empty() {
+            rest_warn!("webhook called but no webhook password configured.");
+            return false;
+        }
+
+        let Some(credentials) = headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Basic "))
+            .and_then(|encoded| {
+                base64::engine::general_purpose::STANDARD
+                    .decode(encoded.trim())
+                    .ok()
+            })
+        else {
+            return false;
+        };
+
+        let expected = format!("{}:{}", self.username, self.password);
+        constant_time_eq(&credentials, expected.as_bytes())
+    }
+}
+
+/// Compares two byte strings in a time independent of their content
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Postmark delivery event
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DeliveryEvent {
+    /// Postmark message ID
+    #[serde(rename = "MessageID")]
+    pub message_id: String,
+
+    /// Address the message was delivered to
+    pub recipient: String,
+
+    /// When the recipient's mail server accepted the message
+    pub delivered_at: DateTime<Utc>,
+}
+
+/// Postmark bounce or spam complaint event
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct BounceEvent {
+    /// Postmark message ID
+    #[serde(rename = "MessageID")]
+    pub message_id: String,
+
+    /// Bounce type, e.g. `HardBounce`, `SoftBounce` or `SpamComplaint`
+    #[serde(rename = "Type")]
+    pub bounce_type: String,
+
+    /// Address the message bounced from
+    pub email: String,
+
+    /// Description of the bounce type
+    #[serde(default)]
+    pub description: String,
+
+    /// When the message bounced
+    pub bounced_at: DateTime<Utc>,
+}
+
+impl BounceEvent {
+    /// Returns true if the address will never accept email
+    pub fn is_hard_bounce(&self) -> bool {
+        HARD_BOUNCE_TYPES.contains(&self.bounce_type.as_str())
+    }
+
+    /// Returns true if the message may still be delivered
+    pub fn is_transient(&self) -> bool {
+        TRANSIENT_BOUNCE_TYPES.contains(&self.bounce_type.as_str())
+    }
+}
+
+/// Postmark webhook event, identified by its `RecordType`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "RecordType")]
+pub enum PostmarkEvent {
+    /// The message was delivered
+    Delivery(DeliveryEvent),
+
+    /// The message bounced
+    Bounce(BounceEvent),
+
+    /// The recipient marked the message as spam
+    SpamComplaint(BounceEvent),
+
+    /// Events this service doesn't subscribe to, e.g. opens and clicks
+    #[serde(other)]
+    Other,
+}
+
+/// Updates the delivery log, and the suppression list for hard bounces.
+/// A transient bounce keeps the message sent, with the bounce as error,
+/// as Postmark may still deliver it.
+async fn process_event(
+    log: &DeliveryLog<'_>,
+    suppressions: &Suppressions<'_>,
+    event: PostmarkEvent,
+) -> Result<(), StoreError> {
+    let (message_id, status, error) = match &event {
+        PostmarkEvent::Delivery(event) => (&event.message_id, DeliveryStatus::Delivered, None),
+        PostmarkEvent::Bounce(event) => (
+            &event.message_id,
+            if event.is_transient() {
+                DeliveryStatus::Sent
+            } else {
+                DeliveryStatus::Bounced
+            },
+            Some(format!("{}: {}", event.bounce_type, event.description)),
+        ),
+        PostmarkEvent::SpamComplaint(event) => {
+            (&event.message_id, DeliveryStatus::Complained, None)
+        }
+        PostmarkEvent::Other => {
+            rest_debug!("ignoring unsupported webhook event.");
+            return Ok(());
+        }
+    };
+
+    match log.update_status(message_id, status, error).await? {
+        Some(record) => rest_info!(
+            "delivery {} of message {} is now {:?}.",
+            record.id,
+            message_id,
+            status
+        ),
+        None => rest_warn!("no delivery found for message {}.", message_id),
+    }
+
+    if let PostmarkEvent::Bounce(event) = &event {
+        if event.is_hard_bounce() {
+            rest_info!(
+                "marking {} undeliverable: {}",
+                event.email,
+                event.bounce_type
+            );
+            suppressions
+                .suppress(&event.email, &event.description)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Receives Postmark delivery, bounce and spam complaint webhooks
+#[utoipa::path(
+    post,
+    path = "/contact/webhooks/postmark",
+    tag = "svc-contact",
+    request_body(content = String, description = "Postmark webhook event", content_type = "application/json"),
+    responses(
+        (status = 200, description = "Event processed."),
+        (status = 400, description = "Invalid event."),
+        (status = 401, description = "Missing or invalid credentials."),
+        (status = 500, description = "Event could not be processed, to be retried."),
+    )
+)]
+pub async fn postmark_webhook(
+    Extension(credentials): Extension<WebhookCredentials>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(), StatusCode> {
+    rest_debug!("entry.");
+
+    // the body is only parsed for authorized callers
+    if !credentials.authorize(&headers) {
+        rest_warn!("unauthorized webhook call.");
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let event: PostmarkEvent = serde_json::from_slice(&body).map_err(|e| {
+        rest_warn!("invalid webhook event: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+
+    let store = crate::store::get_store().await.map_err(|e| {
+        rest_error!("store not available: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let log = crate::delivery::history::get_log().await.map_err(|e| {
+        rest_error!("delivery log not available: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    // a failure is reported so Postmark retries the event later
+    process_event(log, &Suppressions::new(store), event)
+        .await
+        .map_err(|e| {
+            rest_error!("could not process webhook event: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delivery::history::{Channel, DeliveryRecord};
+    use crate::delivery::DeliveryReceipt;
+    use crate::store::memory::MemoryStore;
+    use hyper::header::HeaderValue;
+    use std::time::Duration;
+
+    fn credentials() -> WebhookCredentials {
+        WebhookCredentials {
+            username: "postmark".to_string(),
+            password: "secret".to_string(),
+        }
+    }
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(authorization).unwrap());
+        headers
+    }
+
+    fn bounce(bounce_type: &str) -> BounceEvent {
+        BounceEvent {
+            message_id: "message".to_string(),
+            bounce_type: bounce_type.to_string(),
+            email: "alice@aetheric.nl".to_string(),
+            description: "Unknown user".to_string(),
+            bounced_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_authorize() {
+        // postmark:secret
+        let valid = headers("Basic cG9zdG1hcms6c2VjcmV0");
+        assert!(credentials().authorize(&valid));
+
+        // postmark:wrong
+        assert!(!credentials().authorize(&headers("Basic cG9zdG1hcms6d3Jvbmc=")));
+        assert!(!credentials().authorize(&headers("Basic not base64")));
+        assert!(!credentials().authorize(&headers("Bearer cG9zdG1hcms6c2VjcmV0")));
+        assert!(!credentials().authorize(&HeaderMap::new()));
+
+        // disabled without password
+        let credentials = WebhookCredentials {
+            username: "postmark".to_string(),
+            password: String::new(),
+        };
+        assert!(!credentials.authorize(&headers("Basic cG9zdG1hcms6")));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"ab"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn test_parse_events() {
+        let event: PostmarkEvent = serde_json::from_str(
+            r#"{
+                "RecordType": "Delivery",
+                "ServerID": 23,
+                "MessageStream": "outbound",
+                "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+                "Recipient": "alice@aetheric.nl",
+                "Tag": "",
+                "DeliveredAt": "2024-01-01T10:00:00.9070259Z",
+                "Details": "Test delivery webhook details",
+                "Metadata": {}
+            }"#,
+        )
+        .unwrap();
+        let PostmarkEvent::Delivery(event) = event else {
+            panic!("expected a delivery event");
+        };
+        assert_eq!(event.message_id, "883953f4-6105-42a2-a16a-77a8eac79483");
+        assert_eq!(event.recipient, "alice@aetheric.nl");
+
+        let event: PostmarkEvent = serde_json::from_str(
+            r#"{
+                "RecordType": "Bounce",
+                "ID": 4323372036854775807,
+                "Type": "HardBounce",
+                "TypeCode": 1,
+                "Name": "Hard bounce",
+                "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+                "Description": "The server was unable to deliver your message",
+                "Email": "alice@aetheric.nl",
+                "BouncedAt": "2024-01-01T10:00:00.9070259Z",
+                "Inactive": true
+            }"#,
+        )
+        .unwrap();
+        let PostmarkEvent::Bounce(event) = event else {
+            panic!("expected a bounce event");
+        };
+        assert!(event.is_hard_bounce());
+        assert_eq!(event.email, "alice@aetheric.nl");
+
+        let event: PostmarkEvent = serde_json::from_str(
+            r#"{
+                "RecordType": "SpamComplaint",
+                "Type": "SpamComplaint",
+                "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+                "Email": "alice@aetheric.nl",
+                "BouncedAt": "2024-01-01T10:00:00Z"
+            }"#,
+        )
+        .unwrap();
+        assert!(matches!(event, PostmarkEvent::SpamComplaint(_)));
+
+        let event: PostmarkEvent =
+            serde_json::from_str(r#"{"RecordType": "Open", "MessageID": "id"}"#).unwrap();
+        assert_eq!(event, PostmarkEvent::Other);
+    }
+
+    #[test]
+    fn test_is_hard_bounce() {
+        assert!(bounce("HardBounce").is_hard_bounce());
+        assert!(bounce("BadEmailAddress").is_hard_bounce());
+        assert!(!bounce("SoftBounce").is_hard_bounce());
+        assert!(!bounce("Transient").is_hard_bounce());
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(bounce("SoftBounce").is_transient());
+        assert!(bounce("Transient").is_transient());
+        assert!(!bounce("HardBounce").is_transient());
+        assert!(!bounce("SpamComplaint").is_transient());
+    }
+
+    #[tokio::test]
+    async fn test_process_event() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let log = DeliveryLog::new(&store, Duration::from_secs(60 * 60));
+        let suppressions = Suppressions::new(&store);
+
+        let record = DeliveryRecord::new(
+            "alice",
+            Channel::Email,
+            "alice@aetheric.nl",
+            "cargo-confirmation",
+            &Ok(DeliveryReceipt {
+                message_id: "message".to_string(),
+            }),
+        );
+        log.record(&record).await.unwrap();
+
+        let event = PostmarkEvent::Delivery(DeliveryEvent {
+            message_id: "message".to_string(),
+            recipient: "alice@aetheric.nl".to_string(),
+            delivered_at: Utc::now(),
+        });
+        process_event(&log, &suppressions, event).await.unwrap();
+        let found = log.get(&record.id).await.unwrap().unwrap();
+        assert_eq!(found.status, DeliveryStatus::Delivered);
+
+        // a soft bounce may still be delivered and doesn't suppress the address
+        let event = PostmarkEvent::Bounce(bounce("SoftBounce"));
+        process_event(&log, &suppressions, event).await.unwrap();
+        let found = log.get(&record.id).await.unwrap().unwrap();
+        assert_eq!(found.status, DeliveryStatus::Sent);
+        assert!(!found.status.is_final());
+        assert_eq!(found.error, Some("SoftBounce: Unknown user".to_string()));
+        assert_eq!(suppressions.get("alice@aetheric.nl").await.unwrap(), None);
+
+        let event = PostmarkEvent::Bounce(bounce("HardBounce"));
+        process_event(&log, &suppressions, event).await.unwrap();
+        let found = log.get(&record.id).await.unwrap().unwrap();
+        assert_eq!(found.status, DeliveryStatus::Bounced);
+        let suppression = suppressions.get("alice@aetheric.nl").await.unwrap();
+        assert_eq!(suppression.unwrap().reason, "Unknown user");
+
+        let event = PostmarkEvent::SpamComplaint(bounce("SpamComplaint"));
+        process_event(&log, &suppressions, event).await.unwrap();
+        let found = log.get(&record.id).await.unwrap().unwrap();
+        assert_eq!(found.status, DeliveryStatus::Complained);
+
+        // the address of an unknown message is still suppressed
+        let mut unknown = bounce("HardBounce");
+        unknown.message_id = "unknown".to_string();
+        unknown.email = "bob@aetheric.nl".to_string();
+        process_event(&log, &suppressions, PostmarkEvent::Bounce(unknown))
+            .await
+            .unwrap();
+        assert!(suppressions.get("bob@aetheric.nl").await.unwrap().is_some());
+
+        // other events are ignored
+        process_event(&log, &suppressions, PostmarkEvent::Other)
+            .await
+            .unwrap();
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_postmark_webhook_unauthorized() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let open = Bytes::from(r#"{"RecordType": "Open", "MessageID": "id"}"#);
+        let error = postmark_webhook(Extension(credentials()), HeaderMap::new(), open.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(error, StatusCode::UNAUTHORIZED);
+
+        // the body isn't read before the caller is authorized
+        let error = postmark_webhook(
+            Extension(credentials()),
+            HeaderMap::new(),
+            Bytes::from("not json"),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error, StatusCode::UNAUTHORIZED);
+
+        let error = postmark_webhook(
+            Extension(credentials()),
+            headers("Basic cG9zdG1hcms6c2VjcmV0"),
+            Bytes::from("not json"),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error, StatusCode::BAD_REQUEST);
+
+        let result = postmark_webhook(
+            Extension(credentials()),
+            headers("Basic cG9zdG1hcms6c2VjcmV0"),
+            open,
+        )
+        .await;
+        assert!(result.is_ok());
+
+        ut_info!("Success.");
+    }
+}
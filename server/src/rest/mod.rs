@@ -14,11 +14,24 @@ use utoipa::OpenApi;
 #[openapi(
     paths(
         api::health::health_check,
-        api::user::signup
+        api::user::signup,
+        api::user::verify_link,
+        api::user::verify,
+        api::user::resend_verification,
+        api::preferences::get_preferences,
+        api::preferences::update_preferences,
+        api::unsubscribe::unsubscribe,
+        api::unsubscribe::unsubscribe_link,
+        api::webhook::postmark_webhook
     ),
     components(
         schemas(
-            api::rest_types::SignupRequest
+            api::rest_types::SignupRequest,
+            api::rest_types::UserPreferences,
+            api::rest_types::QuietHours,
+            api::rest_types::UnsubscribeQuery,
+            api::rest_types::VerifyQuery,
+            api::rest_types::ResendVerificationRequest
         )
     ),
     tags(
@@ -66,6 +66,11 @@ pub async fn rest_server(
     //
     // GRPC Clients
     let grpc_clients = GrpcClients::default(config.clone());
+    // Postmark webhook credentials
+    let webhook_credentials = api::webhook::WebhookCredentials {
+        username: config.postmark_webhook_username.clone(),
+        password: config.postmark_webhook_password.clone(),
+    };
 
     //
     // Create Server
@@ -73,6 +78,27 @@ pub async fn rest_server(
     let app = Router::new()
         .route("/health", routing::get(api::health::health_check)) // MUST HAVE
         .route("/contact/signup", routing::post(api::user::signup))
+        .route(
+            "/contact/verify",
+            routing::get(api::user::verify_link).post(api::user::verify),
+        )
+        .route(
+            "/contact/verify/resend",
+            routing::post(api::user::resend_verification),
+        )
+        .route(
+            "/contact/preferences/:user_id",
+            routing::get(api::preferences::get_preferences)
+                .put(api::preferences::update_preferences),
+        )
+        .route(
+            "/contact/unsubscribe",
+            routing::get(api::unsubscribe::unsubscribe_link).post(api::unsubscribe::unsubscribe),
+        )
+        .route(
+            "/contact/webhooks/postmark",
+            routing::post(api::webhook::postmark_webhook),
+        )
         .layer(
             CorsLayer::new()
                 .allow_origin(cors_allowed_origin)
@@ -80,6 +106,7 @@ pub async fn rest_server(
                 .allow_methods(Any),
         )
         .layer(limit_middleware)
+        .layer(Extension(webhook_credentials))
         .layer(Extension(grpc_clients)); // Extension layer must be last
 
     //
@@ -0,0 +1,664 @@
+//! Scheduler
+//! sends pickup reminders a configured time before the origin timeslot of a booking
+
+#[macro_use]
+pub mod macros;
+
+use crate::locale::{Locale, DEFAULT_LANGUAGE};
+use crate::store::{self, Store, StoreError};
+use crate::Config;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+/// Reminder lead times shared by all handlers
+static LEAD_TIMES: OnceCell<Vec<Duration>> = OnceCell::const_new();
+
+/// Namespace of reminders in the store
+const NAMESPACE: &str = "reminder";
+
+/// Namespace of the reminder indexes in the store
+const INDEX_NAMESPACE: &str = "reminder-index";
+
+/// How long a reminder is kept after it was due, in case it is never taken
+const GRACE_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Errors of the reminder scheduler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchedulerError {
+    /// The lead times are not a comma separated list of durations
+    LeadTime(String),
+
+    /// The store keeping the reminders is not available
+    Store(StoreError),
+}
+
+impl std::error::Error for SchedulerError {}
+
+impl Display for SchedulerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SchedulerError::LeadTime(e) => write!(f, "Invalid reminder lead time: {}", e),
+            SchedulerError::Store(e) => write!(f, "Reminder store error: {}", e),
+        }
+    }
+}
+
+/// Parses a comma separated list of lead times in minutes, hours or days,
+/// e.g. `24h,1h` or `90m`. An empty list disables reminders.
+pub fn parse_lead_times(value: &str) -> Result<Vec<Duration>, SchedulerError> {
+    let mut lead_times = vec![];
+    for part in value
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+    {
+        let invalid = || SchedulerError::LeadTime(part.to_string());
+        let split = part.len() - part.chars().last().map_or(0, char::len_utf8);
+        let (amount, unit) = part.split_at(split);
+        let amount: u64 = amount.trim().parse().map_err(|_| invalid())?;
+        let factor = match unit {
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+
+        let seconds = amount
+            .checked_mul(factor)
+            .filter(|seconds| *seconds > 0)
+            .ok_or_else(invalid)?;
+        lead_times.push(Duration::from_secs(seconds));
+    }
+
+    lead_times.sort_unstable_by(|a, b| b.cmp(a));
+    lead_times.dedup();
+    Ok(lead_times)
+}
+
+/// Descriptions of a lead time in one language, `{}` is replaced by the amount
+struct LeadTimeTexts {
+    hour: &'static str,
+    hours: &'static str,
+    minute: &'static str,
+    minutes: &'static str,
+}
+
+/// Lead time descriptions in English, used when the locale has no translation
+const ENGLISH_LEAD_TIME_TEXTS: LeadTimeTexts = LeadTimeTexts {
+    hour: "in {} hour",
+    hours: "in {} hours",
+    minute: "in {} minute",
+    minutes: "in {} minutes",
+};
+
+/// Lead time descriptions by language tag, see [`Locale::lookup`]
+const LEAD_TIME_TEXTS: &[(&str, LeadTimeTexts)] = &[
+    (DEFAULT_LANGUAGE, ENGLISH_LEAD_TIME_TEXTS),
+    (
+        "nl",
+        LeadTimeTexts {
+            hour: "over {} uur",
+            hours: "over {} uur",
+            minute: "over {} minuut",
+            minutes: "over {} minuten",
+        },
+    ),
+];
+
+/// Customer facing description of a lead time in the language of the locale,
+/// e.g. `in 24 hours`, or `over 24 uur` in Dutch
+pub fn lead_time_text(lead_time: Duration, locale: &Locale) -> String {
+    let texts = locale
+        .lookup(LEAD_TIME_TEXTS)
+        .unwrap_or(&ENGLISH_LEAD_TIME_TEXTS);
+    let minutes = lead_time.as_secs() / 60;
+    let (amount, text) = match (minutes % 60, minutes) {
+        (0, 60) => (1, texts.hour),
+        (0, _) => (minutes / 60, texts.hours),
+        (_, 1) => (1, texts.minute),
+        _ => (minutes, texts.minutes),
+    };
+
+    text.replace("{}", &amount.to_string())
+}
+
+/// Returns LEAD_TIMES, parsed from a Config object generated from
+/// environment variables on first use. Invalid lead times disable reminders.
+pub async fn get_lead_times() -> &'static [Duration] {
+    LEAD_TIMES
+        .get_or_init(|| async move {
+            let config = Config::try_from_env().unwrap_or_default();
+            parse_lead_times(&config.reminder_lead_times).unwrap_or_else(|e| {
+                scheduler_error!("reminders disabled: {}", e);
+                vec![]
+            })
+        })
+        .await
+}
+
+/// A pickup reminder to send to the user who booked a parcel
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reminder {
+    /// Reminder ID, the same for every registration of a booking and lead time
+    pub id: String,
+
+    /// Parcel ID
+    pub parcel_id: String,
+
+    /// Itinerary ID
+    pub itinerary_id: String,
+
+    /// Phone number (E.164) to also send a text message reminder to
+    pub phone_number: Option<String>,
+
+    /// Locale requested on confirmation, the user's preferred language applies otherwise
+    #[serde(default)]
+    pub locale: Option<String>,
+
+    /// Time before the origin timeslot start the reminder is sent, in minutes
+    pub lead_minutes: u64,
+
+    /// When the reminder is sent
+    pub due_at: DateTime<Utc>,
+}
+
+impl Reminder {
+    /// Creates the reminders of a booking for every lead time,
+    /// skipping lead times that have already passed
+    pub fn for_booking(
+        parcel_id: &str,
+        itinerary_id: &str,
+        phone_number: Option<&str>,
+        locale: Option<&str>,
+        origin_timeslot_start: DateTime<Utc>,
+        lead_times: &[Duration],
+        now: DateTime<Utc>,
+    ) -> Vec<Reminder> {
+        lead_times
+            .iter()
+            .filter_map(|lead_time| {
+                let lead = chrono::Duration::from_std(*lead_time).ok()?;
+                let due_at = origin_timeslot_start.checked_sub_signed(lead)?;
+                let lead_minutes = lead_time.as_secs() / 60;
+                (due_at > now).then(|| Reminder {
+                    id: format!("{}:{}:{}", itinerary_id, parcel_id, lead_minutes),
+                    parcel_id: parcel_id.to_string(),
+                    itinerary_id: itinerary_id.to_string(),
+                    phone_number: phone_number.map(str::to_string),
+                    locale: locale.map(str::to_string),
+                    lead_minutes,
+                    due_at,
+                })
+            })
+            .collect()
+    }
+
+    /// Time before the origin timeslot start the reminder is sent
+    pub fn lead_time(&self) -> Duration {
+        Duration::from_secs(self.lead_minutes * 60)
+    }
+}
+
+/// Keeps pending reminders in a store, indexed by due time and itinerary
+#[derive(Debug, Clone, Copy)]
+pub struct ReminderSchedule<'a> {
+    store: &'a dyn Store,
+}
+
+impl<'a> ReminderSchedule<'a> {
+    /// Creates a schedule keeping its reminders in the provided store
+    pub fn new(store: &'a dyn Store) -> Self {
+        ReminderSchedule { store }
+    }
+
+    fn reminder_key(id: &str) -> String {
+        store::key(NAMESPACE, id)
+    }
+
+    fn due_key() -> String {
+        store::key(INDEX_NAMESPACE, "due")
+    }
+
+    fn itinerary_key(itinerary_id: &str) -> String {
+        store::key(INDEX_NAMESPACE, &format!("itinerary:{}", itinerary_id))
+    }
+
+    /// Stores a reminder, or reschedules an existing reminder with the same ID
+    pub async fn schedule(&self, reminder: &Reminder) -> Result<(), StoreError> {
+        let value =
+            serde_json::to_string(reminder).map_err(|e| StoreError::Value(e.to_string()))?;
+        let ttl = (reminder.due_at - Utc::now()).to_std().unwrap_or_default() + GRACE_PERIOD;
+
+        self.store
+            .set(&Self::reminder_key(&reminder.id), &value, Some(ttl))
+            .await?;
+
+        let score = reminder.due_at.timestamp_millis();
+        self.store
+            .zadd(
+                &Self::itinerary_key(&reminder.itinerary_id),
+                score,
+                &reminder.id,
+            )
+            .await?;
+        self.store.zadd(&Self::due_key(), score, &reminder.id).await
+    }
+
+    /// Cancels the pending reminders of an itinerary.
+    /// Returns the number of reminders cancelled.
+    pub async fn cancel(&self, itinerary_id: &str) -> Result<usize, StoreError> {
+        let index = Self::itinerary_key(itinerary_id);
+        let ids = self
+            .store
+            .zrange_by_score(&index, i64::MIN, i64::MAX)
+            .await?;
+
+        let mut cancelled = 0;
+        for id in ids {
+            if self.store.zrem(&Self::due_key(), &id).await? {
+                cancelled += 1;
+            }
+
+            self.store.del(&Self::reminder_key(&id)).await?;
+        }
+
+        self.store.del(&index).await?;
+        Ok(cancelled)
+    }
+
+    /// Moves the pending reminders of a booking to a new origin timeslot start,
+    /// keeping their phone number and locale. Reminders that were sent aren't
+    /// sent again, and lead times that have passed by then are dropped.
+    /// A booking without pending reminders, e.g. cancelled, gets none.
+    /// Returns the number of reminders scheduled.
+    pub async fn reschedule(
+        &self,
+        parcel_id: &str,
+        itinerary_id: &str,
+        origin_timeslot_start: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<usize, StoreError> {
+        let index = Self::itinerary_key(itinerary_id);
+        let ids = self
+            .store
+            .zrange_by_score(&index, i64::MIN, i64::MAX)
+            .await?;
+
+        let mut pending = vec![];
+        for id in ids {
+            let Some(value) = self.store.get(&Self::reminder_key(&id)).await? else {
+                continue;
+            };
+            match serde_json::from_str::<Reminder>(&value) {
+                Ok(reminder) if reminder.parcel_id == parcel_id => pending.push(reminder),
+                Ok(_) => (),
+                Err(e) => scheduler_warn!("ignoring invalid reminder {}: {}", id, e),
+            }
+        }
+
+        let Some(booking) = pending.first().cloned() else {
+            return Ok(0);
+        };
+
+        // a reminder taken in the meantime is being sent
+        let mut lead_times = vec![];
+        for reminder in &pending {
+            if self.store.zrem(&Self::due_key(), &reminder.id).await? {
+                self.store.zrem(&index, &reminder.id).await?;
+                self.store.del(&Self::reminder_key(&reminder.id)).await?;
+                lead_times.push(reminder.lead_time());
+            }
+        }
+
+        let reminders = Reminder::for_booking(
+            parcel_id,
+            itinerary_id,
+            booking.phone_number.as_deref(),
+            booking.locale.as_deref(),
+            origin_timeslot_start,
+            &lead_times,
+            now,
+        );
+        for reminder in &reminders {
+            self.schedule(reminder).await?;
+        }
+
+        Ok(reminders.len())
+    }
+
+    /// Removes and returns the reminders due at `now`.
+    /// A reminder is only returned once, even with several instances taking
+    /// reminders from the same store.
+    pub async fn take_due(&self, now: DateTime<Utc>) -> Result<Vec<Reminder>, StoreError> {
+        let ids = self
+            .store
+            .zrange_by_score(&Self::due_key(), i64::MIN, now.timestamp_millis())
+            .await?;
+
+        let mut reminders = vec![];
+        for id in ids {
+            // taken by another instance
+            if !self.store.zrem(&Self::due_key(), &id).await? {
+                continue;
+            }
+
+            let key = Self::reminder_key(&id);
+            let Some(value) = self.store.get(&key).await? else {
+                continue;
+            };
+            self.store.del(&key).await?;
+
+            let reminder: Reminder = match serde_json::from_str(&value) {
+                Ok(reminder) => reminder,
+                Err(e) => {
+                    scheduler_warn!("dropping invalid reminder {}: {}", id, e);
+                    continue;
+                }
+            };
+            self.store
+                .zrem(&Self::itinerary_key(&reminder.itinerary_id), &id)
+                .await?;
+            reminders.push(reminder);
+        }
+
+        Ok(reminders)
+    }
+}
+
+/// Sends the reminders that are due, every `reminder_poll_interval_secs`.
+/// Reminders are sent at most once: a reminder that fails is logged and dropped.
+#[cfg(not(tarpaulin_include))]
+// no_coverage: (Rnever) needs storage and delivery backends, only integration tests
+pub async fn reminder_scheduler(config: Config) -> Result<(), SchedulerError> {
+    scheduler_debug!("entry.");
+
+    let store = store::get_store().await.map_err(SchedulerError::Store)?;
+    if !store.persistent() {
+        // reminders in memory are lost on restart and every instance sends its own
+        return Err(SchedulerError::Store(StoreError::Configuration(format!(
+            "pickup reminders can't be kept in a {} store",
+            store.name()
+        ))));
+    }
+
+    let schedule = ReminderSchedule::new(store);
+    let mut interval = tokio::time::interval(Duration::from_secs(
+        config.reminder_poll_interval_secs.max(1),
+    ));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    scheduler_info!(
+        "sending reminders {} before pickup.",
+        config.reminder_lead_times
+    );
+
+    loop {
+        interval.tick().await;
+
+        let reminders = match schedule.take_due(Utc::now()).await {
+            Ok(reminders) => reminders,
+            Err(e) => {
+                scheduler_warn!("could not take due reminders: {}", e);
+                continue;
+            }
+        };
+
+        for reminder in reminders {
+            match crate::grpc::api::cargo::pickup_reminder(&reminder).await {
+                Ok(()) => scheduler_info!("sent reminder {}.", reminder.id),
+                Err(e) => scheduler_error!("could not send reminder {}: {}", reminder.id, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::memory::MemoryStore;
+    use chrono::TimeZone;
+
+    fn hours(hours: u64) -> Duration {
+        Duration::from_secs(hours * 60 * 60)
+    }
+
+    #[test]
+    fn test_parse_lead_times() {
+        assert_eq!(
+            parse_lead_times("1h, 24h").unwrap(),
+            vec![hours(24), hours(1)]
+        );
+        assert_eq!(
+            parse_lead_times("2d,90m,1h,1h").unwrap(),
+            vec![hours(48), Duration::from_secs(90 * 60), hours(1)]
+        );
+        assert!(parse_lead_times("").unwrap().is_empty());
+        assert!(parse_lead_times(" , ").unwrap().is_empty());
+
+        for invalid in ["24", "h", "1w", "-1h", "0m", "1.5h", "99999999999999999d"] {
+            assert_eq!(
+                parse_lead_times(invalid).unwrap_err(),
+                SchedulerError::LeadTime(invalid.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_lead_time_text() {
+        let en = Locale::default();
+        assert_eq!(lead_time_text(hours(24), &en), "in 24 hours");
+        assert_eq!(lead_time_text(hours(1), &en), "in 1 hour");
+        assert_eq!(
+            lead_time_text(Duration::from_secs(90 * 60), &en),
+            "in 90 minutes"
+        );
+        assert_eq!(lead_time_text(Duration::from_secs(60), &en), "in 1 minute");
+
+        let nl = Locale::parse("nl-BE").unwrap();
+        assert_eq!(lead_time_text(hours(24), &nl), "over 24 uur");
+        assert_eq!(lead_time_text(hours(1), &nl), "over 1 uur");
+        assert_eq!(
+            lead_time_text(Duration::from_secs(90 * 60), &nl),
+            "over 90 minuten"
+        );
+        assert_eq!(
+            lead_time_text(Duration::from_secs(60), &nl),
+            "over 1 minuut"
+        );
+
+        // no Dutch translation
+        let fr = Locale::parse("fr").unwrap();
+        assert_eq!(lead_time_text(hours(1), &fr), "in 1 hour");
+    }
+
+    #[test]
+    fn test_reminder_for_booking() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 2, 10, 0, 0).unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+
+        // the 24h reminder has already passed
+        let reminders = Reminder::for_booking(
+            "parcel",
+            "trip",
+            Some("+31611111111"),
+            Some("nl-BE"),
+            start,
+            &[hours(24), hours(1)],
+            now,
+        );
+        assert_eq!(reminders.len(), 1);
+        assert_eq!(reminders[0].id, "trip:parcel:60");
+        assert_eq!(
+            reminders[0].due_at,
+            Utc.with_ymd_and_hms(2024, 1, 2, 9, 0, 0).unwrap()
+        );
+        assert_eq!(reminders[0].phone_number, Some("+31611111111".to_string()));
+        assert_eq!(reminders[0].locale, Some("nl-BE".to_string()));
+        assert_eq!(reminders[0].lead_time(), hours(1));
+    }
+
+    #[test]
+    fn test_reminder_without_locale() {
+        // reminders scheduled before locales were added
+        let value = r#"{
+            "id": "trip:parcel:60",
+            "parcel_id": "parcel",
+            "itinerary_id": "trip",
+            "phone_number": null,
+            "lead_minutes": 60,
+            "due_at": "2024-01-02T09:00:00Z"
+        }"#;
+        let reminder: Reminder = serde_json::from_str(value).unwrap();
+        assert_eq!(reminder.locale, None);
+        assert_eq!(reminder.lead_time(), hours(1));
+    }
+
+    #[test]
+    fn test_scheduler_error_display() {
+        assert_eq!(
+            SchedulerError::LeadTime("1w".to_string()).to_string(),
+            "Invalid reminder lead time: 1w"
+        );
+        assert_eq!(
+            SchedulerError::Store(StoreError::Backend("down".to_string())).to_string(),
+            "Reminder store error: Store error: down"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reminder_schedule() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let schedule = ReminderSchedule::new(&store);
+        let now = Utc::now();
+        let start = now + chrono::Duration::hours(30);
+
+        let mut reminders = Reminder::for_booking(
+            "p1",
+            "trip-1",
+            None,
+            None,
+            start,
+            &[hours(24), hours(1)],
+            now,
+        );
+        reminders.extend(Reminder::for_booking(
+            "p2",
+            "trip-2",
+            None,
+            None,
+            start,
+            &[hours(24)],
+            now,
+        ));
+        for reminder in &reminders {
+            schedule.schedule(reminder).await.unwrap();
+            // registering a booking again doesn't duplicate its reminders
+            schedule.schedule(reminder).await.unwrap();
+        }
+
+        assert!(schedule.take_due(now).await.unwrap().is_empty());
+
+        // both 24h reminders are due
+        let due = schedule
+            .take_due(now + chrono::Duration::hours(7))
+            .await
+            .unwrap();
+        assert_eq!(due, [reminders[0].clone(), reminders[2].clone()]);
+        assert!(schedule
+            .take_due(now + chrono::Duration::hours(7))
+            .await
+            .unwrap()
+            .is_empty());
+
+        // the 1h reminder of trip-1 is cancelled with its itinerary
+        assert_eq!(schedule.cancel("trip-1").await.unwrap(), 1);
+        assert_eq!(schedule.cancel("trip-1").await.unwrap(), 0);
+        assert!(schedule
+            .take_due(now + chrono::Duration::hours(31))
+            .await
+            .unwrap()
+            .is_empty());
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_reminder_reschedule() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let schedule = ReminderSchedule::new(&store);
+        let now = Utc::now();
+        let start = now + chrono::Duration::hours(30);
+        let lead_times = [hours(24), hours(1)];
+
+        for reminder in Reminder::for_booking(
+            "p1",
+            "trip",
+            Some("+31611111111"),
+            Some("nl-BE"),
+            start,
+            &lead_times,
+            now,
+        )
+        .iter()
+        .chain(&Reminder::for_booking(
+            "p2",
+            "trip",
+            None,
+            None,
+            start,
+            &lead_times,
+            now,
+        )) {
+            schedule.schedule(reminder).await.unwrap();
+        }
+
+        // the flight picking up p1 is delayed by 2 hours
+        let delayed = start + chrono::Duration::hours(2);
+        let count = schedule
+            .reschedule("p1", "trip", delayed, now)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+
+        let due = schedule
+            .take_due(now + chrono::Duration::hours(7))
+            .await
+            .unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].parcel_id, "p2");
+
+        let due = schedule
+            .take_due(now + chrono::Duration::hours(8))
+            .await
+            .unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, "trip:p1:1440");
+        assert_eq!(due[0].due_at, delayed - chrono::Duration::hours(24));
+        assert_eq!(due[0].phone_number, Some("+31611111111".to_string()));
+        assert_eq!(due[0].locale, Some("nl-BE".to_string()));
+
+        // the 24h reminder was sent and isn't sent again
+        let later = delayed + chrono::Duration::hours(1);
+        let count = schedule.reschedule("p1", "trip", later, now).await.unwrap();
+        assert_eq!(count, 1);
+        let due = schedule.take_due(later).await.unwrap();
+        assert_eq!(due.len(), 2);
+        assert_eq!(due[1].id, "trip:p1:60");
+        assert_eq!(due[1].due_at, later - chrono::Duration::hours(1));
+
+        // bookings without pending reminders get none
+        let count = schedule.reschedule("p1", "trip", later, now).await.unwrap();
+        assert_eq!(count, 0);
+
+        ut_info!("Success.");
+    }
+}
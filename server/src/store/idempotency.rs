@@ -0,0 +1,188 @@
+//! Idempotency keys, so a retried request returns the previous result
+//! instead of being executed again
+
+use super::{Store, StoreError};
+use crate::Config;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+/// Idempotency window shared by all handlers
+static WINDOW: OnceCell<Duration> = OnceCell::const_new();
+
+/// Namespace of idempotency keys in the store
+const NAMESPACE: &str = "idempotency";
+
+/// How long a claim is held while its request is being executed.
+/// Bounds how long a crashed instance can block a retry.
+const PENDING_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Stored state of an idempotency key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", content = "result", rename_all = "snake_case")]
+enum Record<T> {
+    /// A request with this key is being executed
+    Pending,
+
+    /// A request with this key completed with this result
+    Done(T),
+}
+
+/// Result of claiming an idempotency key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Claim<T> {
+    /// First request with this key, execute it and [`Idempotency::complete`] it
+    New,
+
+    /// Another request with this key is being executed
+    InProgress,
+
+    /// A request with this key already completed with this result
+    Done(T),
+}
+
+/// Tracks idempotency keys of one operation in a store
+#[derive(Debug, Clone, Copy)]
+pub struct Idempotency<'a> {
+    store: &'a dyn Store,
+    operation: &'a str,
+    window: Duration,
+}
+
+impl<'a> Idempotency<'a> {
+    /// Tracks keys for `operation`, remembering results for `window`
+    pub fn new(store: &'a dyn Store, operation: &'a str, window: Duration) -> Self {
+        Idempotency {
+            store,
+            operation,
+            window,
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        super::key(NAMESPACE, &format!("{}:{}", self.operation, key))
+    }
+
+    /// Claims a key, or returns the state of the request that claimed it before
+    pub async fn claim<T: DeserializeOwned>(&self, key: &str) -> Result<Claim<T>, StoreError> {
+        let key = self.key(key);
+        let pending = serde_json::to_string(&Record::<()>::Pending)
+            .map_err(|e| StoreError::Value(e.to_string()))?;
+
+        if self
+            .store
+            .set_nx(&key, &pending, Some(PENDING_TTL.min(self.window)))
+            .await?
+        {
+            return Ok(Claim::New);
+        }
+
+        let Some(value) = self.store.get(&key).await? else {
+            // expired in between, the caller may simply retry
+            return Ok(Claim::InProgress);
+        };
+
+        let record: Record<T> =
+            serde_json::from_str(&value).map_err(|e| StoreError::Value(e.to_string()))?;
+        Ok(match record {
+            Record::Pending => Claim::InProgress,
+            Record::Done(result) => Claim::Done(result),
+        })
+    }
+
+    /// Stores the result of a claimed key for the idempotency window
+    pub async fn complete<T: Serialize>(&self, key: &str, result: &T) -> Result<(), StoreError> {
+        let value = serde_json::to_string(&Record::Done(result))
+            .map_err(|e| StoreError::Value(e.to_string()))?;
+        self.store
+            .set(&self.key(key), &value, Some(self.window))
+            .await
+    }
+
+    /// Releases a claimed key, so the request can be retried
+    pub async fn release(&self, key: &str) -> Result<(), StoreError> {
+        self.store.del(&self.key(key)).await
+    }
+}
+
+/// Returns WINDOW, read from a Config object generated from environment
+/// variables on first use. A zero window disables idempotency.
+pub async fn get_window() -> Duration {
+    *WINDOW
+        .get_or_init(|| async move {
+            let config = Config::try_from_env().unwrap_or_default();
+            Duration::from_secs(config.idempotency_window_secs)
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::memory::MemoryStore;
+
+    #[tokio::test]
+    async fn test_idempotency() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let idempotency = Idempotency::new(&store, "test", Duration::from_secs(60));
+
+        assert_eq!(idempotency.claim::<u32>("a").await.unwrap(), Claim::New);
+        assert_eq!(
+            idempotency.claim::<u32>("a").await.unwrap(),
+            Claim::InProgress
+        );
+        assert_eq!(idempotency.claim::<u32>("b").await.unwrap(), Claim::New);
+
+        idempotency.complete("a", &42u32).await.unwrap();
+        assert_eq!(
+            idempotency.claim::<u32>("a").await.unwrap(),
+            Claim::Done(42)
+        );
+
+        idempotency.release("b").await.unwrap();
+        assert_eq!(idempotency.claim::<u32>("b").await.unwrap(), Claim::New);
+
+        // keys are scoped per operation
+        let other = Idempotency::new(&store, "other", Duration::from_secs(60));
+        assert_eq!(other.claim::<u32>("a").await.unwrap(), Claim::New);
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_window() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let idempotency = Idempotency::new(&store, "test", Duration::from_millis(20));
+
+        assert_eq!(idempotency.claim::<u32>("a").await.unwrap(), Claim::New);
+        idempotency.complete("a", &42u32).await.unwrap();
+        assert_eq!(
+            idempotency.claim::<u32>("a").await.unwrap(),
+            Claim::Done(42)
+        );
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(idempotency.claim::<u32>("a").await.unwrap(), Claim::New);
+
+        ut_info!("Success.");
+    }
+
+    #[test]
+    fn test_record_format() {
+        assert_eq!(
+            serde_json::to_string(&Record::<u32>::Pending).unwrap(),
+            r#"{"state":"pending"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Record::Done(42u32)).unwrap(),
+            r#"{"state":"done","result":42}"#
+        );
+    }
+}
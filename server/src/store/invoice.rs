@@ -0,0 +1,212 @@
+//! Invoice numbers of bookings: a configurable prefix, the year and a
+//! sequence per year without gaps, e.g. `AET-2024-000042`
+
+use super::{Store, StoreError};
+use crate::Config;
+use chrono::{DateTime, Datelike, Utc};
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+/// Invoice number prefix shared by all handlers
+static PREFIX: OnceCell<String> = OnceCell::const_new();
+
+/// Namespace of the invoice numbers of bookings in the store
+const NAMESPACE: &str = "invoice";
+
+/// Namespace of the yearly invoice sequences in the store
+const SEQUENCE_NAMESPACE: &str = "invoice_sequence";
+
+/// Stored while the number of a booking is being assigned
+const PENDING: &str = "pending";
+
+/// How long a booking is claimed while its number is being assigned.
+/// Bounds how long a crashed instance can block a retry.
+const PENDING_TTL: Duration = Duration::from_secs(60);
+
+/// Minimum number of digits of the sequence, padded with zeros
+const SEQUENCE_DIGITS: usize = 6;
+
+/// Reasons an invoice number could not be assigned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvoiceError {
+    /// Another request is assigning the number of this booking
+    InProgress(String),
+
+    /// The store could not be reached
+    Store(StoreError),
+}
+
+impl std::error::Error for InvoiceError {}
+
+impl Display for InvoiceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            InvoiceError::InProgress(booking) => {
+                write!(f, "Invoice number of booking {} is being assigned", booking)
+            }
+            InvoiceError::Store(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Assigns invoice numbers to bookings, keeping them in a store.
+/// A booking keeps its number, numbers don't expire.
+#[derive(Debug, Clone, Copy)]
+pub struct InvoiceNumbers<'a> {
+    store: &'a dyn Store,
+    prefix: &'a str,
+}
+
+impl<'a> InvoiceNumbers<'a> {
+    /// Keeps invoice numbers starting with `prefix` in `store`
+    pub fn new(store: &'a dyn Store, prefix: &'a str) -> Self {
+        InvoiceNumbers { store, prefix }
+    }
+
+    /// Formats the invoice number of a sequence in a year
+    fn format(&self, year: i32, sequence: i64) -> String {
+        format!(
+            "{}{}-{:0width$}",
+            self.prefix,
+            year,
+            sequence,
+            width = SEQUENCE_DIGITS
+        )
+    }
+
+    /// Returns the invoice number of a booking. A booking without one is
+    /// assigned the next number of the year of `now`.
+    ///
+    /// The booking is claimed before the sequence is incremented, so
+    /// concurrent requests for the same booking don't use up a number.
+    /// A number is only skipped if the store fails after incrementing.
+    pub async fn assign(&self, booking: &str, now: DateTime<Utc>) -> Result<String, InvoiceError> {
+        let key = super::key(NAMESPACE, booking);
+        let claimed = self
+            .store
+            .set_nx(&key, PENDING, Some(PENDING_TTL))
+            .await
+            .map_err(InvoiceError::Store)?;
+
+        if !claimed {
+            return match self.store.get(&key).await.map_err(InvoiceError::Store)? {
+                Some(number) if number != PENDING => Ok(number),
+                _ => Err(InvoiceError::InProgress(booking.to_string())),
+            };
+        }
+
+        let year = now.year();
+        let sequence_key = super::key(SEQUENCE_NAMESPACE, &year.to_string());
+        let sequence = match self.store.incr(&sequence_key).await {
+            Ok(sequence) => sequence,
+            Err(e) => {
+                // let the booking be claimed again
+                let _ = self.store.del(&key).await;
+                return Err(InvoiceError::Store(e));
+            }
+        };
+
+        let number = self.format(year, sequence);
+        if let Err(e) = self.store.set(&key, &number, None).await {
+            store_error!(
+                "invoice number {} of booking {} could not be stored and is skipped: {}",
+                number,
+                booking,
+                e
+            );
+            let _ = self.store.del(&key).await;
+            return Err(InvoiceError::Store(e));
+        }
+
+        Ok(number)
+    }
+}
+
+/// Returns PREFIX, the `invoice_prefix` configuration option,
+/// read from a Config object generated from environment variables on first use
+pub async fn get_prefix() -> &'static str {
+    PREFIX
+        .get_or_init(|| async move {
+            let config = Config::try_from_env().unwrap_or_default();
+            config.invoice_prefix
+        })
+        .await
+        .as_str()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::memory::MemoryStore;
+    use chrono::TimeZone;
+
+    fn date(year: i32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, 12, 31, 23, 59, 0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_assign() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let invoices = InvoiceNumbers::new(&store, "AET-");
+
+        let number = invoices.assign("parcel-1:itinerary", date(2024)).await;
+        assert_eq!(number.unwrap(), "AET-2024-000001");
+        let number = invoices.assign("parcel-2:itinerary", date(2024)).await;
+        assert_eq!(number.unwrap(), "AET-2024-000002");
+
+        // resending keeps the number, also in a later year
+        let number = invoices.assign("parcel-1:itinerary", date(2025)).await;
+        assert_eq!(number.unwrap(), "AET-2024-000001");
+
+        // every year starts a new sequence
+        let number = invoices.assign("parcel-3:itinerary", date(2025)).await;
+        assert_eq!(number.unwrap(), "AET-2025-000001");
+        let number = invoices.assign("parcel-4:itinerary", date(2024)).await;
+        assert_eq!(number.unwrap(), "AET-2024-000003");
+
+        let invoices = InvoiceNumbers::new(&store, "");
+        let number = invoices.assign("parcel-5:itinerary", date(2025)).await;
+        assert_eq!(number.unwrap(), "2025-000002");
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_assign_in_progress() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let invoices = InvoiceNumbers::new(&store, "AET-");
+        let key = crate::store::key(NAMESPACE, "parcel:itinerary");
+        store.set(&key, PENDING, None).await.unwrap();
+
+        let error = invoices
+            .assign("parcel:itinerary", date(2024))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invoice number of booking parcel:itinerary is being assigned"
+        );
+
+        // the sequence was not used up
+        store.del(&key).await.unwrap();
+        let number = invoices.assign("parcel:itinerary", date(2024)).await;
+        assert_eq!(number.unwrap(), "AET-2024-000001");
+
+        ut_info!("Success.");
+    }
+
+    #[test]
+    fn test_format() {
+        let store = MemoryStore::default();
+        let invoices = InvoiceNumbers::new(&store, "INV/");
+        assert_eq!(invoices.format(2024, 42), "INV/2024-000042");
+        assert_eq!(invoices.format(2024, 1234567), "INV/2024-1234567");
+    }
+}
@@ -0,0 +1,291 @@
+//! In memory store, for single instance deployments and tests
+
+use super::{Store, StoreError};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// A stored value with its optional expiry
+#[derive(Debug, Clone)]
+struct Entry {
+    value: String,
+    expires_at: Option<Instant>,
+}
+
+impl Entry {
+    fn new(value: &str, ttl: Option<Duration>) -> Self {
+        Entry {
+            value: value.to_string(),
+            expires_at: ttl.map(|ttl| Instant::now() + ttl),
+        }
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// Members of a sorted set with their score
+type SortedSet = HashMap<String, i64>;
+
+/// Keeps keys in process memory.
+/// Keys are lost on restart and are not shared between instances.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    entries: Mutex<HashMap<String, Entry>>,
+    sorted_sets: Mutex<HashMap<String, SortedSet>>,
+}
+
+impl MemoryStore {
+    /// Locks the entries, dropping expired keys
+    fn entries(&self) -> MutexGuard<'_, HashMap<String, Entry>> {
+        let mut entries = match self.entries.lock() {
+            Ok(entries) => entries,
+            Err(e) => e.into_inner(),
+        };
+
+        let now = Instant::now();
+        entries.retain(|_, entry| !entry.is_expired(now));
+        entries
+    }
+
+    /// Locks the sorted sets
+    fn sorted_sets(&self) -> MutexGuard<'_, HashMap<String, SortedSet>> {
+        match self.sorted_sets.lock() {
+            Ok(sorted_sets) => sorted_sets,
+            Err(e) => e.into_inner(),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Store for MemoryStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn persistent(&self) -> bool {
+        false
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>, StoreError> {
+        Ok(self.entries().get(key).map(|entry| entry.value.clone()))
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> Result<(), StoreError> {
+        self.entries()
+            .insert(key.to_string(), Entry::new(value, ttl));
+        Ok(())
+    }
+
+    async fn set_nx(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Option<Duration>,
+    ) -> Result<bool, StoreError> {
+        let mut entries = self.entries();
+        if entries.contains_key(key) {
+            return Ok(false);
+        }
+
+        entries.insert(key.to_string(), Entry::new(value, ttl));
+        Ok(true)
+    }
+
+    async fn incr(&self, key: &str) -> Result<i64, StoreError> {
+        let mut entries = self.entries();
+        let entry = entries
+            .entry(key.to_string())
+            .or_insert_with(|| Entry::new("0", None));
+
+        let value = entry
+            .value
+            .parse::<i64>()
+            .ok()
+            .and_then(|value| value.checked_add(1))
+            .ok_or_else(|| StoreError::Value(format!("not an integer: {}", entry.value)))?;
+        entry.value = value.to_string();
+        Ok(value)
+    }
+
+    async fn del(&self, key: &str) -> Result<(), StoreError> {
+        self.entries().remove(key);
+        self.sorted_sets().remove(key);
+        Ok(())
+    }
+
+    async fn zadd(&self, key: &str, score: i64, member: &str) -> Result<(), StoreError> {
+        self.sorted_sets()
+            .entry(key.to_string())
+            .or_default()
+            .insert(member.to_string(), score);
+        Ok(())
+    }
+
+    async fn zrange_by_score(
+        &self,
+        key: &str,
+        min: i64,
+        max: i64,
+    ) -> Result<Vec<String>, StoreError> {
+        let sorted_sets = self.sorted_sets();
+        let Some(set) = sorted_sets.get(key) else {
+            return Ok(vec![]);
+        };
+
+        let mut members: Vec<(&i64, &String)> = set
+            .iter()
+            .map(|(member, score)| (score, member))
+            .filter(|(score, _)| (min..=max).contains(*score))
+            .collect();
+        members.sort();
+
+        Ok(members
+            .into_iter()
+            .map(|(_, member)| member.clone())
+            .collect())
+    }
+
+    async fn zrevrange_by_score(
+        &self,
+        key: &str,
+        min: i64,
+        max: i64,
+        offset: usize,
+        count: usize,
+    ) -> Result<Vec<String>, StoreError> {
+        let members = self.zrange_by_score(key, min, max).await?;
+        Ok(members.into_iter().rev().skip(offset).take(count).collect())
+    }
+
+    async fn zrem_range_by_score(&self, key: &str, min: i64, max: i64) -> Result<(), StoreError> {
+        if let Some(set) = self.sorted_sets().get_mut(key) {
+            set.retain(|_, score| !(min..=max).contains(score));
+        }
+        Ok(())
+    }
+
+    async fn zrem(&self, key: &str, member: &str) -> Result<bool, StoreError> {
+        Ok(self
+            .sorted_sets()
+            .get_mut(key)
+            .is_some_and(|set| set.remove(member).is_some()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        assert_eq!(store.get("a").await.unwrap(), None);
+
+        store.set("a", "1", None).await.unwrap();
+        assert_eq!(store.get("a").await.unwrap(), Some("1".to_string()));
+
+        assert!(!store.set_nx("a", "2", None).await.unwrap());
+        assert_eq!(store.get("a").await.unwrap(), Some("1".to_string()));
+        assert!(store.set_nx("b", "2", None).await.unwrap());
+
+        store.del("a").await.unwrap();
+        store.del("a").await.unwrap();
+        assert_eq!(store.get("a").await.unwrap(), None);
+
+        assert_eq!(store.incr("n").await.unwrap(), 1);
+        assert_eq!(store.incr("n").await.unwrap(), 2);
+        assert_eq!(store.get("n").await.unwrap(), Some("2".to_string()));
+        store.set("c", "x", None).await.unwrap();
+        assert!(matches!(store.incr("c").await, Err(StoreError::Value(_))));
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_sorted_set() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        assert!(store.zrange_by_score("s", 0, 10).await.unwrap().is_empty());
+
+        store.zadd("s", 3, "c").await.unwrap();
+        store.zadd("s", 1, "a").await.unwrap();
+        store.zadd("s", 2, "b").await.unwrap();
+        assert_eq!(
+            store
+                .zrange_by_score("s", i64::MIN, i64::MAX)
+                .await
+                .unwrap(),
+            vec!["a", "b", "c"]
+        );
+        assert_eq!(
+            store.zrange_by_score("s", 2, 3).await.unwrap(),
+            vec!["b", "c"]
+        );
+
+        // updates the score of an existing member
+        store.zadd("s", 4, "a").await.unwrap();
+        assert_eq!(
+            store
+                .zrange_by_score("s", i64::MIN, i64::MAX)
+                .await
+                .unwrap(),
+            vec!["b", "c", "a"]
+        );
+
+        assert_eq!(
+            store
+                .zrevrange_by_score("s", i64::MIN, i64::MAX, 0, 2)
+                .await
+                .unwrap(),
+            vec!["a", "c"]
+        );
+        assert_eq!(
+            store.zrevrange_by_score("s", 2, 3, 1, 10).await.unwrap(),
+            vec!["b"]
+        );
+        assert!(store
+            .zrevrange_by_score("unknown", 0, 10, 0, 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        store.zrem_range_by_score("s", i64::MIN, 3).await.unwrap();
+        assert_eq!(store.zrange_by_score("s", 0, 10).await.unwrap(), vec!["a"]);
+
+        store.zadd("s", 5, "d").await.unwrap();
+        assert!(store.zrem("s", "d").await.unwrap());
+        assert!(!store.zrem("s", "d").await.unwrap());
+        assert!(!store.zrem("unknown", "d").await.unwrap());
+        assert_eq!(store.zrange_by_score("s", 0, 10).await.unwrap(), vec!["a"]);
+
+        store.del("s").await.unwrap();
+        assert!(store.zrange_by_score("s", 0, 10).await.unwrap().is_empty());
+
+        ut_info!("Success.");
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_expiry() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let store = MemoryStore::default();
+        let ttl = Some(Duration::from_millis(20));
+        store.set("a", "1", ttl).await.unwrap();
+        assert!(!store.set_nx("a", "2", ttl).await.unwrap());
+        assert_eq!(store.get("a").await.unwrap(), Some("1".to_string()));
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(store.get("a").await.unwrap(), None);
+        assert!(store.set_nx("a", "2", ttl).await.unwrap());
+
+        ut_info!("Success.");
+    }
+}
@@ -0,0 +1,183 @@
+//! Store
+//! provides a key-value store shared by the handlers, in memory or on Valkey
+
+#[macro_use]
+pub mod macros;
+pub mod idempotency;
+pub mod invoice;
+pub mod memory;
+pub mod valkey;
+
+use crate::Config;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+/// Store shared by all handlers
+pub static STORE: OnceCell<Box<dyn Store>> = OnceCell::const_new();
+
+/// Prefix of every key written by this service
+const KEY_PREFIX: &str = "contact";
+
+/// Builds a namespaced key, e.g. `contact:idempotency:<id>`
+pub fn key(namespace: &str, id: &str) -> String {
+    format!("{}:{}:{}", KEY_PREFIX, namespace, id)
+}
+
+/// Errors reported by stores
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreError {
+    /// The store is missing configuration or is misconfigured
+    Configuration(String),
+
+    /// The store could not be reached or refused the command
+    Backend(String),
+
+    /// A stored value could not be encoded or decoded
+    Value(String),
+}
+
+impl std::error::Error for StoreError {}
+
+impl Display for StoreError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Configuration(e) => write!(f, "Invalid store configuration: {}", e),
+            StoreError::Backend(e) => write!(f, "Store error: {}", e),
+            StoreError::Value(e) => write!(f, "Invalid stored value: {}", e),
+        }
+    }
+}
+
+/// Interface every key-value store needs to implement
+#[tonic::async_trait]
+pub trait Store: Debug + Send + Sync {
+    /// Name of the store, used for logging
+    fn name(&self) -> &'static str;
+
+    /// Whether keys outlive a restart and are shared between instances
+    fn persistent(&self) -> bool;
+
+    /// Returns the value of a key, if present and not expired
+    async fn get(&self, key: &str) -> Result<Option<String>, StoreError>;
+
+    /// Sets the value of a key, expiring it after `ttl` if provided
+    async fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> Result<(), StoreError>;
+
+    /// Sets the value of a key only if it is not present yet.
+    /// Returns true if the value was set.
+    async fn set_nx(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Option<Duration>,
+    ) -> Result<bool, StoreError>;
+
+    /// Increments the integer value of a key by one, starting from zero
+    /// if it is not present, keeping its expiry. Returns the new value.
+    async fn incr(&self, key: &str) -> Result<i64, StoreError>;
+
+    /// Removes a key, removing an absent key is not an error
+    async fn del(&self, key: &str) -> Result<(), StoreError>;
+
+    /// Adds a member to a sorted set, or updates the score of an existing member
+    async fn zadd(&self, key: &str, score: i64, member: &str) -> Result<(), StoreError>;
+
+    /// Returns the members of a sorted set with a score between `min` and `max`
+    /// (inclusive), ordered by score
+    async fn zrange_by_score(
+        &self,
+        key: &str,
+        min: i64,
+        max: i64,
+    ) -> Result<Vec<String>, StoreError>;
+
+    /// Returns at most `count` members of a sorted set with a score between
+    /// `min` and `max` (inclusive), ordered by descending score and skipping
+    /// the first `offset` members
+    async fn zrevrange_by_score(
+        &self,
+        key: &str,
+        min: i64,
+        max: i64,
+        offset: usize,
+        count: usize,
+    ) -> Result<Vec<String>, StoreError>;
+
+    /// Removes the members of a sorted set with a score between `min` and `max` (inclusive)
+    async fn zrem_range_by_score(&self, key: &str, min: i64, max: i64) -> Result<(), StoreError>;
+
+    /// Removes a member from a sorted set.
+    /// Returns true if the member was present.
+    async fn zrem(&self, key: &str, member: &str) -> Result<bool, StoreError>;
+}
+
+/// Creates the store selected in the provided configuration:
+/// Valkey if a url is configured, in memory otherwise.
+pub fn new_store(config: &Config) -> Result<Box<dyn Store>, StoreError> {
+    let store: Box<dyn Store> = match config.redis.url {
+        Some(_) => Box::new(valkey::ValkeyStore::new(config)?),
+        None => Box::new(memory::MemoryStore::default()),
+    };
+
+    store_info!("using store: {}", store.name());
+    Ok(store)
+}
+
+/// Returns STORE, the store selected through a Config object generated
+/// from environment variables.
+/// Initializes STORE if it hasn't been initialized yet.
+pub async fn get_store() -> Result<&'static dyn Store, StoreError> {
+    STORE
+        .get_or_try_init(|| async move {
+            let config = Config::try_from_env().unwrap_or_default();
+            new_store(&config)
+        })
+        .await
+        .map(|store| store.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key() {
+        assert_eq!(key("idempotency", "abc"), "contact:idempotency:abc");
+    }
+
+    #[tokio::test]
+    async fn test_new_store() {
+        lib_common::logger::get_log_handle().await;
+        ut_info!("Start.");
+
+        let mut config = Config::default();
+        let store = new_store(&config).unwrap();
+        assert_eq!(store.name(), "memory");
+        assert!(!store.persistent());
+
+        config.redis.url = Some("redis://localhost:6379".to_string());
+        config.redis.connection = None;
+        let store = new_store(&config).unwrap();
+        assert_eq!(store.name(), "valkey");
+        assert!(store.persistent());
+
+        ut_info!("Success.");
+    }
+
+    #[test]
+    fn test_store_error_display() {
+        assert_eq!(
+            StoreError::Configuration("no url".to_string()).to_string(),
+            "Invalid store configuration: no url"
+        );
+        assert_eq!(
+            StoreError::Backend("timeout".to_string()).to_string(),
+            "Store error: timeout"
+        );
+        assert_eq!(
+            StoreError::Value("not json".to_string()).to_string(),
+            "Invalid stored value: not json"
+        );
+    }
+}
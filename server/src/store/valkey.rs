@@ -0,0 +1,226 @@
+//! Valkey (or Redis) store, shared between service instances
+
+use super::{Store, StoreError};
+use crate::Config;
+use deadpool_redis::redis::{self, Cmd};
+use deadpool_redis::{Connection, Pool, Runtime};
+use std::fmt::{self, Debug, Formatter};
+use std::time::Duration;
+
+/// Keeps keys on a Valkey server through a connection pool
+pub struct ValkeyStore {
+    pool: Pool,
+}
+
+impl Debug for ValkeyStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // the url may contain credentials
+        f.debug_struct("ValkeyStore")
+            .field("status", &self.pool.status())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ValkeyStore {
+    /// Creates a new Valkey store from the `redis` configuration options.
+    /// Connections are only opened on first use.
+    pub fn new(config: &Config) -> Result<Self, StoreError> {
+        let pool = config
+            .redis
+            .create_pool(Some(Runtime::Tokio1))
+            .map_err(|e| StoreError::Configuration(e.to_string()))?;
+
+        Ok(ValkeyStore { pool })
+    }
+
+    async fn connection(&self) -> Result<Connection, StoreError> {
+        self.pool
+            .get()
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))
+    }
+
+    /// Builds a SET command with an optional expiry
+    fn set_cmd(key: &str, value: &str, ttl: Option<Duration>) -> Cmd {
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(key).arg(value);
+        if let Some(ttl) = ttl {
+            // at least 1 ms, PX 0 is refused
+            cmd.arg("PX").arg(ttl.as_millis().max(1) as u64);
+        }
+        cmd
+    }
+
+    /// Formats a score bound, using infinity for the extremes
+    fn score_bound(score: i64) -> String {
+        match score {
+            i64::MIN => "-inf".to_string(),
+            i64::MAX => "+inf".to_string(),
+            score => score.to_string(),
+        }
+    }
+
+    async fn query<T: redis::FromRedisValue>(&self, cmd: &Cmd) -> Result<T, StoreError> {
+        let mut connection = self.connection().await?;
+        cmd.query_async(&mut connection)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))
+    }
+}
+
+#[tonic::async_trait]
+impl Store for ValkeyStore {
+    fn name(&self) -> &'static str {
+        "valkey"
+    }
+
+    fn persistent(&self) -> bool {
+        true
+    }
+
+    #[cfg(not(tarpaulin_include))]
+    // no_coverage: (Rnever) needs a Valkey server, only integration tests
+    async fn get(&self, key: &str) -> Result<Option<String>, StoreError> {
+        self.query(redis::cmd("GET").arg(key)).await
+    }
+
+    #[cfg(not(tarpaulin_include))]
+    // no_coverage: (Rnever) needs a Valkey server, only integration tests
+    async fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> Result<(), StoreError> {
+        self.query(&Self::set_cmd(key, value, ttl)).await
+    }
+
+    #[cfg(not(tarpaulin_include))]
+    // no_coverage: (Rnever) needs a Valkey server, only integration tests
+    async fn set_nx(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Option<Duration>,
+    ) -> Result<bool, StoreError> {
+        // replies OK when set, nil when the key exists
+        let reply: Option<String> = self.query(Self::set_cmd(key, value, ttl).arg("NX")).await?;
+        Ok(reply.is_some())
+    }
+
+    #[cfg(not(tarpaulin_include))]
+    // no_coverage: (Rnever) needs a Valkey server, only integration tests
+    async fn incr(&self, key: &str) -> Result<i64, StoreError> {
+        self.query(redis::cmd("INCR").arg(key)).await
+    }
+
+    #[cfg(not(tarpaulin_include))]
+    // no_coverage: (Rnever) needs a Valkey server, only integration tests
+    async fn del(&self, key: &str) -> Result<(), StoreError> {
+        self.query(redis::cmd("DEL").arg(key)).await
+    }
+
+    #[cfg(not(tarpaulin_include))]
+    // no_coverage: (Rnever) needs a Valkey server, only integration tests
+    async fn zadd(&self, key: &str, score: i64, member: &str) -> Result<(), StoreError> {
+        self.query(redis::cmd("ZADD").arg(key).arg(score).arg(member))
+            .await
+    }
+
+    #[cfg(not(tarpaulin_include))]
+    // no_coverage: (Rnever) needs a Valkey server, only integration tests
+    async fn zrange_by_score(
+        &self,
+        key: &str,
+        min: i64,
+        max: i64,
+    ) -> Result<Vec<String>, StoreError> {
+        self.query(
+            redis::cmd("ZRANGEBYSCORE")
+                .arg(key)
+                .arg(Self::score_bound(min))
+                .arg(Self::score_bound(max)),
+        )
+        .await
+    }
+
+    #[cfg(not(tarpaulin_include))]
+    // no_coverage: (Rnever) needs a Valkey server, only integration tests
+    async fn zrevrange_by_score(
+        &self,
+        key: &str,
+        min: i64,
+        max: i64,
+        offset: usize,
+        count: usize,
+    ) -> Result<Vec<String>, StoreError> {
+        self.query(
+            redis::cmd("ZREVRANGEBYSCORE")
+                .arg(key)
+                .arg(Self::score_bound(max))
+                .arg(Self::score_bound(min))
+                .arg("LIMIT")
+                .arg(offset)
+                .arg(count),
+        )
+        .await
+    }
+
+    #[cfg(not(tarpaulin_include))]
+    // no_coverage: (Rnever) needs a Valkey server, only integration tests
+    async fn zrem_range_by_score(&self, key: &str, min: i64, max: i64) -> Result<(), StoreError> {
+        self.query(
+            redis::cmd("ZREMRANGEBYSCORE")
+                .arg(key)
+                .arg(Self::score_bound(min))
+                .arg(Self::score_bound(max)),
+        )
+        .await
+    }
+
+    #[cfg(not(tarpaulin_include))]
+    // no_coverage: (Rnever) needs a Valkey server, only integration tests
+    async fn zrem(&self, key: &str, member: &str) -> Result<bool, StoreError> {
+        let removed: i64 = self.query(redis::cmd("ZREM").arg(key).arg(member)).await?;
+        Ok(removed > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_cmd() {
+        let cmd = ValkeyStore::set_cmd("a", "1", None);
+        assert_eq!(
+            cmd.get_packed_command(),
+            redis::cmd("SET").arg("a").arg("1").get_packed_command()
+        );
+
+        let cmd = ValkeyStore::set_cmd("a", "1", Some(Duration::from_secs(2)));
+        assert_eq!(
+            cmd.get_packed_command(),
+            redis::cmd("SET")
+                .arg("a")
+                .arg("1")
+                .arg("PX")
+                .arg(2000)
+                .get_packed_command()
+        );
+    }
+
+    #[test]
+    fn test_score_bound() {
+        assert_eq!(ValkeyStore::score_bound(i64::MIN), "-inf");
+        assert_eq!(ValkeyStore::score_bound(i64::MAX), "+inf");
+        assert_eq!(ValkeyStore::score_bound(-5), "-5");
+        assert_eq!(ValkeyStore::score_bound(1700000000000), "1700000000000");
+    }
+
+    #[test]
+    fn test_valkey_store_new() {
+        let mut config = Config::default();
+        config.redis.url = Some("not a url".to_string());
+        config.redis.connection = None;
+        assert!(matches!(
+            ValkeyStore::new(&config).unwrap_err(),
+            StoreError::Configuration(_)
+        ));
+    }
+}
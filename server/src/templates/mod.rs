@@ -0,0 +1,968 @@
+//! Templates
+//! provides locally rendered message templates shipped with this crate
+
+use crate::delivery::email::{EmailBody, TemplateModel};
+use crate::delivery::preferences::MessageKind;
+use crate::locale::{Locale, DEFAULT_LANGUAGE};
+use handlebars::Handlebars;
+use std::fmt::{self, Display, Formatter};
+use std::sync::OnceLock;
+
+static RENDERER: OnceLock<Result<TemplateRenderer, TemplateError>> = OnceLock::new();
+
+/// A versioned template with subject, HTML and plain text parts,
+/// and optionally a text message part, in the default language
+/// and translated into other languages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TemplateSpec {
+    /// Template name, used to select the template
+    pub name: &'static str,
+
+    /// Template version, bumped on every content change
+    pub version: u32,
+
+    /// Alias of the equivalent template stored at the provider
+    pub provider_alias: &'static str,
+
+    /// Subject line
+    pub subject: &'static str,
+
+    /// HTML part
+    pub html: &'static str,
+
+    /// Plain text part
+    pub text: &'static str,
+
+    /// Text message part, if the template can be sent by SMS
+    pub sms: Option<&'static str>,
+
+    /// Whether the template is transactional or marketing,
+    /// which decides what the user's preferences allow
+    pub kind: MessageKind,
+
+    /// Fields the template model must provide
+    pub fields: &'static [&'static str],
+
+    /// Whether the generic `sendNotification` RPC may send the template.
+    /// Templates with fields that aren't plain text, e.g. lists or flags,
+    /// or fields only svc-contact may fill in, e.g. invoice numbers or
+    /// signed links, are only sent by their own handler.
+    pub generic: bool,
+
+    /// Translations of the parts into other languages
+    pub translations: &'static [Translation],
+}
+
+/// The parts of a template in another language,
+/// with the same fields as the template
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Translation {
+    /// Language tag, e.g. `nl` or `nl-BE`
+    pub language: &'static str,
+
+    /// Subject line
+    pub subject: &'static str,
+
+    /// HTML part
+    pub html: &'static str,
+
+    /// Plain text part
+    pub text: &'static str,
+
+    /// Text message part, if the template has one
+    pub sms: Option<&'static str>,
+}
+
+impl TemplateSpec {
+    /// The parts of the template in every language, the default language first
+    pub fn variants(&self) -> impl Iterator<Item = Translation> + '_ {
+        std::iter::once(Translation {
+            language: DEFAULT_LANGUAGE,
+            subject: self.subject,
+            html: self.html,
+            text: self.text,
+            sms: self.sms,
+        })
+        .chain(self.translations.iter().copied())
+    }
+
+    /// Returns the language of the template variant to send for a locale,
+    /// the first language of its fallback chain the template is translated into
+    pub fn language(&self, locale: &Locale) -> &'static str {
+        locale
+            .fallback_chain()
+            .iter()
+            .find_map(|tag| {
+                self.translations
+                    .iter()
+                    .find(|translation| translation.language.eq_ignore_ascii_case(tag))
+            })
+            .map_or(DEFAULT_LANGUAGE, |translation| translation.language)
+    }
+
+    /// Alias of the provider template for a locale.
+    /// Translations are stored at the provider with the language appended,
+    /// e.g. `parcel-arrival-nl`.
+    pub fn provider_alias_for(&self, locale: &Locale) -> String {
+        match self.language(locale) {
+            DEFAULT_LANGUAGE => self.provider_alias.to_string(),
+            language => format!("{}-{}", self.provider_alias, language.to_lowercase()),
+        }
+    }
+
+    /// Checks the model provides every declared field and nothing else
+    pub fn validate(&self, model: &TemplateModel) -> Result<(), TemplateError> {
+        let missing: Vec<&str> = self
+            .fields
+            .iter()
+            .filter(|field| model.get(field).is_none())
+            .copied()
+            .collect();
+        let unknown: Vec<&str> = model
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .filter(|key| !self.fields.contains(key))
+            .collect();
+
+        let mut errors = vec![];
+        if !missing.is_empty() {
+            errors.push(format!("missing {}", missing.join(", ")));
+        }
+
+        if !unknown.is_empty() {
+            errors.push(format!("unknown {}", unknown.join(", ")));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(TemplateError::Model(format!(
+                "{}: {}",
+                self.name,
+                errors.join("; ")
+            )))
+        }
+    }
+}
+
+/// Cargo confirmation, sent when an itinerary has been booked
+pub const CARGO_CONFIRMATION: TemplateSpec = TemplateSpec {
+    name: "cargo-confirmation",
+    version: 4,
+    provider_alias: "demo-confirmation",
+    subject: include_str!("../../templates/cargo_confirmation/v4/subject.hbs"),
+    html: include_str!("../../templates/cargo_confirmation/v4/body.html.hbs"),
+    text: include_str!("../../templates/cargo_confirmation/v4/body.txt.hbs"),
+    sms: Some(include_str!(
+        "../../templates/cargo_confirmation/v4/sms.hbs"
+    )),
+    kind: MessageKind::Transactional,
+    fields: &[
+        "customer_name",
+        "customer_dropoff_time",
+        "customer_pickup_time",
+        "parcel_weight_kg",
+        "origin_vertiport_name",
+        "origin_vertiport_address",
+        "target_vertiport_name",
+        "target_vertiport_address",
+        "origin_latitude",
+        "origin_longitude",
+        "target_latitude",
+        "target_longitude",
+        "encoded_polyline",
+        "invoice_id",
+        "invoice_date",
+        "receipt_lines",
+        "subtotal",
+        "tax_rate",
+        "tax",
+        "currency",
+        "total_price",
+    ],
+    generic: false,
+    translations: &[Translation {
+        language: "nl",
+        subject: include_str!("../../templates/cargo_confirmation/v4/nl/subject.hbs"),
+        html: include_str!("../../templates/cargo_confirmation/v4/nl/body.html.hbs"),
+        text: include_str!("../../templates/cargo_confirmation/v4/nl/body.txt.hbs"),
+        sms: Some(include_str!(
+            "../../templates/cargo_confirmation/v4/nl/sms.hbs"
+        )),
+    }],
+};
+
+/// Cargo cancellation, sent when an itinerary has been cancelled
+pub const CARGO_CANCELLATION: TemplateSpec = TemplateSpec {
+    name: "cargo-cancellation",
+    version: 4,
+    provider_alias: "cargo-cancellation",
+    subject: include_str!("../../templates/cargo_cancellation/v4/subject.hbs"),
+    html: include_str!("../../templates/cargo_cancellation/v4/body.html.hbs"),
+    text: include_str!("../../templates/cargo_cancellation/v4/body.txt.hbs"),
+    sms: None,
+    kind: MessageKind::Transactional,
+    fields: &[
+        "customer_name",
+        "customer_pickup_time",
+        "origin_vertiport_name",
+        "target_vertiport_name",
+        "cancellation_reason",
+        "refund",
+        "refund_amount",
+        "currency",
+    ],
+    generic: false,
+    translations: &[Translation {
+        language: "nl",
+        subject: include_str!("../../templates/cargo_cancellation/v4/nl/subject.hbs"),
+        html: include_str!("../../templates/cargo_cancellation/v4/nl/body.html.hbs"),
+        text: include_str!("../../templates/cargo_cancellation/v4/nl/body.txt.hbs"),
+        sms: None,
+    }],
+};
+
+/// Parcel arrival, sent when a parcel is ready for pickup at its destination
+pub const PARCEL_ARRIVAL: TemplateSpec = TemplateSpec {
+    name: "parcel-arrival",
+    version: 3,
+    provider_alias: "parcel-arrival",
+    subject: include_str!("../../templates/parcel_arrival/v3/subject.hbs"),
+    html: include_str!("../../templates/parcel_arrival/v3/body.html.hbs"),
+    text: include_str!("../../templates/parcel_arrival/v3/body.txt.hbs"),
+    sms: Some(include_str!("../../templates/parcel_arrival/v3/sms.hbs")),
+    kind: MessageKind::Transactional,
+    fields: &[
+        "customer_name",
+        "parcel_weight_kg",
+        "target_vertiport_name",
+        "target_vertiport_address",
+        "pickup_window_start",
+        "pickup_window_end",
+    ],
+    generic: true,
+    translations: &[Translation {
+        language: "nl",
+        subject: include_str!("../../templates/parcel_arrival/v3/nl/subject.hbs"),
+        html: include_str!("../../templates/parcel_arrival/v3/nl/body.html.hbs"),
+        text: include_str!("../../templates/parcel_arrival/v3/nl/body.txt.hbs"),
+        sms: Some(include_str!("../../templates/parcel_arrival/v3/nl/sms.hbs")),
+    }],
+};
+
+/// Flight delay, sent when the flight carrying a parcel is rescheduled
+pub const FLIGHT_DELAY: TemplateSpec = TemplateSpec {
+    name: "flight-delay",
+    version: 3,
+    provider_alias: "flight-delay",
+    subject: include_str!("../../templates/flight_delay/v3/subject.hbs"),
+    html: include_str!("../../templates/flight_delay/v3/body.html.hbs"),
+    text: include_str!("../../templates/flight_delay/v3/body.txt.hbs"),
+    sms: None,
+    kind: MessageKind::Transactional,
+    fields: &[
+        "customer_name",
+        "customer_pickup_time",
+        "customer_dropoff_time",
+        "origin_vertiport_name",
+        "origin_vertiport_address",
+        "target_vertiport_name",
+        "target_vertiport_address",
+        "old_departure_time",
+        "new_departure_time",
+        "old_arrival_time",
+        "new_arrival_time",
+    ],
+    generic: true,
+    translations: &[Translation {
+        language: "nl",
+        subject: include_str!("../../templates/flight_delay/v3/nl/subject.hbs"),
+        html: include_str!("../../templates/flight_delay/v3/nl/body.html.hbs"),
+        text: include_str!("../../templates/flight_delay/v3/nl/body.txt.hbs"),
+        sms: None,
+    }],
+};
+
+/// Pickup reminder, sent a configured time before the origin timeslot of a booking
+pub const PICKUP_REMINDER: TemplateSpec = TemplateSpec {
+    name: "pickup-reminder",
+    version: 3,
+    provider_alias: "pickup-reminder",
+    subject: include_str!("../../templates/pickup_reminder/v3/subject.hbs"),
+    html: include_str!("../../templates/pickup_reminder/v3/body.html.hbs"),
+    text: include_str!("../../templates/pickup_reminder/v3/body.txt.hbs"),
+    sms: Some(include_str!("../../templates/pickup_reminder/v3/sms.hbs")),
+    kind: MessageKind::Transactional,
+    fields: &[
+        "customer_name",
+        "lead_time",
+        "customer_pickup_time",
+        "origin_vertiport_name",
+        "origin_vertiport_address",
+        "target_vertiport_name",
+    ],
+    generic: true,
+    translations: &[Translation {
+        language: "nl",
+        subject: include_str!("../../templates/pickup_reminder/v3/nl/subject.hbs"),
+        html: include_str!("../../templates/pickup_reminder/v3/nl/body.html.hbs"),
+        text: include_str!("../../templates/pickup_reminder/v3/nl/body.txt.hbs"),
+        sms: Some(include_str!(
+            "../../templates/pickup_reminder/v3/nl/sms.hbs"
+        )),
+    }],
+};
+
+/// Email verification, sent on signup to confirm the user owns the address
+pub const EMAIL_VERIFICATION: TemplateSpec = TemplateSpec {
+    name: "email-verification",
+    version: 2,
+    provider_alias: "email-verification",
+    subject: include_str!("../../templates/email_verification/v2/subject.hbs"),
+    html: include_str!("../../templates/email_verification/v2/body.html.hbs"),
+    text: include_str!("../../templates/email_verification/v2/body.txt.hbs"),
+    sms: None,
+    kind: MessageKind::Transactional,
+    fields: &["customer_name", "verification_url", "link_valid_hours"],
+    generic: false,
+    translations: &[Translation {
+        language: "nl",
+        subject: include_str!("../../templates/email_verification/v2/nl/subject.hbs"),
+        html: include_str!("../../templates/email_verification/v2/nl/body.html.hbs"),
+        text: include_str!("../../templates/email_verification/v2/nl/body.txt.hbs"),
+        sms: None,
+    }],
+};
+
+/// Route announcement, marketing sent when a new route opens.
+/// Carries the unsubscribe link of the user, see [`crate::delivery::unsubscribe`].
+pub const ROUTE_ANNOUNCEMENT: TemplateSpec = TemplateSpec {
+    name: "route-announcement",
+    version: 1,
+    provider_alias: "route-announcement",
+    subject: include_str!("../../templates/route_announcement/v1/subject.hbs"),
+    html: include_str!("../../templates/route_announcement/v1/body.html.hbs"),
+    text: include_str!("../../templates/route_announcement/v1/body.txt.hbs"),
+    sms: None,
+    kind: MessageKind::Marketing,
+    fields: &[
+        "customer_name",
+        "origin_vertiport_name",
+        "target_vertiport_name",
+        "first_flight_date",
+    ],
+    generic: true,
+    translations: &[Translation {
+        language: "nl",
+        subject: include_str!("../../templates/route_announcement/v1/nl/subject.hbs"),
+        html: include_str!("../../templates/route_announcement/v1/nl/body.html.hbs"),
+        text: include_str!("../../templates/route_announcement/v1/nl/body.txt.hbs"),
+        sms: None,
+    }],
+};
+
+/// All templates shipped with this crate
+pub const TEMPLATES: &[TemplateSpec] = &[
+    CARGO_CONFIRMATION,
+    CARGO_CANCELLATION,
+    PARCEL_ARRIVAL,
+    FLIGHT_DELAY,
+    PICKUP_REMINDER,
+    EMAIL_VERIFICATION,
+    ROUTE_ANNOUNCEMENT,
+];
+
+/// Returns the template with the given name, if it exists
+pub fn find(name: &str) -> Option<&'static TemplateSpec> {
+    TEMPLATES.iter().find(|spec| spec.name == name)
+}
+
+/// Returns the template with the given name if the generic
+/// `sendNotification` RPC may send it
+pub fn find_generic(name: &str) -> Option<&'static TemplateSpec> {
+    find(name).filter(|spec| spec.generic)
+}
+
+/// Errors while registering or rendering templates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    /// No template with this name exists
+    Unknown(String),
+
+    /// A template could not be parsed
+    Invalid(String),
+
+    /// A template could not be rendered with the provided model
+    Render(String),
+
+    /// The model doesn't match the fields declared by the template
+    Model(String),
+}
+
+impl std::error::Error for TemplateError {}
+
+impl Display for TemplateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Unknown(name) => write!(f, "Unknown template: {}", name),
+            TemplateError::Invalid(e) => write!(f, "Invalid template: {}", e),
+            TemplateError::Render(e) => write!(f, "Could not render template: {}", e),
+            TemplateError::Model(e) => write!(f, "Invalid template model: {}", e),
+        }
+    }
+}
+
+/// Renders the templates in [`TEMPLATES`]
+#[derive(Debug)]
+pub struct TemplateRenderer {
+    /// Registry for HTML parts, escapes values
+    html: Handlebars<'static>,
+
+    /// Registry for subjects and plain text parts, no escaping
+    text: Handlebars<'static>,
+}
+
+impl TemplateRenderer {
+    /// Creates a renderer with all templates registered
+    pub fn new() -> Result<Self, TemplateError> {
+        let mut html = Handlebars::new();
+        html.set_strict_mode(true);
+
+        let mut text = Handlebars::new();
+        text.set_strict_mode(true);
+        text.register_escape_fn(handlebars::no_escape);
+
+        for spec in TEMPLATES {
+            let invalid = |e: handlebars::TemplateError| {
+                TemplateError::Invalid(format!("{}: {}", spec.name, e))
+            };
+
+            for variant in spec.variants() {
+                let key = |part: &str| Self::key(spec, variant.language, part);
+                html.register_template_string(&key("html"), variant.html)
+                    .map_err(&invalid)?;
+                text.register_template_string(&key("text"), variant.text)
+                    .map_err(&invalid)?;
+                text.register_template_string(&key("subject"), variant.subject)
+                    .map_err(&invalid)?;
+                if let Some(sms) = variant.sms {
+                    text.register_template_string(&key("sms"), sms)
+                        .map_err(&invalid)?;
+                }
+            }
+        }
+
+        Ok(TemplateRenderer { html, text })
+    }
+
+    fn key(spec: &TemplateSpec, language: &str, part: &str) -> String {
+        format!("{}.v{}.{}.{}", spec.name, spec.version, language, part)
+    }
+
+    /// Renders the subject, HTML and plain text parts of a template,
+    /// in the language best matching the locale
+    pub fn render(
+        &self,
+        name: &str,
+        locale: &Locale,
+        model: &TemplateModel,
+    ) -> Result<EmailBody, TemplateError> {
+        let spec = find(name).ok_or_else(|| TemplateError::Unknown(name.to_string()))?;
+        let language = spec.language(locale);
+        let render = |registry: &Handlebars<'static>, part: &str| {
+            registry
+                .render(&Self::key(spec, language, part), model)
+                .map_err(|e| TemplateError::Render(format!("{}: {}", name, e)))
+        };
+
+        Ok(EmailBody {
+            subject: render(&self.text, "subject")?.trim().to_string(),
+            html: render(&self.html, "html")?,
+            text: render(&self.text, "text")?,
+        })
+    }
+
+    /// Renders the text message part of a template,
+    /// in the language best matching the locale
+    pub fn render_sms(
+        &self,
+        name: &str,
+        locale: &Locale,
+        model: &TemplateModel,
+    ) -> Result<String, TemplateError> {
+        let spec = find(name)
+            .filter(|spec| spec.sms.is_some())
+            .ok_or_else(|| TemplateError::Unknown(format!("{} (text message)", name)))?;
+
+        self.text
+            .render(&Self::key(spec, spec.language(locale), "sms"), model)
+            .map(|text| text.trim().to_string())
+            .map_err(|e| TemplateError::Render(format!("{}: {}", name, e)))
+    }
+}
+
+/// Returns the shared renderer, registering the templates on first use
+pub fn get_renderer() -> Result<&'static TemplateRenderer, TemplateError> {
+    RENDERER
+        .get_or_init(TemplateRenderer::new)
+        .as_ref()
+        .map_err(|e| e.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn confirmation_model() -> TemplateModel {
+        let mut model = TemplateModel::default();
+        model.insert("customer_name", "Alice");
+        model.insert("customer_dropoff_time", "2024-01-01 10:50 CET");
+        model.insert("customer_pickup_time", "2024-01-01 10:10 CET");
+        model.insert("parcel_weight_kg", "1.50");
+        model.insert("origin_vertiport_name", "Amsterdam");
+        model.insert("origin_vertiport_address", "Dam 1");
+        model.insert("target_vertiport_name", "Utrecht <Centraal>");
+        model.insert("target_vertiport_address", "Domplein 1");
+        model.insert("origin_latitude", 52.37);
+        model.insert("origin_longitude", 4.89);
+        model.insert("target_latitude", 52.09);
+        model.insert("target_longitude", 5.12);
+        model.insert("encoded_polyline", "_p~iF~ps|U");
+        model.insert("invoice_id", "1234");
+        model.insert("invoice_date", "2024-01-01 09:00 CET");
+        model.insert(
+            "receipt_lines",
+            json!([
+                { "description": "Flight", "amount": "10.00" },
+                { "description": "Network fee", "amount": "1.00" },
+            ]),
+        );
+        model.insert("subtotal", "11.00");
+        model.insert("tax_rate", "21");
+        model.insert("tax", "2.31");
+        model.insert("currency", "EUR");
+        model.insert("total_price", "13.31");
+        model
+    }
+
+    #[test]
+    fn test_find() {
+        assert_eq!(find("cargo-confirmation"), Some(&CARGO_CONFIRMATION));
+        assert_eq!(find("cargo-cancellation"), Some(&CARGO_CANCELLATION));
+        assert_eq!(find("parcel-arrival"), Some(&PARCEL_ARRIVAL));
+        assert_eq!(find("flight-delay"), Some(&FLIGHT_DELAY));
+        assert_eq!(find("pickup-reminder"), Some(&PICKUP_REMINDER));
+        assert_eq!(find("email-verification"), Some(&EMAIL_VERIFICATION));
+        assert_eq!(find("route-announcement"), Some(&ROUTE_ANNOUNCEMENT));
+        assert_eq!(find("unknown"), None);
+    }
+
+    #[test]
+    fn test_find_generic() {
+        assert_eq!(find_generic("parcel-arrival"), Some(&PARCEL_ARRIVAL));
+        assert_eq!(find_generic("flight-delay"), Some(&FLIGHT_DELAY));
+        assert_eq!(
+            find_generic("route-announcement"),
+            Some(&ROUTE_ANNOUNCEMENT)
+        );
+
+        // the receipt is a list and the invoice number is assigned by svc-contact
+        assert_eq!(find_generic("cargo-confirmation"), None);
+        // the refund is a flag
+        assert_eq!(find_generic("cargo-cancellation"), None);
+        // the verification link is signed by svc-contact
+        assert_eq!(find_generic("email-verification"), None);
+        assert_eq!(find_generic("unknown"), None);
+    }
+
+    #[test]
+    fn test_templates_register() {
+        let renderer = get_renderer().unwrap();
+        for spec in TEMPLATES {
+            for variant in spec.variants() {
+                for part in ["subject", "html", "text"] {
+                    let key = TemplateRenderer::key(spec, variant.language, part);
+                    let registry = match part {
+                        "html" => &renderer.html,
+                        _ => &renderer.text,
+                    };
+                    assert!(registry.has_template(&key), "missing {}", key);
+                }
+
+                // translations have the same parts as the template
+                let key = TemplateRenderer::key(spec, variant.language, "sms");
+                assert_eq!(renderer.text.has_template(&key), spec.sms.is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn test_language() {
+        let locale = |tag: &str| Locale::parse(tag).unwrap();
+        assert_eq!(PARCEL_ARRIVAL.language(&locale("nl-BE")), "nl");
+        assert_eq!(PARCEL_ARRIVAL.language(&locale("nl")), "nl");
+        assert_eq!(PARCEL_ARRIVAL.language(&locale("fr-BE")), "en");
+        assert_eq!(PARCEL_ARRIVAL.language(&Locale::default()), "en");
+
+        assert_eq!(
+            PARCEL_ARRIVAL.provider_alias_for(&locale("nl-BE")),
+            "parcel-arrival-nl"
+        );
+        assert_eq!(
+            PARCEL_ARRIVAL.provider_alias_for(&locale("fr")),
+            "parcel-arrival"
+        );
+    }
+
+    #[test]
+    fn test_render_translation() {
+        let renderer = get_renderer().unwrap();
+        let locale = Locale::parse("nl-BE").unwrap();
+        let mut model = confirmation_model();
+        model.insert("parcel_weight_kg", "1,50");
+
+        let body = renderer
+            .render("cargo-confirmation", &locale, &model)
+            .unwrap();
+        assert_eq!(body.subject, "Je Aetheric-pakketboeking 1234");
+        assert!(body.text.contains("Hallo Alice,"));
+        assert!(body.text.contains("Gewicht pakket: 1,50 kg"));
+        assert!(body.html.contains("<html lang=\"nl\">"));
+
+        let sms = renderer
+            .render_sms("cargo-confirmation", &locale, &model)
+            .unwrap();
+        assert!(sms.starts_with("Aetheric: je pakket (1,50 kg) is geboekt."));
+
+        // no French translation, falls back to English
+        let locale = Locale::parse("fr-BE").unwrap();
+        let body = renderer
+            .render("cargo-confirmation", &locale, &model)
+            .unwrap();
+        assert_eq!(body.subject, "Your Aetheric parcel booking 1234");
+    }
+
+    #[test]
+    fn test_render_without_name() {
+        let mut model = confirmation_model();
+        model.insert("customer_name", "");
+
+        let renderer = get_renderer().unwrap();
+        let body = renderer
+            .render("cargo-confirmation", &Locale::default(), &model)
+            .unwrap();
+        assert!(body.text.starts_with("Hi,\n"));
+        assert!(body.html.contains("<p>Hi,</p>"));
+
+        let locale = Locale::parse("nl").unwrap();
+        let body = renderer
+            .render("cargo-confirmation", &locale, &model)
+            .unwrap();
+        assert!(body.text.starts_with("Hallo,\n"));
+    }
+
+    #[test]
+    fn test_render_cargo_confirmation() {
+        let body = get_renderer()
+            .unwrap()
+            .render(
+                "cargo-confirmation",
+                &Locale::default(),
+                &confirmation_model(),
+            )
+            .unwrap();
+
+        assert_eq!(body.subject, "Your Aetheric parcel booking 1234");
+        assert!(body.text.contains("Hi Alice,"));
+        assert!(body.text.contains("Utrecht <Centraal>"));
+        assert!(body.text.contains("Network fee: 1.00 EUR"));
+        assert!(body.text.contains("Subtotal: 11.00 EUR"));
+        assert!(body.text.contains("VAT (21%): 2.31 EUR"));
+        assert!(body.text.contains("Total: 13.31 EUR"));
+        assert!(body.html.contains("Utrecht &lt;Centraal&gt;"));
+        assert!(body.html.contains("<td>Network fee</td>"));
+        assert!(!body.text.contains("Unsubscribe"));
+    }
+
+    #[test]
+    fn test_render_unsubscribe_url() {
+        let mut model = TemplateModel::default();
+        model.insert("customer_name", "Alice");
+        model.insert("origin_vertiport_name", "Amsterdam");
+        model.insert("target_vertiport_name", "Utrecht");
+        model.insert("first_flight_date", "2024-06-01");
+
+        let renderer = get_renderer().unwrap();
+        let body = renderer
+            .render("route-announcement", &Locale::default(), &model)
+            .unwrap();
+        assert_eq!(body.subject, "Aetheric now flies from Amsterdam to Utrecht");
+        assert!(!body.text.contains("Unsubscribe"));
+
+        model.insert(
+            "unsubscribe_url",
+            "https://contact.aetheric.nl/contact/unsubscribe?token=a.1&b",
+        );
+        let body = renderer
+            .render("route-announcement", &Locale::default(), &model)
+            .unwrap();
+        assert!(body
+            .text
+            .contains("Unsubscribe: https://contact.aetheric.nl/contact/unsubscribe?token=a.1&b"));
+        assert!(body.html.contains(
+            "href=\"https://contact.aetheric.nl/contact/unsubscribe?token&#x3D;a.1&amp;b\""
+        ));
+
+        // only marketing gets an unsubscribe link
+        for spec in TEMPLATES {
+            for variant in spec.variants() {
+                assert_eq!(
+                    variant.text.contains("unsubscribe_url"),
+                    spec.kind == MessageKind::Marketing,
+                    "{} {}",
+                    spec.name,
+                    variant.language
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        CARGO_CONFIRMATION.validate(&confirmation_model()).unwrap();
+
+        let mut model = confirmation_model();
+        model.insert("customer_title", "Dr.");
+        let error = CARGO_CONFIRMATION.validate(&model).unwrap_err();
+        assert_eq!(
+            error,
+            TemplateError::Model("cargo-confirmation: unknown customer_title".to_string())
+        );
+
+        let mut model = TemplateModel::default();
+        model.insert("customer_name", "Alice");
+        model.insert("customer_title", "Dr.");
+        let error = PARCEL_ARRIVAL.validate(&model).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid template model: parcel-arrival: \
+            missing parcel_weight_kg, target_vertiport_name, target_vertiport_address, \
+            pickup_window_start, pickup_window_end; unknown customer_title"
+        );
+    }
+
+    #[test]
+    fn test_render_cargo_cancellation() {
+        let mut model = TemplateModel::default();
+        model.insert("customer_name", "Alice");
+        model.insert("customer_pickup_time", "2024-01-01 10:10 CET");
+        model.insert("origin_vertiport_name", "Amsterdam");
+        model.insert("target_vertiport_name", "Utrecht <Centraal>");
+        model.insert("cancellation_reason", "CANCELLATION_REASON_WEATHER");
+        model.insert("refund", true);
+        model.insert("refund_amount", "12.50");
+        model.insert("currency", "EUR");
+
+        let renderer = get_renderer().unwrap();
+        let body = renderer
+            .render("cargo-cancellation", &Locale::default(), &model)
+            .unwrap();
+        assert_eq!(body.subject, "Your Aetheric parcel booking is cancelled");
+        assert!(body.text.contains("Hi Alice,"));
+        assert!(body
+            .text
+            .contains("The weather does not allow us to fly your parcel safely."));
+        assert!(body.text.contains("12.50 EUR will be refunded"));
+        assert!(body
+            .html
+            .contains("<p>The weather does not allow us to fly your parcel safely.</p>"));
+        assert!(body.html.contains("Utrecht &lt;Centraal&gt;"));
+
+        let nl = Locale::parse("nl").unwrap();
+        let body = renderer.render("cargo-cancellation", &nl, &model).unwrap();
+        assert!(body
+            .text
+            .contains("Het weer laat niet toe dat we je pakket veilig vliegen."));
+
+        // unknown reasons get the general explanation
+        model.insert("cancellation_reason", "CANCELLATION_REASON_UNSPECIFIED");
+        model.insert("refund", false);
+        let body = renderer
+            .render("cargo-cancellation", &Locale::default(), &model)
+            .unwrap();
+        assert!(body
+            .text
+            .contains("Unfortunately we are unable to carry out your booking."));
+        assert!(!body.text.contains("refunded"));
+    }
+
+    #[test]
+    fn test_render_parcel_arrival() {
+        let mut model = TemplateModel::default();
+        model.insert("customer_name", "Alice");
+        model.insert("parcel_weight_kg", "1.50");
+        model.insert("target_vertiport_name", "Utrecht <Centraal>");
+        model.insert("target_vertiport_address", "Domplein 1");
+        model.insert("pickup_window_start", "2024-01-01 11:00 CET");
+        model.insert("pickup_window_end", "2024-01-03 11:00 CET");
+
+        let body = get_renderer()
+            .unwrap()
+            .render("parcel-arrival", &Locale::default(), &model)
+            .unwrap();
+        assert_eq!(
+            body.subject,
+            "Your Aetheric parcel has arrived at Utrecht <Centraal>"
+        );
+        assert!(body.text.contains("Hi Alice,"));
+        assert!(body
+            .text
+            .contains("from 2024-01-01 11:00 CET until 2024-01-03 11:00 CET"));
+        assert!(body.html.contains("Utrecht &lt;Centraal&gt;"));
+
+        let sms = get_renderer()
+            .unwrap()
+            .render_sms("parcel-arrival", &Locale::default(), &model)
+            .unwrap();
+        assert_eq!(
+            sms,
+            "Aetheric: your parcel has arrived at Utrecht <Centraal>, Domplein 1. \
+            Pickup until 2024-01-03 11:00 CET."
+        );
+    }
+
+    #[test]
+    fn test_render_flight_delay() {
+        let mut model = TemplateModel::default();
+        model.insert("customer_name", "Alice");
+        model.insert("customer_pickup_time", "2024-01-01 10:40 CET");
+        model.insert("customer_dropoff_time", "2024-01-01 11:20 CET");
+        model.insert("origin_vertiport_name", "Amsterdam");
+        model.insert("origin_vertiport_address", "Dam 1");
+        model.insert("target_vertiport_name", "Utrecht <Centraal>");
+        model.insert("target_vertiport_address", "Domplein 1");
+        model.insert("old_departure_time", "2024-01-01 10:00 CET");
+        model.insert("new_departure_time", "2024-01-01 10:30 CET");
+        model.insert("old_arrival_time", "2024-01-01 11:00 CET");
+        model.insert("new_arrival_time", "2024-01-01 11:30 CET");
+
+        let body = get_renderer()
+            .unwrap()
+            .render("flight-delay", &Locale::default(), &model)
+            .unwrap();
+
+        assert_eq!(body.subject, "Your Aetheric parcel has a new schedule");
+        assert!(body.text.contains("Hi Alice,"));
+        assert!(body
+            .text
+            .contains("New departure: 2024-01-01 10:30 CET (was 2024-01-01 10:00 CET)"));
+        assert!(body.html.contains("Utrecht &lt;Centraal&gt;"));
+    }
+
+    #[test]
+    fn test_render_pickup_reminder() {
+        let mut model = TemplateModel::default();
+        model.insert("customer_name", "Alice");
+        model.insert("lead_time", "in 1 hour");
+        model.insert("customer_pickup_time", "2024-01-01 10:10 CET");
+        model.insert("origin_vertiport_name", "Amsterdam <Zuid>");
+        model.insert("origin_vertiport_address", "Dam 1");
+        model.insert("target_vertiport_name", "Utrecht");
+        PICKUP_REMINDER.validate(&model).unwrap();
+
+        let renderer = get_renderer().unwrap();
+        let body = renderer
+            .render("pickup-reminder", &Locale::default(), &model)
+            .unwrap();
+        assert_eq!(
+            body.subject,
+            "Reminder: your Aetheric parcel pickup is in 1 hour"
+        );
+        assert!(body.text.contains("Hi Alice,"));
+        assert!(body
+            .text
+            .contains("your parcel is scheduled for pickup in 1 hour."));
+        assert!(body.html.contains("Amsterdam &lt;Zuid&gt;"));
+
+        let sms = renderer
+            .render_sms("pickup-reminder", &Locale::default(), &model)
+            .unwrap();
+        assert_eq!(
+            sms,
+            "Aetheric: your parcel pickup at Amsterdam <Zuid>, Dam 1 is in 1 hour \
+            (2024-01-01 10:10 CET)."
+        );
+    }
+
+    #[test]
+    fn test_render_email_verification() {
+        let mut model = TemplateModel::default();
+        model.insert("customer_name", "Alice");
+        model.insert(
+            "verification_url",
+            "https://contact.aetheric.nl/contact/verify?token=a.1.b",
+        );
+        model.insert("link_valid_hours", 24);
+        EMAIL_VERIFICATION.validate(&model).unwrap();
+
+        let body = get_renderer()
+            .unwrap()
+            .render("email-verification", &Locale::default(), &model)
+            .unwrap();
+        assert_eq!(body.subject, "Confirm your email address for Aetheric");
+        assert!(body.text.contains("Hi Alice,"));
+        assert!(body
+            .text
+            .contains("https://contact.aetheric.nl/contact/verify?token=a.1.b"));
+        assert!(body.text.contains("valid for 24 hours"));
+        assert!(body.html.contains("<a href=\"https://contact.aetheric.nl"));
+    }
+
+    #[test]
+    fn test_render_errors() {
+        let renderer = get_renderer().unwrap();
+
+        let error = renderer
+            .render("unknown", &Locale::default(), &confirmation_model())
+            .unwrap_err();
+        assert_eq!(error, TemplateError::Unknown("unknown".to_string()));
+
+        let error = renderer
+            .render(
+                "cargo-confirmation",
+                &Locale::default(),
+                &TemplateModel::default(),
+            )
+            .unwrap_err();
+        assert!(matches!(error, TemplateError::Render(_)));
+
+        let error = renderer
+            .render_sms("flight-delay", &Locale::default(), &confirmation_model())
+            .unwrap_err();
+        assert_eq!(
+            error,
+            TemplateError::Unknown("flight-delay (text message)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_template_error_display() {
+        assert_eq!(
+            TemplateError::Unknown("a".to_string()).to_string(),
+            "Unknown template: a"
+        );
+        assert_eq!(
+            TemplateError::Invalid("b".to_string()).to_string(),
+            "Invalid template: b"
+        );
+        assert_eq!(
+            TemplateError::Render("c".to_string()).to_string(),
+            "Could not render template: c"
+        );
+        assert_eq!(
+            TemplateError::Model("d".to_string()).to_string(),
+            "Invalid template model: d"
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! Signed tokens
+//! carry an identifier in links sent to users, e.g. to unsubscribe,
+//! so the link can be trusted without storing it
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt::{self, Debug, Display, Formatter};
+
+/// HMAC-SHA256, the signature of every token
+type HmacSha256 = Hmac<Sha256>;
+
+/// Reasons a token is refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenError {
+    /// The token wasn't issued by this service or was cut off
+    Malformed,
+
+    /// The token was altered, or signed with another key or for another purpose
+    Signature,
+
+    /// The token is past its expiry
+    Expired,
+}
+
+impl std::error::Error for TokenError {}
+
+impl Display for TokenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Malformed => write!(f, "Malformed token"),
+            TokenError::Signature => write!(f, "Invalid token signature"),
+            TokenError::Expired => write!(f, "Token expired"),
+        }
+    }
+}
+
+/// Signs and verifies tokens of the form `<subject>.<expiry>.<signature>`,
+/// with the subject and signature base64url encoded and the expiry in
+/// seconds since the epoch. Tokens are bound to a purpose, so a token issued
+/// for one purpose can't be used for another.
+#[derive(Clone)]
+pub struct TokenSigner {
+    mac: HmacSha256,
+}
+
+impl Debug for TokenSigner {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // don't leak the key into logs
+        f.debug_struct("TokenSigner").finish_non_exhaustive()
+    }
+}
+
+impl TokenSigner {
+    /// Creates a signer with the provided key, none if the key is empty
+    pub fn new(key: &str) -> Option<Self> {
+        if key.is_empty() {
+            return None;
+        }
+
+        // HMAC accepts keys of any length
+        HmacSha256::new_from_slice(key.as_bytes())
+            .ok()
+            .map(|mac| TokenSigner { mac })
+    }
+
+    /// Signature of the purpose and payload
+    fn signature(&self, purpose: &str, payload: &str) -> HmacSha256 {
+        let mut mac = self.mac.clone();
+        mac.update(purpose.as_bytes());
+        mac.update(b":");
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    /// Issues a token for `subject` that expires at `expires_at`
+    pub fn sign(&self, purpose: &str, subject: &str, expires_at: DateTime<Utc>) -> String {
+        let payload = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(subject),
+            expires_at.timestamp()
+        );
+        let signature = self.signature(purpose, &payload).finalize().into_bytes();
+
+        format!("{}.{}", payload, URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    /// Checks a token was issued by this signer for `purpose` and hasn't
+    /// expired at `now`, returning its subject
+    pub fn verify(
+        &self,
+        purpose: &str,
+        token: &str,
+        now: DateTime<Utc>,
+    ) -> Result<String, TokenError> {
+        let mut parts = token.trim().split('.');
+        let (Some(subject), Some(expires_at), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(TokenError::Malformed);
+        };
+
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| TokenError::Malformed)?;
+        let payload = format!("{}.{}", subject, expires_at);
+        self.signature(purpose, &payload)
+            .verify_slice(&signature)
+            .map_err(|_| TokenError::Signature)?;
+
+        // the signature is valid, so the payload was issued by this signer
+        let expires_at: i64 = expires_at.parse().map_err(|_| TokenError::Malformed)?;
+        if now.timestamp() >= expires_at {
+            return Err(TokenError::Expired);
+        }
+
+        URL_SAFE_NO_PAD
+            .decode(subject)
+            .ok()
+            .and_then(|subject| String::from_utf8(subject).ok())
+            .ok_or(TokenError::Malformed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn now() -> DateTime<Utc> {
+        "2024-06-01T12:00:00Z".parse().unwrap()
+    }
+
+    #[test]
+    fn test_new() {
+        assert!(TokenSigner::new("").is_none());
+        assert!(TokenSigner::new("secret").is_some());
+        assert_eq!(
+            format!("{:?}", TokenSigner::new("secret").unwrap()),
+            "TokenSigner { .. }"
+        );
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signer = TokenSigner::new("secret").unwrap();
+        let expires_at = now() + Duration::try_days(1).unwrap();
+        let token = signer.sign("unsubscribe", "alice+test@aetheric.nl", expires_at);
+        assert!(token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)));
+
+        assert_eq!(
+            signer.verify("unsubscribe", &token, now()),
+            Ok("alice+test@aetheric.nl".to_string())
+        );
+        assert_eq!(
+            signer.verify("unsubscribe", &token, expires_at),
+            Err(TokenError::Expired)
+        );
+
+        // bound to the key and the purpose
+        let other = TokenSigner::new("other secret").unwrap();
+        assert_eq!(
+            other.verify("unsubscribe", &token, now()),
+            Err(TokenError::Signature)
+        );
+        assert_eq!(
+            signer.verify("verify", &token, now()),
+            Err(TokenError::Signature)
+        );
+
+        // an altered expiry or subject invalidates the signature
+        let parts: Vec<&str> = token.split('.').collect();
+        let extended = format!("{}.{}.{}", parts[0], expires_at.timestamp() + 1, parts[2]);
+        assert_eq!(
+            signer.verify("unsubscribe", &extended, now()),
+            Err(TokenError::Signature)
+        );
+        let forged = format!(
+            "{}.{}.{}",
+            URL_SAFE_NO_PAD.encode("bob"),
+            parts[1],
+            parts[2]
+        );
+        assert_eq!(
+            signer.verify("unsubscribe", &forged, now()),
+            Err(TokenError::Signature)
+        );
+    }
+
+    #[test]
+    fn test_verify_malformed() {
+        let signer = TokenSigner::new("secret").unwrap();
+        for token in ["", "token", "a.b", "a.b.c.d", "a.1.!!!"] {
+            assert_eq!(
+                signer.verify("unsubscribe", token, now()),
+                Err(TokenError::Malformed)
+            );
+        }
+    }
+
+    #[test]
+    fn test_token_error_display() {
+        assert_eq!(TokenError::Malformed.to_string(), "Malformed token");
+        assert_eq!(TokenError::Signature.to_string(), "Invalid token signature");
+        assert_eq!(TokenError::Expired.to_string(), "Token expired");
+    }
+}